@@ -1,22 +1,50 @@
 mod tsv_params;
 
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::ffi::OsString;
 use std::fs::File;
 use std::io;
+use std::path::Path;
 use std::str;
 
 use clap::error::ErrorKind;
-use clap::{crate_authors, crate_version, Arg, Command};
+use clap::{crate_authors, crate_version, Arg, ArgMatches, Command};
 #[cfg(feature = "mmap")]
 use memmap2::Mmap;
 
-use entab::readers::get_reader;
+use entab::archive::{Archive, ArchiveReader};
+use entab::params::parse_constant;
+use entab::parsers::agilent::directory::AgilentDirectoryReader;
+use entab::readers::{get_reader, RecordReader};
 use entab::record::Value;
-use entab::EtError;
+use entab::registry::parsers;
+use entab::select::Selection;
+use entab::{ErrorKind as EtErrorKind, EtError};
 
 use crate::tsv_params::TsvParams;
 
+/// The process exit code for an error (following the `sysexits.h` conventions).
+#[must_use]
+pub fn exit_code(err: &EtError) -> i32 {
+    match err.kind {
+        // EX_USAGE
+        EtErrorKind::UnusedParams | EtErrorKind::InvalidParam => 64,
+        // EX_DATAERR
+        EtErrorKind::InvalidMagic
+        | EtErrorKind::Truncated
+        | EtErrorKind::InvalidValue
+        | EtErrorKind::Decompression
+        | EtErrorKind::LimitExceeded => 65,
+        // EX_UNAVAILABLE
+        EtErrorKind::UnsupportedFormat => 69,
+        // EX_IOERR
+        EtErrorKind::Io => 74,
+        // `Other` and any kinds added later
+        _ => 1,
+    }
+}
+
 /// Parse the provided `stdin` using `args` and write results to `stdout`.
 ///
 /// # Errors
@@ -50,12 +78,71 @@ where
                 .help("Parser to use [if not specified, it will be auto-detected]")
                 .takes_value(true),
         )
+        .arg(
+            Arg::new("param")
+                .long("param")
+                .help("Set a parser option, e.g. \"delimiter=|\" [can be repeated]")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("members")
+                .long("members")
+                .help("Read every file in a ZIP or TAR archive, adding a column with their names"),
+        )
+        .arg(
+            Arg::new("lenient")
+                .long("lenient")
+                .help("Skip malformed records (printing a warning for each) instead of stopping"),
+        )
+        .arg(
+            Arg::new("list_parsers")
+                .long("list-parsers")
+                .help("Lists the available parsers instead of reading a file"),
+        )
         .arg(
             Arg::new("metadata")
                 .short('m')
                 .long("metadata")
                 .help("Reports metadata about the file instead of the data itself"),
         )
+        .arg(
+            Arg::new("columns")
+                .short('c')
+                .long("columns")
+                .help("Comma-separated list of the columns to output")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .help(
+                    "Only output rows matching a comparison, e.g. \"mapq > 30\" [can be repeated]",
+                )
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("format")
+                .short('f')
+                .long("format")
+                .help("Format to write the output in")
+                .possible_values(["tsv", "parquet"])
+                .default_value("tsv"),
+        )
+        .arg(
+            Arg::new("row_group_size")
+                .long("row-group-size")
+                .help("Maximum number of rows in each Parquet row group")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("compression")
+                .long("compression")
+                .help("Compression to use for Parquet output")
+                .possible_values(["none", "snappy", "gzip"])
+                .takes_value(true),
+        )
         .try_get_matches_from(args);
 
     let matches = match clap_result {
@@ -65,10 +152,18 @@ where
                 e.print()?;
                 return Ok(());
             }
-            return Err(e.to_string().into());
+            return Err(EtError::from(e.to_string()).with_kind(EtErrorKind::InvalidParam));
         }
     };
 
+    if matches.is_present("list_parsers") {
+        return list_parsers(stdout);
+    }
+    if matches.is_present("metadata") && matches.value_of("format") == Some("parquet") {
+        return Err(EtError::from("Metadata can't be written out as Parquet")
+            .with_kind(EtErrorKind::InvalidParam));
+    }
+
     // TODO: map/reduce/filter options?
     // every column should either have a reduction set or it'll be dropped from
     // the result? reductions can be e.g. sum,average,count or group or column
@@ -77,23 +172,106 @@ where
 
     #[cfg(feature = "mmap")]
     let mmap: Mmap;
+    let archive: Archive;
 
     let mut parse_params = BTreeMap::new();
+    if let Some(raw_params) = matches.values_of("param") {
+        for raw_param in raw_params {
+            let (key, value) = raw_param.split_once('=').ok_or_else(|| {
+                EtError::from(format!("Param {} must be in the form key=value", raw_param))
+                    .with_kind(EtErrorKind::InvalidParam)
+            })?;
+            let _ = parse_params.insert(key.trim().to_string(), parse_constant(value));
+        }
+    }
+    if matches.is_present("lenient") {
+        let _ = parse_params.insert("lenient".to_string(), Value::Boolean(true));
+    }
     let parser = matches.value_of("parser");
-    let (mut rec_reader, _) = if let Some(i) = matches.value_of("input") {
-        parse_params.insert("filename".to_string(), Value::String(i.into()));
-        let file = File::open(i)?;
-        #[cfg(feature = "mmap")]
-        {
-            mmap = unsafe { Mmap::map(&file)? };
-            get_reader(mmap.as_ref(), parser, Some(parse_params))?
+    let (mut rec_reader, _): (Box<dyn RecordReader + '_>, _) = if matches.is_present("members") {
+        // bad values are reported when the params are checked for each member
+        let max_buffer_size = match parse_params.get("max_buffer_size") {
+            Some(Value::Integer(i)) => usize::try_from(*i).ok(),
+            _ => None,
+        };
+        archive = if let Some(i) = matches.value_of("input") {
+            let file = File::open(i)?;
+            #[cfg(feature = "mmap")]
+            {
+                mmap = unsafe { Mmap::map(&file)? };
+                Archive::new(mmap.as_ref(), max_buffer_size)?
+            }
+            #[cfg(not(feature = "mmap"))]
+            Archive::new(file, max_buffer_size)?
+        } else {
+            let buffer: Box<dyn io::Read> = Box::new(stdin);
+            Archive::new(buffer, max_buffer_size)?
+        };
+        (
+            Box::new(ArchiveReader::new(&archive, parser, Some(parse_params))?),
+            "archive",
+        )
+    } else if let Some(i) = matches.value_of("input") {
+        if Path::new(i).is_dir() {
+            // directories can't be streamed, so they're read as Agilent acquisitions
+            if parser.is_some() {
+                return Err(EtError::from(
+                    "Directories are always read as Agilent acquisitions; a parser can't be set",
+                )
+                .with_kind(EtErrorKind::InvalidParam));
+            }
+            if !parse_params.is_empty() {
+                let keys: Vec<&str> = parse_params.keys().map(AsRef::as_ref).collect();
+                return Err(EtError::from(format!(
+                    "Unused params remain: {} (Agilent directories accept no params)",
+                    keys.join(",")
+                ))
+                .with_kind(EtErrorKind::UnusedParams));
+            }
+            (
+                Box::new(AgilentDirectoryReader::new(i)?),
+                "agilent_directory",
+            )
+        } else {
+            parse_params.insert("filename".to_string(), Value::String(i.into()));
+            let file = File::open(i)?;
+            #[cfg(feature = "mmap")]
+            {
+                mmap = unsafe { Mmap::map(&file)? };
+                get_reader(mmap.as_ref(), parser, Some(parse_params))?
+            }
+            #[cfg(not(feature = "mmap"))]
+            get_reader(file, parser, Some(parse_params))?
         }
-        #[cfg(not(feature = "mmap"))]
-        get_reader(file, parser, Some(parse_params))?
     } else {
         let buffer: Box<dyn io::Read> = Box::new(stdin);
         get_reader(buffer, parser, Some(parse_params))?
     };
+    let mut selection = Selection::default();
+    if let Some(columns) = matches.value_of("columns") {
+        let columns: Vec<&str> = columns.split(',').map(str::trim).collect();
+        selection = selection.columns(&columns);
+    }
+    if let Some(filters) = matches.values_of("filter") {
+        for filter in filters {
+            selection = selection.filter(filter.parse()?);
+        }
+    }
+    if !selection.is_empty() {
+        rec_reader.select(&selection)?;
+    }
+
+    if matches.value_of("format") == Some("parquet") {
+        write_parquet(
+            Box::new(&mut *rec_reader),
+            &matches,
+            matches.value_of("output"),
+            stdout,
+        )?;
+        report_warnings(&*rec_reader);
+        return Ok(());
+    }
+
     // TODO: allow user to set these
     let params = TsvParams::default();
 
@@ -133,10 +311,76 @@ where
         writer.write_all(&params.line_delimiter)?;
     }
     writer.flush()?;
+    report_warnings(&*rec_reader);
+
+    Ok(())
+}
 
+/// Print the errors for the records that were skipped in lenient mode to stderr.
+fn report_warnings(rec_reader: &dyn RecordReader) {
+    for warning in rec_reader.warnings() {
+        eprint!("warning: {}", warning);
+    }
+}
+
+/// Write a TSV of the parsers `get_reader` can use to `stdout`.
+fn list_parsers<W: io::Write>(mut stdout: W) -> Result<(), EtError> {
+    stdout.write_all(b"parser\textensions\tparams\tdescription\n")?;
+    for parser in parsers() {
+        let params: Vec<&str> = parser.params.iter().map(|p| p.name).collect();
+        writeln!(
+            stdout,
+            "{}\t{}\t{}\t{}",
+            parser.name,
+            parser.extensions.join(","),
+            params.join(","),
+            parser.description
+        )?;
+    }
+    stdout.flush()?;
     Ok(())
 }
 
+/// Write the records from `rec_reader` out as Parquet to `output` (or `stdout`).
+#[cfg(feature = "parquet")]
+fn write_parquet<W: io::Write>(
+    rec_reader: Box<dyn RecordReader + '_>,
+    matches: &ArgMatches,
+    output: Option<&str>,
+    mut stdout: W,
+) -> Result<(), EtError> {
+    use entab::parquet::{write_parquet, ParquetParams};
+
+    let mut params = ParquetParams::default();
+    if let Some(size) = matches.value_of("row_group_size") {
+        params.row_group_size = size.parse()?;
+    }
+    if let Some(compression) = matches.value_of("compression") {
+        params.compression = compression.parse()?;
+    }
+    if let Some(path) = output {
+        let _ = write_parquet(rec_reader, File::create(path)?, Some(params))?;
+    } else {
+        // the Parquet writer needs a `Send` writer (and the footer can only be written once all
+        // the row groups are), so build the file up in memory and then copy that out
+        let mut buffer = Vec::new();
+        let _ = write_parquet(rec_reader, &mut buffer, Some(params))?;
+        stdout.write_all(&buffer)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+fn write_parquet<W: io::Write>(
+    _rec_reader: Box<dyn RecordReader + '_>,
+    _matches: &ArgMatches,
+    _output: Option<&str>,
+    _stdout: W,
+) -> Result<(), EtError> {
+    Err("entab was compiled without Parquet support".into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -149,6 +393,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_exit_codes() {
+        let mut out = Vec::new();
+        let err = run(["entab"], &b">test\nAC>GT"[..], io::Cursor::new(&mut out))
+            .expect_err("the FASTA is malformed");
+        assert_eq!(err.kind, EtErrorKind::InvalidValue);
+        assert_eq!(exit_code(&err), 65);
+
+        let err = run(
+            ["entab", "--param", "delimiter"],
+            &b"a,b\n"[..],
+            io::Cursor::new(&mut out),
+        )
+        .expect_err("the param has no value");
+        assert_eq!(exit_code(&err), 64);
+
+        let err = run(["entab", "-p", "nope"], &b""[..], io::Cursor::new(&mut out))
+            .expect_err("there's no parser named nope");
+        assert_eq!(exit_code(&err), 69);
+    }
+
+    #[test]
+    fn test_lenient() -> Result<(), EtError> {
+        let data = &b">a\nAC>GT\n>b\nTT\n"[..];
+        let mut out = Vec::new();
+        assert!(run(["entab"], data, io::Cursor::new(&mut out)).is_err());
+
+        let mut out = Vec::new();
+        run(["entab", "--lenient"], data, io::Cursor::new(&mut out))?;
+        assert_eq!(&out[..], b"id\tsequence\nb\tTT\n");
+        Ok(())
+    }
+
     #[test]
     fn test_output() -> Result<(), EtError> {
         let mut out = Vec::new();
@@ -157,6 +434,142 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_columns_and_filter() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--columns", "sequence,id", "--filter", "id != b"],
+            &b">a\nACGT\n>b\nTT\n>c\nGG"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"sequence\tid\nACGT\ta\nGG\tc\n");
+
+        let mut out = Vec::new();
+        assert!(run(
+            ["entab", "--columns", "quality"],
+            &b">a\nACGT"[..],
+            io::Cursor::new(&mut out),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_params() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "-p", "tsv", "--param", "delimiter=|"],
+            &b"a|b\n1|2\n"[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(&out[..], b"a\tb\n1\t2\n");
+
+        let mut out = Vec::new();
+        assert!(run(
+            ["entab", "-p", "tsv", "--param", "delimiter"],
+            &b"a|b\n1|2\n"[..],
+            io::Cursor::new(&mut out),
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_agilent_directory() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../entab/tests/data/chemstation_mwd.d"
+        );
+        assert!(run(["entab", "-i", path], &b""[..], io::Cursor::new(&mut out)).is_ok());
+        assert!(out.starts_with(b"source_file\tsignal\ttime\tmz\twavelength\tintensity\n"));
+
+        // nothing that would change how the files are read can be set
+        let err = run(["entab", "-i", path, "--lenient"], &b""[..], io::sink()).unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::UnusedParams);
+        let err = run(
+            ["entab", "-i", path, "--param", "per_scan=true"],
+            &b""[..],
+            io::sink(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::UnusedParams);
+        let err = run(
+            ["entab", "-i", path, "-p", "chemstation_mwd"],
+            &b""[..],
+            io::sink(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::InvalidParam);
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_members() -> Result<(), EtError> {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../entab/tests/data/reads.tar.gz"
+        );
+        let mut out = Vec::new();
+        run(
+            ["entab", "--members", "-i", path, "-c", "member"],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        assert_eq!(
+            &out[..],
+            &b"member\nreads/a.fastq\nreads/a.fastq\nreads/b.fastq\nreads/b.fastq\nreads/b.fastq\n"
+                [..]
+        );
+
+        // archives can also be piped in
+        let mut out = Vec::new();
+        let data = std::fs::read(path)?;
+        run(["entab", "--members"], &data[..], io::Cursor::new(&mut out))?;
+        assert!(out.starts_with(b"member\tid\tsequence\tquality\nreads/a.fastq\t"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parquet_output() {
+        let mut out = Vec::new();
+        let result = run(
+            ["entab", "-f", "parquet", "--compression", "gzip"],
+            &b">test\nACGT"[..],
+            io::Cursor::new(&mut out),
+        );
+        if cfg!(feature = "parquet") {
+            assert!(result.is_ok());
+            assert!(out.starts_with(b"PAR1"));
+            assert!(out.ends_with(b"PAR1"));
+        } else {
+            assert!(result.is_err());
+        }
+
+        let err = run(
+            ["entab", "-f", "parquet", "--metadata"],
+            &b">test\nACGT"[..],
+            io::Cursor::new(Vec::new()),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind, EtErrorKind::InvalidParam);
+    }
+
+    #[test]
+    fn test_list_parsers() -> Result<(), EtError> {
+        let mut out = Vec::new();
+        run(
+            ["entab", "--list-parsers"],
+            &b""[..],
+            io::Cursor::new(&mut out),
+        )?;
+        let out = String::from_utf8(out)?;
+        assert!(out.starts_with("parser\textensions\tparams\tdescription\n"));
+        assert!(out.contains("\nfasta\tfa,faa,fasta,fna\t\tFASTA sequences\n"));
+        assert!(out.contains("\ntsv\ttsv\tdelimiter,quote,skip_lines,sniff,infer_types\t"));
+        Ok(())
+    }
+
     #[test]
     fn test_metadata() -> Result<(), EtError> {
         let mut out = Vec::new();
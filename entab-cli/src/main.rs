@@ -1,7 +1,7 @@
 use std::env::args_os;
 use std::io;
 
-use entab_cli::run;
+use entab_cli::{exit_code, run};
 
 pub fn main() {
     let stdin = io::stdin();
@@ -11,5 +11,6 @@ pub fn main() {
         eprintln!("##### AN ERROR OCCURRED ####");
         eprintln!("{}", e);
         eprintln!("#####");
+        std::process::exit(exit_code(&e));
     }
 }
@@ -0,0 +1,468 @@
+#![deny(
+    missing_docs,
+    trivial_casts,
+    trivial_numeric_casts,
+    unreachable_pub,
+    unused_import_braces,
+    unused_qualifications,
+    unused_results
+)]
+//! Derive macros for writing entab parsers.
+//!
+//! These are re-exported from `entab` when its `derive` feature is enabled, so
+//! use them through `entab::parsers::FromSlice` rather than this crate.
+
+use std::collections::BTreeSet;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::meta::ParseNestedMeta;
+use syn::{
+    parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Fields, GenericParam,
+    Ident, Lifetime, LitByteStr, LitInt, LitStr, Path, Type,
+};
+
+/// Derive `FromSlice` for a struct with a fixed binary layout.
+///
+/// Fields are read in order. Integers and floats use the struct's byte order
+/// (little-endian unless `#[entab(endian = "big")]` is given), `&[u8]` and
+/// `&str` fields need a `count` to know how many bytes to take, and any other
+/// field is read with its own `FromSlice` implementation.
+///
+/// Alongside `FromSlice`, this generates the record conversions from
+/// `impl_record!` and (unless `#[entab(state = ...)]` is given) a `<Name>State`
+/// whose header lists every field, so the struct can be passed straight to
+/// `impl_reader!`.
+///
+/// The generated code refers to `::entab`; if entab's been renamed in
+/// `Cargo.toml` (e.g. `entab_base = { package = "entab", ... }`), pass the
+/// name it's imported under with `#[entab(crate = "entab_base")]`.
+#[proc_macro_derive(FromSlice, attributes(entab))]
+pub fn derive_from_slice(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Endian {
+    Big,
+    Little,
+}
+
+enum Count {
+    Field(Ident),
+    Fixed(LitInt),
+}
+
+#[derive(Default)]
+struct StructAttrs {
+    endian: Option<Endian>,
+    magic: Option<LitByteStr>,
+    state: Option<Path>,
+    krate: Option<Path>,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    endian: Option<Endian>,
+    count: Option<Count>,
+    skip: Option<LitInt>,
+    magic: Option<LitByteStr>,
+}
+
+/// How the `FromSlice` state for a field is created
+enum FieldKind {
+    /// An integer or float that needs an `Endian`
+    Number(Endian),
+    /// A `&[u8]` or `&str` that needs a length
+    Slice(Count),
+    /// Anything else; uses the default state for that type
+    Other,
+}
+
+struct FieldLayout<'a> {
+    ident: &'a Ident,
+    ty: &'a Type,
+    kind: FieldKind,
+    skip: Option<LitInt>,
+    magic: Option<LitByteStr>,
+}
+
+fn parse_endian(meta: &ParseNestedMeta<'_>) -> syn::Result<Endian> {
+    let value: LitStr = meta.value()?.parse()?;
+    match value.value().as_str() {
+        "big" => Ok(Endian::Big),
+        "little" => Ok(Endian::Little),
+        _ => Err(Error::new(
+            value.span(),
+            "endian must be either \"big\" or \"little\"",
+        )),
+    }
+}
+
+fn parse_struct_attrs(attrs: &[Attribute]) -> syn::Result<StructAttrs> {
+    let mut parsed = StructAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("entab")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("endian") {
+                parsed.endian = Some(parse_endian(&meta)?);
+            } else if meta.path.is_ident("magic") {
+                parsed.magic = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("state") {
+                parsed.state = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("crate") {
+                let value: LitStr = meta.value()?.parse()?;
+                parsed.krate = Some(value.parse()?);
+            } else {
+                return Err(
+                    meta.error("unknown entab attribute; expected endian, magic, state or crate")
+                );
+            }
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+fn parse_field_attrs(attrs: &[Attribute]) -> syn::Result<FieldAttrs> {
+    let mut parsed = FieldAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("entab")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("endian") {
+                parsed.endian = Some(parse_endian(&meta)?);
+            } else if meta.path.is_ident("count") {
+                let value = meta.value()?;
+                parsed.count = Some(if value.peek(LitInt) {
+                    Count::Fixed(value.parse()?)
+                } else {
+                    Count::Field(value.parse()?)
+                });
+            } else if meta.path.is_ident("skip") {
+                parsed.skip = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("magic") {
+                parsed.magic = Some(meta.value()?.parse()?);
+            } else {
+                return Err(
+                    meta.error("unknown entab attribute; expected endian, count, skip or magic")
+                );
+            }
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+fn is_number(ty: &Type) -> bool {
+    const NUMBERS: [&str; 10] = [
+        "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "f32", "f64",
+    ];
+    match ty {
+        Type::Path(path) if path.qself.is_none() => {
+            NUMBERS.iter().any(|number| path.path.is_ident(number))
+        }
+        _ => false,
+    }
+}
+
+fn is_slice(ty: &Type) -> bool {
+    match ty {
+        Type::Reference(reference) if reference.mutability.is_none() => match &*reference.elem {
+            Type::Slice(slice) => matches!(&*slice.elem, Type::Path(p) if p.path.is_ident("u8")),
+            Type::Path(path) => path.path.is_ident("str"),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    name,
+                    "FromSlice can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                name,
+                "FromSlice can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut lifetimes = input.generics.params.iter().map(|param| match param {
+        GenericParam::Lifetime(def) => Ok(&def.lifetime),
+        _ => Err(Error::new_spanned(
+            param,
+            "FromSlice can only be derived for structs without type or const parameters",
+        )),
+    });
+    let buffer_lt = lifetimes.next().transpose()?;
+    if let Some(extra) = lifetimes.next() {
+        return Err(Error::new_spanned(
+            extra?,
+            "FromSlice can only be derived for structs with at most one lifetime",
+        ));
+    }
+
+    let struct_attrs = parse_struct_attrs(&input.attrs)?;
+    let default_endian = struct_attrs.endian.unwrap_or(Endian::Little);
+    let krate = struct_attrs
+        .krate
+        .clone()
+        .unwrap_or_else(|| parse_quote!(::entab));
+
+    let mut seen = BTreeSet::new();
+    let mut referenced = BTreeSet::new();
+    let mut layouts = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have idents");
+        let attrs = parse_field_attrs(&field.attrs)?;
+        let kind = if is_number(&field.ty) {
+            if attrs.count.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "count only applies to &[u8] and &str fields",
+                ));
+            }
+            FieldKind::Number(attrs.endian.unwrap_or(default_endian))
+        } else if is_slice(&field.ty) {
+            match attrs.count {
+                Some(Count::Field(count_field)) => {
+                    if !seen.contains(&count_field) {
+                        return Err(Error::new_spanned(
+                            &count_field,
+                            "count must refer to a number field declared before this one",
+                        ));
+                    }
+                    let _ = referenced.insert(count_field.clone());
+                    FieldKind::Slice(Count::Field(count_field))
+                }
+                Some(count) => FieldKind::Slice(count),
+                None => {
+                    return Err(Error::new_spanned(
+                        field,
+                        "&[u8] and &str fields need a #[entab(count = ...)]",
+                    ))
+                }
+            }
+        } else {
+            if attrs.count.is_some() || attrs.endian.is_some() {
+                return Err(Error::new_spanned(
+                    field,
+                    "count and endian only apply to numbers, &[u8] and &str fields",
+                ));
+            }
+            FieldKind::Other
+        };
+        let _ = seen.insert(ident.clone());
+        layouts.push(FieldLayout {
+            ident,
+            ty: &field.ty,
+            kind,
+            skip: attrs.skip,
+            magic: attrs.magic,
+        });
+    }
+    for layout in &layouts {
+        if referenced.contains(layout.ident) && !matches!(layout.kind, FieldKind::Number(_)) {
+            return Err(Error::new_spanned(
+                layout.ident,
+                "only number fields can be used as a count",
+            ));
+        }
+    }
+
+    let b = buffer_lt
+        .cloned()
+        .unwrap_or_else(|| Lifetime::new("'entab_b", Span::call_site()));
+    let s = Lifetime::new("'entab_s", Span::call_site());
+    let self_ty = if buffer_lt.is_some() {
+        quote!(#name<#b>)
+    } else {
+        quote!(#name)
+    };
+    let record_ty = if buffer_lt.is_some() {
+        quote!(#name<'r>)
+    } else {
+        quote!(#name)
+    };
+
+    let name_str = name.to_string();
+    let mut parse_steps = Vec::new();
+    let mut get_steps = Vec::new();
+    if let Some(magic) = &struct_attrs.magic {
+        parse_steps.push(check_magic(&krate, magic, &name_str));
+        get_steps.push(quote! { pos += #magic.len(); });
+    }
+    for layout in &layouts {
+        let ident = layout.ident;
+        let ty = layout.ty;
+        let field_str = ident.to_string();
+        if let Some(skip) = &layout.skip {
+            let msg = format!(
+                "Record ended before the bytes before `{}` could be skipped",
+                field_str
+            );
+            parse_steps.push(quote! {
+                if buffer.len() < pos + #skip {
+                    return Err(#krate::EtError::from(#msg).incomplete());
+                }
+                pos += #skip;
+            });
+            get_steps.push(quote! { pos += #skip; });
+        }
+        if let Some(magic) = &layout.magic {
+            parse_steps.push(check_magic(&krate, magic, &field_str));
+            get_steps.push(quote! { pos += #magic.len(); });
+        }
+
+        let bad_count = format!("Invalid count for `{}`", field_str);
+        let field_state = |count_field: TokenStream2| match &layout.kind {
+            FieldKind::Number(Endian::Big) => quote!(#krate::parsers::Endian::Big),
+            FieldKind::Number(Endian::Little) => quote!(#krate::parsers::Endian::Little),
+            FieldKind::Slice(Count::Fixed(count)) => quote!(#count),
+            FieldKind::Slice(Count::Field(_)) => quote! {
+                <usize as ::core::convert::TryFrom<_>>::try_from(#count_field)
+                    .map_err(|_| #krate::EtError::from(#bad_count))?
+            },
+            FieldKind::Other => quote! {
+                <<#ty as #krate::parsers::FromSlice<#b, '_>>::State as ::core::default::Default>::default()
+            },
+        };
+        let (parse_state, get_state) = match &layout.kind {
+            FieldKind::Slice(Count::Field(count_field)) => {
+                let local = format_ident!("entab_{}", count_field);
+                (
+                    field_state(quote!(#local)),
+                    field_state(quote!(self.#count_field)),
+                )
+            }
+            _ => (field_state(quote!()), field_state(quote!())),
+        };
+
+        let missing = format!("Record ended before `{}` could be read", field_str);
+        parse_steps.push(quote! {
+            let start = pos;
+            let mut field_state = #parse_state;
+            if !<#ty as #krate::parsers::FromSlice<#b, '_>>::parse(
+                &buffer[start..],
+                eof,
+                &mut pos,
+                &mut field_state,
+            )? {
+                return Err(#krate::EtError::from(#missing).incomplete());
+            }
+        });
+        if referenced.contains(ident) {
+            let local = format_ident!("entab_{}", ident);
+            parse_steps.push(quote! {
+                let mut #local = <#ty as ::core::default::Default>::default();
+                #krate::parsers::FromSlice::get(&mut #local, &buffer[start..pos], &field_state)?;
+            });
+        } else {
+            parse_steps.push(quote! { let _ = (start, field_state); });
+        }
+        get_steps.push(quote! {
+            let start = pos;
+            let mut field_state = #get_state;
+            let _ = <#ty as #krate::parsers::FromSlice<#b, '_>>::parse(
+                &buffer[start..],
+                true,
+                &mut pos,
+                &mut field_state,
+            )?;
+            <#ty as #krate::parsers::FromSlice<#b, '_>>::get(
+                &mut self.#ident,
+                &buffer[start..pos],
+                &field_state,
+            )?;
+        });
+    }
+
+    let field_idents: Vec<_> = layouts.iter().map(|l| l.ident).collect();
+    let field_names: Vec<_> = layouts.iter().map(|l| l.ident.to_string()).collect();
+    let vis = &input.vis;
+    let (state_ty, state_def) = match &struct_attrs.state {
+        Some(state) => (quote!(#state), quote!()),
+        None => {
+            let state = format_ident!("{}State", name);
+            let doc = format!("The parser state for a `{}`", name_str);
+            let def = quote! {
+                #[doc = #doc]
+                #[derive(Clone, Copy, Debug, Default)]
+                #vis struct #state;
+
+                impl #krate::record::StateMetadata for #state {
+                    fn header(&self) -> #krate::__alloc::vec::Vec<&str> {
+                        #krate::__alloc::vec![#(#field_names),*]
+                    }
+                }
+
+                impl<#b: #s, #s> #krate::parsers::FromSlice<#b, #s> for #state {
+                    type State = ();
+                }
+            };
+            (quote!(#state), def)
+        }
+    };
+
+    Ok(quote! {
+        impl<#b: #s, #s> #krate::parsers::FromSlice<#b, #s> for #self_ty {
+            type State = #state_ty;
+
+            fn parse(
+                buffer: &[u8],
+                eof: bool,
+                consumed: &mut usize,
+                _state: &mut Self::State,
+            ) -> ::core::result::Result<bool, #krate::EtError> {
+                if eof && buffer.is_empty() {
+                    return Ok(false);
+                }
+                let mut pos = 0;
+                #(#parse_steps)*
+                *consumed += pos;
+                Ok(true)
+            }
+
+            fn get(
+                &mut self,
+                buffer: &#b [u8],
+                _state: &#s Self::State,
+            ) -> ::core::result::Result<(), #krate::EtError> {
+                let mut pos = 0;
+                #(#get_steps)*
+                let _ = pos;
+                Ok(())
+            }
+        }
+
+        #state_def
+
+        #krate::impl_record!(#record_ty: #(#field_idents),*);
+    })
+}
+
+fn check_magic(krate: &Path, magic: &LitByteStr, location: &str) -> TokenStream2 {
+    let msg = format!("Magic bytes for `{}` did not match", location);
+    let short = format!("Record ended before the magic bytes for `{}`", location);
+    quote! {
+        let magic: &[u8] = #magic;
+        if buffer.len() < pos + magic.len() {
+            return Err(#krate::EtError::from(#short).incomplete());
+        }
+        if &buffer[pos..pos + magic.len()] != magic {
+            return Err(#msg.into());
+        }
+        pos += magic.len();
+    }
+}
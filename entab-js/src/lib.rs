@@ -4,11 +4,14 @@ mod utils;
 use std::collections::BTreeMap;
 use std::convert::AsRef;
 use std::io::{Cursor, Read};
+use std::num::NonZeroU64;
 
+use entab_base::batch::{Column, ColumnBatch, ColumnData};
 use entab_base::error::EtError;
 use entab_base::readers::{get_reader, RecordReader};
 use entab_base::record::Value;
-use js_sys::{Array, Object};
+use entab_base::registry;
+use js_sys::{Array, BigInt64Array, Float64Array, Object, Reflect};
 use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
@@ -27,32 +30,104 @@ pub struct Reader {
     parser: String,
     headers: Vec<String>,
     reader: Box<dyn RecordReader>,
+    batch: ColumnBatch,
 }
 
+/// Turn an `EtError` into a JS `Error` with a `kind` property (e.g. "Truncated")
 fn to_js(err: EtError) -> JsValue {
-    let res = err.to_string().into();
+    let js_err = js_sys::Error::new(&err.to_string());
+    js_err.set_name(&format!("{}Error", err.kind));
+    let res: JsValue = js_err.into();
+    let _ = Reflect::set(&res, &"kind".into(), &err.kind.name().into());
     // technically we could just take a &EtError, but to have a nice function signature we consume
     // the err so we should also drop it in here to make clippy happy
     drop(err);
     res
 }
 
+/// Convert a JS value into a `Value` (for passing params through to the parsers)
+fn value_from_js(value: &JsValue) -> Result<Value<'static>, JsValue> {
+    Ok(if value.is_null() || value.is_undefined() {
+        Value::Null
+    } else if let Some(b) = value.as_bool() {
+        Value::Boolean(b)
+    } else if let Some(f) = value.as_f64() {
+        // JS only has one kind of number so whole numbers are treated as integers
+        #[allow(clippy::cast_possible_truncation)]
+        if f.fract() == 0. && f.abs() < 9_007_199_254_740_992. {
+            Value::Integer(f as i64)
+        } else {
+            Value::Float(f)
+        }
+    } else if let Some(s) = value.as_string() {
+        Value::String(s.into())
+    } else if Array::is_array(value) {
+        Value::List(
+            Array::from(value)
+                .iter()
+                .map(|v| value_from_js(&v))
+                .collect::<Result<_, _>>()?,
+        )
+    } else {
+        return Err(JsValue::from_str(
+            "Param values must be booleans, numbers, strings or arrays",
+        ));
+    })
+}
+
+/// Convert a column from a `ColumnBatch` into a typed array (for numeric columns without nulls)
+/// or a plain `Array`.
+fn column_to_js(column: &Column) -> Result<JsValue, JsValue> {
+    Ok(match &column.data {
+        ColumnData::Float(v) if column.validity.is_none() => Float64Array::from(&v[..]).into(),
+        ColumnData::Integer(v) if column.validity.is_none() => BigInt64Array::from(&v[..]).into(),
+        _ => {
+            let array = Array::new();
+            for i in 0..column.len() {
+                let _ = array.push(
+                    &JsValue::from_serde(&column.get(i))
+                        .map_err(|_| JsValue::from_str("Error translating batch"))?,
+                );
+            }
+            array.into()
+        }
+    })
+}
+
 #[wasm_bindgen]
 impl Reader {
     #[wasm_bindgen(constructor)]
-    pub fn new(data: Box<[u8]>, parser: Option<String>) -> Result<Reader, JsValue> {
+    pub fn new(
+        data: Box<[u8]>,
+        parser: Option<String>,
+        params: Option<Object>,
+    ) -> Result<Reader, JsValue> {
         utils::set_panic_hook();
         if data.is_empty() {
             return Err(JsValue::from_str("Data is empty or of the wrong type."));
         }
         let stream: Box<dyn Read> = Box::new(Cursor::new(data));
 
-        let (reader, parser_used) = get_reader(stream, parser.as_deref(), None).map_err(to_js)?;
+        let mut parse_params = BTreeMap::new();
+        if let Some(params) = params {
+            for entry in Object::entries(&params).iter() {
+                let entry = Array::from(&entry);
+                let key = entry
+                    .get(0)
+                    .as_string()
+                    .ok_or_else(|| JsValue::from_str("Param names must be strings"))?;
+                let _ = parse_params.insert(key, value_from_js(&entry.get(1))?);
+            }
+        }
+
+        let (reader, parser_used) =
+            get_reader(stream, parser.as_deref(), Some(parse_params)).map_err(to_js)?;
         let headers = reader.headers();
         Ok(Reader {
             parser: parser_used.to_string(),
             headers,
             reader,
+            batch: ColumnBatch::default(),
         })
     }
 
@@ -76,6 +151,55 @@ impl Reader {
             .map_err(|_| JsValue::from_str("Error translating metadata"))
     }
 
+    #[wasm_bindgen(getter)]
+    pub fn schema(&self) -> Result<JsValue, JsValue> {
+        JsValue::from_serde(&self.reader.schema())
+            .map_err(|_| JsValue::from_str("Error translating schema"))
+    }
+
+    /// The malformed records skipped so far (with the `lenient` param), each with a `message`,
+    /// `kind` and the `byte`, `record`, `line` and `column` it was found at.
+    #[wasm_bindgen(getter)]
+    pub fn warnings(&self) -> JsValue {
+        // positions that aren't known are null
+        let number = |n: Option<u64>| n.map_or(JsValue::NULL, |n| JsValue::from_f64(n as f64));
+        let array = Array::new();
+        for warning in self.reader.warnings() {
+            let obj = Object::new();
+            let context = warning.context.as_ref();
+            let _ = Reflect::set(&obj, &"message".into(), &warning.msg.as_ref().into());
+            let _ = Reflect::set(&obj, &"kind".into(), &warning.kind.name().into());
+            for (key, value) in [
+                ("byte", context.map(|c| c.byte)),
+                ("record", context.map(|c| c.record)),
+                ("line", context.and_then(|c| c.line).map(NonZeroU64::get)),
+                (
+                    "column",
+                    context.and_then(|c| c.column).map(NonZeroU64::get),
+                ),
+            ] {
+                let _ = Reflect::set(&obj, &key.into(), &number(value));
+            }
+            array.push(&obj);
+        }
+        array.into()
+    }
+
+    /// Read up to `n` records into an object mapping each header to a column of values.
+    ///
+    /// Returns `null` once there are no more records.
+    #[wasm_bindgen]
+    pub fn next_batch(&mut self, n: usize) -> Result<JsValue, JsValue> {
+        if self.reader.next_batch(&mut self.batch, n).map_err(to_js)? == 0 {
+            return Ok(JsValue::NULL);
+        }
+        let obj = Object::new();
+        for column in self.batch.columns() {
+            let _ = Reflect::set(&obj, &column.name.as_str().into(), &column_to_js(column)?)?;
+        }
+        Ok(obj.into())
+    }
+
     #[allow(clippy::should_implement_trait)]
     #[wasm_bindgen]
     pub fn next(&mut self) -> Result<JsValue, JsValue> {
@@ -101,6 +225,38 @@ impl Reader {
     }
 }
 
+/// List the parsers that are available for reading files.
+#[wasm_bindgen]
+pub fn parsers() -> Result<JsValue, JsValue> {
+    let list = Array::new();
+    for parser in registry::parsers() {
+        let obj = Object::new();
+        let _ = Reflect::set(&obj, &"name".into(), &parser.name.into())?;
+        let _ = Reflect::set(&obj, &"description".into(), &parser.description.into())?;
+        let extensions: Array = parser
+            .extensions
+            .iter()
+            .map(|e| JsValue::from(*e))
+            .collect();
+        let _ = Reflect::set(&obj, &"extensions".into(), &extensions.into())?;
+        let params = Array::new();
+        for param in parser.params {
+            let param_obj = Object::new();
+            let _ = Reflect::set(&param_obj, &"name".into(), &param.name.into())?;
+            let _ = Reflect::set(
+                &param_obj,
+                &"type".into(),
+                &param.value_type.as_str().into(),
+            )?;
+            let _ = Reflect::set(&param_obj, &"description".into(), &param.description.into())?;
+            let _ = params.push(&param_obj.into());
+        }
+        let _ = Reflect::set(&obj, &"params".into(), &params.into())?;
+        let _ = list.push(&obj.into());
+    }
+    Ok(list.into())
+}
+
 #[wasm_bindgen(inline_js = "
   export function make_reader_iter(proto) { proto[Symbol.iterator] = function () { return this; }; }
 ")]
@@ -112,7 +268,11 @@ extern "C" {
 pub fn start() -> Result<(), JsValue> {
     // this is kind of hacky, but we create a simple object and get its prototype so we can add the
     // iterable marker onto it to allow e.g. `for (row of reader) {}`
-    let reader = Reader::new(b"\n".to_vec().into_boxed_slice(), Some("csv".to_string()))?;
+    let reader = Reader::new(
+        b"\n".to_vec().into_boxed_slice(),
+        Some("csv".to_string()),
+        None,
+    )?;
     make_reader_iter(&Object::get_prototype_of(&reader.into()));
     Ok(())
 }
@@ -2,8 +2,8 @@
 
 #![cfg(target_arch = "wasm32")]
 
-use entab::Reader;
-use js_sys::{Object, Reflect};
+use entab::{parsers, Reader};
+use js_sys::{Array, BigInt64Array, Object, Reflect};
 use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_test::*;
 
@@ -14,8 +14,8 @@ fn create_reader() {
     // doesn't work for obvious reasons, but it'd be nice to test against a Uint8Array
     // let data = Uint8Array::new(&JsValue::from_str(">test\nACGT"));
     let data = b">test\nACGT";
-    let mut reader =
-        Reader::new(data.to_vec().into_boxed_slice(), None).expect("Error creating the reader");
+    let mut reader = Reader::new(data.to_vec().into_boxed_slice(), None, None)
+        .expect("Error creating the reader");
     assert_eq!(reader.parser(), "fasta");
     let raw_rec = reader.next().expect("Error reading first record");
     let rec = raw_rec
@@ -31,3 +31,118 @@ fn create_reader() {
     assert_eq!(raw_id, JsValue::from_str("test"));
     assert!(Reflect::has(&value, &JsValue::from_str("sequence")).expect("value has sequence"));
 }
+
+#[wasm_bindgen_test]
+fn read_batch() {
+    let data = b"a,b\n1,x\n2,y";
+    let mut reader = Reader::new(
+        data.to_vec().into_boxed_slice(),
+        Some("csv".to_string()),
+        None,
+    )
+    .expect("Error creating the reader");
+    let batch = reader
+        .next_batch(10)
+        .expect("Error reading batch")
+        .dyn_into::<Object>()
+        .expect("next_batch() returns an object");
+    let a = Reflect::get(&batch, &JsValue::from_str("a")).expect("batch has a");
+    assert!(a.is_instance_of::<BigInt64Array>());
+    let b = Reflect::get(&batch, &JsValue::from_str("b")).expect("batch has b");
+    assert_eq!(Array::from(&b).length(), 2);
+    assert!(reader
+        .next_batch(10)
+        .expect("Error reading batch")
+        .is_null());
+}
+
+#[wasm_bindgen_test]
+fn list_parsers() {
+    let parsers = Array::from(&parsers().expect("Error listing parsers"));
+    assert!(parsers.iter().any(|parser| {
+        Reflect::get(&parser, &JsValue::from_str("name")).expect("parser has a name")
+            == JsValue::from_str("fasta")
+    }));
+}
+
+#[wasm_bindgen_test]
+fn reader_params() {
+    let params = Object::new();
+    let _ = Reflect::set(
+        &params,
+        &JsValue::from_str("delimiter"),
+        &JsValue::from_str("|"),
+    )
+    .expect("Error setting param");
+    let reader = Reader::new(
+        b"a|b\n1|2".to_vec().into_boxed_slice(),
+        Some("csv".to_string()),
+        Some(params),
+    )
+    .expect("Error creating the reader");
+    assert_eq!(Array::from(&reader.headers()).length(), 2);
+
+    let params = Object::new();
+    let _ = Reflect::set(
+        &params,
+        &JsValue::from_str("delimeter"),
+        &JsValue::from_str("|"),
+    )
+    .expect("Error setting param");
+    assert!(Reader::new(
+        b"a|b\n1|2".to_vec().into_boxed_slice(),
+        Some("csv".to_string()),
+        Some(params),
+    )
+    .is_err());
+}
+
+#[wasm_bindgen_test]
+fn reader_size_limits() {
+    let params = Object::new();
+    let _ = Reflect::set(
+        &params,
+        &JsValue::from_str("max_record_size"),
+        &JsValue::from_f64(8.),
+    )
+    .expect("Error setting param");
+    let mut reader = Reader::new(
+        b">a\nACGTACGT\n".to_vec().into_boxed_slice(),
+        None,
+        Some(params),
+    )
+    .expect("Error creating the reader");
+    let err = reader.next().expect_err("the record is too long");
+    assert_eq!(
+        Reflect::get(&err, &JsValue::from_str("kind")).expect("errors have a kind"),
+        JsValue::from_str("LimitExceeded")
+    );
+}
+
+#[wasm_bindgen_test]
+fn reader_lenient() {
+    let params = Object::new();
+    let _ = Reflect::set(
+        &params,
+        &JsValue::from_str("lenient"),
+        &JsValue::from_bool(true),
+    )
+    .expect("Error setting param");
+    let mut reader = Reader::new(
+        b">a\nAC>GT\n>b\nTT\n".to_vec().into_boxed_slice(),
+        None,
+        Some(params),
+    )
+    .expect("Error creating the reader");
+    let rec = reader.next().expect("Error reading the record");
+    assert_eq!(
+        Reflect::get(&rec, &JsValue::from_str("done")).expect("record has done"),
+        JsValue::FALSE
+    );
+    let warnings = Array::from(&reader.warnings());
+    assert_eq!(warnings.length(), 1);
+    assert_eq!(
+        Reflect::get(&warnings.get(0), &JsValue::from_str("kind")).expect("warnings have a kind"),
+        JsValue::from_str("InvalidValue")
+    );
+}
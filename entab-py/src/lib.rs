@@ -4,22 +4,49 @@ mod raw_io_wrapper;
 use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Cursor, Read};
+use std::num::NonZeroU64;
+use std::path::Path;
 
-use entab_base::error::EtError;
+use entab_base::batch::{Column, ColumnBatch, ColumnData};
+use entab_base::error::{ErrorKind, EtError};
+use entab_base::parsers::agilent::directory::AgilentDirectoryReader;
 use entab_base::readers::{get_reader, RecordReader};
 use entab_base::record::Value;
+use entab_base::registry;
 use pyo3::class::{PyIterProtocol, PyObjectProtocol};
 use pyo3::prelude::*;
-use pyo3::types::{PyDict, PyList, PyTuple};
+use pyo3::types::{PyBool, PyBytes, PyDict, PyList, PyString, PyTuple};
 use pyo3::{create_exception, exceptions};
 
 use crate::raw_io_wrapper::RawIoWrapper;
 
 create_exception!(entab, EntabError, exceptions::PyException);
+create_exception!(entab, IoError, EntabError);
+create_exception!(entab, UnsupportedFormatError, EntabError);
+create_exception!(entab, InvalidMagicError, EntabError);
+create_exception!(entab, TruncatedError, EntabError);
+create_exception!(entab, InvalidValueError, EntabError);
+create_exception!(entab, DecompressionError, EntabError);
+create_exception!(entab, UnusedParamsError, EntabError);
+create_exception!(entab, InvalidParamError, EntabError);
+create_exception!(entab, LimitExceededError, EntabError);
 
 fn to_py(err: EtError) -> PyErr {
     // TODO: somehow bind err.byte and err.record in here too?
-    let res = EntabError::new_err(err.to_string());
+    let msg = err.to_string();
+    let res = match err.kind {
+        ErrorKind::Io => IoError::new_err(msg),
+        ErrorKind::UnsupportedFormat => UnsupportedFormatError::new_err(msg),
+        ErrorKind::InvalidMagic => InvalidMagicError::new_err(msg),
+        ErrorKind::Truncated => TruncatedError::new_err(msg),
+        ErrorKind::InvalidValue => InvalidValueError::new_err(msg),
+        ErrorKind::Decompression => DecompressionError::new_err(msg),
+        ErrorKind::UnusedParams => UnusedParamsError::new_err(msg),
+        ErrorKind::InvalidParam => InvalidParamError::new_err(msg),
+        ErrorKind::LimitExceeded => LimitExceededError::new_err(msg),
+        // `Other` and any kinds added later
+        _ => EntabError::new_err(msg),
+    };
     // we could technically just take an `&EtError` here, but the function signature is nicer with
     // a `EtError` so we have to drop it here to make clippy happy
     drop(err);
@@ -55,14 +82,75 @@ fn py_from_value(value: Value, py: Python) -> PyResult<PyObject> {
     })
 }
 
+/// Map a Python object into a Value (for passing params through to the parsers)
+fn value_from_py(obj: &PyAny) -> PyResult<Value<'static>> {
+    Ok(if obj.is_none() {
+        Value::Null
+    } else if let Ok(b) = obj.downcast::<PyBool>() {
+        // bools have to be checked first because they're also ints
+        Value::Boolean(b.is_true())
+    } else if let Ok(i) = obj.extract::<i64>() {
+        Value::Integer(i)
+    } else if let Ok(f) = obj.extract::<f64>() {
+        Value::Float(f)
+    } else if let Ok(s) = obj.extract::<String>() {
+        Value::String(s.into())
+    } else if let Ok(l) = obj.downcast::<PyList>() {
+        Value::List(l.iter().map(value_from_py).collect::<PyResult<_>>()?)
+    } else {
+        return Err(EntabError::new_err(format!(
+            "Param value {} must be a bool, int, float, str or list",
+            obj
+        )));
+    })
+}
+
+/// Map a column of a `ColumnBatch` into a `PyObject`
+///
+/// Numeric columns without nulls are copied wholesale into an `array.array`; everything else
+/// becomes a list.
+fn py_from_column(column: &Column, py: Python) -> PyResult<PyObject> {
+    let array = |typecode: &str, bytes: Vec<u8>| -> PyResult<PyObject> {
+        let array_class = PyModule::import(py, "array")?.getattr("array")?;
+        Ok(array_class
+            .call1((typecode, PyBytes::new(py, &bytes)))?
+            .into())
+    };
+    Ok(match &column.data {
+        ColumnData::Float(v) if column.validity.is_none() => {
+            array("d", v.iter().flat_map(|x| x.to_ne_bytes()).collect())?
+        }
+        ColumnData::Integer(v) if column.validity.is_none() => {
+            array("q", v.iter().flat_map(|x| x.to_ne_bytes()).collect())?
+        }
+        ColumnData::String { .. } if column.validity.is_none() => {
+            let list = PyList::empty(py);
+            for i in 0..column.len() {
+                if let Some(bytes) = column.bytes(i) {
+                    list.append(PyString::new(py, &String::from_utf8_lossy(bytes)))?;
+                }
+            }
+            list.into()
+        }
+        _ => {
+            let list = PyList::empty(py);
+            for i in 0..column.len() {
+                list.append(py_from_value(column.get(i), py)?)?;
+            }
+            list.into()
+        }
+    })
+}
+
 // TODO: remove the unsendable; by wrapping reader in an Arc?
 #[pyclass(unsendable)]
-#[pyo3(text_signature = "(/, data=None, filename=None, parser=None)")]
+#[pyo3(text_signature = "(/, data=None, filename=None, parser=None, params=None)")]
 pub struct Reader {
     #[pyo3(get)]
     parser: String,
     record_class: Py<PyAny>,
     reader: Box<dyn RecordReader>,
+    batch: ColumnBatch,
 }
 
 #[pyproto]
@@ -91,12 +179,55 @@ impl PyIterProtocol for Reader {
     }
 }
 
+impl Reader {
+    /// Wrap a `RecordReader` for use from Python
+    fn from_reader(reader: Box<dyn RecordReader>, parser_used: &str) -> PyResult<Self> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let headers: Vec<String> = reader
+            .headers()
+            .iter()
+            .map(|h| h.replace(" ", "_").replace("-", "_"))
+            .collect();
+        let collections = PyModule::import(py, "collections")?;
+        let record_class = collections
+            .getattr("namedtuple")?
+            .call1(("Record", headers))?
+            .into();
+
+        Ok(Reader {
+            parser: parser_used.to_string(),
+            record_class,
+            reader,
+            batch: ColumnBatch::default(),
+        })
+    }
+}
+
 #[pymethods]
 impl Reader {
     #[new]
-    #[args(data = "None", filename = "None", parser = "None")]
-    fn new(data: Option<&PyAny>, filename: Option<&str>, parser: Option<&str>) -> PyResult<Self> {
-        let mut params = BTreeMap::new();
+    #[args(data = "None", filename = "None", parser = "None", params = "None")]
+    fn new(
+        data: Option<&PyAny>,
+        filename: Option<&str>,
+        parser: Option<&str>,
+        params: Option<&PyDict>,
+    ) -> PyResult<Self> {
+        let mut params = match params {
+            Some(dict) => dict
+                .iter()
+                .map(|(key, value)| Ok((key.extract::<String>()?, value_from_py(value)?)))
+                .collect::<PyResult<BTreeMap<_, _>>>()?,
+            None => BTreeMap::new(),
+        };
+        if let (None, Some(f)) = (data, filename) {
+            if Path::new(f).is_dir() {
+                let reader = AgilentDirectoryReader::new(f).map_err(to_py)?;
+                return Self::from_reader(Box::new(reader), "agilent_directory");
+            }
+        }
         let stream: Box<dyn Read> = match (data, filename) {
             (Some(d), None) => {
                 if let Ok(bytes) = d.extract::<Vec<u8>>() {
@@ -122,25 +253,25 @@ impl Reader {
             }
         };
         let (reader, parser_used) = get_reader(stream, parser, Some(params)).map_err(to_py)?;
+        Self::from_reader(reader, parser_used)
+    }
+
+    /// Read up to `n` records into a dict mapping each header to a column of values.
+    ///
+    /// Returns `None` once there are no more records.
+    #[pyo3(text_signature = "($self, n)")]
+    pub fn next_batch(&mut self, n: usize) -> PyResult<Option<PyObject>> {
         let gil = Python::acquire_gil();
         let py = gil.python();
 
-        let headers: Vec<String> = reader
-            .headers()
-            .iter()
-            .map(|h| h.replace(" ", "_").replace("-", "_"))
-            .collect();
-        let collections = PyModule::import(py, "collections")?;
-        let record_class = collections
-            .getattr("namedtuple")?
-            .call1(("Record", headers))?
-            .into();
-
-        Ok(Reader {
-            parser: parser_used.to_string(),
-            record_class,
-            reader,
-        })
+        if self.reader.next_batch(&mut self.batch, n).map_err(to_py)? == 0 {
+            return Ok(None);
+        }
+        let dict = PyDict::new(py);
+        for column in self.batch.columns() {
+            dict.set_item(&column.name, py_from_column(column, py)?)?;
+        }
+        Ok(Some(dict.into()))
     }
 
     #[getter]
@@ -160,6 +291,56 @@ impl Reader {
         Ok(dict.into())
     }
 
+    #[getter]
+    pub fn get_schema(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let list = PyList::empty(py);
+        for column in self.reader.schema() {
+            let dict = PyDict::new(py);
+            dict.set_item("name", column.name)?;
+            dict.set_item("type", column.value_type.as_str())?;
+            dict.set_item("nullable", column.nullable)?;
+            dict.set_item("unit", column.unit)?;
+            list.append(dict)?;
+        }
+        Ok(list.into())
+    }
+
+    #[getter]
+    pub fn get_warnings(&self) -> PyResult<PyObject> {
+        let gil = Python::acquire_gil();
+        let py = gil.python();
+
+        let list = PyList::empty(py);
+        for warning in self.reader.warnings() {
+            let dict = PyDict::new(py);
+            dict.set_item("message", warning.msg.as_ref())?;
+            dict.set_item("kind", warning.kind.name())?;
+            dict.set_item("byte", warning.context.as_ref().map(|c| c.byte))?;
+            dict.set_item("record", warning.context.as_ref().map(|c| c.record))?;
+            dict.set_item(
+                "line",
+                warning
+                    .context
+                    .as_ref()
+                    .and_then(|c| c.line)
+                    .map(NonZeroU64::get),
+            )?;
+            dict.set_item(
+                "column",
+                warning
+                    .context
+                    .as_ref()
+                    .and_then(|c| c.column)
+                    .map(NonZeroU64::get),
+            )?;
+            list.append(dict)?;
+        }
+        Ok(list.into())
+    }
+
     #[getter]
     pub fn get_parser(&self) -> PyResult<String> {
         Ok(self.parser.clone())
@@ -173,10 +354,47 @@ impl PyObjectProtocol for Reader {
     }
 }
 
+/// List the parsers that are available for reading files.
+#[pyfunction]
+fn parsers(py: Python) -> PyResult<PyObject> {
+    let list = PyList::empty(py);
+    for parser in registry::parsers() {
+        let dict = PyDict::new(py);
+        dict.set_item("name", parser.name)?;
+        dict.set_item("description", parser.description)?;
+        dict.set_item("extensions", parser.extensions.to_vec())?;
+        let params = PyList::empty(py);
+        for param in parser.params {
+            let param_dict = PyDict::new(py);
+            param_dict.set_item("name", param.name)?;
+            param_dict.set_item("type", param.value_type.as_str())?;
+            param_dict.set_item("description", param.description)?;
+            params.append(param_dict)?;
+        }
+        dict.set_item("params", params)?;
+        list.append(dict)?;
+    }
+    Ok(list.to_object(py))
+}
+
 /// entab provides interconversion from streaming record formats.
 #[pymodule]
-fn entab(_py: Python, m: &PyModule) -> PyResult<()> {
+fn entab(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Reader>()?;
+    m.add_function(wrap_pyfunction!(parsers, m)?)?;
+    m.add("EntabError", py.get_type::<EntabError>())?;
+    m.add("IoError", py.get_type::<IoError>())?;
+    m.add(
+        "UnsupportedFormatError",
+        py.get_type::<UnsupportedFormatError>(),
+    )?;
+    m.add("InvalidMagicError", py.get_type::<InvalidMagicError>())?;
+    m.add("TruncatedError", py.get_type::<TruncatedError>())?;
+    m.add("InvalidValueError", py.get_type::<InvalidValueError>())?;
+    m.add("DecompressionError", py.get_type::<DecompressionError>())?;
+    m.add("UnusedParamsError", py.get_type::<UnusedParamsError>())?;
+    m.add("InvalidParamError", py.get_type::<InvalidParamError>())?;
+    m.add("LimitExceededError", py.get_type::<LimitExceededError>())?;
     Ok(())
 }
 
@@ -193,11 +411,11 @@ mod tests {
         let py = gil.python();
 
         // a filename or data has to be passed in
-        assert!(Reader::new(None, None, None).is_err());
+        assert!(Reader::new(None, None, None, None).is_err());
 
         // if data's passed in, it works
         let test_data = b">test\nACGT".to_object(py);
-        let reader = Reader::new(Some(test_data.as_ref(py)), None, None)?;
+        let reader = Reader::new(Some(test_data.as_ref(py)), None, None, None)?;
         assert_eq!(&reader.parser, "fasta");
 
         // metadata are available
@@ -208,6 +426,10 @@ mod tests {
         let headers = reader.get_headers()?;
         assert_eq!(headers.len(), 2);
 
+        // as is the schema
+        let schema = reader.get_schema()?;
+        assert_eq!(schema.as_ref(py).downcast::<PyList>()?.len(), 2);
+
         Ok(())
     }
 
@@ -227,6 +449,51 @@ reader = entab.Reader(data=">test\nACGT")
 assert reader.metadata == {}
 for record in reader:
     pass
+
+assert any(p["name"] == "fasta" and "fa" in p["extensions"] for p in entab.parsers())
+
+reader = entab.Reader(data="a,b\n1,x\n2,\n", parser="csv")
+batch = reader.next_batch(10)
+assert list(batch["a"]) == [1, 2]
+assert batch["a"].typecode == "q"
+assert batch["b"] == ["x", ""]
+assert reader.next_batch(10) is None
+
+reader = entab.Reader(data="a|b\n1|x\n", parser="csv", params={"delimiter": "|"})
+assert reader.headers == ["a", "b"]
+try:
+    entab.Reader(data="a,b\n", parser="csv", params={"delimeter": "|"})
+    failed = False
+except Exception as e:
+    failed = "delimiter" in str(e)
+assert failed
+csv = next(p for p in entab.parsers() if p["name"] == "csv")
+assert any(p["name"] == "delimiter" and p["type"] == "string" for p in csv["params"])
+
+try:
+    list(entab.Reader(data=">a\nACGTACGT\n", params={"max_record_size": 8}))
+    failed = False
+except entab.LimitExceededError as e:
+    failed = "max_record_size" in str(e)
+assert failed
+
+reader = entab.Reader(data=">a\nAC>GT\n>b\nTT\n", params={"lenient": True})
+assert [r.id for r in reader] == ["b"]
+assert reader.warnings == [{
+    "message": "Unexpected '>' found",
+    "kind": "InvalidValue",
+    "byte": 0,
+    "record": 1,
+    "line": 1,
+    "column": 1,
+}]
+
+try:
+    entab.Reader(data="a,b\n", parser="csv", params={"delimeter": "|"})
+    failed = False
+except entab.UnusedParamsError as e:
+    failed = isinstance(e, entab.EntabError)
+assert failed
         "#,
             None,
             Some(locals),
@@ -1,9 +1,13 @@
 use std::collections::BTreeMap;
 use std::fs::File;
+use std::path::Path;
 
+use entab_base::batch::{Column, ColumnBatch, ColumnData};
 use entab_base::error::EtError;
+use entab_base::parsers::agilent::directory::AgilentDirectoryReader;
 use entab_base::readers::{get_reader, RecordReader};
 use entab_base::record::Value;
+use entab_base::registry;
 use extendr_api::prelude::*;
 
 #[allow(clippy::needless_pass_by_value)]
@@ -38,6 +42,48 @@ fn value_to_robj(value: Value) -> Robj {
     }
 }
 
+/// Convert an R value into a `Value` (for passing params through to the parsers)
+fn robj_to_value(robj: &Robj) -> Result<Value<'static>> {
+    if robj.is_null() {
+        return Ok(Value::Null);
+    }
+    if robj.len() > 1 || robj.rtype() == RType::List {
+        // vectors and lists are passed through as lists
+        if let Some(strings) = robj.as_string_vector() {
+            return Ok(Value::List(
+                strings
+                    .into_iter()
+                    .map(|s| Value::String(s.into()))
+                    .collect(),
+            ));
+        }
+        if let Some(list) = robj.as_list() {
+            return Ok(Value::List(
+                list.values()
+                    .map(|v| robj_to_value(&v))
+                    .collect::<Result<_>>()?,
+            ));
+        }
+    }
+    if let Some(b) = robj.as_bool() {
+        Ok(Value::Boolean(b))
+    } else if let Some(i) = robj.as_integer() {
+        Ok(Value::Integer(i.into()))
+    } else if let Some(f) = robj.as_real() {
+        // R numbers are doubles by default so treat whole ones as integers
+        #[allow(clippy::cast_possible_truncation)]
+        if f.fract() == 0. {
+            Ok(Value::Integer(f as i64))
+        } else {
+            Ok(Value::Float(f))
+        }
+    } else if let Some(s) = robj.as_str() {
+        Ok(Value::String(s.to_string().into()))
+    } else {
+        Err("Param values must be logical, numeric, character or lists".into())
+    }
+}
+
 struct Reader {
     parser: String,
     header_names: Vec<String>,
@@ -47,16 +93,31 @@ struct Reader {
 #[extendr]
 impl Reader {
     #[allow(clippy::new_ret_no_self)]
-    fn new(filename: &str, parser: &str) -> Result<Robj> {
+    fn new(filename: &str, parser: &str, params: Robj) -> Result<Robj> {
+        if Path::new(filename).is_dir() {
+            let reader = AgilentDirectoryReader::new(filename).map_err(to_r)?;
+            let header_names = reader.headers();
+            return Ok(Reader {
+                parser: "agilent_directory".to_string(),
+                header_names,
+                reader: Box::new(reader),
+            }
+            .into());
+        }
         let file = File::open(filename).map_err(|e| Error::from(e.to_string()))?;
         let parser = if parser.is_empty() {
             None
         } else {
             Some(parser)
         };
-        let mut params = BTreeMap::new();
-        params.insert("filename".to_string(), Value::String(filename.into()));
-        let (reader, parser_used) = get_reader(file, parser, Some(params)).map_err(to_r)?;
+        let mut parse_params = BTreeMap::new();
+        if let Some(list) = params.as_list() {
+            for (key, value) in list.iter() {
+                parse_params.insert(key.to_string(), robj_to_value(&value)?);
+            }
+        }
+        parse_params.insert("filename".to_string(), Value::String(filename.into()));
+        let (reader, parser_used) = get_reader(file, parser, Some(parse_params)).map_err(to_r)?;
         let header_names = reader.headers();
         Ok(Reader {
             parser: parser_used.to_string(),
@@ -85,6 +146,27 @@ impl Reader {
         List::from_names_and_values(names, values).into()
     }
 
+    fn schema(&self) -> Robj {
+        let schema = self.reader.schema();
+        let names: Vec<&str> = schema.iter().map(|c| c.name.as_ref()).collect();
+        let types: Vec<&str> = schema.iter().map(|c| c.value_type.as_str()).collect();
+        let nullable: Vec<bool> = schema.iter().map(|c| c.nullable).collect();
+        let units: Vec<&str> = schema
+            .iter()
+            .map(|c| c.unit.as_deref().unwrap_or(""))
+            .collect();
+        List::from_names_and_values(
+            &["name", "type", "nullable", "unit"],
+            &[
+                names.iter().collect_robj(),
+                types.iter().collect_robj(),
+                nullable.iter().collect_robj(),
+                units.iter().collect_robj(),
+            ],
+        )
+        .into()
+    }
+
     fn next(&mut self) -> Result<Robj> {
         if let Some(record) = self.reader.next_record().map_err(to_r)? {
             let mut values = Vec::new();
@@ -98,63 +180,80 @@ impl Reader {
     }
 }
 
-pub enum ValueList {
-    Null(usize),
-    Boolean(Vec<bool>),
-    Float(Vec<f64>),
-    Integer(Vec<i64>),
-    String(Vec<String>),
-    Misc(Vec<Robj>),
+/// Convert a column from a `ColumnBatch` into an R vector (with `NA`s for any nulls) or into a
+/// list if it has mixed types
+fn column_to_robj(column: &Column) -> Robj {
+    let valid = |i: usize| !column.is_null(i);
+    match &column.data {
+        ColumnData::Boolean(v) => (0..v.len())
+            .map(|i| if valid(i) { Some(v[i]) } else { None })
+            .collect_robj(),
+        ColumnData::Float(v) => (0..v.len())
+            .map(|i| if valid(i) { Some(v[i]) } else { None })
+            .collect_robj(),
+        ColumnData::Integer(v) => (0..v.len())
+            .map(|i| if valid(i) { Some(v[i]) } else { None })
+            .collect_robj(),
+        ColumnData::String { .. } => (0..column.len())
+            .map(|i| {
+                column
+                    .bytes(i)
+                    .filter(|_| valid(i))
+                    .map(|b| String::from_utf8_lossy(b).into_owned())
+            })
+            .collect::<Vec<Option<String>>>()
+            .into_iter()
+            .collect_robj(),
+        _ => {
+            let values: Vec<Robj> = (0..column.len())
+                .map(|i| value_to_robj(column.get(i)))
+                .collect();
+            values.into()
+        }
+    }
 }
 
 #[extendr]
 fn as_data_frame(reader: &mut Reader) -> Result<Robj> {
-    let mut data: Vec<ValueList> = Vec::new();
-    if let Some(first) = reader.reader.next_record().map_err(to_r)? {
-        for v in first {
-            data.push(match v {
-                Value::Null => ValueList::Null(1),
-                Value::Boolean(b) => ValueList::Boolean(vec![b]),
-                Value::Float(f) => ValueList::Float(vec![f]),
-                Value::Integer(i) => ValueList::Integer(vec![i]),
-                Value::String(s) => ValueList::String(vec![s.to_string()]),
-                x => ValueList::Misc(vec![value_to_robj(x)]),
-            });
-        }
-        while let Some(record) = reader.reader.next_record().map_err(to_r)? {
-            for (ix, v) in record.into_iter().enumerate() {
-                match (&mut data[ix], v) {
-                    (ValueList::Null(x), Value::Null) => *x += 1,
-                    (ValueList::Boolean(v), Value::Boolean(b)) => v.push(b),
-                    (ValueList::Float(v), Value::Float(f)) => v.push(f),
-                    (ValueList::Integer(v), Value::Integer(i)) => v.push(i),
-                    (ValueList::String(v), Value::String(s)) => v.push(s.to_string()),
-                    (ValueList::Misc(v), x) => v.push(value_to_robj(x)),
-                    _ => panic!("Tried to append wrong data type"),
-                }
-            }
-        }
-    } else {
-        for _ in &reader.header_names {
-            data.push(ValueList::Null(0));
-        }
-    }
+    // read everything into one batch so each column can be converted in one go
+    let mut batch = ColumnBatch::default();
+    let n_rows = reader
+        .reader
+        .next_batch(&mut batch, usize::MAX)
+        .map_err(to_r)?;
 
-    let mut vectors: Vec<Robj> = vec![];
-    for v in data {
-        vectors.push(match v {
-            ValueList::Null(x) => vec![r!(NULL); x].into(),
-            ValueList::Boolean(v) => v.iter().collect_robj(),
-            ValueList::Float(v) => v.iter().collect_robj(),
-            ValueList::Integer(v) => v.iter().collect_robj(),
-            ValueList::String(v) => v.iter().collect_robj(),
-            ValueList::Misc(v) => v.into(),
-        });
-    }
+    let vectors: Vec<Robj> = batch.columns().iter().map(column_to_robj).collect();
     let obj: Robj = List::from_names_and_values(&reader.header_names, &vectors).into();
+    obj.set_attrib(row_names_symbol(), (1i32..=n_rows as i32).collect_robj())?;
+    obj.set_class(&["data.frame"])?;
+    Ok(obj)
+}
+
+#[extendr]
+fn parsers() -> Result<Robj> {
+    let parsers = registry::parsers();
+    let names: Vec<&str> = parsers.iter().map(|p| p.name).collect();
+    let descriptions: Vec<&str> = parsers.iter().map(|p| p.description).collect();
+    let extensions: Vec<String> = parsers.iter().map(|p| p.extensions.join(",")).collect();
+    let params: Vec<String> = parsers
+        .iter()
+        .map(|p| {
+            let names: Vec<&str> = p.params.iter().map(|param| param.name).collect();
+            names.join(",")
+        })
+        .collect();
+    let vectors: Vec<Robj> = vec![
+        names.iter().collect_robj(),
+        descriptions.iter().collect_robj(),
+        extensions.iter().collect_robj(),
+        params.iter().collect_robj(),
+    ];
+    let obj: Robj =
+        List::from_names_and_values(&["name", "description", "extensions", "params"], &vectors)
+            .into();
     obj.set_attrib(
         row_names_symbol(),
-        (1i32..=vectors[0].len() as i32).collect_robj(),
+        (1i32..=parsers.len() as i32).collect_robj(),
     )?;
     obj.set_class(&["data.frame"])?;
     Ok(obj)
@@ -164,4 +263,5 @@ extendr_module! {
     mod entab;
     impl Reader;
     fn as_data_frame;
+    fn parsers;
 }
@@ -0,0 +1,605 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::str::from_utf8;
+use std::io::{Cursor, Read};
+
+use flate2::read::DeflateDecoder;
+
+use crate::buffer::ReadBuffer;
+use crate::compression::decompress_all;
+use crate::filetype::FileType;
+use crate::readers::{get_reader, RecordReader};
+use crate::record::Value;
+use crate::select::{ResolvedSelection, Selection};
+use crate::{ErrorKind, EtError};
+
+/// A single file inside an archive
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveMember {
+    /// The path of the file inside the archive
+    pub name: String,
+    /// The (uncompressed) size of the file
+    pub size: u64,
+    /// Where the member's data starts in the archive
+    start: usize,
+    /// How many bytes of the archive the member's data takes up
+    len: usize,
+    /// The ZIP compression method (always 0, i.e. stored, for TAR members)
+    method: u16,
+}
+
+/// Read a little-endian `u16` out of `buf` at `pos`.
+fn le_u16(buf: &[u8], pos: usize) -> Result<u16, EtError> {
+    match buf.get(pos..pos + 2) {
+        Some(b) => Ok(u16::from_le_bytes([b[0], b[1]])),
+        None => {
+            Err(EtError::from("ZIP archive ended unexpectedly").with_kind(ErrorKind::Truncated))
+        }
+    }
+}
+
+/// Read a little-endian `u32` out of `buf` at `pos`.
+fn le_u32(buf: &[u8], pos: usize) -> Result<u32, EtError> {
+    match buf.get(pos..pos + 4) {
+        Some(b) => Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        None => {
+            Err(EtError::from("ZIP archive ended unexpectedly").with_kind(ErrorKind::Truncated))
+        }
+    }
+}
+
+/// List the files in a ZIP archive from its central directory.
+fn zip_members(data: &[u8]) -> Result<Vec<ArchiveMember>, EtError> {
+    // the end of central directory record is at least 22 bytes and followed by a comment of
+    // up to 64 KiB
+    let search_start = data.len().saturating_sub(22 + 0xFFFF);
+    let eocd = data[search_start..]
+        .windows(4)
+        .rposition(|w| w == b"PK\x05\x06")
+        .map(|p| search_start + p)
+        .ok_or_else(|| {
+            EtError::from("Could not find the end of the ZIP central directory")
+                .with_kind(ErrorKind::InvalidValue)
+        })?;
+    let n_entries = le_u16(data, eocd + 10)?;
+    let mut pos = le_u32(data, eocd + 16)? as usize;
+    if n_entries == 0xFFFF || pos == 0xFFFF_FFFF {
+        return Err(EtError::from("ZIP64 archives aren't supported")
+            .with_kind(ErrorKind::UnsupportedFormat));
+    }
+
+    let mut members = Vec::new();
+    for _ in 0..n_entries {
+        if data.get(pos..pos + 4) != Some(b"PK\x01\x02") {
+            return Err(EtError::from("Invalid ZIP central directory entry")
+                .with_kind(ErrorKind::InvalidValue));
+        }
+        let flags = le_u16(data, pos + 8)?;
+        let method = le_u16(data, pos + 10)?;
+        let len = le_u32(data, pos + 20)?;
+        let size = le_u32(data, pos + 24)?;
+        let name_len = usize::from(le_u16(data, pos + 28)?);
+        let extra_len = usize::from(le_u16(data, pos + 30)?);
+        let comment_len = usize::from(le_u16(data, pos + 32)?);
+        let header_pos = le_u32(data, pos + 42)? as usize;
+        let name = data.get(pos + 46..pos + 46 + name_len).ok_or_else(|| {
+            EtError::from("ZIP archive ended unexpectedly").with_kind(ErrorKind::Truncated)
+        })?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        pos += 46 + name_len + extra_len + comment_len;
+
+        if name.ends_with('/') {
+            // directories don't have any data
+            continue;
+        }
+        if len == 0xFFFF_FFFF || size == 0xFFFF_FFFF || header_pos == 0xFFFF_FFFF {
+            return Err(EtError::from("ZIP64 archives aren't supported")
+                .with_kind(ErrorKind::UnsupportedFormat));
+        }
+        if flags & 1 != 0 {
+            return Err(EtError::from(format!("ZIP member {} is encrypted", name))
+                .with_kind(ErrorKind::UnsupportedFormat));
+        }
+        if data.get(header_pos..header_pos + 4) != Some(b"PK\x03\x04") {
+            return Err(EtError::from(format!("Invalid ZIP header for {}", name))
+                .with_kind(ErrorKind::InvalidValue));
+        }
+        let start = header_pos
+            + 30
+            + usize::from(le_u16(data, header_pos + 26)?)
+            + usize::from(le_u16(data, header_pos + 28)?);
+        if start + len as usize > data.len() {
+            return Err(EtError::from(format!("ZIP member {} is truncated", name))
+                .with_kind(ErrorKind::Truncated));
+        }
+        members.push(ArchiveMember {
+            name,
+            size: u64::from(size),
+            start,
+            len: len as usize,
+            method,
+        });
+    }
+    Ok(members)
+}
+
+/// Read a NUL-terminated string out of a TAR header field.
+fn tar_str(field: &[u8]) -> String {
+    let end = field.iter().position(|c| *c == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Read an octal number out of a TAR header field.
+fn tar_octal(field: &[u8]) -> Result<u64, EtError> {
+    let end = field.iter().position(|c| *c == 0).unwrap_or(field.len());
+    let raw = from_utf8(&field[..end])?.trim();
+    if raw.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(raw, 8).map_err(|_| "Invalid number in TAR header".into())
+}
+
+/// List the regular files in a TAR archive.
+fn tar_members(data: &[u8]) -> Result<Vec<ArchiveMember>, EtError> {
+    let mut members = Vec::new();
+    let mut pos = 0;
+    // GNU and pax archives can put a long name in the entry before the file
+    let mut long_name: Option<String> = None;
+    while let Some(header) = data.get(pos..pos + 512) {
+        if header.iter().all(|c| *c == 0) {
+            break;
+        }
+        let size = tar_octal(&header[124..136])? as usize;
+        let start = pos + 512;
+        let contents = data.get(start..start + size).ok_or_else(|| {
+            EtError::from("TAR archive ended unexpectedly").with_kind(ErrorKind::Truncated)
+        })?;
+        pos = start + size.next_multiple_of(512);
+
+        match header[156] {
+            b'0' | b'\0' | b'7' => {
+                let name = match long_name.take() {
+                    Some(name) => name,
+                    None => {
+                        let name = tar_str(&header[..100]);
+                        let prefix = tar_str(&header[345..500]);
+                        if &header[257..262] == b"ustar" && !prefix.is_empty() {
+                            format!("{}/{}", prefix, name)
+                        } else {
+                            name
+                        }
+                    }
+                };
+                members.push(ArchiveMember {
+                    name,
+                    size: size as u64,
+                    start,
+                    len: size,
+                    method: 0,
+                });
+            }
+            b'L' => long_name = Some(tar_str(contents)),
+            b'x' => {
+                // pax records are "<length> <key>=<value>\n"
+                for record in contents.split(|c| *c == b'\n') {
+                    let record = String::from_utf8_lossy(record);
+                    let path = record
+                        .split_once(' ')
+                        .and_then(|(_, kv)| kv.strip_prefix("path="));
+                    if let Some(path) = path {
+                        long_name = Some(path.to_string());
+                    }
+                }
+            }
+            // directories, links, global pax headers, etc. aren't files we can read
+            _ => long_name = None,
+        }
+    }
+    Ok(members)
+}
+
+/// A ZIP or TAR archive that records can be read out of
+///
+/// The whole (decompressed) archive is kept in memory so members can be read in any order;
+/// if the archive is already in memory (e.g. a slice or mmap) TAR members and uncompressed ZIP
+/// members are read from it without copying.
+#[derive(Debug)]
+pub struct Archive<'r> {
+    file_type: FileType,
+    data: Cow<'r, [u8]>,
+    members: Vec<ArchiveMember>,
+    max_buffer_size: Option<usize>,
+}
+
+impl<'r> Archive<'r> {
+    /// Read an archive, decompressing it first if needed (e.g. for a `.tar.gz`).
+    ///
+    /// If `max_buffer_size` is set, neither the (decompressed) archive nor any member read out of
+    /// it can be bigger than that.
+    ///
+    /// # Errors
+    /// If the data can't be read, is bigger than `max_buffer_size` or isn't a ZIP or TAR archive,
+    /// an error is returned.
+    pub fn new<B>(data: B, max_buffer_size: Option<usize>) -> Result<Self, EtError>
+    where
+        B: TryInto<ReadBuffer<'r>>,
+        EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
+    {
+        let (mut rb, _) = decompress_all(data, None)?;
+        rb.max_buffer_size = max_buffer_size;
+        rb.read_to_end()?;
+        let file_type = rb.sniff_filetype()?;
+        let consumed = rb.consumed;
+        let data = match rb.buffer {
+            Cow::Borrowed(b) => Cow::Borrowed(&b[consumed..]),
+            Cow::Owned(mut b) => {
+                let _ = b.drain(..consumed);
+                Cow::Owned(b)
+            }
+        };
+        let members = match file_type {
+            FileType::Zip => zip_members(&data)?,
+            FileType::Tar => tar_members(&data)?,
+            _ => {
+                return Err(EtError::new("Data is not a ZIP or TAR archive")
+                    .with_kind(ErrorKind::UnsupportedFormat))
+            }
+        };
+        Ok(Archive {
+            file_type,
+            data,
+            members,
+            max_buffer_size,
+        })
+    }
+
+    /// The type of archive (`FileType::Zip` or `FileType::Tar`)
+    #[must_use]
+    pub fn file_type(&self) -> FileType {
+        self.file_type
+    }
+
+    /// The files in the archive, in the order they're stored
+    #[must_use]
+    pub fn members(&self) -> &[ArchiveMember] {
+        &self.members
+    }
+
+    /// The (uncompressed) contents of the member named `name`.
+    ///
+    /// # Errors
+    /// If there's no member named `name`, it's bigger than the archive's `max_buffer_size` or it
+    /// can't be decompressed, an error is returned.
+    pub fn member_data(&self, name: &str) -> Result<Cow<'_, [u8]>, EtError> {
+        let member = self
+            .members
+            .iter()
+            .find(|m| m.name == name)
+            .ok_or_else(|| {
+                EtError::from(format!("Archive has no member named {}", name))
+                    .with_kind(ErrorKind::InvalidParam)
+            })?;
+        if let Some(max) = self.max_buffer_size {
+            if member.size > max as u64 {
+                return Err(EtError::from(format!(
+                    "Archive member {} is bigger than the {} byte limit (max_buffer_size)",
+                    name, max
+                ))
+                .with_kind(ErrorKind::LimitExceeded));
+            }
+        }
+        let data = &self.data[member.start..member.start + member.len];
+        Ok(match member.method {
+            0 => Cow::Borrowed(data),
+            8 => {
+                // the size is from the archive's headers, so it's only trusted as far as not
+                // inflating anything past it
+                let mut out = Vec::new();
+                let _ = DeflateDecoder::new(data)
+                    .take(member.size)
+                    .read_to_end(&mut out)?;
+                Cow::Owned(out)
+            }
+            method => {
+                return Err(EtError::from(format!(
+                    "ZIP member {} uses an unsupported compression method ({})",
+                    name, method
+                ))
+                .with_kind(ErrorKind::UnsupportedFormat))
+            }
+        })
+    }
+
+    /// Open a reader on the member named `name`.
+    ///
+    /// This works like `readers::get_reader`; if `parser` is `None` it's detected from the
+    /// member's contents and name.
+    ///
+    /// # Errors
+    /// If the member can't be read or its parser can't be set up, an error is returned.
+    pub fn get_reader<'n, 'p>(
+        &self,
+        name: &str,
+        parser: Option<&'n str>,
+        params: Option<BTreeMap<String, Value<'p>>>,
+    ) -> Result<(Box<dyn RecordReader + '_>, &'n str), EtError> {
+        let rb = match self.member_data(name)? {
+            Cow::Borrowed(data) => ReadBuffer::from(data),
+            Cow::Owned(data) => ReadBuffer::from_reader(Box::new(Cursor::new(data)), None)?,
+        };
+        let mut params = params.unwrap_or_default();
+        let _ = params
+            .entry("filename".to_string())
+            .or_insert_with(|| Value::String(name.to_string().into()));
+        get_reader(rb, parser, Some(params))
+    }
+}
+
+/// Reads the records from every member of an archive in turn, adding a `member` column with
+/// the name of the member each record came from
+///
+/// All of the members have to have the same columns. When the parser is auto-detected, members
+/// that aren't in a format entab can detect (e.g. a README) are skipped.
+pub struct ArchiveReader<'a, 'p> {
+    archive: &'a Archive<'a>,
+    parser: Option<String>,
+    params: BTreeMap<String, Value<'p>>,
+    /// The index of the next member to open
+    next_member: usize,
+    current: Option<(Value<'static>, Box<dyn RecordReader + 'a>)>,
+    headers: Vec<String>,
+    selection: Option<ResolvedSelection>,
+    /// The warnings from the members that have been read through
+    warnings: Vec<EtError>,
+}
+
+impl<'a, 'p> ArchiveReader<'a, 'p> {
+    /// Set up a reader over all the members of `archive`.
+    ///
+    /// # Errors
+    /// If none of the members can be read or the first member's parser can't be set up, an
+    /// error is returned.
+    pub fn new(
+        archive: &'a Archive<'a>,
+        parser: Option<&str>,
+        params: Option<BTreeMap<String, Value<'p>>>,
+    ) -> Result<Self, EtError> {
+        let mut reader = ArchiveReader {
+            archive,
+            parser: parser.map(String::from),
+            params: params.unwrap_or_default(),
+            next_member: 0,
+            current: None,
+            headers: Vec::new(),
+            selection: None,
+            warnings: Vec::new(),
+        };
+        if !reader.open_next()? {
+            return Err(EtError::from("Archive has no members that can be read")
+                .with_kind(ErrorKind::UnsupportedFormat));
+        }
+        Ok(reader)
+    }
+
+    /// Open the next member that can be read, returning false if there are none left.
+    fn open_next(&mut self) -> Result<bool, EtError> {
+        self.collect_warnings();
+        while let Some(member) = self.archive.members.get(self.next_member) {
+            self.next_member += 1;
+            let opened = self.archive.get_reader(
+                &member.name,
+                self.parser.as_deref(),
+                Some(self.params.clone()),
+            );
+            let reader = match opened {
+                Ok((reader, _)) => reader,
+                Err(_) if self.parser.is_none() && !detectable(self.archive, member)? => continue,
+                Err(e) => {
+                    let kind = e.kind;
+                    return Err(EtError::from(format!("{}: {}", member.name, e)).with_kind(kind));
+                }
+            };
+            let mut headers = vec!["member".to_string()];
+            headers.extend(reader.headers());
+            if self.headers.is_empty() {
+                self.headers = headers;
+            } else if self.headers != headers {
+                return Err(EtError::from(format!(
+                    "Member {} has different columns than the members before it",
+                    member.name
+                ))
+                .with_kind(ErrorKind::InvalidValue));
+            }
+            self.current = Some((Value::String(member.name.clone().into()), reader));
+            return Ok(true);
+        }
+        self.current = None;
+        Ok(false)
+    }
+
+    /// Read the next record from the current member, moving on to the next member whenever one
+    /// runs out.
+    ///
+    /// The records are copied out of the member's reader so that it can be swapped for the next
+    /// one without holding on to a borrow of it.
+    fn next_member_record(&mut self) -> Result<Option<Vec<Value<'static>>>, EtError> {
+        loop {
+            let record = match &mut self.current {
+                Some((_, reader)) => reader
+                    .next_record()?
+                    .map(|record| record.into_iter().map(Value::into_owned).collect()),
+                None => return Ok(None),
+            };
+            match record {
+                Some(record) => return Ok(Some(record)),
+                None => {
+                    if !self.open_next()? {
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Keep the warnings from the current member (prefixed with its name) before moving on.
+    fn collect_warnings(&mut self) {
+        if let Some((Value::String(name), reader)) = &self.current {
+            for warning in reader.warnings() {
+                let mut err =
+                    EtError::from(format!("{}: {}", name, warning.msg)).with_kind(warning.kind);
+                err.context = warning.context.clone();
+                self.warnings.push(err);
+            }
+        }
+    }
+}
+
+/// Check if entab can work out what kind of file `member` is.
+fn detectable(archive: &Archive<'_>, member: &ArchiveMember) -> Result<bool, EtError> {
+    let data = archive.member_data(&member.name)?;
+    let mut rb = ReadBuffer::from(data.as_ref());
+    Ok(crate::registry::detect_parser(&mut rb, Some(&member.name)).is_ok())
+}
+
+impl<'a, 'p> ::core::fmt::Debug for ArchiveReader<'a, 'p> {
+    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+        write!(f, "<ArchiveReader member={}>", self.next_member)
+    }
+}
+
+impl<'a, 'p> RecordReader for ArchiveReader<'a, 'p> {
+    /// The next record, expressed as a `Vec` of `Value`s.
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        while let Some(record) = self.next_member_record()? {
+            let name = match &self.current {
+                Some((name, _)) => name.clone(),
+                None => break,
+            };
+            let mut values = Vec::with_capacity(record.len() + 1);
+            values.push(name);
+            values.extend(record);
+            match &self.selection {
+                Some(s) => {
+                    if let Some(values) = s.apply(values) {
+                        return Ok(Some(values));
+                    }
+                }
+                None => return Ok(Some(values)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The headers for this Reader.
+    fn headers(&self) -> Vec<String> {
+        match &self.selection {
+            Some(s) => s.project(&self.headers),
+            None => self.headers.clone(),
+        }
+    }
+
+    /// The metadata for the member that's currently being read.
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.current
+            .as_ref()
+            .map(|(_, reader)| reader.metadata())
+            .unwrap_or_default()
+    }
+
+    /// Only return the columns and rows picked out by `selection`
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        self.selection = if selection.is_empty() {
+            None
+        } else {
+            Some(selection.resolve(&self.headers)?)
+        };
+        Ok(())
+    }
+    /// The warnings from the members that have been read through.
+    fn warnings(&self) -> &[EtError] {
+        &self.warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn test_archive_members() -> Result<(), EtError> {
+        for (path, file_type) in [
+            ("tests/data/reads.tar.gz", FileType::Tar),
+            ("tests/data/reads.zip", FileType::Zip),
+        ] {
+            let archive = Archive::new(File::open(path)?, None)?;
+            assert_eq!(archive.file_type(), file_type);
+            let names: Vec<&str> = archive.members().iter().map(|m| m.name.as_ref()).collect();
+            assert_eq!(names, ["reads/a.fastq", "reads/README", "reads/b.fastq"]);
+            assert_eq!(archive.members()[1].size, 19);
+            assert_eq!(
+                &archive.member_data("reads/README")?[..],
+                b"two lanes of reads\n"
+            );
+
+            let (mut reader, parser) = archive.get_reader("reads/b.fastq", None, None)?;
+            assert_eq!(parser, "fastq");
+            let mut n_records = 0;
+            while reader.next_record()?.is_some() {
+                n_records += 1;
+            }
+            assert_eq!(n_records, 3);
+            assert!(archive.get_reader("reads/c.fastq", None, None).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_reader() -> Result<(), EtError> {
+        let data = std::fs::read("tests/data/reads.zip")?;
+        let archive = Archive::new(&data[..], None)?;
+        let mut reader = ArchiveReader::new(&archive, None, None)?;
+        assert_eq!(reader.headers(), ["member", "id", "sequence", "quality"]);
+        let mut members = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            if let Value::String(s) = &record[0] {
+                members.push(s.to_string());
+            }
+        }
+        // the README's skipped since it's not in a format that can be detected
+        assert_eq!(members.len(), 5);
+        assert_eq!(members[0], "reads/a.fastq");
+        assert_eq!(members[4], "reads/b.fastq");
+
+        let mut reader = ArchiveReader::new(&archive, None, None)?;
+        reader.select(&Selection::default().filter("member = reads/b.fastq".parse()?))?;
+        let mut n_records = 0;
+        while reader.next_record()?.is_some() {
+            n_records += 1;
+        }
+        assert_eq!(n_records, 3);
+
+        assert!(Archive::new(&b">id\nACGT"[..], None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_limits() -> Result<(), EtError> {
+        let file = File::open("tests/data/reads.tar.gz")?;
+        let err = Archive::new(file, Some(1024)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded);
+
+        // data that's already in memory isn't limited, but the members read out of it are
+        let data = std::fs::read("tests/data/reads.zip")?;
+        let archive = Archive::new(&data[..], Some(18))?;
+        assert!(archive.member_data("reads/a.fastq").is_err());
+        let err = archive.member_data("reads/README").unwrap_err();
+        assert_eq!(err.kind, ErrorKind::LimitExceeded);
+        Ok(())
+    }
+}
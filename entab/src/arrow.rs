@@ -0,0 +1,547 @@
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use std::collections::HashMap;
+
+use arrow::array::{
+    new_null_array, ArrayRef, BooleanArray, Float64Array, Int64Array, ListArray, StringArray,
+    StructArray, TimestampMicrosecondArray,
+};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::{RecordBatch, RecordBatchReader};
+
+use crate::readers::RecordReader;
+use crate::record::{ColumnSchema, Value, ValueType};
+use crate::{ErrorKind, EtError};
+
+/// Parameters for converting records into Arrow `RecordBatch`es
+#[derive(Clone, Copy, Debug)]
+pub struct ArrowParams {
+    /// The maximum number of rows in each `RecordBatch`
+    pub batch_size: usize,
+    /// The number of rows to read ahead to determine the type of each column
+    ///
+    /// Integers can still appear after these rows in a column that held floats in them, but e.g.
+    /// a float in a column that only held integers or a string in one that only held numbers is
+    /// an error.
+    pub infer_rows: usize,
+}
+
+impl Default for ArrowParams {
+    fn default() -> Self {
+        ArrowParams {
+            batch_size: 1024,
+            infer_rows: 1024,
+        }
+    }
+}
+
+/// Find a type that can hold values of both `a` and `b`.
+fn merge_types(a: DataType, b: DataType) -> DataType {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (DataType::Null, t) | (t, DataType::Null) => t,
+        (DataType::Int64, DataType::Float64) | (DataType::Float64, DataType::Int64) => {
+            DataType::Float64
+        }
+        (DataType::List(a), DataType::List(b)) => DataType::List(Arc::new(Field::new(
+            "item",
+            merge_types(a.data_type().clone(), b.data_type().clone()),
+            true,
+        ))),
+        (DataType::Struct(a), DataType::Struct(b)) => {
+            let mut fields: Vec<Field> = a.iter().map(|f| f.as_ref().clone()).collect();
+            for field in b.iter() {
+                if let Some(f) = fields.iter_mut().find(|f| f.name() == field.name()) {
+                    let data_type = merge_types(f.data_type().clone(), field.data_type().clone());
+                    *f = Field::new(f.name(), data_type, true);
+                } else {
+                    fields.push(field.as_ref().clone());
+                }
+            }
+            DataType::Struct(fields.into())
+        }
+        _ => DataType::Utf8,
+    }
+}
+
+/// The Arrow type a single `Value` would be stored as.
+fn value_type(value: &Value<'_>) -> DataType {
+    match value {
+        Value::Null => DataType::Null,
+        Value::Boolean(_) => DataType::Boolean,
+        Value::Datetime(_) => DataType::Timestamp(TimeUnit::Microsecond, None),
+        Value::Float(_) => DataType::Float64,
+        Value::Integer(_) => DataType::Int64,
+        Value::String(_) => DataType::Utf8,
+        Value::List(l) => {
+            let item_type = l.iter().map(value_type).fold(DataType::Null, merge_types);
+            DataType::List(Arc::new(Field::new("item", item_type, true)))
+        }
+        Value::Record(r) => DataType::Struct(
+            r.iter()
+                .map(|(k, v)| Field::new(k, value_type(v), true))
+                .collect(),
+        ),
+    }
+}
+
+/// Columns that are entirely null while inferring are stored as strings.
+fn widen_inferred(data_type: DataType) -> DataType {
+    match data_type {
+        DataType::Null => DataType::Utf8,
+        DataType::List(f) => DataType::List(Arc::new(Field::new(
+            "item",
+            widen_inferred(f.data_type().clone()),
+            true,
+        ))),
+        DataType::Struct(fields) => DataType::Struct(
+            fields
+                .iter()
+                .map(|f| Field::new(f.name(), widen_inferred(f.data_type().clone()), true))
+                .collect(),
+        ),
+        t => t,
+    }
+}
+
+/// Build an Arrow schema from the declared schema and a sample of the records.
+///
+/// Columns with a declared scalar type use it directly; the rest are inferred from the sample.
+fn infer_schema(columns: &[ColumnSchema], rows: &VecDeque<Vec<Value<'static>>>) -> Schema {
+    let fields: Vec<Field> = columns
+        .iter()
+        .enumerate()
+        .map(|(ix, column)| {
+            let (data_type, nullable) = match column.value_type {
+                ValueType::Boolean => (DataType::Boolean, column.nullable),
+                ValueType::Datetime => (
+                    DataType::Timestamp(TimeUnit::Microsecond, None),
+                    column.nullable,
+                ),
+                ValueType::Float => (DataType::Float64, column.nullable),
+                ValueType::Integer => (DataType::Int64, column.nullable),
+                ValueType::String => (DataType::Utf8, column.nullable),
+                ValueType::Any | ValueType::List | ValueType::Record => {
+                    let data_type = rows
+                        .iter()
+                        .filter_map(|row| row.get(ix))
+                        .map(value_type)
+                        .fold(DataType::Null, merge_types);
+                    (widen_inferred(data_type), true)
+                }
+            };
+            let field = Field::new(&column.name, data_type, nullable);
+            if let Some(unit) = &column.unit {
+                let mut metadata = HashMap::new();
+                drop(metadata.insert("unit".to_string(), unit.clone()));
+                field.with_metadata(metadata)
+            } else {
+                field
+            }
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+fn mismatch(value: &Value<'_>, data_type: &DataType) -> EtError {
+    EtError::from(format!(
+        "Value {:?} does not match the column type {}",
+        value, data_type
+    ))
+    .with_kind(ErrorKind::InvalidValue)
+}
+
+/// Convert a column of `Value`s into an Arrow array of the given type.
+fn values_to_array(values: &[&Value<'_>], data_type: &DataType) -> Result<ArrayRef, EtError> {
+    Ok(match data_type {
+        DataType::Boolean => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Boolean(b) => Ok(Some(*b)),
+                    v => Err(mismatch(v, data_type)),
+                })
+                .collect::<Result<BooleanArray, EtError>>()?,
+        ),
+        DataType::Int64 => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Integer(i) => Ok(Some(*i)),
+                    v => Err(mismatch(v, data_type)),
+                })
+                .collect::<Result<Int64Array, EtError>>()?,
+        ),
+        DataType::Float64 => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Float(f) => Ok(Some(*f)),
+                    Value::Integer(i) => Ok(Some(*i as f64)),
+                    v => Err(mismatch(v, data_type)),
+                })
+                .collect::<Result<Float64Array, EtError>>()?,
+        ),
+        DataType::Timestamp(TimeUnit::Microsecond, None) => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Datetime(d) => Ok(Some(d.and_utc().timestamp_micros())),
+                    v => Err(mismatch(v, data_type)),
+                })
+                .collect::<Result<TimestampMicrosecondArray, EtError>>()?,
+        ),
+        DataType::Utf8 => Arc::new(
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::Null => Ok(None),
+                    Value::Boolean(b) => Ok(Some(b.to_string())),
+                    Value::Datetime(d) => Ok(Some(d.to_string())),
+                    Value::Float(f) => Ok(Some(f.to_string())),
+                    Value::Integer(i) => Ok(Some(i.to_string())),
+                    Value::String(s) => Ok(Some(s.to_string())),
+                    v => Err(mismatch(v, data_type)),
+                })
+                .collect::<Result<StringArray, EtError>>()?,
+        ),
+        DataType::List(field) => {
+            let mut lengths = Vec::with_capacity(values.len());
+            let mut valid = Vec::with_capacity(values.len());
+            let mut items = Vec::new();
+            for value in values {
+                match value {
+                    Value::Null => {
+                        lengths.push(0);
+                        valid.push(false);
+                    }
+                    Value::List(l) => {
+                        lengths.push(l.len());
+                        valid.push(true);
+                        items.extend(l.iter());
+                    }
+                    v => return Err(mismatch(v, data_type)),
+                }
+            }
+            Arc::new(ListArray::try_new(
+                Arc::clone(field),
+                OffsetBuffer::from_lengths(lengths),
+                values_to_array(&items, field.data_type())?,
+                Some(NullBuffer::from(valid)),
+            )?)
+        }
+        DataType::Struct(fields) => {
+            let mut valid = Vec::with_capacity(values.len());
+            let mut columns: Vec<Vec<&Value<'_>>> = fields
+                .iter()
+                .map(|_| Vec::with_capacity(values.len()))
+                .collect();
+            for value in values {
+                match value {
+                    Value::Null => {
+                        valid.push(false);
+                        for column in &mut columns {
+                            column.push(&Value::Null);
+                        }
+                    }
+                    Value::Record(r) => {
+                        valid.push(true);
+                        for (field, column) in fields.iter().zip(&mut columns) {
+                            column.push(r.get(field.name()).unwrap_or(&Value::Null));
+                        }
+                    }
+                    v => return Err(mismatch(v, data_type)),
+                }
+            }
+            let arrays = fields
+                .iter()
+                .zip(&columns)
+                .map(|(f, c)| values_to_array(c, f.data_type()))
+                .collect::<Result<Vec<ArrayRef>, EtError>>()?;
+            Arc::new(StructArray::try_new(
+                fields.clone(),
+                arrays,
+                Some(NullBuffer::from(valid)),
+            )?)
+        }
+        DataType::Null => new_null_array(data_type, values.len()),
+        t => return Err(format!("Unsupported Arrow type {}", t).into()),
+    })
+}
+
+/// Reads the records from any `RecordReader` into Arrow `RecordBatch`es
+///
+/// The schema is taken from the reader's declared schema; the types of any columns it doesn't
+/// declare are inferred from the values in the first `infer_rows` records. Columns that are null
+/// in all of those records or that mix types are read as strings (integer columns are only read
+/// as floats if a float turns up in those records).
+#[derive(Debug)]
+pub struct ArrowReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    schema: SchemaRef,
+    batch_size: usize,
+    pending: VecDeque<Vec<Value<'static>>>,
+    done: bool,
+}
+
+impl<'r> ArrowReader<'r> {
+    /// Create a new `ArrowReader` wrapping `reader`
+    ///
+    /// # Errors
+    /// If the records used to infer the schema can't be read, an error is returned.
+    pub fn new(
+        mut reader: Box<dyn RecordReader + 'r>,
+        params: Option<ArrowParams>,
+    ) -> Result<Self, EtError> {
+        let params = params.unwrap_or_default();
+        if params.batch_size == 0 {
+            return Err("Batch size must be greater than zero".into());
+        }
+        let mut pending = VecDeque::new();
+        let mut done = false;
+        while pending.len() < params.infer_rows.max(1) {
+            if let Some(record) = reader.next_record()? {
+                pending.push_back(record.into_iter().map(Value::into_owned).collect());
+            } else {
+                done = true;
+                break;
+            }
+        }
+        let schema = Arc::new(infer_schema(&reader.schema(), &pending));
+        Ok(ArrowReader {
+            reader,
+            schema,
+            batch_size: params.batch_size,
+            pending,
+            done,
+        })
+    }
+
+    /// The Arrow schema of the batches produced
+    #[must_use]
+    pub fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+
+    /// Read up to `batch_size` records into a `RecordBatch`
+    ///
+    /// # Errors
+    /// If a record can't be read or doesn't match the inferred schema, an error is returned.
+    pub fn next_batch(&mut self) -> Result<Option<RecordBatch>, EtError> {
+        let mut rows = Vec::with_capacity(self.batch_size);
+        while rows.len() < self.batch_size {
+            if let Some(row) = self.pending.pop_front() {
+                rows.push(row);
+            } else if self.done {
+                break;
+            } else if let Some(record) = self.reader.next_record()? {
+                rows.push(record.into_iter().map(Value::into_owned).collect());
+            } else {
+                self.done = true;
+            }
+        }
+        if rows.is_empty() {
+            return Ok(None);
+        }
+
+        let mut columns = Vec::with_capacity(self.schema.fields().len());
+        for (ix, field) in self.schema.fields().iter().enumerate() {
+            let values: Vec<&Value<'_>> = rows
+                .iter()
+                .map(|row| row.get(ix).unwrap_or(&Value::Null))
+                .collect();
+            columns.push(values_to_array(&values, field.data_type())?);
+        }
+        Ok(Some(RecordBatch::try_new(self.schema(), columns)?))
+    }
+}
+
+impl<'r> Iterator for ArrowReader<'r> {
+    type Item = Result<RecordBatch, ArrowError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_batch()
+            .map_err(|e| ArrowError::ExternalError(e.to_string().into()))
+            .transpose()
+    }
+}
+
+impl<'r> RecordBatchReader for ArrowReader<'r> {
+    fn schema(&self) -> SchemaRef {
+        Arc::clone(&self.schema)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::string::String;
+    use alloc::vec;
+    use arrow::array::Array;
+
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_arrow_fasta() -> Result<(), EtError> {
+        let rb: &[u8] = include_bytes!("../tests/data/sequence.fasta");
+        let (reader, _) = get_reader(rb, None, None)?;
+        let params = ArrowParams {
+            batch_size: 1,
+            ..ArrowParams::default()
+        };
+        let mut reader = ArrowReader::new(reader, Some(params))?;
+        let schema = reader.schema();
+        assert_eq!(schema.field(0).name(), "id");
+        assert_eq!(schema.field(0).data_type(), &DataType::Utf8);
+
+        let batch = reader.next_batch()?.unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        assert_eq!(batch.num_columns(), 2);
+        let mut n_batches = 1;
+        while reader.next_batch()?.is_some() {
+            n_batches += 1;
+        }
+        assert_eq!(n_batches, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_arrow_numeric() -> Result<(), EtError> {
+        let rb: &[u8] = include_bytes!("../tests/data/b3_alkanes.dxf");
+        let (reader, _) = get_reader(rb, None, None)?;
+        let mut reader = ArrowReader::new(reader, None)?;
+        let schema = reader.schema();
+        assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+        assert!(!schema.field(0).is_nullable());
+        assert_eq!(schema.field(0).metadata()["unit"], "min");
+        let mut n_rows = 0;
+        for batch in &mut reader {
+            let batch = batch.map_err(|e| EtError::from(e.to_string()))?;
+            assert!(batch.num_rows() <= 1024);
+            n_rows += batch.num_rows();
+        }
+        assert!(n_rows > 1024);
+        Ok(())
+    }
+
+    /// A reader with a single undeclared column holding the given values
+    #[derive(Debug)]
+    struct ValuesReader(vec::IntoIter<Value<'static>>);
+
+    impl RecordReader for ValuesReader {
+        fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+            Ok(self.0.next().map(|v| vec![v]))
+        }
+
+        fn headers(&self) -> Vec<String> {
+            vec!["value".to_string()]
+        }
+
+        fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+            BTreeMap::new()
+        }
+    }
+
+    #[test]
+    fn test_arrow_widening() -> Result<(), EtError> {
+        let params = ArrowParams {
+            batch_size: 2,
+            infer_rows: 2,
+        };
+
+        // a float in the inferred rows makes the column a float column
+        let values = vec![Value::Integer(1), Value::Float(1.5), Value::Integer(2)];
+        let reader = Box::new(ValuesReader(values.into_iter()));
+        let mut reader = ArrowReader::new(reader, Some(params))?;
+        assert_eq!(reader.schema().field(0).data_type(), &DataType::Float64);
+        let first = reader.next_batch()?.unwrap();
+        let second = reader.next_batch()?.unwrap();
+        let column = second
+            .column(0)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(first.num_rows() + second.num_rows(), 3);
+        assert_eq!(column.value(0), 2.);
+        assert!(reader.next_batch()?.is_none());
+
+        // otherwise integers are kept as they are
+        let big = (1 << 53) + 1;
+        let values = vec![Value::Integer(1), Value::Integer(big)];
+        let reader = Box::new(ValuesReader(values.into_iter()));
+        let mut reader = ArrowReader::new(reader, Some(params))?;
+        assert_eq!(reader.schema().field(0).data_type(), &DataType::Int64);
+        let batch = reader.next_batch()?.unwrap();
+        let column = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .unwrap();
+        assert_eq!(column.value(1), big);
+
+        // and a float after the inferred rows is an error
+        let values = vec![Value::Integer(1), Value::Integer(2), Value::Float(2.5)];
+        let reader = Box::new(ValuesReader(values.into_iter()));
+        let mut reader = ArrowReader::new(reader, Some(params))?;
+        assert_eq!(reader.schema().field(0).data_type(), &DataType::Int64);
+        assert!(reader.next_batch()?.is_some());
+        let err = reader.next_batch().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidValue);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_values() -> Result<(), EtError> {
+        let mut record = BTreeMap::new();
+        drop(record.insert("a".to_string(), Value::Integer(1)));
+        drop(record.insert("b".to_string(), Value::String("x".into())));
+        let values = [
+            Value::List(vec![Value::Integer(1), Value::Float(2.5)]),
+            Value::Null,
+            Value::List(vec![]),
+        ];
+        let data_type = values
+            .iter()
+            .map(value_type)
+            .fold(DataType::Null, merge_types);
+        assert_eq!(
+            data_type,
+            DataType::List(Arc::new(Field::new("item", DataType::Float64, true)))
+        );
+        let refs: Vec<&Value<'_>> = values.iter().collect();
+        let array = values_to_array(&refs, &data_type)?;
+        assert_eq!(array.len(), 3);
+        assert!(array.is_null(1));
+
+        let values = [Value::Record(record), Value::Null];
+        let data_type = widen_inferred(
+            values
+                .iter()
+                .map(value_type)
+                .fold(DataType::Null, merge_types),
+        );
+        let refs: Vec<&Value<'_>> = values.iter().collect();
+        let array = values_to_array(&refs, &data_type)?;
+        let array = array.as_any().downcast_ref::<StructArray>().unwrap();
+        assert_eq!(array.num_columns(), 2);
+        assert!(array.is_null(1));
+
+        assert_eq!(
+            merge_types(DataType::Int64, DataType::Boolean),
+            DataType::Utf8
+        );
+        assert!(values_to_array(&[&Value::String("x".into())], &DataType::Int64).is_err());
+        Ok(())
+    }
+}
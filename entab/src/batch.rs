@@ -0,0 +1,504 @@
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+
+use crate::record::{ColumnSchema, Value, ValueType};
+use crate::EtError;
+
+/// The buffer holding the values of a single column
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnData {
+    /// `Value::Boolean`s
+    Boolean(Vec<bool>),
+    /// `Value::Datetime`s, as microseconds since the Unix epoch
+    Datetime(Vec<i64>),
+    /// `Value::Float`s
+    Float(Vec<f64>),
+    /// `Value::Integer`s
+    Integer(Vec<i64>),
+    /// `Value::String`s, concatenated together
+    ///
+    /// The bytes of the `i`th value are `data[offsets[i]..offsets[i + 1]]`. These are copied
+    /// directly out of the file so they're not guaranteed to be valid UTF-8.
+    String {
+        /// The start of every value in `data` followed by the end of the last value
+        offsets: Vec<usize>,
+        /// The bytes of every value
+        data: Vec<u8>,
+    },
+    /// Anything else (or columns that turned out to have mixed types)
+    Values(Vec<Value<'static>>),
+}
+
+impl ColumnData {
+    /// An empty buffer suitable for holding values of `value_type`
+    #[must_use]
+    pub fn new(value_type: ValueType) -> Self {
+        match value_type {
+            ValueType::Boolean => ColumnData::Boolean(Vec::new()),
+            ValueType::Datetime => ColumnData::Datetime(Vec::new()),
+            ValueType::Float => ColumnData::Float(Vec::new()),
+            ValueType::Integer => ColumnData::Integer(Vec::new()),
+            ValueType::String => ColumnData::String {
+                offsets: vec![0],
+                data: Vec::new(),
+            },
+            ValueType::Any | ValueType::List | ValueType::Record => ColumnData::Values(Vec::new()),
+        }
+    }
+
+    /// The number of values in the buffer
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnData::Boolean(v) => v.len(),
+            ColumnData::Datetime(v) | ColumnData::Integer(v) => v.len(),
+            ColumnData::Float(v) => v.len(),
+            ColumnData::String { offsets, .. } => offsets.len() - 1,
+            ColumnData::Values(v) => v.len(),
+        }
+    }
+
+    /// If the buffer has no values in it
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove all of the values while keeping the allocated memory
+    pub fn clear(&mut self) {
+        match self {
+            ColumnData::Boolean(v) => v.clear(),
+            ColumnData::Datetime(v) | ColumnData::Integer(v) => v.clear(),
+            ColumnData::Float(v) => v.clear(),
+            ColumnData::String { offsets, data } => {
+                offsets.truncate(1);
+                data.clear();
+            }
+            ColumnData::Values(v) => v.clear(),
+        }
+    }
+}
+
+/// A single named column of values in a `ColumnBatch`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    /// The name of the column (matching the corresponding header)
+    pub name: String,
+    /// The values in the column
+    pub data: ColumnData,
+    /// `false` for every null value in the column or `None` if there are no nulls
+    ///
+    /// Nulls still take up a slot (with a zero/empty value) in a typed `data` buffer.
+    pub validity: Option<Vec<bool>>,
+    value_type: ValueType,
+}
+
+impl Column {
+    /// Create an empty column for holding the values described by `schema`
+    #[must_use]
+    pub fn new(schema: &ColumnSchema) -> Self {
+        Column {
+            name: schema.name.clone(),
+            data: ColumnData::new(schema.value_type),
+            validity: None,
+            value_type: schema.value_type,
+        }
+    }
+
+    /// The number of values in the column
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// If the column has no values in it
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Remove all of the values while keeping the allocated memory
+    pub fn clear(&mut self) {
+        if matches!(self.data, ColumnData::Values(_))
+            && !matches!(
+                self.value_type,
+                ValueType::Any | ValueType::List | ValueType::Record
+            )
+        {
+            // go back to the typed buffer if the last batch had to switch to `Values`
+            self.data = ColumnData::new(self.value_type);
+        }
+        self.data.clear();
+        self.validity = None;
+    }
+
+    /// If the `i`th value is null
+    #[must_use]
+    pub fn is_null(&self, i: usize) -> bool {
+        self.validity.as_ref().is_some_and(|v| !v[i])
+    }
+
+    /// The bytes of the `i`th value if this is a string column
+    #[must_use]
+    pub fn bytes(&self, i: usize) -> Option<&[u8]> {
+        if let ColumnData::String { offsets, data } = &self.data {
+            Some(&data[offsets[i]..offsets[i + 1]])
+        } else {
+            None
+        }
+    }
+
+    /// The `i`th value in the column, converted back into a `Value`
+    ///
+    /// # Panics
+    /// If `i` is out of bounds.
+    #[must_use]
+    pub fn get(&self, i: usize) -> Value<'_> {
+        if self.is_null(i) {
+            return Value::Null;
+        }
+        match &self.data {
+            ColumnData::Boolean(v) => Value::Boolean(v[i]),
+            ColumnData::Datetime(v) => DateTime::from_timestamp_micros(v[i])
+                .map_or(Value::Null, |d| Value::Datetime(d.naive_utc())),
+            ColumnData::Float(v) => Value::Float(v[i]),
+            ColumnData::Integer(v) => Value::Integer(v[i]),
+            ColumnData::String { offsets, data } => {
+                String::from_utf8_lossy(&data[offsets[i]..offsets[i + 1]]).into()
+            }
+            ColumnData::Values(v) => v[i].clone(),
+        }
+    }
+
+    fn mark_valid(&mut self) {
+        if let Some(validity) = &mut self.validity {
+            validity.push(true);
+        }
+    }
+
+    /// Add a null to the end of the column
+    pub fn push_null(&mut self) {
+        let len = self.len();
+        self.validity
+            .get_or_insert_with(|| vec![true; len])
+            .push(false);
+        match &mut self.data {
+            ColumnData::Boolean(v) => v.push(false),
+            ColumnData::Datetime(v) | ColumnData::Integer(v) => v.push(0),
+            ColumnData::Float(v) => v.push(0.),
+            ColumnData::String { offsets, data } => offsets.push(data.len()),
+            ColumnData::Values(v) => v.push(Value::Null),
+        }
+    }
+
+    /// Add a boolean to the end of the column
+    pub fn push_bool(&mut self, value: bool) {
+        if let ColumnData::Boolean(v) = &mut self.data {
+            v.push(value);
+            self.mark_valid();
+        } else {
+            self.push_other(Value::Boolean(value));
+        }
+    }
+
+    /// Add a float to the end of the column
+    pub fn push_float(&mut self, value: f64) {
+        if let ColumnData::Float(v) = &mut self.data {
+            v.push(value);
+            self.mark_valid();
+        } else {
+            self.push_other(Value::Float(value));
+        }
+    }
+
+    /// Add an integer to the end of the column
+    #[allow(clippy::cast_precision_loss)]
+    pub fn push_integer(&mut self, value: i64) {
+        match &mut self.data {
+            ColumnData::Integer(v) => v.push(value),
+            ColumnData::Float(v) => v.push(value as f64),
+            _ => return self.push_other(Value::Integer(value)),
+        }
+        self.mark_valid();
+    }
+
+    /// Add a string (as bytes) to the end of the column
+    pub fn push_bytes(&mut self, value: &[u8]) {
+        if let ColumnData::String { offsets, data } = &mut self.data {
+            data.extend_from_slice(value);
+            offsets.push(data.len());
+            self.mark_valid();
+        } else {
+            self.push_other(String::from_utf8_lossy(value).into());
+        }
+    }
+
+    /// Add a datetime to the end of the column
+    pub fn push_datetime(&mut self, value: NaiveDateTime) {
+        if let ColumnData::Datetime(v) = &mut self.data {
+            v.push(value.and_utc().timestamp_micros());
+            self.mark_valid();
+        } else {
+            self.push_other(Value::Datetime(value));
+        }
+    }
+
+    /// Add an arbitrary `Value` to the end of the column
+    pub fn push(&mut self, value: Value<'_>) {
+        match value {
+            Value::Null => self.push_null(),
+            Value::Boolean(b) => self.push_bool(b),
+            Value::Datetime(d) => self.push_datetime(d),
+            Value::Float(f) => self.push_float(f),
+            Value::Integer(i) => self.push_integer(i),
+            Value::String(s) => self.push_bytes(s.as_bytes()),
+            x => self.push_other(x),
+        }
+    }
+
+    /// Add a value that doesn't fit in the typed buffer, switching the column over to
+    /// `ColumnData::Values` if needed.
+    fn push_other(&mut self, value: Value<'_>) {
+        if !matches!(self.data, ColumnData::Values(_)) {
+            let values = (0..self.len()).map(|i| self.get(i).into_owned()).collect();
+            self.data = ColumnData::Values(values);
+        }
+        if let ColumnData::Values(v) = &mut self.data {
+            v.push(value.into_owned());
+        }
+        self.mark_valid();
+    }
+}
+
+/// A set of records stored column-by-column in reusable, typed buffers
+///
+/// Created empty and filled by `RecordReader::next_batch`; the buffers are cleared and refilled
+/// for each subsequent batch so their allocations are reused.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ColumnBatch {
+    columns: Vec<Column>,
+    len: usize,
+}
+
+impl ColumnBatch {
+    /// Create an empty batch with columns matching `schema`
+    #[must_use]
+    pub fn new(schema: &[ColumnSchema]) -> Self {
+        ColumnBatch {
+            columns: schema.iter().map(Column::new).collect(),
+            len: 0,
+        }
+    }
+
+    /// The number of records in the batch
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// If there are no records in the batch
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The columns in the batch
+    #[must_use]
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    /// The column named `name`, if there is one
+    #[must_use]
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Consume the batch, returning its columns
+    #[must_use]
+    pub fn into_columns(self) -> Vec<Column> {
+        self.columns
+    }
+
+    /// Remove all of the records while keeping the allocated memory
+    pub fn clear(&mut self) {
+        for column in &mut self.columns {
+            column.clear();
+        }
+        self.len = 0;
+    }
+
+    /// Get the batch ready for refilling, setting up the columns from `schema` if this
+    /// batch hasn't been used yet.
+    pub fn reset<F: FnOnce() -> Vec<ColumnSchema>>(&mut self, schema: F) {
+        if self.columns.is_empty() {
+            self.columns = schema().iter().map(Column::new).collect();
+        }
+        self.clear();
+    }
+
+    /// The `i`th record in the batch, converted back into `Value`s
+    #[must_use]
+    pub fn record(&self, i: usize) -> Vec<Value<'_>> {
+        self.columns.iter().map(|c| c.get(i)).collect()
+    }
+
+    /// Add a record to the end of the batch
+    ///
+    /// # Errors
+    /// If the record has a different number of values than the batch has columns.
+    pub fn push_record(&mut self, record: Vec<Value<'_>>) -> Result<(), EtError> {
+        for (column, value) in self.next_row(record.len())?.iter_mut().zip(record) {
+            column.push(value);
+        }
+        Ok(())
+    }
+
+    /// Start a new record, returning the columns its values should be pushed onto
+    ///
+    /// # Errors
+    /// If `n_columns` doesn't match the number of columns in the batch.
+    #[doc(hidden)]
+    pub fn next_row(&mut self, n_columns: usize) -> Result<&mut [Column], EtError> {
+        if n_columns != self.columns.len() {
+            return Err(format!(
+                "Record has {} values, but the batch has {} columns",
+                n_columns,
+                self.columns.len()
+            )
+            .into());
+        }
+        self.len += 1;
+        Ok(&mut self.columns)
+    }
+}
+
+/// A record that can be pushed directly onto the columns of a `ColumnBatch`
+///
+/// This is derived by `impl_record!`.
+pub trait BatchRecord {
+    /// Add this record to the end of `batch`
+    ///
+    /// # Errors
+    /// If the record doesn't have the same number of values as the batch has columns.
+    fn push_to(self, batch: &mut ColumnBatch) -> Result<(), EtError>;
+}
+
+/// A Rust type that can be pushed onto a `Column` without going through a `Value`
+pub trait ColumnValue {
+    /// Add this value to the end of `column`
+    fn push_to(self, column: &mut Column);
+}
+
+macro_rules! impl_column_value {
+    ($method:ident($arg:ident): $($type:ty => $convert:expr),*) => {
+        $(
+            impl ColumnValue for $type {
+                fn push_to(self, column: &mut Column) {
+                    let $arg = self;
+                    column.$method($convert);
+                }
+            }
+        )*
+    };
+}
+
+impl_column_value!(push_bool(x): bool => x);
+impl_column_value!(push_float(x): f32 => x.into(), f64 => x);
+impl_column_value!(push_integer(x): u8 => x.into(), u16 => x.into(), i32 => x.into(), u32 => x.into(), i64 => x);
+impl ColumnValue for u64 {
+    /// Values too big for an `i64` are stored as floats rather than being truncated
+    #[allow(clippy::cast_precision_loss)]
+    fn push_to(self, column: &mut Column) {
+        match i64::try_from(self) {
+            Ok(x) => column.push_integer(x),
+            Err(_) => column.push_float(self as f64),
+        }
+    }
+}
+impl_column_value!(push_bytes(x): &str => x.as_bytes(), String => x.as_bytes(), Cow<'_, str> => x.as_bytes());
+impl_column_value!(push_bytes(x): &[u8] => x, Vec<u8> => &x, Cow<'_, [u8]> => &x);
+impl_column_value!(push_datetime(x): NaiveDateTime => x, NaiveDate => x.and_time(NaiveTime::default()));
+impl_column_value!(push(x): &[String] => x.into(), Vec<String> => x.into(), Vec<Value<'_>> => x.into(), Value<'_> => x);
+
+impl<T: ColumnValue> ColumnValue for Option<T> {
+    fn push_to(self, column: &mut Column) {
+        match self {
+            Some(x) => x.push_to(column),
+            None => column.push_null(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_batch() -> Result<(), EtError> {
+        let schema = [
+            ColumnSchema::new("id", ValueType::String),
+            ColumnSchema::new("count", ValueType::Integer).nullable(true),
+            ColumnSchema::new("value", ValueType::Float),
+        ];
+        let mut batch = ColumnBatch::new(&schema);
+        batch.push_record(vec!["a".into(), 1.into(), 0.5.into()])?;
+        batch.push_record(vec!["bc".into(), Value::Null, 2i64.into()])?;
+        assert_eq!(batch.len(), 2);
+        assert!(batch.push_record(vec!["d".into()]).is_err());
+
+        let ids = batch.column("id").unwrap();
+        assert_eq!(
+            ids.data,
+            ColumnData::String {
+                offsets: vec![0, 1, 3],
+                data: b"abc".to_vec(),
+            }
+        );
+        assert_eq!(ids.bytes(1), Some(&b"bc"[..]));
+        let counts = batch.column("count").unwrap();
+        assert_eq!(counts.data, ColumnData::Integer(vec![1, 0]));
+        assert_eq!(counts.validity, Some(vec![true, false]));
+        // integers are widened to fit into float columns
+        assert_eq!(
+            batch.column("value").unwrap().data,
+            ColumnData::Float(vec![0.5, 2.])
+        );
+        assert_eq!(
+            batch.record(1),
+            vec!["bc".into(), Value::Null, Value::Float(2.)]
+        );
+
+        // a value of the wrong type switches the column over to generic values
+        batch.push_record(vec!["e".into(), "many".into(), 3.into()])?;
+        assert_eq!(
+            batch.column("count").unwrap().data,
+            ColumnData::Values(vec![1.into(), Value::Null, "many".into()])
+        );
+
+        // and clearing resets everything back to the declared types
+        batch.clear();
+        assert!(batch.is_empty());
+        assert_eq!(
+            batch.column("count").unwrap().data,
+            ColumnData::Integer(Vec::new())
+        );
+        assert_eq!(batch.column("count").unwrap().validity, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_large_u64() {
+        let mut column = Column::new(&ColumnSchema::new("count", ValueType::Integer));
+        3u64.push_to(&mut column);
+        u64::MAX.push_to(&mut column);
+        assert_eq!(column.get(0), Value::Integer(3));
+        assert_eq!(column.get(1), Value::Float(u64::MAX as f64));
+    }
+}
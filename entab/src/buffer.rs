@@ -1,29 +1,175 @@
 use alloc::borrow::Cow;
 #[cfg(feature = "std")]
 use alloc::boxed::Box;
-#[cfg(feature = "std")]
+use alloc::format;
+use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::convert::{AsRef, From};
 #[cfg(feature = "std")]
 use core::mem::swap;
+#[cfg(feature = "async")]
+use core::pin::Pin;
 #[cfg(feature = "std")]
 use core::ptr;
+use core::task::{Context, Poll};
+#[cfg(feature = "async")]
+use std::cell::RefCell;
 #[cfg(feature = "std")]
 use std::fs::File;
 #[cfg(feature = "std")]
-use std::io::{Cursor, Read};
+use std::io::{copy, sink, Cursor, Read, Seek, SeekFrom};
+#[cfg(feature = "async")]
+use std::rc::Rc;
+
+#[cfg(feature = "async")]
+use futures_io::AsyncRead;
+use memchr::{memchr_iter, memrchr};
 
 use crate::filetype::FileType;
 use crate::parsers::FromSlice;
-use crate::EtError;
+use crate::{ErrorKind, EtError};
 
 /// Default buffer size
 pub const BUFFER_SIZE: usize = 10_000;
 
+/// Where a `ReadBuffer` gets more data from
+#[cfg(feature = "std")]
+enum Source<'r> {
+    Read(Box<dyn Read + 'r>),
+    /// A source that can jump straight to the data `seek_to` asks for
+    Seek(Box<dyn ReadSeek + 'r>),
+    /// Shared so `get_reader_async` can retry setting up a reader as more data comes in
+    #[cfg(feature = "async")]
+    AsyncRead(Rc<RefCell<Pin<Box<dyn AsyncRead + 'r>>>>),
+}
+
+/// Something that can be both read and seeked
+#[cfg(feature = "std")]
+trait ReadSeek: Read + Seek {}
+
+#[cfg(feature = "std")]
+impl<T: Read + Seek> ReadSeek for T {}
+
+/// Bytes pulled in from an async source that haven't been read by a decoder yet
+#[cfg(feature = "async")]
+struct PendingBytes {
+    data: Vec<u8>,
+    pos: usize,
+    eof: bool,
+}
+
+/// A `Read` over `PendingBytes` that returns `WouldBlock` when it's waiting for more
+#[cfg(feature = "async")]
+struct PendingRead(Rc<RefCell<PendingBytes>>);
+
+#[cfg(feature = "async")]
+impl Read for PendingRead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut pending = self.0.borrow_mut();
+        if pending.pos == pending.data.len() {
+            if pending.eof {
+                return Ok(0);
+            }
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        let amt = buf.len().min(pending.data.len() - pending.pos);
+        buf[..amt].copy_from_slice(&pending.data[pending.pos..pending.pos + amt]);
+        pending.pos += amt;
+        if pending.pos == pending.data.len() {
+            pending.data.clear();
+            pending.pos = 0;
+        }
+        Ok(amt)
+    }
+}
+
+/// Runs a blocking decoder (e.g. a decompressor) over an async source
+///
+/// The decoders in `compression` keep their place when their input returns `WouldBlock`, so
+/// whenever that happens more data is pulled in from the source and the read is retried.
+#[cfg(feature = "async")]
+pub(crate) struct AsyncDecoder<'r> {
+    decoder: Box<dyn Read + 'r>,
+    input: Rc<RefCell<PendingBytes>>,
+    source: Rc<RefCell<Pin<Box<dyn AsyncRead + 'r>>>>,
+}
+
+#[cfg(feature = "async")]
+impl<'r> AsyncRead for AsyncDecoder<'r> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let mut chunk = [0; BUFFER_SIZE];
+        loop {
+            match self.decoder.read(buf) {
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                result => return Poll::Ready(result),
+            }
+            let amt_read = match self.source.borrow_mut().as_mut().poll_read(cx, &mut chunk) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(result) => result?,
+            };
+            let mut input = self.input.borrow_mut();
+            input.data.extend_from_slice(&chunk[..amt_read]);
+            input.eof = amt_read == 0;
+        }
+    }
+}
+
+/// Stands in for an async source when a `ReadBuffer` is turned into a (blocking) `Read`
+#[cfg(feature = "async")]
+struct AsyncSourceRead;
+
+#[cfg(feature = "async")]
+impl Read for AsyncSourceRead {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            "Async sources can't be read from synchronously",
+        ))
+    }
+}
+
+/// How far through the data newlines have been counted (so errors can say what line they're on)
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct LineCount {
+    /// The position in the buffer that's been counted up to
+    pos: usize,
+    /// The number of newlines before `pos`
+    lines: u64,
+    /// The position in the source of the start of the line `pos` is on
+    line_start: u64,
+}
+
+impl LineCount {
+    /// Count on through `buffer` (which starts at `reader_pos` in the source) up to `pos`.
+    fn count_to(self, buffer: &[u8], reader_pos: u64, pos: usize) -> Self {
+        let counted = &buffer[self.pos..pos];
+        LineCount {
+            pos,
+            lines: self.lines + memchr_iter(b'\n', counted).count() as u64,
+            line_start: memrchr(b'\n', counted)
+                .map_or(self.line_start, |p| reader_pos + (self.pos + p + 1) as u64),
+        }
+    }
+
+    /// The line and column (both counting from 1) of byte `pos` of `buffer`, counting on from
+    /// here.
+    pub(crate) fn position(self, buffer: &[u8], reader_pos: u64, pos: usize) -> (u64, u64) {
+        let count = self.count_to(buffer, reader_pos, pos);
+        (
+            count.lines + 1,
+            reader_pos + pos as u64 - count.line_start + 1,
+        )
+    }
+}
+
 /// Buffers Read to provide something that can be used for parsing
 pub struct ReadBuffer<'r> {
     #[cfg(feature = "std")]
-    reader: Box<dyn Read + 'r>,
+    reader: Source<'r>,
     pub(crate) buffer: Cow<'r, [u8]>,
     /// The total amount of data read before byte 0 of this buffer (used for error messages)
     pub reader_pos: u64,
@@ -35,6 +181,20 @@ pub struct ReadBuffer<'r> {
     pub eof: bool,
     /// After the parser has had a chance to run through eof, then this will be set to end parsing.
     pub end: bool,
+    /// The most data the buffer can grow to hold (unlimited if `None`)
+    pub max_buffer_size: Option<usize>,
+    /// The most bytes a single record can take up (unlimited if `None`)
+    pub max_record_size: Option<usize>,
+    /// Skip malformed records (keeping their errors in `warnings`) rather than failing on them
+    pub lenient: bool,
+    /// The errors for the records skipped in lenient mode
+    pub warnings: Vec<EtError>,
+    /// The lines counted before byte 0 of this buffer (`None` once data has been skipped or
+    /// dropped without counting them)
+    lines_before: Option<LineCount>,
+    /// The lines counted up to the last place an error was reported (so lenient readers don't
+    /// recount the whole buffer for every warning)
+    lines_counted: LineCount,
 }
 
 impl<'r> ReadBuffer<'r> {
@@ -51,13 +211,203 @@ impl<'r> ReadBuffer<'r> {
         let amt_read = reader.read(&mut buffer)?;
         buffer.truncate(amt_read);
         Ok(ReadBuffer {
-            reader,
+            reader: Source::Read(reader),
+            buffer: Cow::Owned(buffer),
+            reader_pos: 0,
+            record_pos: 0,
+            consumed: 0,
+            eof: false,
+            end: false,
+            max_buffer_size: None,
+            max_record_size: None,
+            lenient: false,
+            warnings: Vec::new(),
+            lines_before: Some(LineCount::default()),
+            lines_counted: LineCount::default(),
+        })
+    }
+
+    /// Create a new buffer from a seekable reader (e.g. a `File`).
+    ///
+    /// These work the same as other buffers, except that `seek_to` can jump anywhere in the
+    /// source rather than having to read through (and only forwards to) the data it wants.
+    ///
+    /// # Errors
+    /// This will fail if there's an error reading into the buffer to initialize it.
+    #[cfg(feature = "std")]
+    pub fn from_seekable_reader<R>(reader: R, buffer_size: Option<usize>) -> Result<Self, EtError>
+    where
+        R: Read + Seek + 'r,
+    {
+        let mut reader = Box::new(reader);
+        let mut buffer = vec![0; buffer_size.unwrap_or(BUFFER_SIZE)];
+        let amt_read = reader.read(&mut buffer)?;
+        buffer.truncate(amt_read);
+        Ok(ReadBuffer {
+            reader: Source::Seek(reader),
             buffer: Cow::Owned(buffer),
             reader_pos: 0,
             record_pos: 0,
             consumed: 0,
             eof: false,
             end: false,
+            max_buffer_size: None,
+            max_record_size: None,
+            lenient: false,
+            warnings: Vec::new(),
+            lines_before: Some(LineCount::default()),
+            lines_counted: LineCount::default(),
+        })
+    }
+
+    /// Create a new buffer from a boxed `AsyncRead`.
+    ///
+    /// Parsing works the same as for other buffers, except that running out of data returns a
+    /// "pending" error (see `EtError::pending`) instead of blocking; use `fill` (or
+    /// `readers::get_reader_async` and `next_record_async`) to wait for more data.
+    ///
+    /// # Errors
+    /// This will fail if there's an error reading into the buffer to initialize it.
+    #[cfg(feature = "async")]
+    pub async fn from_async_reader(
+        reader: Pin<Box<dyn AsyncRead + 'r>>,
+        buffer_size: Option<usize>,
+    ) -> Result<ReadBuffer<'r>, EtError> {
+        let mut rb = ReadBuffer {
+            reader: Source::AsyncRead(Rc::new(RefCell::new(reader))),
+            buffer: Cow::Owned(Vec::with_capacity(buffer_size.unwrap_or(BUFFER_SIZE))),
+            reader_pos: 0,
+            record_pos: 0,
+            consumed: 0,
+            eof: false,
+            end: false,
+            max_buffer_size: None,
+            max_record_size: None,
+            lenient: false,
+            warnings: Vec::new(),
+            lines_before: Some(LineCount::default()),
+            lines_counted: LineCount::default(),
+        };
+        let _ = rb.fill().await?;
+        Ok(rb)
+    }
+
+    /// Wait for more data from an async source, returning false if there's no more to read.
+    ///
+    /// # Errors
+    /// This will fail if there's an error retrieving data from the reader.
+    #[cfg(feature = "async")]
+    pub async fn fill(&mut self) -> Result<bool, EtError> {
+        core::future::poll_fn(|cx| self.poll_fill(cx)).await
+    }
+
+    /// Try to pull more data into the buffer, returning false if there's no more to read.
+    ///
+    /// Only async sources can return `Poll::Pending`; other sources are refilled immediately.
+    ///
+    /// # Errors
+    /// This will fail if there's an error retrieving data from the reader.
+    pub fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, EtError>> {
+        #[cfg(feature = "async")]
+        if let Source::AsyncRead(reader) = &self.reader {
+            if self.eof {
+                return Poll::Ready(Ok(false));
+            }
+            let reader = Rc::clone(reader);
+            if let Err(e) = self.check_buffer_size(self.buffer.len() - self.consumed) {
+                return Poll::Ready(Err(e));
+            }
+            let mut buffer = self.take_unconsumed();
+            let len = buffer.len();
+            if len == buffer.capacity() {
+                buffer.reserve(len.max(BUFFER_SIZE));
+            }
+            buffer.resize(buffer.capacity(), 0);
+            let result = reader
+                .borrow_mut()
+                .as_mut()
+                .poll_read(cx, &mut buffer[len..]);
+            let amt_read = match &result {
+                Poll::Ready(Ok(amt_read)) => *amt_read,
+                _ => 0,
+            };
+            buffer.truncate(len + amt_read);
+            self.buffer = Cow::Owned(buffer);
+            return match result {
+                Poll::Pending => Poll::Pending,
+                Poll::Ready(Err(e)) => {
+                    Poll::Ready(Err(EtError::from(e).add_context_from_readbuffer(self)))
+                }
+                Poll::Ready(Ok(0)) => {
+                    self.eof = true;
+                    Poll::Ready(Ok(false))
+                }
+                Poll::Ready(Ok(_)) => Poll::Ready(Ok(true)),
+            };
+        }
+        let _ = cx;
+        Poll::Ready(self.refill())
+    }
+
+    /// Pull the unconsumed data out of the buffer and move it to the front.
+    #[cfg(feature = "async")]
+    fn take_unconsumed(&mut self) -> Vec<u8> {
+        self.rebase_lines();
+        let mut buffer = core::mem::take(&mut self.buffer).into_owned();
+        let _ = buffer.drain(..self.consumed);
+        self.reader_pos += self.consumed as u64;
+        self.consumed = 0;
+        buffer
+    }
+
+    /// Copy this buffer (sharing the same async source) so a parser can be tried out on it.
+    #[cfg(feature = "async")]
+    pub(crate) fn try_clone(&self) -> Option<Self> {
+        match &self.reader {
+            Source::AsyncRead(reader) => Some(ReadBuffer {
+                reader: Source::AsyncRead(Rc::clone(reader)),
+                buffer: self.buffer.clone(),
+                reader_pos: self.reader_pos,
+                record_pos: self.record_pos,
+                consumed: self.consumed,
+                eof: self.eof,
+                end: self.end,
+                max_buffer_size: self.max_buffer_size,
+                max_record_size: self.max_record_size,
+                lenient: self.lenient,
+                warnings: Vec::new(),
+                lines_before: self.lines_before,
+                lines_counted: self.lines_counted,
+            }),
+            Source::Read(_) | Source::Seek(_) => None,
+        }
+    }
+
+    /// Converts this `ReadBuffer` (over an async source) into an `AsyncRead` of its contents
+    /// after they've been run through the blocking decoder `wrap` returns.
+    ///
+    /// # Errors
+    /// If this buffer doesn't have an async source or the decoder can't be created.
+    #[cfg(feature = "async")]
+    pub(crate) fn into_async_decoder<F>(self, wrap: F) -> Result<AsyncDecoder<'r>, EtError>
+    where
+        F: FnOnce(Box<dyn Read + 'r>) -> Result<Box<dyn Read + 'r>, EtError>,
+    {
+        let source = match self.reader {
+            Source::AsyncRead(source) => source,
+            Source::Read(_) | Source::Seek(_) => {
+                return Err("Only async sources can be decoded asynchronously".into())
+            }
+        };
+        let input = Rc::new(RefCell::new(PendingBytes {
+            data: self.buffer.into_owned(),
+            pos: 0,
+            eof: self.eof,
+        }));
+        Ok(AsyncDecoder {
+            decoder: wrap(Box::new(PendingRead(Rc::clone(&input))))?,
+            input,
+            source,
         })
     }
 
@@ -82,6 +432,17 @@ impl<'r> ReadBuffer<'r> {
         if self.eof {
             return Ok(false);
         }
+        #[cfg(feature = "async")]
+        if let Source::AsyncRead(_) = self.reader {
+            return Err(EtError::new("Waiting for more data from an async source").pending());
+        }
+
+        if self.consumed == 0 {
+            self.check_buffer_size(self.buffer.len())?;
+        }
+
+        // count the lines in the data that's about to be dropped
+        self.rebase_lines();
 
         // pull the buffer out; if self.buffer's Borrowed then eof should
         // always be true above and we shouldn't hit this
@@ -98,6 +459,9 @@ impl<'r> ReadBuffer<'r> {
             buffer.reserve(2 * capacity);
             capacity = buffer.capacity();
         };
+        if let Some(max) = self.max_buffer_size {
+            capacity = capacity.min(max.max(buffer.len()));
+        }
 
         let len = buffer.len() - self.consumed;
         unsafe {
@@ -109,8 +473,13 @@ impl<'r> ReadBuffer<'r> {
             // resize the buffer in prep to read in new data
             buffer.set_len(capacity);
         }
-        let amt_read = self
-            .reader
+        let reader: &mut dyn Read = match &mut self.reader {
+            Source::Read(reader) => reader,
+            Source::Seek(reader) => reader,
+            #[cfg(feature = "async")]
+            Source::AsyncRead(_) => unreachable!("async sources are refilled in poll_fill"),
+        };
+        let amt_read = reader
             .read(&mut buffer[len..])
             .map_err(|e| EtError::from(e).add_context_from_readbuffer(self))?;
         buffer.truncate(len + amt_read);
@@ -123,6 +492,78 @@ impl<'r> ReadBuffer<'r> {
         Ok(true)
     }
 
+    /// Error out if the buffer has `len` bytes in it that can't be dropped and would have to grow
+    /// past `max_buffer_size` to read any more.
+    #[cfg(feature = "std")]
+    fn check_buffer_size(&self, len: usize) -> Result<(), EtError> {
+        match self.max_buffer_size {
+            Some(max) if len >= max => Err(EtError::from(format!(
+                "Buffer would grow past the {} byte limit (max_buffer_size); the data may be malformed",
+                max
+            ))
+            .add_context_from_readbuffer(self).with_kind(ErrorKind::LimitExceeded)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Error out if the record starting at `start` takes up more than `max_record_size` bytes.
+    fn check_record_size(&self, start: usize, size: usize) -> Result<(), EtError> {
+        match self.max_record_size {
+            Some(max) if size > max => Err(EtError::from(format!(
+                "Record is larger than the {} byte limit (max_record_size); it may be malformed",
+                max
+            ))
+            .add_context_at(self, start)
+            .with_kind(ErrorKind::LimitExceeded)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The line and column (both counting from 1) of byte `pos` in the buffer.
+    ///
+    /// This is `None` if the lines couldn't be counted because a seek skipped over data.
+    #[must_use]
+    pub fn line_and_column(&self, pos: usize) -> Option<(u64, u64)> {
+        let pos = pos.min(self.buffer.len());
+        let count = self.line_count_before(pos)?;
+        Some(count.position(&self.buffer, self.reader_pos, pos))
+    }
+
+    /// The closest line count to (but not after) `pos` to start counting from.
+    pub(crate) fn line_count_before(&self, pos: usize) -> Option<LineCount> {
+        match self.lines_before {
+            Some(_) if self.lines_counted.pos <= pos => Some(self.lines_counted),
+            before => before,
+        }
+    }
+
+    /// Save the line count up to `pos` (e.g. before an error's reported there or the data before
+    /// it is dropped).
+    pub(crate) fn count_lines(&mut self, pos: usize) {
+        let pos = pos.min(self.buffer.len());
+        if let Some(count) = self.line_count_before(pos) {
+            self.lines_counted = count.count_to(&self.buffer, self.reader_pos, pos);
+        }
+    }
+
+    /// Move the line count to the front of the buffer before the consumed data is dropped.
+    ///
+    /// The lines are only still known afterwards if they'd been counted up to there (which text
+    /// formats do before refilling; see `FromSlice::TEXT`).
+    #[cfg(feature = "std")]
+    fn rebase_lines(&mut self) {
+        if self.consumed == 0 {
+            return;
+        }
+        if self.lines_before.is_some() && self.lines_counted.pos == self.consumed {
+            self.lines_counted.pos = 0;
+            self.lines_before = Some(self.lines_counted);
+        } else {
+            self.lines_before = None;
+            self.lines_counted = LineCount::default();
+        }
+    }
+
     /// Refill implementation for no_std
     #[cfg(not(feature = "std"))]
     fn refill(&mut self) -> Result<bool, EtError> {
@@ -133,23 +574,129 @@ impl<'r> ReadBuffer<'r> {
         Ok(true)
     }
 
+    /// Read the rest of the source into the buffer (e.g. so it can be split up and parsed in
+    /// parallel).
+    ///
+    /// # Errors
+    /// This will fail if there's an error retrieving data from the reader.
+    pub fn read_to_end(&mut self) -> Result<(), EtError> {
+        while self.refill()? {}
+        Ok(())
+    }
+
+    /// If the source can jump to any offset (see `from_seekable_reader`).
+    fn is_seekable(&self) -> bool {
+        #[cfg(feature = "std")]
+        if let Source::Seek(_) = self.reader {
+            return true;
+        }
+        false
+    }
+
+    /// Move the buffer to the absolute byte `offset` in the source so the next record is parsed
+    /// from there.
+    ///
+    /// Offsets inside the data that's already buffered are always available. Seekable sources
+    /// (see `from_seekable_reader`) can jump anywhere else; other sources can only skip forwards
+    /// by reading through (and throwing away) the data in between.
+    ///
+    /// # Errors
+    /// If the offset is past the end of the data, can't be reached by this source, or if there's
+    /// an error reading from it.
+    pub fn seek_to(&mut self, offset: u64) -> Result<(), EtError> {
+        let buffer_end = self.reader_pos + self.buffer.len() as u64;
+        if offset >= self.reader_pos && offset <= buffer_end {
+            self.consumed = usize::try_from(offset - self.reader_pos)?;
+            return Ok(());
+        }
+        if offset > buffer_end && self.eof {
+            return Err(EtError::from(format!(
+                "Tried to seek to byte {} past the end of the data",
+                offset
+            ))
+            .with_kind(ErrorKind::Truncated));
+        }
+        self.seek_source(offset)
+    }
+
+    /// Move the source itself to `offset` and refill the buffer from there.
+    #[cfg(feature = "std")]
+    fn seek_source(&mut self, offset: u64) -> Result<(), EtError> {
+        let buffer_end = self.reader_pos + self.buffer.len() as u64;
+        let reader: &mut dyn Read = match &mut self.reader {
+            Source::Seek(reader) => {
+                let _ = reader.seek(SeekFrom::Start(offset))?;
+                reader
+            }
+            Source::Read(reader) if offset > buffer_end => {
+                let to_skip = offset - buffer_end;
+                if copy(&mut reader.by_ref().take(to_skip), &mut sink())? < to_skip {
+                    self.eof = true;
+                    return Err(EtError::from(format!(
+                        "Tried to seek to byte {} past the end of the data",
+                        offset
+                    ))
+                    .with_kind(ErrorKind::Truncated));
+                }
+                reader
+            }
+            _ => {
+                return Err(format!(
+                "Can't seek to byte {} in a source that's not seekable once it's been read past",
+                offset
+            )
+                .into())
+            }
+        };
+        let mut buffer = core::mem::take(&mut self.buffer).into_owned();
+        let size = if buffer.capacity() == 0 {
+            BUFFER_SIZE
+        } else {
+            buffer.capacity()
+        };
+        buffer.clear();
+        buffer.resize(size, 0);
+        let amt_read = reader.read(&mut buffer)?;
+        buffer.truncate(amt_read);
+        self.buffer = Cow::Owned(buffer);
+        self.reader_pos = offset;
+        self.consumed = 0;
+        self.eof = amt_read == 0;
+        // there's no telling how many lines were jumped over
+        self.lines_before = None;
+        self.lines_counted = LineCount::default();
+        Ok(())
+    }
+
+    /// Seeking implementation for no_std (everything is already in the buffer)
+    #[cfg(not(feature = "std"))]
+    fn seek_source(&mut self, offset: u64) -> Result<(), EtError> {
+        Err(
+            EtError::from(format!("Can't seek to byte {} outside of the data", offset))
+                .with_kind(ErrorKind::Truncated),
+        )
+    }
+
     /// Converts this `ReadBuffer` into a `Box<Read>`.
     #[cfg(feature = "std")]
     #[must_use]
     pub fn into_box_read(self) -> Box<dyn Read + 'r> {
-        Box::new(Cursor::new(self.buffer).chain(self.reader))
+        match self.reader {
+            Source::Read(reader) => Box::new(Cursor::new(self.buffer).chain(reader)),
+            Source::Seek(reader) => Box::new(Cursor::new(self.buffer).chain(reader)),
+            #[cfg(feature = "async")]
+            Source::AsyncRead(_) => Box::new(Cursor::new(self.buffer).chain(AsyncSourceRead)),
+        }
     }
 
-    /// Uses the state to extract a record from the buffer.
+    /// Run the parser for `T` up to the end of the next record, returning where the record
+    /// starts in the buffer (or `None` if there are no more records).
     ///
-    /// # Errors
-    /// Most commonly if the parser failed, but potentially also if the buffer could not be
-    /// refilled.
-    #[inline]
-    pub fn next<'b: 's, 's, T>(
-        &'b mut self,
-        state: &'s mut <T as FromSlice<'b, 's>>::State,
-    ) -> Result<Option<T>, EtError>
+    /// In lenient mode, records the parser fails on are skipped (see `FromSlice::resync`).
+    fn parse_record<'b: 's, 's, T>(
+        &mut self,
+        state: &mut <T as FromSlice<'b, 's>>::State,
+    ) -> Result<Option<usize>, EtError>
     where
         T: FromSlice<'b, 's>,
     {
@@ -164,21 +711,188 @@ impl<'r> ReadBuffer<'r> {
                 Ok(true) => break,
                 Ok(false) => return Ok(None),
                 Err(e) => {
-                    if !e.incomplete || self.eof {
-                        return Err(e.add_context_from_readbuffer(self));
+                    if let Some(offset) = e.seek_offset() {
+                        if T::TEXT {
+                            self.count_lines(self.consumed);
+                        }
+                        // a source that can't seek is read in up to the offset (keeping this
+                        // record's data) so the parser can still jump back into it
+                        while !self.is_seekable()
+                            && offset > self.reader_pos + self.buffer.len() as u64
+                            && self.refill()?
+                        {}
+                        self.seek_to(offset)
+                            .map_err(|e| e.add_context_from_readbuffer(self))?;
+                        consumed = self.consumed;
+                        continue;
                     }
-                    if !self.refill()? {
-                        return Ok(None);
+                    if e.incomplete && !self.eof {
+                        // the record needs at least one more byte than what's left in the buffer
+                        self.check_record_size(consumed, self.buffer.len() - consumed + 1)?;
+                        if T::TEXT {
+                            // keep track of the lines in the data that's about to be dropped
+                            self.count_lines(self.consumed);
+                        }
+                        if !self.refill()? {
+                            return Ok(None);
+                        }
+                        consumed = 0;
+                        continue;
                     }
-                    consumed = 0;
+                    self.count_lines(self.consumed);
+                    let e = e.add_context_from_readbuffer(self);
+                    if !self.lenient || !self.resync::<T>(consumed)? {
+                        return Err(e);
+                    }
+                    self.record_pos += 1;
+                    self.warnings.push(e);
+                    consumed = self.consumed;
                 }
             }
         }
-        self.record_pos += 1;
+        self.check_record_size(consumed, self.consumed - consumed)?;
+        Ok(Some(consumed))
+    }
+
+    /// Move past the malformed record at `start` to where `T::resync` says the next one begins.
+    ///
+    /// Returns `false` if `T` can't find where the next record starts.
+    fn resync<'b: 's, 's, T>(&mut self, mut start: usize) -> Result<bool, EtError>
+    where
+        T: FromSlice<'b, 's>,
+    {
+        self.consumed = start;
+        loop {
+            match T::resync(&self.buffer[start..], self.eof) {
+                Ok(Some(len)) if len > 0 => {
+                    self.consumed = start + len;
+                    return Ok(true);
+                }
+                Ok(_) => return Ok(false),
+                Err(e) if e.incomplete && !self.eof => {
+                    self.check_record_size(start, self.buffer.len() - start + 1)?;
+                    let _ = self.refill()?;
+                    start = 0;
+                }
+                Err(e) => return Err(e.add_context_from_readbuffer(self)),
+            }
+        }
+    }
+
+    /// Handle an error reading out the record at `start`: in lenient mode it's kept in
+    /// `warnings` and the record is skipped, otherwise it's returned.
+    fn skip_malformed(&mut self, err: EtError, start: usize) -> Result<(), EtError> {
+        self.count_lines(start);
+        if !self.lenient {
+            return Err(err);
+        }
+        self.warnings.push(err);
+        Ok(())
+    }
+
+    /// Parse up to the end of the next record, returning where it starts in the buffer (or
+    /// `None` if there are no more records).
+    ///
+    /// The record itself can then be read out with `record_at`.
+    ///
+    /// # Errors
+    /// If the parser failed or the buffer could not be refilled.
+    fn parse_next<'b: 's, 's, T>(
+        &mut self,
+        state: &mut <T as FromSlice<'b, 's>>::State,
+    ) -> Result<Option<usize>, EtError>
+    where
+        T: FromSlice<'b, 's>,
+    {
+        let start = self.parse_record::<T>(state)?;
+        if start.is_some() {
+            self.record_pos += 1;
+        }
+        Ok(start)
+    }
+
+    /// Read out the record parsed by the last `parse_next` (which returned `start`).
+    ///
+    /// # Errors
+    /// If the record could not be read out of the buffer.
+    fn record_at<'b: 's, 's, T>(
+        &'b self,
+        start: usize,
+        state: &'s <T as FromSlice<'b, 's>>::State,
+    ) -> Result<T, EtError>
+    where
+        T: FromSlice<'b, 's>,
+    {
         let mut record = T::default();
-        T::get(&mut record, &self.buffer[consumed..self.consumed], state)
-            .map_err(|e| e.add_context_from_readbuffer(self))?;
-        Ok(Some(record))
+        T::get(&mut record, &self.buffer[start..self.consumed], state)
+            .map_err(|e| e.add_context_at(self, start))?;
+        Ok(record)
+    }
+
+    /// Uses the state to extract a record from the buffer.
+    ///
+    /// In lenient mode, records the parser fails on are skipped, but an error reading the record
+    /// out is still returned (the readers skip those; see `skip_malformed`) and the next call
+    /// carries on from the record after it.
+    ///
+    /// # Errors
+    /// Most commonly if the parser failed, but potentially also if the buffer could not be
+    /// refilled.
+    #[inline]
+    pub fn next<'b: 's, 's, T>(
+        &'b mut self,
+        state: &'s mut <T as FromSlice<'b, 's>>::State,
+    ) -> Result<Option<T>, EtError>
+    where
+        T: FromSlice<'b, 's>,
+    {
+        match self.parse_next::<T>(state)? {
+            Some(start) => self.record_at(start, state).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parse and read out the next record `keep` accepts, skipping any others.
+    ///
+    /// Each record is only read out once; the ones `keep` turns down are dropped before the next
+    /// one's parsed. Records that can't be read out are skipped in lenient mode (see
+    /// `skip_malformed`).
+    ///
+    /// # Errors
+    /// If the parser failed, the buffer could not be refilled or (outside of lenient mode) a
+    /// record could not be read out.
+    #[doc(hidden)]
+    pub fn next_kept<'b: 's, 's, T, F>(
+        &'b mut self,
+        state: &'s mut <T as FromSlice<'b, 's>>::State,
+        mut keep: F,
+    ) -> Result<Option<T>, EtError>
+    where
+        T: FromSlice<'b, 's>,
+        F: FnMut(&T) -> bool,
+    {
+        // the borrow checker doesn't know that a record that isn't returned stops borrowing the
+        // buffer and the state (it's the "conditional return" case Polonius fixes) so they're
+        // reborrowed through pointers here instead
+        let this: *mut Self = self;
+        let state: *mut <T as FromSlice<'b, 's>>::State = state;
+        loop {
+            // SAFETY: records that aren't kept and errors (which don't borrow anything) are
+            // dropped before the buffer or state are used again, so the only borrows that
+            // outlive an iteration are the returned record's
+            let start = match unsafe { (*this).parse_next::<T>(&mut *state)? } {
+                Some(start) => start,
+                None => return Ok(None),
+            };
+            match unsafe { (*this).record_at::<T>(start, &*state) } {
+                Ok(record) => {
+                    if keep(&record) {
+                        return Ok(Some(record));
+                    }
+                }
+                Err(e) => unsafe { (*this).skip_malformed(e, start)? },
+            }
+        }
     }
 
     /// Reads a record into an existing value.
@@ -206,38 +920,27 @@ impl<'r> ReadBuffer<'r> {
     where
         T: FromSlice<'b, 's>,
     {
-        let mut consumed = self.consumed;
         loop {
-            match T::parse(
-                &self.buffer[consumed..],
-                self.eof,
-                &mut self.consumed,
-                state,
-            ) {
-                Ok(true) => break,
-                Ok(false) => return Ok(false),
+            let start = match self.parse_record::<T>(state)? {
+                Some(start) => start,
+                None => return Ok(false),
+            };
+            let buffer = { ::core::mem::transmute::<_, &'b Cow<'b, [u8]>>(&self.buffer) };
+            let cur_state = {
+                ::core::mem::transmute::<
+                    &mut <T as FromSlice<'b, 's>>::State,
+                    &'s mut <T as FromSlice<'b, 's>>::State,
+                >(&mut *state)
+            };
+            self.record_pos += 1;
+            match T::get(record, &buffer[start..self.consumed], cur_state) {
+                Ok(()) => return Ok(true),
                 Err(e) => {
-                    if !e.incomplete || self.eof {
-                        return Err(e.add_context_from_readbuffer(self));
-                    }
-                    if !self.refill()? {
-                        return Ok(false);
-                    }
-                    consumed = 0;
+                    let e = e.add_context_at(self, start);
+                    self.skip_malformed(e, start)?;
                 }
             }
         }
-        let buffer = { ::core::mem::transmute::<_, &'b Cow<'b, [u8]>>(&self.buffer) };
-        let cur_state = {
-            ::core::mem::transmute::<
-                &mut <T as FromSlice<'b, 's>>::State,
-                &'s mut <T as FromSlice<'b, 's>>::State,
-            >(state)
-        };
-        self.record_pos += 1;
-        T::get(record, &buffer[consumed..self.consumed], cur_state)
-            .map_err(|e| e.add_context_from_readbuffer(self))?;
-        Ok(true)
     }
 }
 
@@ -258,13 +961,19 @@ impl<'r> ::core::default::Default for ReadBuffer<'r> {
     fn default() -> Self {
         ReadBuffer {
             #[cfg(feature = "std")]
-            reader: Box::new(Cursor::new(b"")),
+            reader: Source::Read(Box::new(Cursor::new(b""))),
             buffer: Cow::Borrowed(b""),
             reader_pos: 0,
             record_pos: 0,
             consumed: 0,
             eof: true,
             end: false,
+            max_buffer_size: None,
+            max_record_size: None,
+            lenient: false,
+            warnings: Vec::new(),
+            lines_before: Some(LineCount::default()),
+            lines_counted: LineCount::default(),
         }
     }
 }
@@ -283,7 +992,7 @@ impl<'r> TryFrom<File> for ReadBuffer<'r> {
     type Error = EtError;
 
     fn try_from(reader: File) -> Result<Self, Self::Error> {
-        ReadBuffer::from_reader(Box::new(reader), None)
+        ReadBuffer::from_seekable_reader(reader, None)
     }
 }
 
@@ -291,13 +1000,19 @@ impl<'r> From<&'r [u8]> for ReadBuffer<'r> {
     fn from(buffer: &'r [u8]) -> Self {
         ReadBuffer {
             #[cfg(feature = "std")]
-            reader: Box::new(Cursor::new(b"")),
+            reader: Source::Read(Box::new(Cursor::new(b""))),
             buffer: Cow::Borrowed(buffer),
             reader_pos: 0,
             record_pos: 0,
             consumed: 0,
             eof: true,
             end: false,
+            max_buffer_size: None,
+            max_record_size: None,
+            lenient: false,
+            warnings: Vec::new(),
+            lines_before: Some(LineCount::default()),
+            lines_counted: LineCount::default(),
         }
     }
 }
@@ -316,10 +1031,42 @@ mod test {
     use std::io::Cursor;
 
     use crate::parsers::common::{NewLine, SeekPattern};
+    use crate::parsers::FromSlice;
     use crate::EtError;
 
     use super::ReadBuffer;
 
+    /// A byte found at the offset given in the first byte of the data
+    #[derive(Debug, Default)]
+    struct Pointed(u8);
+
+    impl<'b: 's, 's> FromSlice<'b, 's> for Pointed {
+        /// If the pointer's been followed yet
+        type State = bool;
+
+        fn parse(
+            buffer: &[u8],
+            _eof: bool,
+            consumed: &mut usize,
+            followed: &mut Self::State,
+        ) -> Result<bool, EtError> {
+            if buffer.is_empty() {
+                return Err(EtError::from("Data ended early").incomplete());
+            }
+            if !*followed {
+                *followed = true;
+                return Err(EtError::from("Following pointer").seek(u64::from(buffer[0])));
+            }
+            *consumed += 1;
+            Ok(true)
+        }
+
+        fn get(&mut self, buffer: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+            self.0 = buffer[0];
+            Ok(())
+        }
+    }
+
     #[cfg(feature = "std")]
     #[test]
     fn test_buffer_small() -> Result<(), EtError> {
@@ -402,6 +1149,83 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_seek_to() -> Result<(), EtError> {
+        let mut data = [0; 201];
+        data[0] = 200;
+        data[200] = b'X';
+
+        let mut rb = ReadBuffer::from(&data[..]);
+        assert_eq!(rb.next::<Pointed>(&mut false)?.map(|p| p.0), Some(b'X'));
+        assert_eq!(rb.reader_pos + rb.consumed as u64, 201);
+        rb.seek_to(0)?;
+        assert_eq!(rb.next::<Pointed>(&mut true)?.map(|p| p.0), Some(200));
+        assert!(rb.seek_to(202).is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_seek_to_reader() -> Result<(), EtError> {
+        let mut data = [0; 201];
+        data[0] = 200;
+        data[200] = b'X';
+
+        // non-seekable sources read in everything up to the data so they can still go back
+        let mut rb = ReadBuffer::from_reader(Box::new(Cursor::new(data)), Some(4))?;
+        assert_eq!(rb.next::<Pointed>(&mut false)?.map(|p| p.0), Some(b'X'));
+        assert_eq!(rb.reader_pos, 0);
+        rb.seek_to(0)?;
+        assert_eq!(rb.next::<Pointed>(&mut true)?.map(|p| p.0), Some(200));
+
+        // seekable sources can go back too
+        let mut rb = ReadBuffer::from_seekable_reader(Cursor::new(data), Some(4))?;
+        assert_eq!(rb.next::<Pointed>(&mut false)?.map(|p| p.0), Some(b'X'));
+        assert_eq!(rb.reader_pos, 200);
+        rb.seek_to(0)?;
+        assert_eq!(rb.as_ref(), &[200, 0, 0, 0]);
+        assert_eq!(rb.next::<Pointed>(&mut true)?.map(|p| p.0), Some(200));
+
+        let mut rb = ReadBuffer::from_reader(Box::new(Cursor::new(data)), Some(4))?;
+        assert!(rb.seek_to(300).is_err());
+        assert!(rb.eof);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_size_limits() -> Result<(), EtError> {
+        let data = [b'A'; 100];
+
+        let mut rb = ReadBuffer::from_reader(Box::new(Cursor::new(data)), Some(8))?;
+        rb.max_record_size = Some(32);
+        let e = rb
+            .next::<NewLine>(&mut 0)
+            .expect_err("the line is too long");
+        assert!(e.to_string().contains("max_record_size"));
+        assert_eq!(e.context.map(|c| c.byte), Some(0));
+
+        let mut rb = ReadBuffer::from_reader(Box::new(Cursor::new(data)), Some(8))?;
+        rb.max_buffer_size = Some(20);
+        let e = rb
+            .next::<NewLine>(&mut 0)
+            .expect_err("the buffer can't fit the line");
+        assert!(e.to_string().contains("max_buffer_size"));
+        assert!(rb.as_ref().len() <= 20);
+
+        // short records are fine even if the whole file's bigger than the limits
+        let data = b"1\n2\n3\n".repeat(20);
+        let mut rb = ReadBuffer::from_reader(Box::new(Cursor::new(data)), Some(4))?;
+        rb.max_buffer_size = Some(8);
+        rb.max_record_size = Some(2);
+        let mut n_lines = 0;
+        while rb.next::<NewLine>(&mut 0)?.is_some() {
+            n_lines += 1;
+        }
+        assert_eq!(n_lines, 60);
+        Ok(())
+    }
+
     #[test]
     fn test_next_into() -> Result<(), EtError> {
         let mut rb = ReadBuffer::from(&b"1\n2\n3"[..]);
@@ -1,110 +1,577 @@
 #[cfg(feature = "std")]
 use alloc::boxed::Box;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::vec::Vec;
 use core::convert::TryInto;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use std::io::{self, Chain, Cursor};
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use std::sync::mpsc::{sync_channel, Receiver};
+
+#[cfg(feature = "async")]
+use futures_io::AsyncRead;
 
 #[cfg(all(feature = "compression", feature = "std"))]
 use bzip2::read::BzDecoder;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use flate2::read::DeflateDecoder;
 #[cfg(feature = "std")]
 use flate2::read::MultiGzDecoder;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use flate2::Crc;
 #[cfg(all(feature = "compression", feature = "std"))]
 use xz2::read::XzDecoder;
 #[cfg(all(feature = "compression", feature = "std"))]
 use zstd::stream::read::Decoder as ZstdDecoder;
 
+#[cfg(feature = "brotli")]
+use brotli_decompressor::Decompressor as BrotliDecoder;
+#[cfg(feature = "lz4")]
+use lz4_flex::frame::FrameDecoder as Lz4Decoder;
+#[cfg(feature = "snappy")]
+use snap::read::FrameDecoder as SnappyDecoder;
+
+#[cfg(feature = "brotli")]
+use crate::buffer::BUFFER_SIZE;
+
 use crate::buffer::ReadBuffer;
 use crate::filetype::FileType;
-use crate::EtError;
+use crate::{ErrorKind, EtError};
+
+/// The gzip header of a BGZF block, up to and including `XLEN`
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const BGZF_HEADER_LEN: usize = 12;
+
+/// The most data a BGZF block can decompress to
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const BGZF_MAX_DATA_LEN: usize = 1 << 16;
+
+/// Find the total size of the BGZF block at the start of `header` from the `BC` extra field.
+///
+/// Returns `None` if this is some other kind of gzip member (or there isn't enough data to tell).
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+fn bgzf_block_size(header: &[u8]) -> Option<usize> {
+    // magic, compression method (deflate) and flags (FEXTRA only)
+    if header.len() < BGZF_HEADER_LEN || header[..4] != [0x1F, 0x8B, 0x08, 0x04] {
+        return None;
+    }
+    let xlen = usize::from(u16::from_le_bytes([header[10], header[11]]));
+    let mut extra = header.get(BGZF_HEADER_LEN..BGZF_HEADER_LEN + xlen)?;
+    while extra.len() >= 4 {
+        let slen = usize::from(u16::from_le_bytes([extra[2], extra[3]]));
+        if extra[..2] == *b"BC" && slen == 2 {
+            let bsize = u16::from_le_bytes([*extra.get(4)?, *extra.get(5)?]);
+            return Some(usize::from(bsize) + 1);
+        }
+        extra = extra.get(4 + slen..)?;
+    }
+    None
+}
+
+/// Decompress (and check) the data in a single BGZF block.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+fn inflate_bgzf_block(block: &[u8]) -> io::Result<Vec<u8>> {
+    let bad_block = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let xlen = usize::from(u16::from_le_bytes([block[10], block[11]]));
+    if block.len() < BGZF_HEADER_LEN + xlen + 8 {
+        return Err(bad_block("BGZF block is too short"));
+    }
+    let (data, trailer) =
+        block[BGZF_HEADER_LEN + xlen..].split_at(block.len() - BGZF_HEADER_LEN - xlen - 8);
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]) as usize;
+    if size > BGZF_MAX_DATA_LEN {
+        return Err(bad_block("BGZF block is too big"));
+    }
+
+    let mut out = Vec::with_capacity(size);
+    // one more byte than the block should have is enough to tell if it's the wrong size
+    let _ = DeflateDecoder::new(data)
+        .take(size as u64 + 1)
+        .read_to_end(&mut out)?;
+    if out.len() != size {
+        return Err(bad_block("BGZF block has the wrong decompressed size"));
+    }
+    let mut check = Crc::new();
+    check.update(&out);
+    if check.sum() != crc {
+        return Err(bad_block("BGZF block failed its CRC check"));
+    }
+    Ok(out)
+}
+
+/// Decompresses BGZF (blocked gzip, as used for BAM and tabix-indexed files) on the current
+/// rayon thread pool.
+///
+/// Blocks are read from the underlying stream on the calling thread (up to a fixed number
+/// ahead of what's been read out) and handed off to the pool; the decompressed blocks are
+/// returned in their original order. If a later gzip member isn't a BGZF block, the rest of
+/// the stream is decompressed on the calling thread instead.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+struct BgzfDecoder<'r> {
+    reader: Box<dyn Read + 'r>,
+    pending: VecDeque<Receiver<io::Result<Vec<u8>>>>,
+    max_pending: usize,
+    current: Vec<u8>,
+    pos: usize,
+    eof: bool,
+    /// The start of a gzip member that isn't a BGZF block (found after all the blocks)
+    plain_start: Option<Vec<u8>>,
+    /// The decoder for everything from `plain_start` on
+    plain: Option<MultiGzDecoder<Chain<Cursor<Vec<u8>>, Box<dyn Read + 'r>>>>,
+}
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+impl<'r> BgzfDecoder<'r> {
+    fn new(reader: Box<dyn Read + 'r>) -> Self {
+        BgzfDecoder {
+            reader,
+            pending: VecDeque::new(),
+            max_pending: 4 * rayon::current_num_threads(),
+            current: Vec::new(),
+            pos: 0,
+            eof: false,
+            plain_start: None,
+            plain: None,
+        }
+    }
+
+    /// Read the next complete block from the stream, if there is one.
+    ///
+    /// If the next gzip member isn't a BGZF block, the part of it that's been read is kept in
+    /// `plain_start` and this returns `None` like the stream's ended.
+    fn read_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut block = vec![0; BGZF_HEADER_LEN];
+        let mut read = 0;
+        while read < BGZF_HEADER_LEN {
+            match self.reader.read(&mut block[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => {
+                    block.truncate(read);
+                    self.plain_start = Some(block);
+                    return Ok(None);
+                }
+                n => read += n,
+            }
+        }
+        if block[..4] != [0x1F, 0x8B, 0x08, 0x04] {
+            self.plain_start = Some(block);
+            return Ok(None);
+        }
+        let xlen = usize::from(u16::from_le_bytes([block[10], block[11]]));
+        block.resize(BGZF_HEADER_LEN + xlen, 0);
+        self.reader.read_exact(&mut block[BGZF_HEADER_LEN..])?;
+        let size = match bgzf_block_size(&block) {
+            Some(size) => size,
+            None => {
+                self.plain_start = Some(block);
+                return Ok(None);
+            }
+        };
+        if size < block.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "BGZF block is too short",
+            ));
+        }
+        let start = block.len();
+        block.resize(size, 0);
+        self.reader.read_exact(&mut block[start..])?;
+        Ok(Some(block))
+    }
+}
+
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+impl<'r> Read for BgzfDecoder<'r> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.current.len() {
+                let amt = buf.len().min(self.current.len() - self.pos);
+                buf[..amt].copy_from_slice(&self.current[self.pos..self.pos + amt]);
+                self.pos += amt;
+                return Ok(amt);
+            }
+            if let Some(plain) = &mut self.plain {
+                return plain.read(buf);
+            }
+            while !self.eof && self.pending.len() < self.max_pending {
+                if let Some(block) = self.read_block()? {
+                    let (result, receiver) = sync_channel(1);
+                    rayon::spawn(move || drop(result.send(inflate_bgzf_block(&block))));
+                    self.pending.push_back(receiver);
+                } else {
+                    self.eof = true;
+                }
+            }
+            let receiver = match self.pending.pop_front() {
+                Some(r) => r,
+                None => match self.plain_start.take() {
+                    // every block's been read out, so carry on with the rest of the stream
+                    Some(start) => {
+                        let rest = core::mem::replace(&mut self.reader, Box::new(io::empty()));
+                        self.plain = Some(MultiGzDecoder::new(Cursor::new(start).chain(rest)));
+                        continue;
+                    }
+                    None => return Ok(0),
+                },
+            };
+            self.current = receiver
+                .recv()
+                .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "BGZF worker exited"))??;
+            self.pos = 0;
+        }
+    }
+}
+
+/// Wrap a gzipped stream in a decoder, decompressing it on the rayon thread pool if it's BGZF.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+fn gzip_decoder<'r>(reader: ReadBuffer<'r>) -> Box<dyn Read + 'r> {
+    if bgzf_block_size(reader.as_ref()).is_some() {
+        Box::new(BgzfDecoder::new(reader.into_box_read()))
+    } else {
+        Box::new(MultiGzDecoder::new(reader.into_box_read()))
+    }
+}
+
+/// Wrap a gzipped stream in a decoder.
+#[cfg(all(
+    feature = "std",
+    not(all(feature = "parallel", not(target_arch = "wasm32")))
+))]
+fn gzip_decoder<'r>(reader: ReadBuffer<'r>) -> Box<dyn Read + 'r> {
+    Box::new(MultiGzDecoder::new(reader.into_box_read()))
+}
 
 /// Decompress the contents of a `ReadBuffer` into a new `ReadBuffer` and return the type of compression.
 ///
 /// # Errors
 /// If reading fails or if the stream can't be decompressed, return `EtError`.
-#[cfg(all(feature = "compression", feature = "std"))]
 pub fn decompress<'r, B>(data: B) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError>
+where
+    B: TryInto<ReadBuffer<'r>>,
+    EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
+{
+    decompress_with_filename(data, None)
+}
+
+/// Work out how a file's compressed from its magic bytes or, for formats without any (i.e.
+/// Brotli), from the extension of `filename`.
+///
+/// # Errors
+/// If an error reading data from the `reader` occurs, an error will be returned.
+fn sniff_compression(rb: &mut ReadBuffer<'_>, filename: Option<&str>) -> Result<FileType, EtError> {
+    let extension = filename
+        .and_then(|f| f.rsplit_once('.'))
+        .map(|(_, ext)| ext);
+    if extension.is_some_and(|ext| ext.eq_ignore_ascii_case("br")) {
+        return Ok(FileType::Brotli);
+    }
+    rb.sniff_filetype()
+}
+
+/// Decompress the contents of a `ReadBuffer` into a new `ReadBuffer` and return the type of
+/// compression, using `filename` to detect compression formats that don't have magic bytes.
+///
+/// # Errors
+/// If reading fails or if the stream can't be decompressed, return `EtError`.
+#[cfg(feature = "std")]
+pub fn decompress_with_filename<'r, B>(
+    data: B,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError>
 where
     B: TryInto<ReadBuffer<'r>>,
     EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
 {
     let mut reader = data.try_into()?;
-    let file_type = reader.sniff_filetype()?;
-    Ok(match file_type {
-        FileType::Gzip => {
-            let gz_reader = MultiGzDecoder::new(reader.into_box_read());
-            (
-                ReadBuffer::from_reader(Box::new(gz_reader), None)?,
-                Some(file_type),
-            )
+    let file_type = sniff_compression(&mut reader, filename)?;
+    let decoder: Box<dyn Read + 'r> = match file_type {
+        FileType::Gzip => gzip_decoder(reader),
+        #[cfg(feature = "compression")]
+        FileType::Bzip => Box::new(BzDecoder::new(reader.into_box_read())),
+        #[cfg(feature = "compression")]
+        FileType::Lzma => Box::new(XzDecoder::new(reader.into_box_read())),
+        #[cfg(feature = "compression")]
+        FileType::Zstd => Box::new(ZstdDecoder::new(reader.into_box_read())?),
+        #[cfg(feature = "lz4")]
+        FileType::Lz4 => Box::new(Lz4Decoder::new(reader.into_box_read())),
+        #[cfg(feature = "snappy")]
+        FileType::Snappy => Box::new(SnappyDecoder::new(reader.into_box_read())),
+        #[cfg(feature = "brotli")]
+        FileType::Brotli => Box::new(BrotliDecoder::new(reader.into_box_read(), BUFFER_SIZE)),
+        #[cfg(not(feature = "compression"))]
+        FileType::Bzip | FileType::Lzma | FileType::Zstd => {
+            return Err(
+                EtError::new("entab was not compiled with support for compressed files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
         }
-        FileType::Bzip => {
-            let bz_reader = BzDecoder::new(reader.into_box_read());
-            (
-                ReadBuffer::from_reader(Box::new(bz_reader), None)?,
-                Some(file_type),
+        #[cfg(not(feature = "lz4"))]
+        FileType::Lz4 => {
+            return Err(
+                EtError::new("entab was not compiled with support for LZ4 files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
             )
         }
-        FileType::Lzma => {
-            let xz_reader = XzDecoder::new(reader.into_box_read());
-            (
-                ReadBuffer::from_reader(Box::new(xz_reader), None)?,
-                Some(file_type),
-            )
+        #[cfg(not(feature = "snappy"))]
+        FileType::Snappy => {
+            return Err(
+                EtError::new("entab was not compiled with support for Snappy files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
         }
-        FileType::Zstd => {
-            let zstd_reader = ZstdDecoder::new(reader.into_box_read())?;
-            (
-                ReadBuffer::from_reader(Box::new(zstd_reader), None)?,
-                Some(file_type),
-            )
+        #[cfg(not(feature = "brotli"))]
+        FileType::Brotli => {
+            return Err(
+                EtError::new("entab was not compiled with support for Brotli files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
         }
-        _ => (reader, None),
-    })
+        _ => return Ok((reader, None)),
+    };
+    Ok((ReadBuffer::from_reader(decoder, None)?, Some(file_type)))
 }
 
-/// Decompress a `Read` stream and returns the inferred file type.
+/// Decompress the contents of a `ReadBuffer` into a new `ReadBuffer` and return the type of
+/// compression, using `filename` to detect compression formats that don't have magic bytes.
 ///
 /// # Errors
 /// If reading fails or if the stream can't be decompressed, return `EtError`.
-#[cfg(all(not(feature = "compression"), feature = "std"))]
-pub fn decompress<'r, B>(data: B) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError>
+#[cfg(not(feature = "std"))]
+pub fn decompress_with_filename<'r, B>(
+    data: B,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError>
 where
     B: TryInto<ReadBuffer<'r>>,
     EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
 {
     let mut reader = data.try_into()?;
-    let file_type = reader.sniff_filetype()?;
+    let file_type = sniff_compression(&mut reader, filename)?;
     Ok(match file_type {
-        FileType::Gzip => {
-            let gz_reader = MultiGzDecoder::new(reader.into_box_read());
-            (
-                ReadBuffer::from_reader(Box::new(gz_reader), None)?,
-                Some(file_type),
+        FileType::Gzip
+        | FileType::Bzip
+        | FileType::Lzma
+        | FileType::Zstd
+        | FileType::Lz4
+        | FileType::Snappy
+        | FileType::Brotli => {
+            return Err(EtError::new(
+                "entab was not compiled with support for any compressed files",
             )
-        }
-        FileType::Bzip | FileType::Lzma | FileType::Zstd => {
-            return Err("entab was not compiled with support for compressed files".into());
+            .with_kind(ErrorKind::UnsupportedFormat));
         }
         _ => (reader, None),
     })
 }
 
-/// Decompress a `Read` stream and returns the inferred file type.
+/// Wraps a (blocking) stream in a decompressor
+#[cfg(feature = "async")]
+type DecoderFn<'r> = fn(Box<dyn Read + 'r>) -> Result<Box<dyn Read + 'r>, EtError>;
+
+/// Decompress an `AsyncRead` stream into a `ReadBuffer` and return the type of compression.
+///
+/// Like `decompress_with_filename`, `filename` is used to detect formats without magic bytes.
+///
+/// The LZ4, Snappy and Brotli decoders can't pick up where they left off if their input runs out
+/// partway through a read, so those streams are read completely into memory before decompressing.
 ///
 /// # Errors
 /// If reading fails or if the stream can't be decompressed, return `EtError`.
-#[cfg(all(not(feature = "std")))]
-pub fn decompress<'r, B>(data: B) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError>
+#[cfg(feature = "async")]
+pub async fn decompress_async<'r, R>(
+    reader: R,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError>
+where
+    R: AsyncRead + 'r,
+{
+    let reader = ReadBuffer::from_async_reader(Box::pin(reader), None).await?;
+    decompress_buffer_async(reader, filename).await
+}
+
+/// Decompress a `ReadBuffer` over an async source into a new `ReadBuffer` and return the type of
+/// compression.
+///
+/// LZ4, Snappy and Brotli input is read in completely first (see `decompress_async`); this is
+/// subject to the `max_buffer_size` of `reader`, which is also carried over to the new buffer.
+///
+/// # Errors
+/// If reading fails, if the stream can't be decompressed or if the compressed input is larger
+/// than `max_buffer_size`, return `EtError`.
+#[cfg(feature = "async")]
+async fn decompress_buffer_async<'r>(
+    mut reader: ReadBuffer<'r>,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Option<FileType>), EtError> {
+    // make sure there's enough to check the magic bytes
+    while reader.as_ref().len() < 8 && reader.fill().await? {}
+    let file_type = sniff_compression(&mut reader, filename)?;
+    let wrap: DecoderFn<'r> = match file_type {
+        FileType::Gzip => |r| Ok(Box::new(MultiGzDecoder::new(r))),
+        #[cfg(feature = "compression")]
+        FileType::Bzip => |r| Ok(Box::new(BzDecoder::new(r))),
+        #[cfg(feature = "compression")]
+        FileType::Lzma => |r| Ok(Box::new(XzDecoder::new(r))),
+        #[cfg(feature = "compression")]
+        FileType::Zstd => |r| Ok(Box::new(ZstdDecoder::new(r)?)),
+        #[cfg(feature = "lz4")]
+        FileType::Lz4 => |r| Ok(Box::new(Lz4Decoder::new(r))),
+        #[cfg(feature = "snappy")]
+        FileType::Snappy => |r| Ok(Box::new(SnappyDecoder::new(r))),
+        #[cfg(feature = "brotli")]
+        FileType::Brotli => |r| Ok(Box::new(BrotliDecoder::new(r, BUFFER_SIZE))),
+        #[cfg(not(feature = "compression"))]
+        FileType::Bzip | FileType::Lzma | FileType::Zstd => {
+            return Err(
+                EtError::new("entab was not compiled with support for compressed files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
+        }
+        #[cfg(not(feature = "lz4"))]
+        FileType::Lz4 => {
+            return Err(
+                EtError::new("entab was not compiled with support for LZ4 files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            )
+        }
+        #[cfg(not(feature = "snappy"))]
+        FileType::Snappy => {
+            return Err(
+                EtError::new("entab was not compiled with support for Snappy files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
+        }
+        #[cfg(not(feature = "brotli"))]
+        FileType::Brotli => {
+            return Err(
+                EtError::new("entab was not compiled with support for Brotli files")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
+        }
+        _ => return Ok((reader, None)),
+    };
+    if matches!(
+        file_type,
+        FileType::Lz4 | FileType::Snappy | FileType::Brotli
+    ) {
+        // these decoders lose their place if their input runs dry partway through a read, so
+        // read everything in first (`fill` stops at `max_buffer_size`)
+        while reader.fill().await? {}
+    }
+    let max_buffer_size = reader.max_buffer_size;
+    let decoder = reader.into_async_decoder(wrap)?;
+    let mut decompressed = ReadBuffer::from_async_reader(Box::pin(decoder), None).await?;
+    decompressed.max_buffer_size = max_buffer_size;
+    Ok((decompressed, Some(file_type)))
+}
+
+/// The most layers of compression `decompress_all` will remove before giving up (in case the
+/// data's e.g. a decompression bomb)
+pub const MAX_DECOMPRESSION_DEPTH: usize = 4;
+
+/// The error returned when data's nested deeper than `MAX_DECOMPRESSION_DEPTH`
+fn too_many_layers() -> EtError {
+    let err: EtError = format!(
+        "Data has more than {} layers of compression",
+        MAX_DECOMPRESSION_DEPTH
+    )
+    .into();
+    err.with_kind(ErrorKind::LimitExceeded)
+}
+
+/// Strip the extension off of `filename` if it's the one used for `file_type` (e.g. so
+/// `test.csv.br.gz` becomes `test.csv.br` after the gzip layer is removed).
+fn inner_filename(filename: &str, file_type: FileType) -> &str {
+    match filename.rsplit_once('.') {
+        Some((stem, ext)) if FileType::from_extension(&ext.to_ascii_lowercase()) == [file_type] => {
+            stem
+        }
+        _ => filename,
+    }
+}
+
+/// Decompress the contents of a `ReadBuffer` until there are no more layers of compression
+/// (e.g. for a file that's been gzipped twice) and return the types of compression removed,
+/// outermost first.
+///
+/// `filename` is used to detect compression formats that don't have magic bytes.
+///
+/// # Errors
+/// If reading fails, if the stream can't be decompressed or if there are more than
+/// `MAX_DECOMPRESSION_DEPTH` layers of compression, return `EtError`.
+pub fn decompress_all<'r, B>(
+    data: B,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Vec<FileType>), EtError>
 where
     B: TryInto<ReadBuffer<'r>>,
     EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
 {
-    let mut reader = data.try_into()?;
-    let file_type = reader.sniff_filetype()?;
-    Ok(match file_type {
-        FileType::Gzip | FileType::Bzip | FileType::Lzma | FileType::Zstd => {
-            return Err("entab was not compiled with support for any compressed files".into());
+    let mut filename = filename;
+    let (mut reader, mut file_type) = decompress_with_filename(data, filename)?;
+    let mut compression = Vec::new();
+    while let Some(ft) = file_type {
+        if compression.len() == MAX_DECOMPRESSION_DEPTH {
+            return Err(too_many_layers());
         }
-        _ => (reader, None),
-    })
+        compression.push(ft);
+        filename = filename.map(|f| inner_filename(f, ft));
+        let (next_reader, next_file_type) =
+            decompress_with_filename::<ReadBuffer<'r>>(reader, filename)?;
+        reader = next_reader;
+        file_type = next_file_type;
+    }
+    Ok((reader, compression))
+}
+
+/// Decompress an `AsyncRead` stream until there are no more layers of compression and return
+/// the types of compression removed, outermost first (see `decompress_all`).
+///
+/// # Errors
+/// If reading fails, if the stream can't be decompressed or if there are more than
+/// `MAX_DECOMPRESSION_DEPTH` layers of compression, return `EtError`.
+#[cfg(feature = "async")]
+pub async fn decompress_all_async<'r, R>(
+    reader: R,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Vec<FileType>), EtError>
+where
+    R: AsyncRead + 'r,
+{
+    let reader = ReadBuffer::from_async_reader(Box::pin(reader), None).await?;
+    decompress_all_buffer_async(reader, filename).await
+}
+
+/// Decompress every layer of compression from a `ReadBuffer` over an async source (see
+/// `decompress_all_async`), keeping its `max_buffer_size`.
+///
+/// # Errors
+/// If reading fails, if the stream can't be decompressed or if there are more than
+/// `MAX_DECOMPRESSION_DEPTH` layers of compression, return `EtError`.
+#[cfg(feature = "async")]
+pub(crate) async fn decompress_all_buffer_async<'r>(
+    reader: ReadBuffer<'r>,
+    filename: Option<&str>,
+) -> Result<(ReadBuffer<'r>, Vec<FileType>), EtError> {
+    let mut filename = filename;
+    let (mut reader, mut file_type) = decompress_buffer_async(reader, filename).await?;
+    let mut compression = Vec::new();
+    while let Some(ft) = file_type {
+        if compression.len() == MAX_DECOMPRESSION_DEPTH {
+            return Err(too_many_layers());
+        }
+        compression.push(ft);
+        filename = filename.map(|f| inner_filename(f, ft));
+        let (next_reader, next_file_type) = decompress_buffer_async(reader, filename).await?;
+        reader = next_reader;
+        file_type = next_file_type;
+    }
+    Ok((reader, compression))
 }
 
 #[cfg(all(test, feature = "compression", feature = "std"))]
@@ -124,6 +591,138 @@ mod tests {
         Ok(())
     }
 
+    /// Compress `data` into BGZF blocks holding up to `block_size` bytes each.
+    fn bgzf(data: &[u8], block_size: usize) -> Vec<u8> {
+        use flate2::write::DeflateEncoder;
+        use flate2::{Compression, Crc};
+        use std::io::Write;
+
+        let mut out = Vec::new();
+        // the last chunk's empty like the BGZF end-of-file marker block
+        for chunk in data.chunks(block_size).chain([&b""[..]]) {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(chunk).unwrap();
+            let cdata = encoder.finish().unwrap();
+            let bsize = (cdata.len() + 25) as u16;
+            out.extend([
+                0x1F, 0x8B, 8, 4, 0, 0, 0, 0, 0, 0xFF, 6, 0, b'B', b'C', 2, 0,
+            ]);
+            out.extend(bsize.to_le_bytes());
+            out.extend(&cdata);
+            let mut crc = Crc::new();
+            crc.update(chunk);
+            out.extend(crc.sum().to_le_bytes());
+            out.extend((chunk.len() as u32).to_le_bytes());
+        }
+        out
+    }
+
+    #[test]
+    fn test_read_bgzf() -> Result<(), EtError> {
+        let data: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let compressed = bgzf(&data, 65_280);
+        // the second block should start right after the first
+        #[cfg(feature = "parallel")]
+        {
+            let size = bgzf_block_size(&compressed).unwrap();
+            assert_eq!(bgzf_block_size(&compressed[size..]), Some(size));
+        }
+
+        let (mut rb, compression) = decompress(&compressed[..])?;
+        assert_eq!(compression, Some(FileType::Gzip));
+        rb.read_to_end()?;
+        let mut expected = Vec::new();
+        let _ = MultiGzDecoder::new(&compressed[..]).read_to_end(&mut expected)?;
+        assert_eq!(expected, data);
+        assert!(rb.as_ref() == &data[..]);
+
+        // the BAM test file's also BGZF
+        #[cfg(feature = "parallel")]
+        assert!(bgzf_block_size(&std::fs::read("tests/data/test.bam")?).is_some());
+
+        // a bad CRC should still be caught
+        let mut bad = compressed.clone();
+        let n = bad.len();
+        bad[n - 36] ^= 0xFF;
+        let (mut rb, _) = decompress(&bad[..])?;
+        assert!(rb.read_to_end().is_err());
+
+        // and so should a block claiming to decompress to more than a block can hold
+        #[cfg(feature = "parallel")]
+        {
+            let mut bad = compressed.clone();
+            let size = bgzf_block_size(&compressed).unwrap();
+            bad[size - 4..size].copy_from_slice(&(1u32 << 31).to_le_bytes());
+            assert!(inflate_bgzf_block(&bad[..size]).is_err());
+            assert!(decompress(&bad[..])
+                .and_then(|(mut rb, _)| rb.read_to_end())
+                .is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_plain_gzip() -> Result<(), EtError> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"id,value\na,1\n")?;
+        let compressed = encoder.finish()?;
+        #[cfg(feature = "parallel")]
+        assert_eq!(bgzf_block_size(&compressed), None);
+        let (mut rb, compression) = decompress(&compressed[..])?;
+        assert_eq!(compression, Some(FileType::Gzip));
+        rb.read_to_end()?;
+        assert_eq!(rb.as_ref(), b"id,value\na,1\n");
+
+        // BGZF blocks followed by a plain gzip member
+        let mut mixed = bgzf(b"id,value\n", 65_280);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"a,1\n")?;
+        mixed.extend(encoder.finish()?);
+        let (mut rb, _) = decompress(&mixed[..])?;
+        rb.read_to_end()?;
+        assert_eq!(rb.as_ref(), b"id,value\na,1\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress_all() -> Result<(), EtError> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut data = b"id,value\na,1\n".to_vec();
+        for n_layers in 0..=MAX_DECOMPRESSION_DEPTH + 1 {
+            let result = decompress_all(&data[..], Some("test.csv.gz.gz"));
+            if n_layers > MAX_DECOMPRESSION_DEPTH {
+                let e = match result {
+                    Ok(_) => panic!("there are too many layers of compression"),
+                    Err(e) => e,
+                };
+                assert!(e.to_string().contains("layers of compression"));
+                break;
+            }
+            let (mut rb, compression) = result?;
+            assert_eq!(compression, vec![FileType::Gzip; n_layers]);
+            rb.read_to_end()?;
+            assert_eq!(rb.as_ref(), b"id,value\na,1\n");
+            drop(rb);
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&data)?;
+            data = encoder.finish()?;
+        }
+        assert_eq!(inner_filename("test.csv.gz", FileType::Gzip), "test.csv");
+        assert_eq!(inner_filename("test.csv.GZ", FileType::Gzip), "test.csv");
+        assert_eq!(inner_filename("test.csv.gz", FileType::Zstd), "test.csv.gz");
+        assert_eq!(inner_filename("test", FileType::Gzip), "test");
+        Ok(())
+    }
+
     #[test]
     fn test_read_bzip2() -> Result<(), EtError> {
         let f = File::open("tests/data/test.csv.bz2")?;
@@ -153,4 +752,118 @@ mod tests {
         assert_eq!(rb.as_ref().len(), 48);
         Ok(())
     }
+
+    #[test]
+    fn test_read_lz4_snappy_brotli() -> Result<(), EtError> {
+        for (path, file_type, compiled) in [
+            (
+                "tests/data/test.csv.lz4",
+                FileType::Lz4,
+                cfg!(feature = "lz4"),
+            ),
+            (
+                "tests/data/test.csv.sz",
+                FileType::Snappy,
+                cfg!(feature = "snappy"),
+            ),
+            (
+                "tests/data/test.csv.br",
+                FileType::Brotli,
+                cfg!(feature = "brotli"),
+            ),
+        ] {
+            let f = File::open(path)?;
+            match decompress_with_filename(f, Some(path)) {
+                Ok((mut rb, compression)) => {
+                    assert!(compiled);
+                    assert_eq!(compression, Some(file_type));
+                    rb.read_to_end()?;
+                    assert_eq!(rb.as_ref().len(), 48);
+                }
+                Err(e) => {
+                    assert!(!compiled);
+                    assert!(e.to_string().contains("not compiled with support"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_decompress_async() -> Result<(), EtError> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use futures_executor::block_on;
+        use futures_util::io::Cursor;
+        use std::io::Write;
+
+        for (path, file_type, compiled) in [
+            ("tests/data/test.csv.bz2", FileType::Bzip, true),
+            ("tests/data/test.csv.xz", FileType::Lzma, true),
+            ("tests/data/test.csv.zst", FileType::Zstd, true),
+            (
+                "tests/data/test.csv.lz4",
+                FileType::Lz4,
+                cfg!(feature = "lz4"),
+            ),
+            (
+                "tests/data/test.csv.sz",
+                FileType::Snappy,
+                cfg!(feature = "snappy"),
+            ),
+            (
+                "tests/data/test.csv.br",
+                FileType::Brotli,
+                cfg!(feature = "brotli"),
+            ),
+        ] {
+            if !compiled {
+                continue;
+            }
+            let data = std::fs::read(path)?;
+            let (mut rb, compression) = block_on(decompress_async(Cursor::new(data), Some(path)))?;
+            assert_eq!(compression, Some(file_type));
+            while block_on(rb.fill())? {}
+            assert_eq!(rb.as_ref().len(), 48);
+        }
+
+        // the whole compressed stream has to fit in the buffer for these
+        for path in [
+            #[cfg(feature = "lz4")]
+            "tests/data/test.csv.lz4",
+            #[cfg(feature = "snappy")]
+            "tests/data/test.csv.sz",
+            #[cfg(feature = "brotli")]
+            "tests/data/test.csv.br",
+        ] {
+            let data = std::fs::read(path)?;
+            let mut rb = block_on(ReadBuffer::from_async_reader(
+                Box::pin(Cursor::new(data)),
+                None,
+            ))?;
+            rb.max_buffer_size = Some(32);
+            let e = block_on(decompress_all_buffer_async(rb, Some(path)))
+                .map(|_| ())
+                .unwrap_err();
+            assert_eq!(e.kind, ErrorKind::LimitExceeded);
+        }
+
+        let data = std::fs::read("tests/data/test.bam")?;
+        let (mut rb, compression) = block_on(decompress_async(Cursor::new(data), None))?;
+        assert_eq!(compression, Some(FileType::Gzip));
+        while block_on(rb.fill())? {}
+        let x: &[u8] = rb.next(&mut 1392)?.unwrap();
+        assert_eq!(x.len(), 1392);
+
+        // a zstd file that's been gzipped on top
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&std::fs::read("tests/data/test.csv.zst")?)?;
+        let data = encoder.finish()?;
+        let (mut rb, compression) = block_on(decompress_all_async(Cursor::new(data), None))?;
+        assert_eq!(compression, vec![FileType::Gzip, FileType::Zstd]);
+        while block_on(rb.fill())? {}
+        assert_eq!(rb.as_ref().len(), 48);
+        Ok(())
+    }
 }
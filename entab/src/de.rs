@@ -0,0 +1,435 @@
+use alloc::borrow::Cow;
+use alloc::collections::btree_map;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::iter::Zip;
+use core::marker::PhantomData;
+use core::slice;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor,
+};
+use serde::{forward_to_deserialize_any, Deserialize};
+
+use crate::readers::RecordReader;
+use crate::record::Value;
+use crate::EtError;
+
+/// Deserialize a single `Value` into a `T`
+///
+/// # Errors
+/// If the value can't be turned into a `T`.
+pub fn from_value<'de, T: Deserialize<'de>>(value: Value<'de>) -> Result<T, EtError> {
+    T::deserialize(ValueDeserializer::new(value))
+}
+
+/// Deserialize a record into a `T`, matching up the fields of `T` with `headers`
+///
+/// Structs (and maps) are filled by header name, ignoring any extra columns; tuples (and
+/// sequences) are filled in column order.
+///
+/// # Errors
+/// If a field is missing or a value can't be turned into the type of its field.
+pub fn from_record<'de, T: Deserialize<'de>>(
+    headers: &[String],
+    values: Vec<Value<'de>>,
+) -> Result<T, EtError> {
+    T::deserialize(RecordDeserializer::new(headers, values))
+}
+
+/// A serde `Deserializer` for a single `Value`
+#[derive(Debug)]
+pub struct ValueDeserializer<'de> {
+    value: Value<'de>,
+}
+
+impl<'de> ValueDeserializer<'de> {
+    /// Create a new `ValueDeserializer`
+    #[must_use]
+    pub fn new(value: Value<'de>) -> Self {
+        ValueDeserializer { value }
+    }
+}
+
+impl<'de> IntoDeserializer<'de, EtError> for Value<'de> {
+    type Deserializer = ValueDeserializer<'de>;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        ValueDeserializer::new(self)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer<'de> {
+    type Error = EtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EtError> {
+        match self.value {
+            Value::Null => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            // chrono parses this back into a `NaiveDateTime`
+            Value::Datetime(d) => {
+                visitor.visit_string(d.format("%Y-%m-%dT%H:%M:%S%.f").to_string())
+            }
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::String(Cow::Borrowed(s)) => visitor.visit_borrowed_str(s),
+            Value::String(Cow::Owned(s)) => visitor.visit_string(s),
+            Value::List(l) => visitor.visit_seq(ValueSeqAccess {
+                values: l.into_iter(),
+            }),
+            Value::Record(r) => visitor.visit_map(ValueMapAccess {
+                entries: r.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EtError> {
+        match self.value {
+            Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EtError> {
+        match self.value {
+            Value::String(Cow::Borrowed(s)) => visitor.visit_borrowed_bytes(s.as_bytes()),
+            Value::String(Cow::Owned(s)) => visitor.visit_byte_buf(s.into_bytes()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EtError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, EtError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, EtError> {
+        // only unit variants can be stored in a `Value`
+        match self.value {
+            Value::String(s) => visitor.visit_enum(s.into_owned().into_deserializer()),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct ValueSeqAccess<'de> {
+    values: vec::IntoIter<Value<'de>>,
+}
+
+impl<'de> SeqAccess<'de> for ValueSeqAccess<'de> {
+    type Error = EtError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, EtError> {
+        self.values
+            .next()
+            .map(|v| seed.deserialize(ValueDeserializer::new(v)))
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct ValueMapAccess<'de> {
+    entries: btree_map::IntoIter<String, Value<'de>>,
+    value: Option<Value<'de>>,
+}
+
+impl<'de> MapAccess<'de> for ValueMapAccess<'de> {
+    type Error = EtError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, EtError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, EtError> {
+        let value = self.value.take().ok_or("Value requested before key")?;
+        seed.deserialize(ValueDeserializer::new(value))
+    }
+}
+
+/// A serde `Deserializer` for a record from a `RecordReader`
+#[derive(Debug)]
+pub struct RecordDeserializer<'h, 'de> {
+    headers: &'h [String],
+    values: Vec<Value<'de>>,
+}
+
+impl<'h, 'de> RecordDeserializer<'h, 'de> {
+    /// Create a new `RecordDeserializer` for `values`, which are named by `headers`
+    #[must_use]
+    pub fn new(headers: &'h [String], values: Vec<Value<'de>>) -> Self {
+        RecordDeserializer { headers, values }
+    }
+}
+
+impl<'h, 'de> de::Deserializer<'de> for RecordDeserializer<'h, 'de> {
+    type Error = EtError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EtError> {
+        visitor.visit_map(RecordMapAccess {
+            fields: self.headers.iter().zip(self.values),
+            value: None,
+        })
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, EtError> {
+        visitor.visit_seq(ValueSeqAccess {
+            values: self.values.into_iter(),
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, EtError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, EtError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, EtError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct map struct enum identifier ignored_any
+    }
+}
+
+struct RecordMapAccess<'h, 'de> {
+    fields: Zip<slice::Iter<'h, String>, vec::IntoIter<Value<'de>>>,
+    value: Option<(&'h str, Value<'de>)>,
+}
+
+impl<'h, 'de> MapAccess<'de> for RecordMapAccess<'h, 'de> {
+    type Error = EtError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, EtError> {
+        match self.fields.next() {
+            Some((header, value)) => {
+                self.value = Some((header, value));
+                seed.deserialize(header.as_str().into_deserializer())
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, EtError> {
+        let (header, value) = self.value.take().ok_or("Value requested before key")?;
+        seed.deserialize(ValueDeserializer::new(value))
+            .map_err(|e| format!("Could not read column {}: {}", header, e.msg).into())
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+/// An iterator over the records of a `RecordReader`, deserialized into `T`s
+///
+/// Created by `RecordReader::deserialize`.
+#[derive(Debug)]
+pub struct DeserializeRecords<'a, 'r, T> {
+    reader: &'a mut (dyn RecordReader + 'r),
+    headers: Vec<String>,
+    record: u64,
+    marker: PhantomData<T>,
+}
+
+impl<'a, 'r, T: DeserializeOwned> Iterator for DeserializeRecords<'a, 'r, T> {
+    type Item = Result<T, EtError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let values = match self.reader.next_record() {
+            Ok(Some(values)) => values,
+            Ok(None) => return None,
+            Err(e) => return Some(Err(e)),
+        };
+        self.record += 1;
+        let record = self.record;
+        Some(
+            from_record(&self.headers, values)
+                .map_err(|e| format!("Record {}: {}", record, e.msg).into()),
+        )
+    }
+}
+
+impl<'r> dyn RecordReader + 'r {
+    /// Iterate over the remaining records, deserializing each one into a `T`
+    ///
+    /// The fields of `T` are matched up against the `headers` of this reader; see `from_record`.
+    pub fn deserialize<T: DeserializeOwned>(&mut self) -> DeserializeRecords<'_, 'r, T> {
+        DeserializeRecords {
+            headers: self.headers(),
+            reader: self,
+            record: 0,
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::collections::BTreeMap;
+
+    use chrono::NaiveDate;
+    use serde::Deserialize;
+
+    use crate::readers::get_reader;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct FastqRow {
+        id: String,
+        quality: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct SamRow<'a> {
+        query_name: &'a str,
+        flag: u16,
+        pos: Option<u64>,
+    }
+
+    #[test]
+    fn test_deserialize_reader() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/test.fastq");
+        let (mut reader, _) = get_reader(data, None, None)?;
+        let rows = reader
+            .deserialize::<FastqRow>()
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(rows.len(), 2500);
+        assert!(rows[0].id.starts_with("SRR062634.1 "));
+
+        let (mut reader, _) = get_reader(data, None, None)?;
+        let mut rows = reader.deserialize::<(String, String, String)>();
+        let (id, sequence, quality) = rows.next().unwrap()?;
+        assert!(id.starts_with("SRR062634.1 "));
+        assert_eq!(sequence.len(), quality.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_borrowed() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/test.sam");
+        let (mut reader, _) = get_reader(data, None, None)?;
+        let headers = reader.headers();
+        let values = reader.next_record()?.unwrap();
+        let row: SamRow = from_record(&headers, values)?;
+        assert_eq!(
+            row,
+            SamRow {
+                query_name: "SRR062634.1",
+                flag: 4,
+                pos: None,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct Row {
+            id: u64,
+        }
+
+        let data: &[u8] = b">seq1\nACGT\n";
+        let (mut reader, _) = get_reader(data, None, None).unwrap();
+        let err = reader.deserialize::<Row>().next().unwrap().unwrap_err();
+        assert_eq!(
+            err.msg,
+            "Record 1: Could not read column id: invalid type: string \"seq1\", expected u64"
+        );
+
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct MissingRow {
+            name: String,
+        }
+        let (mut reader, _) = get_reader(data, None, None).unwrap();
+        let err = reader
+            .deserialize::<MissingRow>()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(err.msg, "Record 1: missing field `name`");
+    }
+
+    #[test]
+    fn test_deserialize_values() -> Result<(), EtError> {
+        let date = NaiveDate::from_ymd_opt(2021, 3, 4)
+            .unwrap()
+            .and_hms_opt(5, 6, 7)
+            .unwrap();
+        assert_eq!(
+            from_value::<chrono::NaiveDateTime>(Value::Datetime(date))?,
+            date
+        );
+        assert_eq!(from_value::<f64>(Value::Integer(2))?, 2.);
+        assert!(from_value::<u8>(Value::Integer(-1)).is_err());
+        assert_eq!(
+            from_value::<Vec<String>>(Value::List(vec!["a".into(), "b".into()]))?,
+            ["a", "b"]
+        );
+        let mut record = BTreeMap::new();
+        drop(record.insert("x".to_string(), Value::Integer(1)));
+        assert_eq!(
+            from_value::<BTreeMap<String, i64>>(Value::Record(record))?,
+            [("x".to_string(), 1)].iter().cloned().collect()
+        );
+        Ok(())
+    }
+}
@@ -6,14 +6,19 @@ use alloc::string::{FromUtf8Error, String, ToString};
 use alloc::vec::Vec;
 use core::convert::Infallible;
 use core::fmt;
-use core::num::{ParseFloatError, ParseIntError, TryFromIntError};
+use core::num::{NonZeroU64, ParseFloatError, ParseIntError, TryFromIntError};
 #[cfg(feature = "std")]
 use std::error::Error;
 #[cfg(feature = "std")]
 use std::io::Error as IoError;
 
+use memchr::{memchr, memrchr};
+
 use crate::buffer::ReadBuffer;
 
+/// The most bytes of a line to either side of an error to show in its context
+const LINE_CONTEXT: usize = 80;
+
 /// Extra information about the error to help identify where in the file being
 /// parsed the error occurred.
 #[derive(Clone, Debug, Default)]
@@ -30,6 +35,66 @@ pub struct EtErrorContext {
     pub context: Vec<u8>,
     /// The position in `context` where the error occured
     pub context_pos: usize,
+    /// For text, the line the error occured on (counting from 1)
+    ///
+    /// When this is set, `context` is the text of the line (or as much of it as was available).
+    pub line: Option<NonZeroU64>,
+    /// For text, the column the error occured at (counting bytes from 1)
+    pub column: Option<NonZeroU64>,
+}
+
+/// The broad category of an `EtError` (so callers can handle e.g. I/O errors differently from
+/// malformed files without matching on the message).
+///
+/// More kinds may be added later, so matches on this need a wildcard arm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// Reading from (or writing to) the underlying source failed
+    Io,
+    /// The file type, version or a feature it uses isn't supported
+    UnsupportedFormat,
+    /// The data doesn't start with the magic bytes the parser expected
+    InvalidMagic,
+    /// The data ended before a record (or header) was complete
+    Truncated,
+    /// A value in the data couldn't be parsed or doesn't make sense
+    InvalidValue,
+    /// Compressed data couldn't be decompressed
+    Decompression,
+    /// Params were passed to a reader that it doesn't use
+    UnusedParams,
+    /// A param passed to a reader has the wrong type or an invalid value
+    InvalidParam,
+    /// Reading the data would take more memory (or nesting) than allowed
+    LimitExceeded,
+    /// Anything else
+    Other,
+}
+
+impl ErrorKind {
+    /// The name of the kind (e.g. for the error classes in the bindings)
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Io => "Io",
+            ErrorKind::UnsupportedFormat => "UnsupportedFormat",
+            ErrorKind::InvalidMagic => "InvalidMagic",
+            ErrorKind::Truncated => "Truncated",
+            ErrorKind::InvalidValue => "InvalidValue",
+            ErrorKind::Decompression => "Decompression",
+            ErrorKind::UnusedParams => "UnusedParams",
+            ErrorKind::InvalidParam => "InvalidParam",
+            ErrorKind::LimitExceeded => "LimitExceeded",
+            ErrorKind::Other => "Other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
 }
 
 #[derive(Debug)]
@@ -37,10 +102,18 @@ pub struct EtErrorContext {
 pub struct EtError {
     /// A succinct message describing the error
     pub msg: Cow<'static, str>,
+    /// What sort of error this is
+    pub kind: ErrorKind,
     /// Extra context, if available
     pub context: Option<EtErrorContext>,
     /// If the error could be recovered from by pulling more data into the buffer.
     pub incomplete: bool,
+    /// If the error is from an async source that doesn't have more data ready yet.
+    pub pending: bool,
+    /// If the parser needs the buffer moved to `seek_offset` before it can continue (these are
+    /// kept apart from each other rather than in an `Option` to keep `EtError` small).
+    seeking: bool,
+    seek_offset: u64,
     #[cfg(feature = "std")]
     orig_err: Option<Box<dyn Error>>,
 }
@@ -51,30 +124,69 @@ impl EtError {
     pub fn new(msg: &'static str) -> Self {
         EtError {
             msg: Cow::Borrowed(msg),
+            kind: ErrorKind::Other,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: None,
         }
     }
 
+    /// Sets what kind of error this is.
+    #[must_use]
+    pub fn with_kind(mut self, kind: ErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     /// Marks the `EtError` as "incomplete"; a sentinel returned from a parser to indicate that
     /// more data needs to be parsed.
+    ///
+    /// If there isn't more data, the error's returned as `ErrorKind::Truncated`.
     #[must_use]
     pub fn incomplete(mut self) -> Self {
         self.incomplete = true;
+        self.kind = ErrorKind::Truncated;
+        self
+    }
+
+    /// Marks the `EtError` as "pending"; returned when an async source needs to be polled for
+    /// more data before parsing can continue.
+    #[must_use]
+    pub fn pending(mut self) -> Self {
+        self.pending = true;
         self
     }
 
+    /// Marks the `EtError` as a request to seek; a sentinel returned from a parser to have the
+    /// buffer jump to the absolute byte `offset` (see `ReadBuffer::seek_to`) and parse again.
+    ///
+    /// Sources that can't seek are read in up to `offset` without dropping the data for the
+    /// record being parsed, so it's still possible to jump back into it afterwards.
+    #[must_use]
+    pub fn seek(mut self, offset: u64) -> Self {
+        self.seeking = true;
+        self.seek_offset = offset;
+        self
+    }
+
+    /// The byte offset the parser asked the buffer to move to (see `seek`), if any.
+    #[must_use]
+    pub fn seek_offset(&self) -> Option<u64> {
+        if self.seeking {
+            Some(self.seek_offset)
+        } else {
+            None
+        }
+    }
+
     /// Fill the positional error information from a `ReadBuffer` directly.
     #[must_use]
     pub fn add_context_from_readbuffer(self, buffer: &ReadBuffer) -> Self {
-        self.add_context(
-            buffer.as_ref(),
-            buffer.consumed,
-            buffer.record_pos,
-            buffer.reader_pos,
-        )
+        self.add_context_at(buffer, buffer.consumed)
     }
 
     /// Fill the positional error information on the error.
@@ -107,15 +219,91 @@ impl EtError {
             byte: reader_pos + consumed as u64,
             context,
             context_pos,
+            line: None,
+            column: None,
         });
         self
     }
+
+    /// Fill the positional error information for byte `pos` of `buffer`.
+    ///
+    /// If the data around `pos` is text, the context is the line it's on and the line and column
+    /// are filled in too.
+    #[must_use]
+    pub(crate) fn add_context_at(self, buffer: &ReadBuffer, pos: usize) -> Self {
+        let position = buffer.line_and_column(pos);
+        self.add_line_context(
+            buffer.as_ref(),
+            pos,
+            buffer.record_pos,
+            buffer.reader_pos,
+            position,
+        )
+    }
+
+    /// Fill the positional error information like `add_context`, but if `data` is text around
+    /// `consumed` the context is the line it's on (at the line and column in `position`).
+    #[must_use]
+    pub(crate) fn add_line_context(
+        self,
+        data: &[u8],
+        consumed: usize,
+        record_pos: u64,
+        reader_pos: u64,
+        position: Option<(u64, u64)>,
+    ) -> Self {
+        let pos = consumed.min(data.len());
+        let mut err = self.add_context(data, pos, record_pos, reader_pos);
+        let (context, (line, column)) = match (&mut err.context, position) {
+            (Some(context), Some(position)) => (context, position),
+            _ => return err,
+        };
+        let min_start = pos.saturating_sub(LINE_CONTEXT);
+        let start = memrchr(b'\n', &data[min_start..pos]).map_or(min_start, |p| min_start + p + 1);
+        let max_end = (pos + LINE_CONTEXT).min(data.len());
+        let mut end = memchr(b'\n', &data[pos..max_end]).map_or(max_end, |p| pos + p);
+        if end > pos && data[end - 1] == b'\r' {
+            end -= 1;
+        }
+        let text = &data[start..end];
+        if text.iter().all(|c| *c == b'\t' || (*c > 31 && *c != 127)) {
+            context.context = text.to_vec();
+            context.context_pos = pos - start;
+            context.line = NonZeroU64::new(line);
+            context.column = NonZeroU64::new(column);
+        }
+        err
+    }
+}
+
+/// Display the text line an error's on with a caret under where it occured.
+fn fmt_line(
+    f: &mut fmt::Formatter<'_>,
+    context: &EtErrorContext,
+    line: u64,
+    column: u64,
+) -> fmt::Result {
+    writeln!(
+        f,
+        "line {}, column {} (byte {}):",
+        line, column, context.byte
+    )?;
+    writeln!(f, "{}", String::from_utf8_lossy(&context.context))?;
+    // tabs are kept so the caret lines up with the text however wide they're displayed
+    let indent: String = String::from_utf8_lossy(&context.context[..context.context_pos])
+        .chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    writeln!(f, "{}^", indent)
 }
 
 impl fmt::Display for EtError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "{}", self.msg)?;
         if let Some(context) = &self.context {
+            if let (Some(line), Some(column)) = (context.line, context.column) {
+                return fmt_line(f, context, line.get(), column.get());
+            }
             for c in &context.context {
                 write!(f, "{:X}", c)?;
             }
@@ -153,6 +341,15 @@ impl Error for EtError {
     }
 }
 
+#[cfg(not(feature = "std"))]
+impl serde::de::StdError for EtError {}
+
+impl serde::de::Error for EtError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        msg.to_string().into()
+    }
+}
+
 impl From<Infallible> for EtError {
     fn from(_error: Infallible) -> Self {
         panic!("Infallible things shouldn't panic!")
@@ -163,8 +360,12 @@ impl From<&'static str> for EtError {
     fn from(error: &'static str) -> Self {
         EtError {
             msg: Cow::Borrowed(error),
+            kind: ErrorKind::Other,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: None,
         }
@@ -175,8 +376,12 @@ impl From<String> for EtError {
     fn from(msg: String) -> Self {
         EtError {
             msg: Cow::Owned(msg),
+            kind: ErrorKind::Other,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: None,
         }
@@ -187,8 +392,12 @@ impl From<FromUtf8Error> for EtError {
     fn from(error: FromUtf8Error) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::InvalidValue,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -200,8 +409,17 @@ impl From<IoError> for EtError {
     fn from(error: IoError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            // decoders report corrupt data as `InvalidData`
+            kind: if error.kind() == std::io::ErrorKind::InvalidData {
+                ErrorKind::Decompression
+            } else {
+                ErrorKind::Io
+            },
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -212,8 +430,12 @@ impl From<Utf8Error> for EtError {
     fn from(error: Utf8Error) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::InvalidValue,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -224,8 +446,12 @@ impl From<ParseFloatError> for EtError {
     fn from(error: ParseFloatError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::InvalidValue,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -236,8 +462,12 @@ impl From<ParseIntError> for EtError {
     fn from(error: ParseIntError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::InvalidValue,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
@@ -248,20 +478,76 @@ impl From<TryFromIntError> for EtError {
     fn from(error: TryFromIntError) -> Self {
         EtError {
             msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::InvalidValue,
             context: None,
             incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
             #[cfg(feature = "std")]
             orig_err: Some(Box::new(error)),
         }
     }
 }
 
+#[cfg(feature = "arrow")]
+impl From<arrow::error::ArrowError> for EtError {
+    fn from(error: arrow::error::ArrowError) -> Self {
+        EtError {
+            msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::Other,
+            context: None,
+            incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
+            orig_err: Some(Box::new(error)),
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for EtError {
+    fn from(error: parquet::errors::ParquetError) -> Self {
+        EtError {
+            msg: Cow::Owned(error.to_string()),
+            kind: ErrorKind::Other,
+            context: None,
+            incomplete: false,
+            pending: false,
+            seeking: false,
+            seek_offset: 0,
+            orig_err: Some(Box::new(error)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use alloc::format;
 
     use super::*;
 
+    #[test]
+    fn test_error_kinds() {
+        assert_eq!(EtError::new("Test").kind, ErrorKind::Other);
+        assert_eq!(EtError::new("Test").incomplete().kind, ErrorKind::Truncated);
+        assert_eq!(
+            EtError::new("Test").with_kind(ErrorKind::InvalidMagic).kind,
+            ErrorKind::InvalidMagic
+        );
+        assert_eq!(
+            EtError::from("x".parse::<u8>().unwrap_err()).kind,
+            ErrorKind::InvalidValue
+        );
+        #[cfg(feature = "std")]
+        {
+            let err = IoError::new(std::io::ErrorKind::NotFound, "Test");
+            assert_eq!(EtError::from(err).kind, ErrorKind::Io);
+        }
+        assert_eq!(ErrorKind::LimitExceeded.to_string(), "LimitExceeded");
+    }
+
     #[test]
     fn test_context_display() {
         let buf: ReadBuffer = b"1234567890ABCDEF"[..].into();
@@ -270,9 +556,9 @@ mod tests {
         assert_eq!(
             msg,
             "Test\
-                       \n31323334353637383930414243444546\
-                       \n 1 2 3 4 5 6 7 8 9 0 A B C D E F\
-                       \n^^ 0\n"
+                       \nline 1, column 1 (byte 0):\
+                       \n1234567890ABCDEF\
+                       \n^\n"
         );
 
         let mut buf: ReadBuffer = b"1234567890ABCDEF"[..].into();
@@ -282,9 +568,59 @@ mod tests {
         assert_eq!(
             msg,
             "Test\
-                       \n31323334353637383930414243444546\
-                       \n 1 2 3 4 5 6 7 8 9 0 A B C D E F\
+                       \nline 1, column 11 (byte 10):\
+                       \n1234567890ABCDEF\
+                       \n          ^\n"
+        );
+    }
+
+    #[test]
+    fn test_binary_context_display() {
+        let buf: ReadBuffer = b"\x01\x0234567890ABCDEF"[..].into();
+        let err = EtError::new("Test").add_context_from_readbuffer(&buf);
+        let msg = format!("{}", err);
+        assert_eq!(
+            msg,
+            "Test\
+                       \n123334353637383930414243444546\
+                       \n     3 4 5 6 7 8 9 0 A B C D E F\
+                       \n^^ 0\n"
+        );
+
+        let mut buf: ReadBuffer = b"\x01\x0234567890ABCDEF"[..].into();
+        buf.consumed += 10;
+        let err = EtError::new("Test").add_context_from_readbuffer(&buf);
+        let msg = format!("{}", err);
+        assert_eq!(
+            msg,
+            "Test\
+                       \n123334353637383930414243444546\
+                       \n     3 4 5 6 7 8 9 0 A B C D E F\
                        \n                  ^^ 10\n"
         );
     }
+
+    #[test]
+    fn test_line_display() {
+        let mut buf: ReadBuffer = b"id\tseq\nr1\tACGT\r\nr2\tAC>T\nr3\tTT\n"[..].into();
+        buf.consumed = 21;
+        let err = EtError::new("Test").add_context_from_readbuffer(&buf);
+        let context = err.context.as_ref().expect("the error has a position");
+        assert_eq!(
+            (context.line, context.column),
+            (NonZeroU64::new(3), NonZeroU64::new(6))
+        );
+        assert_eq!(
+            format!("{}", err),
+            "Test\nline 3, column 6 (byte 21):\nr2\tAC>T\n  \t  ^\n"
+        );
+
+        // errors right at the end of a line point just past it
+        buf.consumed = 6;
+        let err = EtError::new("Test").add_context_from_readbuffer(&buf);
+        assert_eq!(
+            format!("{}", err),
+            "Test\nline 1, column 7 (byte 6):\nid\tseq\n  \t   ^\n"
+        );
+    }
 }
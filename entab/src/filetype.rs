@@ -1,7 +1,7 @@
 use alloc::format;
 use core::marker::Copy;
 
-use crate::error::EtError;
+use crate::error::{ErrorKind, EtError};
 
 /// A file format.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -15,6 +15,18 @@ pub enum FileType {
     Lzma,
     /// Zstd compression container
     Zstd,
+    /// LZ4 frame compression container
+    Lz4,
+    /// Snappy framed compression container
+    Snappy,
+    /// Brotli compression container (these have no magic bytes so they're only detected from the
+    /// file extension)
+    Brotli,
+    // archives
+    /// ZIP archive
+    Zip,
+    /// TAR archive
+    Tar,
     // bioinformatics
     /// "Binary alignment map" data. Compressed version of SAM.
     Bam,
@@ -82,6 +94,9 @@ impl FileType {
     /// format that file is in.
     #[must_use]
     pub fn from_magic(magic: &[u8]) -> FileType {
+        if magic.len() > 262 && &magic[257..262] == b"ustar" {
+            return FileType::Tar;
+        }
         if magic.len() > 8 {
             match &magic[..8] {
                 b"FCS2.0  " | b"FCS3.0  " | b"FCS3.1  " => return FileType::Facs,
@@ -91,6 +106,7 @@ impl FileType {
                 b"\x04\x03\x02\x01SPAH" => return FileType::InficonHapsite,
                 b"\xAEZTR\x0D\x0A\x1A\x0A" => return FileType::Ztr,
                 b"\x01\xA1F\x00i\x00n\x00" => return FileType::ThermoRaw,
+                b"\xFF\x06\x00\x00sNaP" => return FileType::Snappy,
                 _ => {}
             }
         }
@@ -106,8 +122,10 @@ impl FileType {
                 [0x02, 0x02, 0x00, 0x00] => return FileType::AgilentMasshunterDadHeader,
                 [0x03, 0x02, 0x00, 0x00] => return FileType::AgilentMasshunterDad,
                 [0x28, 0xB5, 0x2F, 0xFD] => return FileType::Zstd,
+                [0x04, 0x22, 0x4D, 0x18] => return FileType::Lz4,
+                b"PK\x03\x04" | b"PK\x05\x06" => return FileType::Zip,
                 [0xFF, 0xFF, 0x06 | 0x05, 0x00] => {
-                    if magic.len() >= 78 && &magic[52..64] == b"C\x00I\x00s\x00o\x00G\x00C\x00" {
+                    if magic.len() >= 78 && &magic[53..65] == b"C\x00I\x00s\x00o\x00G\x00C\x00" {
                         return FileType::ThermoCf;
                     }
                     return FileType::ThermoDxf;
@@ -141,6 +159,7 @@ impl FileType {
             "ami" => &[FileType::BrukerMsms],
             "baf" => &[FileType::BrukerBaf],
             "bam" => &[FileType::Bam],
+            "br" => &[FileType::Brotli],
             "bz" | "bz2" | "bzip" => &[FileType::Bzip],
             "cdf" => &[FileType::NetCdf],
             "cf" => &[FileType::ThermoCf],
@@ -157,16 +176,20 @@ impl FileType {
             "hdf" => &[FileType::Hdf5],
             "hps" => &[FileType::InficonHapsite],
             "idx" => &[FileType::WatersAutospec],
+            "lz4" => &[FileType::Lz4],
             "ms" => &[FileType::AgilentChemstationMs],
             "mzxml" => &[FileType::MzXml],
             "png" => &[FileType::Png],
             "raw" => &[FileType::ThermoRaw],
             "sam" => &[FileType::Sam],
             "scf" => &[FileType::Scf],
+            "sz" => &[FileType::Snappy],
+            "tar" => &[FileType::Tar],
             "sd" => &[FileType::AgilentMasshunterDadHeader],
             "sp" => &[FileType::AgilentMasshunterDad],
             "uv" => &[FileType::AgilentChemstationUv],
             "xz" => &[FileType::Lzma],
+            "zip" => &[FileType::Zip],
             "zstd" => &[FileType::Zstd],
             "ztr" => &[FileType::Ztr],
             _ => &[FileType::Unknown],
@@ -184,20 +207,21 @@ impl FileType {
             (FileType::AgilentChemstationMwd, None) => "chemstation_mwd",
             (FileType::AgilentChemstationUv, None) => "chemstation_uv",
             (FileType::AgilentMasshunterDad, None) => "masshunter_dad",
-            (FileType::AgilentMasshunterDadHeader, None) => return Err("Reading the \".sd\" file is unsupported. Please open the \".sp\" data file instead".into()),
+            (FileType::AgilentMasshunterDadHeader, None) => return Err(EtError::from("Reading the \".sd\" file is unsupported. Please open the \".sp\" data file instead").with_kind(ErrorKind::UnsupportedFormat)),
             (FileType::Bam, None) => "bam",
             (FileType::Fasta, None) => "fasta",
             (FileType::Fastq, None) => "fastq",
             (FileType::Facs, None) => "flow",
-            (FileType::InficonHapsite, None) => "inficon_hapsite",
+            (FileType::InficonHapsite, None) => "inficon",
             (FileType::Png, None) => "png",
             (FileType::Sam, None) => "sam",
             (FileType::ThermoCf, None) => "thermo_cf",
             (FileType::ThermoDxf, None) => "thermo_dxf",
             (FileType::ThermoRaw, None) => "thermo_raw",
             (FileType::DelimitedText, None) => "tsv",
+            (FileType::Zip | FileType::Tar, None) => return Err(EtError::from(format!("{:?} files are archives; please read one of their members instead (see `archive::Archive`)", self)).with_kind(ErrorKind::UnsupportedFormat)),
             (_, Some(x)) => x,
-            (x, _) => return Err(format!("{:?} doesn't have a parser", x).into())
+            (x, _) => return Err(EtError::from(format!("{:?} doesn't have a parser", x)).with_kind(ErrorKind::UnsupportedFormat))
         })
     }
 }
@@ -218,7 +242,7 @@ mod tests {
             (FileType::Fasta, "fasta"),
             (FileType::Fastq, "fastq"),
             (FileType::Facs, "flow"),
-            (FileType::InficonHapsite, "inficon_hapsite"),
+            (FileType::InficonHapsite, "inficon"),
             (FileType::Png, "png"),
             (FileType::Sam, "sam"),
             (FileType::ThermoCf, "thermo_cf"),
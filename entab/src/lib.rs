@@ -55,22 +55,109 @@
 //! # use entab::EtError;
 //! # Ok::<(), EtError>(())
 //! ```
+//!
+//! With the `async` feature, any `futures_io::AsyncRead` can be read without blocking
+//! (tokio streams can be adapted with `tokio_util::compat`):
+//! ```
+//! # #[cfg(feature = "async")] {
+//! # futures_executor::block_on(async {
+//! use entab::readers::get_reader_async;
+//!
+//! let stream = futures_util::io::Cursor::new(b">id\nACGT".to_vec());
+//! let (mut reader, _) = get_reader_async(stream, None, None).await?;
+//! while let Some(record) = reader.next_record_async().await? {
+//!     println!("{:?}", record[0]);
+//! }
+//! # Ok::<(), entab::EtError>(())
+//! # })?;
+//! # }
+//! # Ok::<(), entab::EtError>(())
+//! ```
+//!
+//! With the `parallel` feature, the FASTA, FASTQ, SAM and TSV readers can also parse everything
+//! that's left in a file at once on the rayon thread pool (records stay in file order):
+//! ```
+//! # #[cfg(feature = "parallel")] {
+//! use entab::parsers::fasta::FastaReader;
+//!
+//! let mut reader = FastaReader::new(&b">id\nACGT\n>id2\nTGCA\n"[..], None)?;
+//! for record in reader.par_records()? {
+//!     println!("{}", record.id);
+//! }
+//! # }
+//! # Ok::<(), entab::EtError>(())
+//! ```
+//!
+//! New fixed-layout binary formats can be read without implementing
+//! `FromSlice` by hand by deriving it (this needs the `derive` feature):
+//! ```
+//! # #[cfg(feature = "derive")] {
+//! use entab::parsers::FromSlice;
+//! use entab::{impl_reader, EtError};
+//!
+//! #[derive(Debug, Default, FromSlice)]
+//! #[entab(endian = "big")]
+//! pub struct Point<'r> {
+//!     pub x: u16,
+//!     pub y: u16,
+//!     pub n_label: u8,
+//!     #[entab(count = n_label)]
+//!     pub label: &'r str,
+//! }
+//!
+//! impl_reader!(PointReader, Point, Point<'r>, PointState, ());
+//!
+//! let mut reader = PointReader::new(&b"\x00\x01\x00\x02\x03abc"[..], None)?;
+//! while let Some(Point { x, y, label, .. }) = reader.next()? {
+//!     println!("{} {} {}", x, y, label);
+//! }
+//! # }
+//! # Ok::<(), entab::EtError>(())
+//! ```
 
 extern crate alloc;
+// lets the derive macros refer to `::entab` inside this crate too
+extern crate self as entab;
+
+// used by the exported macros so crates using them don't need `extern crate alloc`
+#[doc(hidden)]
+pub extern crate alloc as __alloc;
 
+/// Reading records out of the members of ZIP and TAR archives
+#[cfg(feature = "std")]
+pub mod archive;
+/// Conversion of records into Arrow `RecordBatch`es
+#[cfg(feature = "arrow")]
+pub mod arrow;
+/// Reading records into reusable, column-oriented buffers
+pub mod batch;
 /// The buffer interface that underlies the file readers
 pub mod buffer;
 /// Generic file decompression
 pub mod compression;
+/// Deserializing records into structs with serde
+pub mod de;
 /// Miscellanous utility functions and error handling
 pub mod error;
 /// File format inference
 pub mod filetype;
+/// Parsing line-oriented formats on a thread pool
+#[cfg(feature = "parallel")]
+pub mod parallel;
+/// Reader params that can be passed through `get_reader`
+pub mod params;
+/// Writing records out to Parquet files
+#[cfg(feature = "parquet")]
+pub mod parquet;
 /// Lightweight parsers to read records out of buffers
 pub mod parsers;
 /// Parsers for specific file formats
 pub mod readers;
 /// Record and abstract record reading
 pub mod record;
+/// The parsers available to `get_reader`
+pub mod registry;
+/// Column projection and row filtering for readers
+pub mod select;
 
-pub use error::EtError;
+pub use error::{ErrorKind, EtError};
@@ -0,0 +1,284 @@
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use memchr::memchr_iter;
+use rayon::prelude::*;
+
+use crate::buffer::ReadBuffer;
+use crate::EtError;
+
+/// Chunks smaller than this aren't worth handing off to another thread
+const MIN_CHUNK_SIZE: usize = 1 << 16;
+
+/// Find the byte offsets chunks of `buf` should start at.
+///
+/// Chunks are only split directly after a newline where `is_start` says a new record could
+/// begin; the first chunk always starts at 0.
+pub fn split_chunks(buf: &[u8], n_chunks: usize, is_start: fn(&[u8]) -> bool) -> Vec<usize> {
+    let mut starts = vec![0];
+    if n_chunks < 2 {
+        return starts;
+    }
+    let target = buf.len() / n_chunks;
+    for i in 1..n_chunks {
+        let from = (i * target).max(starts[starts.len() - 1] + 1);
+        if from >= buf.len() {
+            break;
+        }
+        // the newline can be right before `from` so a record starting there can be split off
+        if let Some(start) = memchr_iter(b'\n', &buf[from - 1..])
+            .map(|p| from + p)
+            .find(|&p| is_start(&buf[p..]))
+        {
+            if start < buf.len() {
+                starts.push(start);
+            }
+        } else {
+            break;
+        }
+    }
+    starts
+}
+
+/// Read the rest of a `ReadBuffer` and parse all of its records on the rayon thread pool.
+///
+/// The buffer is split into chunks directly after newlines where `is_start` says a record could
+/// begin and `parse_range` parses all of the records in each chunk (returning the position of
+/// and number of records before any error, with the error boxed to keep the `Err` small).
+/// Records are returned in the same order they appear in the file. If any chunk fails to parse
+/// (e.g. because the split heuristic picked a bad spot), everything is reparsed on the current
+/// thread so the records (or error) are the same as from reading the records one at a time.
+///
+/// # Errors
+/// If the data can't be read or any record fails to parse, an error is returned.
+pub fn par_records<'b, T, F>(
+    rb: &'b mut ReadBuffer<'_>,
+    is_start: fn(&[u8]) -> bool,
+    parse_range: F,
+) -> Result<Vec<T>, EtError>
+where
+    T: Send,
+    F: Fn(&'b [u8], usize, usize) -> Result<Vec<T>, (usize, usize, Box<EtError>)> + Sync,
+{
+    // these are all text formats, so keep counting lines through the data that's dropped
+    rb.count_lines(rb.consumed);
+    rb.read_to_end()?;
+    let lines = rb.line_count_before(rb.consumed);
+    let ReadBuffer {
+        buffer,
+        consumed,
+        record_pos,
+        reader_pos,
+        ..
+    } = rb;
+    let buffer: &'b Cow<'_, [u8]> = buffer;
+    let buffer: &'b [u8] = buffer.as_ref();
+    let start = *consumed;
+
+    let n_chunks = rayon::current_num_threads()
+        .min((buffer.len() - start) / MIN_CHUNK_SIZE)
+        .max(1);
+    let mut bounds: Vec<usize> = split_chunks(&buffer[start..], n_chunks, is_start)
+        .into_iter()
+        .map(|p| start + p)
+        .collect();
+    bounds.push(buffer.len());
+
+    let chunks: Option<Vec<Vec<T>>> = bounds
+        .par_windows(2)
+        .map(|w| parse_range(buffer, w[0], w[1]).ok())
+        .collect();
+    let records: Vec<T> = match chunks {
+        Some(chunks) => chunks.into_iter().flatten().collect(),
+        None => parse_range(buffer, start, buffer.len()).map_err(|(pos, n, e)| {
+            let position = lines.map(|l| l.position(buffer, *reader_pos, pos));
+            (*e).add_line_context(buffer, pos, *record_pos + n as u64, *reader_pos, position)
+        })?,
+    };
+    *consumed = buffer.len();
+    *record_pos += records.len() as u64;
+    Ok(records)
+}
+
+/// Add a `par_records` method to a reader created with `impl_reader`.
+///
+/// The record type has to implement `FromSlice` in a way that it doesn't borrow from the state
+/// (each chunk is parsed with its own copy of it).
+macro_rules! impl_par_records {
+    ($reader:ident, $record:ident, $is_start:expr) => {
+        impl<'r> $reader<'r> {
+            /// Read all of the remaining records, parsing them on the rayon thread pool.
+            ///
+            /// Records are returned in the same order they're in the file. Malformed records
+            /// can't be skipped and rows can't be filtered in parallel, so this fails on readers
+            /// that are lenient or have a selection (use `next_record` for those).
+            ///
+            /// # Errors
+            /// If the reader is lenient or has a selection, the data can't be read or a record
+            /// fails to parse, an error is returned.
+            pub fn par_records(&mut self) -> Result<Vec<$record<'_>>, EtError> {
+                if self.rb.lenient || self.selection.is_some() {
+                    return Err(EtError::from(
+                        "par_records can't be used on lenient readers or with a selection",
+                    )
+                    .with_kind(crate::ErrorKind::InvalidParam));
+                }
+                let state = &self.state;
+                crate::parallel::par_records(&mut self.rb, $is_start, |buf, start, end| {
+                    let mut state = state.clone();
+                    let mut records = Vec::new();
+                    let mut consumed = start;
+                    while consumed < end {
+                        let record_start = consumed;
+                        let parsed =
+                            $record::parse(&buf[consumed..end], true, &mut consumed, &mut state);
+                        match parsed {
+                            Ok(true) => {}
+                            Ok(false) => break,
+                            Err(e) => {
+                                return Err((
+                                    record_start,
+                                    records.len(),
+                                    alloc::boxed::Box::new(e),
+                                ))
+                            }
+                        }
+                        let mut record = $record::default();
+                        if let Err(e) = record.get(&buf[record_start..consumed], &state) {
+                            return Err((record_start, records.len(), alloc::boxed::Box::new(e)));
+                        }
+                        records.push(record);
+                    }
+                    Ok(records)
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use impl_par_records;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+    use alloc::string::{String, ToString};
+
+    use crate::parsers::fasta::FastaReader;
+    use crate::parsers::fastq::FastqReader;
+    use crate::parsers::sam::SamReader;
+    use crate::parsers::tsv::TsvReader;
+    use crate::record::Value;
+
+    /// Check that `par_records` gets the same records as `next`
+    macro_rules! assert_par_matches {
+        ($reader:ident, $data:expr) => {{
+            let mut reader = $reader::new($data, None)?;
+            let mut expected = Vec::new();
+            while let Some(record) = reader.next()? {
+                expected.push(format!("{:?}", Vec::<Value>::from(record)));
+            }
+            // make sure the data's split up even on machines with one core (this fails if the
+            // global pool's already been set up by another test, but then it's already set)
+            let _ = rayon::ThreadPoolBuilder::new()
+                .num_threads(4)
+                .build_global();
+            let mut reader = $reader::new($data, None)?;
+            let records = reader.par_records()?;
+            let records: Vec<_> = records
+                .into_iter()
+                .map(|r| format!("{:?}", Vec::<Value>::from(r)))
+                .collect();
+            assert_eq!(records, expected);
+            assert!(reader.next()?.is_none());
+        }};
+    }
+
+    #[test]
+    fn test_par_records() -> Result<(), EtError> {
+        let mut fasta = String::new();
+        let mut fastq = String::new();
+        let mut sam = String::from("@HD\tVN:1.6\n");
+        let mut tsv = String::from("id\tvalue\n");
+        for i in 0..20_000 {
+            let seq = &"ACGTTGCA"[..1 + i % 8];
+            fasta.push_str(&format!(">seq{}\n{}\n{}\n", i, seq, seq));
+            // quality lines that start with an @ shouldn't be split on
+            fastq.push_str(&format!(
+                "@seq{}\n{}\n+\n@{}\n",
+                i,
+                seq,
+                &"IIIIIIII"[..i % 8]
+            ));
+            sam.push_str(&format!(
+                "seq{}\t0\tchr1\t{}\t30\t4M\t*\t0\t0\t{}\t*\n",
+                i,
+                i + 1,
+                seq
+            ));
+            tsv.push_str(&format!("seq{}\t{}\n", i, i));
+        }
+        assert_par_matches!(FastaReader, fasta.as_bytes());
+        assert_par_matches!(FastqReader, fastq.as_bytes());
+        assert_par_matches!(SamReader, sam.as_bytes());
+        assert_par_matches!(TsvReader, tsv.as_bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn test_par_records_error() -> Result<(), EtError> {
+        let mut fasta = String::new();
+        for i in 0..20_000 {
+            fasta.push_str(&format!(">seq{}\nACGT\n", i));
+        }
+        fasta.push_str(">bad\nAC>GT\n");
+        let mut reader = FastaReader::new(fasta.as_bytes(), None)?;
+        let err = loop {
+            if let Err(e) = reader.next() {
+                break e;
+            }
+        };
+        let mut reader = FastaReader::new(fasta.as_bytes(), None)?;
+        let par_err = match reader.par_records() {
+            Ok(_) => panic!("the last record is bad"),
+            Err(e) => e,
+        };
+        assert_eq!(par_err.to_string(), err.to_string());
+        assert_eq!(
+            format!("{:?}", par_err.context),
+            format!("{:?}", err.context)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_par_records_unsupported() -> Result<(), EtError> {
+        use crate::readers::RecordReader;
+        use crate::select::Selection;
+
+        let data: &[u8] = b">a\nACGT\n";
+        let mut reader = FastaReader::new(data, None)?;
+        reader.select(&Selection::default().columns(&["id"]))?;
+        assert!(reader.par_records().is_err());
+
+        let mut rb = ReadBuffer::from(data);
+        rb.lenient = true;
+        let mut reader = FastaReader::new(rb, None)?;
+        assert!(reader.par_records().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        let buf = b">a\nAC\n>b\nGT\n>c\nTT\n";
+        let starts = split_chunks(buf, 3, |b| b.first() == Some(&b'>'));
+        assert_eq!(starts, vec![0, 6, 12]);
+        // nowhere valid to split
+        let starts = split_chunks(buf, 3, |_| false);
+        assert_eq!(starts, vec![0]);
+        let starts = split_chunks(b"a\nb\n", 4, |_| true);
+        assert_eq!(starts, vec![0, 2]);
+    }
+}
@@ -0,0 +1,212 @@
+use alloc::borrow::ToOwned;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use serde::Serialize;
+
+use crate::record::{Value, ValueType};
+use crate::{ErrorKind, EtError};
+
+/// A param that can be passed to `get_reader` to change how a parser reads a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ParamInfo {
+    /// The key the param is passed as
+    pub name: &'static str,
+    /// The type of value expected
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    /// What the param does
+    pub description: &'static str,
+}
+
+impl ParamInfo {
+    /// Describe a new param.
+    #[must_use]
+    pub const fn new(name: &'static str, value_type: ValueType, description: &'static str) -> Self {
+        ParamInfo {
+            name,
+            value_type,
+            description,
+        }
+    }
+}
+
+/// The params every parser accepts
+pub const COMMON_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new(
+        "columns",
+        ValueType::List,
+        "The columns to return (as a list or a comma-separated string)",
+    ),
+    ParamInfo::new(
+        "filter",
+        ValueType::List,
+        "Only return rows matching these comparisons, e.g. \"mapq > 30\"",
+    ),
+    ParamInfo::new(
+        "filename",
+        ValueType::String,
+        "The path of the file; used to detect registered parsers from their extensions",
+    ),
+    ParamInfo::new(
+        "max_buffer_size",
+        ValueType::Integer,
+        "The most bytes of the file to hold in memory at once",
+    ),
+    ParamInfo::new(
+        "max_record_size",
+        ValueType::Integer,
+        "The most bytes a single record can take up before it's treated as malformed",
+    ),
+    ParamInfo::new(
+        "lenient",
+        ValueType::Boolean,
+        "Skip malformed records (reporting them as warnings) instead of stopping at them",
+    ),
+];
+
+/// Reader params that can be built from the params passed to `get_reader`
+pub trait FromParams: Sized {
+    /// The params that are read
+    const PARAMS: &'static [ParamInfo];
+
+    /// Build up the reader params from a params map, removing the keys that are used.
+    ///
+    /// # Errors
+    /// If any of the values are of the wrong type or are invalid, an error is returned.
+    fn from_params(params: &mut BTreeMap<String, Value<'_>>) -> Result<Self, EtError>;
+}
+
+/// Read a constant like `30`, `2.5`, `true` or `"chr1"` (quotes are optional for strings).
+#[must_use]
+pub fn parse_constant(raw: &str) -> Value<'static> {
+    let raw = raw.trim();
+    let quoted = raw.len() >= 2
+        && ((raw.starts_with('"') && raw.ends_with('"'))
+            || (raw.starts_with('\'') && raw.ends_with('\'')));
+    if quoted {
+        Value::String(raw[1..raw.len() - 1].to_owned().into())
+    } else if let Ok(i) = raw.parse::<i64>() {
+        Value::Integer(i)
+    } else if let Ok(f) = raw.parse::<f64>() {
+        Value::Float(f)
+    } else if let Ok(b) = raw.parse::<bool>() {
+        Value::Boolean(b)
+    } else {
+        Value::String(raw.to_owned().into())
+    }
+}
+
+/// Remove `key` from `params` as a boolean.
+pub(crate) fn take_bool(
+    params: &mut BTreeMap<String, Value<'_>>,
+    key: &str,
+) -> Result<Option<bool>, EtError> {
+    match params.remove(key) {
+        None => Ok(None),
+        Some(Value::Boolean(b)) => Ok(Some(b)),
+        _ => Err(
+            EtError::from(format!("Param {} must be true or false", key))
+                .with_kind(ErrorKind::InvalidParam),
+        ),
+    }
+}
+
+/// Remove `key` from `params` as a number.
+pub(crate) fn take_float(
+    params: &mut BTreeMap<String, Value<'_>>,
+    key: &str,
+) -> Result<Option<f64>, EtError> {
+    match params.remove(key) {
+        None => Ok(None),
+        Some(Value::Float(f)) => Ok(Some(f)),
+        #[allow(clippy::cast_precision_loss)]
+        Some(Value::Integer(i)) => Ok(Some(i as f64)),
+        _ => Err(EtError::from(format!("Param {} must be a number", key))
+            .with_kind(ErrorKind::InvalidParam)),
+    }
+}
+
+/// Remove `key` from `params` as a non-negative integer.
+pub(crate) fn take_usize(
+    params: &mut BTreeMap<String, Value<'_>>,
+    key: &str,
+) -> Result<Option<usize>, EtError> {
+    match params.remove(key) {
+        None => Ok(None),
+        Some(Value::Integer(i)) if i >= 0 => Ok(Some(i as usize)),
+        _ => Err(
+            EtError::from(format!("Param {} must be a non-negative integer", key))
+                .with_kind(ErrorKind::InvalidParam),
+        ),
+    }
+}
+
+/// Remove `key` from `params` as a string.
+pub(crate) fn take_string(
+    params: &mut BTreeMap<String, Value<'_>>,
+    key: &str,
+) -> Result<Option<String>, EtError> {
+    match params.remove(key) {
+        None => Ok(None),
+        Some(Value::String(s)) => Ok(Some(s.into_owned())),
+        _ => Err(EtError::from(format!("Param {} must be a string", key))
+            .with_kind(ErrorKind::InvalidParam)),
+    }
+}
+
+/// Remove `key` from `params` as a single (ASCII) character.
+pub(crate) fn take_byte(
+    params: &mut BTreeMap<String, Value<'_>>,
+    key: &str,
+) -> Result<Option<u8>, EtError> {
+    match take_string(params, key)? {
+        None => Ok(None),
+        Some(s) if s.len() == 1 => Ok(Some(s.as_bytes()[0])),
+        Some(_) => Err(
+            EtError::from(format!("Param {} must be a single character", key))
+                .with_kind(ErrorKind::InvalidParam),
+        ),
+    }
+}
+
+/// Remove `key` from `params` as a list (a single value is treated as a list of one).
+pub(crate) fn take_list<'p>(
+    params: &mut BTreeMap<String, Value<'p>>,
+    key: &str,
+) -> Option<Vec<Value<'p>>> {
+    params.remove(key).map(|value| match value {
+        Value::List(l) => l,
+        v => vec![v],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_params() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        drop(params.insert("a".into(), Value::Integer(2)));
+        drop(params.insert("b".into(), "|".into()));
+        drop(params.insert("c".into(), "ab".into()));
+        drop(params.insert("d".into(), Value::Integer(-1)));
+        assert_eq!(take_float(&mut params, "a")?, Some(2.));
+        assert_eq!(take_byte(&mut params, "b")?, Some(b'|'));
+        assert!(take_byte(&mut params, "c").is_err());
+        assert!(take_usize(&mut params, "d").is_err());
+        assert_eq!(take_bool(&mut params, "e")?, None);
+        assert!(params.is_empty());
+
+        assert_eq!(parse_constant(" 30"), Value::Integer(30));
+        assert_eq!(parse_constant("2.5"), Value::Float(2.5));
+        assert_eq!(parse_constant("true"), Value::Boolean(true));
+        assert_eq!(parse_constant("'30'"), "30".into());
+        assert_eq!(parse_constant("chr1"), "chr1".into());
+        Ok(())
+    }
+}
@@ -0,0 +1,153 @@
+use alloc::boxed::Box;
+use alloc::format;
+use core::str::FromStr;
+use std::io::Write;
+
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, GzipLevel};
+use parquet::file::properties::WriterProperties;
+
+use crate::arrow::{ArrowParams, ArrowReader};
+use crate::readers::RecordReader;
+use crate::EtError;
+
+/// The compression codec used for the pages in a Parquet file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParquetCompression {
+    /// No compression
+    Uncompressed,
+    /// Snappy compression
+    Snappy,
+    /// Gzip compression
+    Gzip,
+}
+
+impl FromStr for ParquetCompression {
+    type Err = EtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "none" | "uncompressed" => ParquetCompression::Uncompressed,
+            "snappy" => ParquetCompression::Snappy,
+            "gzip" => ParquetCompression::Gzip,
+            x => return Err(format!("Unknown Parquet compression {}", x).into()),
+        })
+    }
+}
+
+impl From<ParquetCompression> for Compression {
+    fn from(compression: ParquetCompression) -> Self {
+        match compression {
+            ParquetCompression::Uncompressed => Compression::UNCOMPRESSED,
+            ParquetCompression::Snappy => Compression::SNAPPY,
+            ParquetCompression::Gzip => Compression::GZIP(GzipLevel::default()),
+        }
+    }
+}
+
+/// Parameters for writing Parquet files
+#[derive(Clone, Copy, Debug)]
+pub struct ParquetParams {
+    /// The maximum number of rows in each row group
+    pub row_group_size: usize,
+    /// The compression to use for each column
+    pub compression: ParquetCompression,
+    /// How records are batched and how their types are inferred
+    pub arrow: ArrowParams,
+}
+
+impl Default for ParquetParams {
+    fn default() -> Self {
+        ParquetParams {
+            row_group_size: 1024 * 1024,
+            compression: ParquetCompression::Snappy,
+            arrow: ArrowParams::default(),
+        }
+    }
+}
+
+/// Write all of the records in `reader` out to `writer` as a Parquet file.
+///
+/// Returns the number of records written.
+///
+/// # Errors
+/// If a record can't be read or the file can't be written, an error is returned.
+pub fn write_parquet<'r, W: Write + Send>(
+    reader: Box<dyn RecordReader + 'r>,
+    writer: W,
+    params: Option<ParquetParams>,
+) -> Result<usize, EtError> {
+    let params = params.unwrap_or_default();
+    if params.row_group_size == 0 {
+        return Err("Row group size must be greater than zero".into());
+    }
+    let mut batches = ArrowReader::new(reader, Some(params.arrow))?;
+    let props = WriterProperties::builder()
+        .set_max_row_group_size(params.row_group_size)
+        .set_compression(params.compression.into())
+        .build();
+    let mut parquet_writer = ArrowWriter::try_new(writer, batches.schema(), Some(props))?;
+    let mut n_rows = 0;
+    while let Some(batch) = batches.next_batch()? {
+        n_rows += batch.num_rows();
+        parquet_writer.write(&batch)?;
+    }
+    let _ = parquet_writer.close()?;
+    Ok(n_rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    use arrow::datatypes::DataType;
+    use arrow::record_batch::RecordBatchReader;
+    use bytes::Bytes;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    use crate::readers::get_reader;
+
+    #[test]
+    fn test_write_parquet() -> Result<(), EtError> {
+        let rb: &[u8] = include_bytes!("../tests/data/b3_alkanes.dxf");
+        let (reader, _) = get_reader(rb, None, None)?;
+        let params = ParquetParams {
+            row_group_size: 1000,
+            compression: ParquetCompression::Gzip,
+            ..ParquetParams::default()
+        };
+        let mut out = Vec::new();
+        let n_rows = write_parquet(reader, &mut out, Some(params))?;
+        assert!(n_rows > 1000);
+
+        let file_reader = SerializedFileReader::new(Bytes::from(out.clone()))?;
+        let metadata = file_reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows() as usize, n_rows);
+        assert_eq!(metadata.num_row_groups(), n_rows.div_ceil(1000));
+        assert_eq!(
+            metadata.row_group(0).column(0).compression(),
+            Compression::GZIP(GzipLevel::default())
+        );
+
+        let arrow_reader = ParquetRecordBatchReaderBuilder::try_new(Bytes::from(out))?.build()?;
+        let schema = arrow_reader.schema();
+        assert_eq!(schema.field(0).name(), "time");
+        assert_eq!(schema.field(0).data_type(), &DataType::Float64);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parquet_compression_names() {
+        assert_eq!(
+            "snappy".parse::<ParquetCompression>().unwrap(),
+            ParquetCompression::Snappy
+        );
+        assert_eq!(
+            "none".parse::<ParquetCompression>().unwrap(),
+            ParquetCompression::Uncompressed
+        );
+        assert!("lzma".parse::<ParquetCompression>().is_err());
+    }
+}
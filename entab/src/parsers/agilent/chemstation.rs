@@ -7,11 +7,14 @@ use core::marker::Copy;
 
 use chrono::NaiveDateTime;
 
+use crate::batch::{BatchRecord, ColumnBatch};
 use crate::parsers::agilent::read_agilent_header;
+use crate::parsers::common::Skip;
 use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
-use crate::EtError;
+use crate::record::{ColumnSchema, RecordSchema, StateMetadata, Value, ValueType};
+use crate::select::{ResolvedSelection, SelectRecord};
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 const CHEMSTATION_TIME_STEP: f64 = 0.2;
 
@@ -95,7 +98,7 @@ fn get_metadata(header: &[u8]) -> Result<ChemstationMetadata, EtError> {
 
     let signal_name_len = usize::from(header[596]);
     if signal_name_len > 40 {
-        return Err("Invalid signal name length".into());
+        return Err(EtError::from("Invalid signal name length").with_kind(ErrorKind::InvalidValue));
     }
     let signal_name = str::from_utf8(&header[597..597 + signal_name_len])?
         .trim()
@@ -103,28 +106,28 @@ fn get_metadata(header: &[u8]) -> Result<ChemstationMetadata, EtError> {
 
     let sample_len = usize::from(header[24]);
     if sample_len > 60 {
-        return Err("Invalid sample length".into());
+        return Err(EtError::from("Invalid sample length").with_kind(ErrorKind::InvalidValue));
     }
     let sample = str::from_utf8(&header[25..25 + sample_len])?
         .trim()
         .to_string();
     let description_len = usize::from(header[86]);
     if description_len > 60 {
-        return Err("Invalid sample length".into());
+        return Err(EtError::from("Invalid sample length").with_kind(ErrorKind::InvalidValue));
     }
     let description = str::from_utf8(&header[87..87 + description_len])?
         .trim()
         .to_string();
     let operator_len = usize::from(header[148]);
     if operator_len > 28 {
-        return Err("Invalid sample length".into());
+        return Err(EtError::from("Invalid sample length").with_kind(ErrorKind::InvalidValue));
     }
     let operator = str::from_utf8(&header[149..149 + operator_len])?
         .trim()
         .to_string();
     let run_date_len = usize::from(header[178]);
     if run_date_len > 60 {
-        return Err("Invalid sample length".into());
+        return Err(EtError::from("Invalid sample length").with_kind(ErrorKind::InvalidValue));
     }
     // We need to detect the date format before we can convert into a
     // NaiveDateTime; not sure the format even maps to the file type
@@ -197,6 +200,13 @@ impl StateMetadata for ChemstationFidState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidState {
@@ -248,7 +258,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationFidRecord {
         if buffer.is_empty() && eof {
             return Ok(false);
         } else if buffer.len() == 1 && eof {
-            return Err("FID record was incomplete".into());
+            return Err(EtError::from("FID record was incomplete").with_kind(ErrorKind::Truncated));
         } else if buffer.len() < 2 {
             return Err(EtError::from("Incomplete FID file").incomplete());
         }
@@ -296,6 +306,14 @@ impl StateMetadata for ChemstationMsState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("mz"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsState {
@@ -354,7 +372,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsRecord {
             // handle the record header
             let raw_n_mzs_left: u16 = extract(rb, con, &mut Endian::Big)?;
             if raw_n_mzs_left < 14 {
-                return Err("Invalid Chemstation MS record header".into());
+                return Err(EtError::from("Invalid Chemstation MS record header")
+                    .with_kind(ErrorKind::InvalidValue));
             }
             n_mzs_left = usize::from((raw_n_mzs_left - 14) / 2);
             state.cur_time = f64::from(extract::<u32>(rb, con, &mut Endian::Big)?) / 60000.;
@@ -374,8 +393,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsRecord {
         // just read the mz/intensity
         state.cur_mz = f64::from(extract::<u16>(rb, con, &mut Endian::Big)?) / 20.;
         let raw_intensity: u16 = extract(rb, con, &mut Endian::Big)?;
-        state.cur_intensity =
-            f64::from(raw_intensity & 16383) * 8f64.powi(i32::from(raw_intensity) >> 14);
+        state.cur_intensity = decode_ms_intensity(raw_intensity);
         if n_mzs_left == 1 {
             state.n_scans_left -= 1;
             // eat the footer
@@ -396,6 +414,117 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsRecord {
     }
 }
 
+/// Intensities are stored as a 14-bit mantissa and a 2-bit (base 8) exponent
+fn decode_ms_intensity(raw_intensity: u16) -> f64 {
+    f64::from(raw_intensity & 16383) * 8f64.powi(i32::from(raw_intensity) >> 14)
+}
+
+#[derive(Clone, Debug, Default)]
+/// Internal state for the `ChemstationMsScanRecord` parser
+///
+/// The file header's read the same way as for `ChemstationMsRecord`; only the columns differ.
+pub struct ChemstationMsScanState(ChemstationMsState);
+
+impl StateMetadata for ChemstationMsScanState {
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.0.metadata()
+    }
+
+    fn header(&self) -> Vec<&str> {
+        vec![
+            "time",
+            "tic",
+            "base_peak_mz",
+            "base_peak_intensity",
+            "n_peaks",
+        ]
+    }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("tic"),
+            ColumnSchema::any("base_peak_mz"),
+            ColumnSchema::any("base_peak_intensity"),
+            ColumnSchema::any("n_peaks"),
+        ]
+    }
+}
+
+impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsScanState {
+    type State = ();
+
+    fn parse(
+        buffer: &[u8],
+        eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        ChemstationMsState::parse(buffer, eof, consumed, state)
+    }
+
+    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        self.0.get(buffer, state)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// A summary of a single scan from a Chemstation MS file
+pub struct ChemstationMsScanRecord {
+    /// The time the scan was recorded at
+    pub time: f64,
+    /// The total ion count of the scan
+    pub tic: f64,
+    /// The m/z of the most intense peak in the scan
+    pub base_peak_mz: f64,
+    /// The intensity of the most intense peak in the scan
+    pub base_peak_intensity: f64,
+    /// The number of m/z-intensity pairs in the scan
+    pub n_peaks: u32,
+}
+
+impl_record!(ChemstationMsScanRecord: time, tic, base_peak_mz, base_peak_intensity, n_peaks);
+
+impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMsScanRecord {
+    type State = ChemstationMsScanState;
+
+    fn parse(
+        rb: &[u8],
+        _eof: bool,
+        consumed: &mut usize,
+        state: &mut Self::State,
+    ) -> Result<bool, EtError> {
+        if state.0.n_scans_left == 0 {
+            return Ok(false);
+        }
+        let con = &mut 0;
+        let raw_n_mzs: u16 = extract(rb, con, &mut Endian::Big)?;
+        if raw_n_mzs < 14 {
+            return Err(EtError::from("Invalid Chemstation MS record header")
+                .with_kind(ErrorKind::InvalidValue));
+        }
+        let n_mzs = usize::from((raw_n_mzs - 14) / 2);
+        // time, then 12 bytes of scan header, the mz/intensity pairs, and a 10 byte footer
+        let _ = extract::<Skip>(rb, con, &mut (16 + 4 * n_mzs + 10))?;
+        state.0.n_scans_left -= 1;
+        *consumed += *con;
+        Ok(true)
+    }
+
+    fn get(&mut self, buf: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+        let n_mzs = usize::from((u16::extract(&buf[..2], &Endian::Big)? - 14) / 2);
+        self.time = f64::from(u32::extract(&buf[2..6], &Endian::Big)?) / 60000.;
+        // the last 4 bytes of the scan header are a u16/u16 pair for the highest peak
+        self.base_peak_mz = f64::from(u16::extract(&buf[14..16], &Endian::Big)?) / 20.;
+        self.base_peak_intensity = decode_ms_intensity(u16::extract(&buf[16..18], &Endian::Big)?);
+        self.n_peaks = n_mzs as u32;
+        // and the last 4 bytes of the footer are a u32 for the TIC
+        let tic_pos = 18 + 4 * n_mzs + 6;
+        self.tic = f64::from(u32::extract(&buf[tic_pos..tic_pos + 4], &Endian::Big)?);
+        Ok(())
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 /// Internal state for the `ChemstationMwdRecord` parser
 pub struct ChemstationMwdState {
@@ -414,6 +543,14 @@ impl StateMetadata for ChemstationMwdState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "signal", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("signal").unit("nm"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdState {
@@ -469,6 +606,28 @@ impl<'r> From<ChemstationMwdRecord<'r>> for Vec<Value<'r>> {
     }
 }
 
+impl RecordSchema for ChemstationMwdRecord<'_> {
+    fn schema() -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::new("time", ValueType::Float),
+            ColumnSchema::new("signal", ValueType::Float),
+            ColumnSchema::new("intensity", ValueType::Float),
+        ]
+    }
+}
+
+impl<'r> SelectRecord<'r> for ChemstationMwdRecord<'r> {
+    fn matches(&self, selection: &ResolvedSelection) -> bool {
+        selection.matches(&Vec::from(self.clone()))
+    }
+}
+
+impl BatchRecord for ChemstationMwdRecord<'_> {
+    fn push_to(self, batch: &mut ColumnBatch) -> Result<(), EtError> {
+        batch.push_record(self.into())
+    }
+}
+
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationMwdRecord<'s> {
     type State = ChemstationMwdState;
 
@@ -577,6 +736,13 @@ impl_reader!(
     ChemstationMsState,
     ()
 );
+impl_reader!(
+    ChemstationMsScanReader,
+    ChemstationMsScanRecord,
+    ChemstationMsScanRecord,
+    ChemstationMsScanState,
+    ()
+);
 impl_reader!(
     ChemstationMwdReader,
     ChemstationMwdRecord,
@@ -642,6 +808,67 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_chemstation_reader_ms_scans() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../../../tests/data/carotenoid_extract.d/MSD1.MS");
+        let mut reader = ChemstationMsScanReader::new(data, None)?;
+        let _ = reader.metadata();
+        assert_eq!(
+            reader.headers(),
+            [
+                "time",
+                "tic",
+                "base_peak_mz",
+                "base_peak_intensity",
+                "n_peaks"
+            ]
+        );
+        let ChemstationMsScanRecord {
+            time,
+            tic,
+            base_peak_mz,
+            base_peak_intensity,
+            n_peaks,
+        } = reader.next()?.unwrap();
+        assert!((time - 0.079166).abs() < 0.000001);
+        assert_eq!(tic, 13924.);
+        assert!((base_peak_mz - 105.2).abs() < 0.000001);
+        assert_eq!(base_peak_intensity, 644.);
+        assert_eq!(n_peaks, 83);
+
+        let mut n_scans = 1;
+        let mut total_peaks = u64::from(n_peaks);
+        while let Some(scan) = reader.next()? {
+            n_scans += 1;
+            total_peaks += u64::from(scan.n_peaks);
+        }
+        assert_eq!(total_peaks, 95471);
+        assert_eq!(n_scans, 2534);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chemstation_per_scan_param() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+
+        let data: &[u8] = include_bytes!("../../../tests/data/carotenoid_extract.d/MSD1.MS");
+        let mut params = BTreeMap::new();
+        drop(params.insert("per_scan".to_string(), true.into()));
+        let (reader, _) = get_reader(data, Some("chemstation_ms"), Some(params))?;
+        assert_eq!(reader.headers()[1], "tic");
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("per_scan".to_string(), false.into()));
+        let (reader, _) = get_reader(data, Some("chemstation_ms"), Some(params))?;
+        assert_eq!(reader.headers()[1], "mz");
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("per_scan".to_string(), "yes".into()));
+        let err = get_reader(data, Some("chemstation_ms"), Some(params)).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidParam);
+        Ok(())
+    }
+
     #[test]
     fn test_chemstation_reader_mwd() -> Result<(), EtError> {
         let data: &[u8] = include_bytes!("../../../tests/data/chemstation_mwd.d/mwd1A.ch");
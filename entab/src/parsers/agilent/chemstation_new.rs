@@ -10,9 +10,9 @@ use chrono::NaiveDateTime;
 
 use crate::parsers::agilent::read_agilent_header;
 use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
-use crate::EtError;
+use crate::record::{ColumnSchema, StateMetadata, Value};
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 #[derive(Clone, Debug, Default)]
 /// Metadata consistly found in new Chemstation file formats
@@ -121,6 +121,14 @@ impl StateMetadata for ChemstationUvState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "wavelength", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("wavelength").unit("nm"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvState {
@@ -186,11 +194,14 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationUvRecord {
             let wv_start: u16 = extract(rb, con, &mut Endian::Little)?;
             let wv_end: u16 = extract(rb, con, &mut Endian::Little)?;
             if wv_start > wv_end {
-                return Err("Wavelength range has invalid bounds".into());
+                return Err(EtError::from("Wavelength range has invalid bounds")
+                    .with_kind(ErrorKind::InvalidValue));
             }
             let wv_step: u16 = extract(rb, con, &mut Endian::Little)?;
             if wv_step == 0 {
-                return Err("Invalid wavelength step".into());
+                return Err(
+                    EtError::from("Invalid wavelength step").with_kind(ErrorKind::InvalidValue)
+                );
             }
 
             n_wvs_left = usize::from((wv_end - wv_start) / wv_step) + 1;
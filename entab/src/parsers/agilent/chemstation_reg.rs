@@ -6,7 +6,7 @@ use encoding::{DecoderTrap, Encoding};
 
 use crate::parsers::{extract, Endian, FromSlice};
 use crate::record::StateMetadata;
-use crate::EtError;
+use crate::{ErrorKind, EtError};
 use crate::{impl_reader, impl_record};
 use crate::record::Value;
 
@@ -41,7 +41,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
         let header = extract::<&[u8]>(buf, con, &mut 45)?;
 
         if header[25] != b'A' {
-            return Err(EtError::from("Version of REG file is too new"));
+            return Err(EtError::from("Version of REG file is too new").with_kind(ErrorKind::UnsupportedFormat));
         }
         let n_sections = u16::extract(&header[38..], &Endian::Little)?;
 
@@ -83,7 +83,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
                 // part of a linked list
                 1538 => {
                     if record_data.len() != 39 {
-                        return Err(EtError::from("Data type 1538 was an unexpected size"));
+                        return Err(EtError::from("Data type 1538 was an unexpected size").with_kind(ErrorKind::InvalidValue));
                     }
                     let _ = names.insert(record_id, decode_iso_8859(&record_data[14..35])?);
                     let _ = metadata.insert(record_id, u32::extract(&record_data[35..], &Endian::Little)?.into());
@@ -91,7 +91,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
                 // another part of a linked list with a table reference
                 1539 => {
                     if record_data.len() != 39 {
-                        return Err(EtError::from("Data type 1539 was an unexpected size"));
+                        return Err(EtError::from("Data type 1539 was an unexpected size").with_kind(ErrorKind::InvalidValue));
                     }
                     let id = u32::extract(&record_data[35..], &Endian::Little)?;
                     let _ = names.insert(id, decode_iso_8859(&record_data[14..35])?);
@@ -116,7 +116,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ChemstationRegState {
                 // flattened numeric array; contains the raw data for 1281/1283 records
                 32770 => {
                     if record_data.len() < 4 {
-                        return Err(EtError::from("Array was undersized"));
+                        return Err(EtError::from("Array was undersized").with_kind(ErrorKind::InvalidValue));
                     }
                     let n_points = record_data.len() / 4 - 1;
                     let mut data: Vec<Value> = Vec::with_capacity(n_points);
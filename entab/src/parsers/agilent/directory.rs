@@ -0,0 +1,308 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use std::fs::{read_dir, File};
+use std::path::{Path, PathBuf};
+
+use crate::buffer::ReadBuffer;
+use crate::filetype::FileType;
+use crate::parsers::agilent::chemstation::{
+    ChemstationFidReader, ChemstationMsReader, ChemstationMwdReader,
+};
+use crate::parsers::agilent::chemstation_new::ChemstationUvReader;
+use crate::parsers::agilent::masshunter::MasshunterDadReader;
+use crate::readers::RecordReader;
+use crate::record::{ColumnSchema, Value, ValueType};
+use crate::select::{ResolvedSelection, Selection};
+use crate::{ErrorKind, EtError};
+
+/// The columns every file in the directory is mapped onto
+const DIRECTORY_HEADERS: [&str; 6] = [
+    "source_file",
+    "signal",
+    "time",
+    "mz",
+    "wavelength",
+    "intensity",
+];
+
+/// A single signal file inside an Agilent directory
+#[derive(Debug)]
+struct SignalFile {
+    name: String,
+    reader: Box<dyn RecordReader>,
+    signal: Value<'static>,
+    columns: Vec<Option<usize>>,
+}
+
+/// Reader for all of the supported signal files inside an Agilent `.d` directory
+///
+/// Every file is read in turn and mapped onto a common set of columns; columns a file doesn't
+/// have are reported as null.
+#[derive(Debug)]
+pub struct AgilentDirectoryReader {
+    files: VecDeque<SignalFile>,
+    metadata: BTreeMap<String, Value<'static>>,
+    selection: Option<ResolvedSelection>,
+}
+
+/// Open `path` with the appropriate reader if it's a signal file we know how to read.
+fn open_signal_file(path: &Path) -> Result<Option<Box<dyn RecordReader>>, EtError> {
+    let mut rb = ReadBuffer::try_from(File::open(path)?)?;
+    Ok(Some(match rb.sniff_filetype()? {
+        FileType::AgilentChemstationFid => Box::new(ChemstationFidReader::new(rb, None)?),
+        FileType::AgilentChemstationMs => Box::new(ChemstationMsReader::new(rb, None)?),
+        FileType::AgilentChemstationMwd => Box::new(ChemstationMwdReader::new(rb, None)?),
+        FileType::AgilentChemstationUv => Box::new(ChemstationUvReader::new(rb, None)?),
+        FileType::AgilentMasshunterDad => Box::new(MasshunterDadReader::new(
+            rb,
+            Some(path.to_string_lossy().into_owned()),
+        )?),
+        _ => return Ok(None),
+    }))
+}
+
+/// List the files in `path` (and in its Masshunter `AcqData` subdirectory, if present).
+fn list_files(path: &Path) -> Result<Vec<PathBuf>, EtError> {
+    let mut files = Vec::new();
+    for dir in [path.to_path_buf(), path.join("AcqData")] {
+        if !dir.is_dir() {
+            continue;
+        }
+        let mut dir_files = Vec::new();
+        for entry in read_dir(dir)? {
+            let entry_path = entry?.path();
+            if entry_path.is_file() {
+                dir_files.push(entry_path);
+            }
+        }
+        dir_files.sort();
+        files.extend(dir_files);
+    }
+    Ok(files)
+}
+
+impl AgilentDirectoryReader {
+    /// Create a new `AgilentDirectoryReader` from the path to a `.d` directory
+    ///
+    /// # Errors
+    /// If the directory can't be read or contains no supported files, an error is returned.
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, EtError> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(
+                EtError::from(format!("{} is not a directory", path.display()))
+                    .with_kind(ErrorKind::Io),
+            );
+        }
+
+        let mut files = VecDeque::new();
+        let mut metadata = BTreeMap::new();
+        let mut source_files = Vec::new();
+        for file_path in list_files(path)? {
+            let reader = match open_signal_file(&file_path)? {
+                Some(r) => r,
+                None => continue,
+            };
+            let name = file_path
+                .strip_prefix(path)
+                .unwrap_or(&file_path)
+                .to_string_lossy()
+                .into_owned();
+
+            // if the same key shows up in multiple files, the first file's value wins
+            let file_metadata = reader.metadata();
+            let signal = match file_metadata.get("signal_name") {
+                Some(Value::String(s)) if !s.is_empty() => {
+                    Value::String(s.clone().into_owned().into())
+                }
+                _ => Value::Null,
+            };
+            for (key, value) in file_metadata {
+                let _ = metadata.entry(key).or_insert_with(|| value.into_owned());
+            }
+
+            let columns = reader
+                .headers()
+                .iter()
+                .map(|h| {
+                    // MWD files report the wavelength of the signal as "signal"
+                    let h = if h == "signal" { "wavelength" } else { h };
+                    DIRECTORY_HEADERS.iter().position(|d| *d == h)
+                })
+                .collect();
+            source_files.push(Value::String(name.clone().into()));
+            files.push_back(SignalFile {
+                name,
+                reader,
+                signal,
+                columns,
+            });
+        }
+        if files.is_empty() {
+            return Err(
+                EtError::from(format!("No supported files found in {}", path.display()))
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
+        }
+        drop(metadata.insert("source_files".to_string(), Value::List(source_files)));
+
+        Ok(AgilentDirectoryReader {
+            files,
+            metadata,
+            selection: None,
+        })
+    }
+}
+
+impl RecordReader for AgilentDirectoryReader {
+    /// The next record, expressed as a `Vec` of `Value`s.
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        while let Some(file) = self.files.front_mut() {
+            let record = match file.reader.next_record()? {
+                Some(r) => r,
+                None => {
+                    drop(self.files.pop_front());
+                    continue;
+                }
+            };
+            let mut values = vec![Value::Null; DIRECTORY_HEADERS.len()];
+            values[0] = Value::String(file.name.clone().into());
+            values[1] = file.signal.clone();
+            for (value, column) in record.into_iter().zip(&file.columns) {
+                if let Some(idx) = column {
+                    values[*idx] = value.into_owned();
+                }
+            }
+            match &self.selection {
+                Some(s) => {
+                    if let Some(values) = s.apply(values) {
+                        return Ok(Some(values));
+                    }
+                }
+                None => return Ok(Some(values)),
+            }
+        }
+        Ok(None)
+    }
+
+    /// The headers for this Reader.
+    fn headers(&self) -> Vec<String> {
+        let headers = match &self.selection {
+            Some(s) => s.project(&DIRECTORY_HEADERS),
+            None => DIRECTORY_HEADERS.to_vec(),
+        };
+        headers.iter().map(|s| (*s).to_string()).collect()
+    }
+
+    /// The metadata for this Reader.
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        self.metadata.clone()
+    }
+
+    /// The schema for this Reader.
+    fn schema(&self) -> Vec<ColumnSchema> {
+        let schema = vec![
+            ColumnSchema::new("source_file", ValueType::String),
+            ColumnSchema::new("signal", ValueType::String).nullable(true),
+            ColumnSchema::new("time", ValueType::Float).unit("min"),
+            ColumnSchema::new("mz", ValueType::Float).nullable(true),
+            ColumnSchema::new("wavelength", ValueType::Float)
+                .nullable(true)
+                .unit("nm"),
+            ColumnSchema::new("intensity", ValueType::Float),
+        ];
+        match &self.selection {
+            Some(s) => s.project(&schema),
+            None => schema,
+        }
+    }
+
+    /// Only return the selected columns and rows.
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        self.selection = if selection.is_empty() {
+            None
+        } else {
+            Some(selection.resolve(&DIRECTORY_HEADERS)?)
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_chemstation_directory() -> Result<(), EtError> {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/data/chemstation_mwd.d");
+        let mut reader = AgilentDirectoryReader::new(&path)?;
+        assert_eq!(
+            reader.headers(),
+            [
+                "source_file",
+                "signal",
+                "time",
+                "mz",
+                "wavelength",
+                "intensity"
+            ]
+        );
+        let metadata = reader.metadata();
+        assert_eq!(
+            metadata["source_files"],
+            Value::List(vec![
+                "mwd1A.ch".into(),
+                "mwd1B.ch".into(),
+                "mwd1C.ch".into(),
+                "mwd1D.ch".into(),
+                "mwd1E.ch".into(),
+            ])
+        );
+
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], "mwd1A.ch".into());
+        assert_eq!(record[1], "MWD A, Sig=210,5 Ref=360,100".into());
+        assert_eq!(record[3], Value::Null);
+        assert_eq!(record[4], Value::Float(210.));
+
+        let mut n_rows = 1;
+        let mut last_file = String::new();
+        while let Some(record) = reader.next_record()? {
+            if let Value::String(s) = &record[0] {
+                last_file = s.to_string();
+            }
+            n_rows += 1;
+        }
+        assert_eq!(last_file, "mwd1E.ch");
+        assert!(n_rows > 5 * 1800);
+        Ok(())
+    }
+
+    #[test]
+    fn test_masshunter_directory() -> Result<(), EtError> {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/data/masshunter_example");
+        let mut reader = AgilentDirectoryReader::new(&path)?;
+        let record = reader.next_record()?.unwrap();
+        assert_eq!(record[0], Value::String("AcqData/DAD1.sp".into()));
+        assert_eq!(record[3], Value::Null);
+        assert_ne!(record[4], Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bad_directory() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("tests/data/masshunter_example/AcqData/RJB_Airs2001FIA.m");
+        assert!(AgilentDirectoryReader::new(&path).is_err());
+        path.push("../Devices.xml");
+        assert!(AgilentDirectoryReader::new(&path).is_err());
+    }
+}
@@ -7,8 +7,9 @@ use crate::buffer::ReadBuffer;
 use crate::impl_record;
 use crate::parsers::{Endian, FromSlice};
 use crate::readers::{init_state, RecordReader};
-use crate::record::{StateMetadata, Value};
-use crate::EtError;
+use crate::record::{merge_schemas, ColumnSchema, RecordSchema, StateMetadata, Value};
+use crate::select::{ResolvedSelection, SelectRecord, Selection};
+use crate::{ErrorKind, EtError};
 
 /// Store the current state of the `MasshunterDadReader`
 #[derive(Copy, Clone, Debug, Default)]
@@ -25,6 +26,14 @@ impl<'s> StateMetadata for MasshunterDadState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "wavelength", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("wavelength").unit("nm"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for MasshunterDadState {
@@ -153,6 +162,7 @@ pub struct MasshunterDadReader<'r> {
     header_rb: ReadBuffer<'r>,
     data_rb: ReadBuffer<'r>,
     state: MasshunterDadState,
+    selection: Option<ResolvedSelection>,
 }
 
 impl<'r> MasshunterDadReader<'r> {
@@ -167,6 +177,7 @@ impl<'r> MasshunterDadReader<'r> {
     {
         let filename = params.ok_or_else(|| {
             EtError::new("Parser requires a filename; streams can not be parsed.")
+                .with_kind(ErrorKind::InvalidParam)
         })?;
         let mut header_filename = Path::new(&filename).to_path_buf();
         let _ = header_filename.set_extension("sd");
@@ -178,6 +189,7 @@ impl<'r> MasshunterDadReader<'r> {
             header_rb,
             data_rb,
             state,
+            selection: None,
         })
     }
 
@@ -201,22 +213,61 @@ impl<'r> MasshunterDadReader<'r> {
 impl<'r> RecordReader for MasshunterDadReader<'r> {
     /// The next record, expressed as a `Vec` of `Value`s.
     fn next_record(&mut self) -> Result<Option<::alloc::vec::Vec<Value>>, EtError> {
-        Ok(self.next()?.map(core::convert::Into::into))
+        let selection = match &self.selection {
+            Some(s) => s,
+            None => return Ok(self.next()?.map(Into::into)),
+        };
+        while self
+            .header_rb
+            .next::<MasshunterDadHeaderRecord>(&mut self.state)?
+            .is_some()
+        {
+            let record = match self.data_rb.next::<MasshunterDadRecord>(&mut self.state)? {
+                Some(r) => r,
+                None => break,
+            };
+            if let Some(values) = record.select(selection) {
+                return Ok(Some(values));
+            }
+        }
+        Ok(None)
     }
 
     /// The headers for this Reader.
     fn headers(&self) -> ::alloc::vec::Vec<::alloc::string::String> {
-        self.state
-            .header()
-            .iter()
-            .map(|s| (*s).to_string())
-            .collect()
+        let headers = self.state.header();
+        match &self.selection {
+            Some(s) => s.project(&headers),
+            None => headers,
+        }
+        .iter()
+        .map(|s| (*s).to_string())
+        .collect()
     }
 
     /// The metadata for this Reader.
     fn metadata(&self) -> BTreeMap<String, Value> {
         self.state.metadata()
     }
+
+    /// The schema for this Reader.
+    fn schema(&self) -> Vec<ColumnSchema> {
+        let schema = merge_schemas(self.state.schema(), MasshunterDadRecord::schema());
+        match &self.selection {
+            Some(s) => s.project(&schema),
+            None => schema,
+        }
+    }
+
+    /// Only return the selected columns and rows.
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        self.selection = if selection.is_empty() {
+            None
+        } else {
+            Some(selection.resolve(&self.state.header())?)
+        };
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -7,11 +7,14 @@ pub mod chemstation;
 pub mod chemstation_new;
 // /// Reader for Chemstation's logging files
 // pub mod chemstation_reg;
+/// Reader for entire Agilent `.d` acquisition directories
+#[cfg(feature = "std")]
+pub mod directory;
 /// Readers for formats generated by the GC/LC control software Masshunter
 #[cfg(feature = "std")]
 pub mod masshunter;
 
-use crate::error::EtError;
+use crate::error::{ErrorKind, EtError};
 use crate::parsers::common::Skip;
 use crate::parsers::{extract, Endian, FromSlice};
 
@@ -24,16 +27,16 @@ pub(crate) fn read_agilent_header(rb: &[u8], ms_format: bool) -> Result<usize, E
     // figure out how big the header should be and then get it
     let raw_header_size = u32::extract(&rb[264..268], &Endian::Big)? as usize;
     if raw_header_size == 0 {
-        return Err("Invalid header length of 0".into());
+        return Err(EtError::from("Invalid header length of 0").with_kind(ErrorKind::InvalidValue));
     }
     let mut header_size = 2 * (raw_header_size - 1);
     if !ms_format {
         header_size *= 256;
     }
     if header_size < 512 {
-        return Err("Header length too short".into());
+        return Err(EtError::from("Header length too short").with_kind(ErrorKind::InvalidValue));
     } else if header_size > 20_000 {
-        return Err("Header length too long".into());
+        return Err(EtError::from("Header length too long").with_kind(ErrorKind::InvalidValue));
     }
     let con = &mut 0;
     let _ = extract::<Skip>(rb, con, &mut header_size)?;
@@ -158,6 +158,26 @@ impl<'b: 's, 's> FromSlice<'b, 's> for NewLine<'b> {
     }
 }
 
+/// Find where the first line after the start of `buf` that begins with `prefix` starts (or the
+/// end of the data if there isn't one).
+///
+/// Used by line-based formats to resynchronize after a malformed record.
+pub(crate) fn next_line_start(buf: &[u8], eof: bool, prefix: &[u8]) -> Result<usize, EtError> {
+    for pos in memchr_iter(b'\n', buf) {
+        let line = &buf[pos + 1..];
+        if line.starts_with(prefix) {
+            return Ok(pos + 1);
+        } else if !eof && line.len() < prefix.len() {
+            break;
+        }
+    }
+    if eof {
+        Ok(buf.len())
+    } else {
+        Err(EtError::new("Could not find the start of the next record").incomplete())
+    }
+}
+
 /// Used to read from a buffer until the given `state` slice is found and then discard everything before
 /// that `state` slice. Note that this never returns a consumed length of more than 0 because it
 /// silently updates the state as it consumes so it doesn't have to re-search the buffer if the
@@ -236,30 +256,3 @@ impl<'b: 's, 's> FromSlice<'b, 's> for Skip {
         Ok(())
     }
 }
-
-/// Used to skip ahead in a buffer
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
-pub(crate) struct EndOfFile;
-
-impl<'b: 's, 's> FromSlice<'b, 's> for EndOfFile {
-    type State = ();
-
-    #[inline]
-    fn parse(
-        buffer: &[u8],
-        eof: bool,
-        consumed: &mut usize,
-        _state: &mut Self::State,
-    ) -> Result<bool, EtError> {
-        if !eof {
-            return Err(EtError::from("No EOF yet").incomplete());
-        }
-        *consumed += buffer.len();
-        Ok(true)
-    }
-
-    #[inline]
-    fn get(&mut self, _buf: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
-        Ok(())
-    }
-}
@@ -3,10 +3,11 @@ use alloc::vec::Vec;
 
 use memchr::{memchr, memchr_iter};
 
+use crate::parsers::common::next_line_start;
 use crate::parsers::FromSlice;
 use crate::record::StateMetadata;
-use crate::EtError;
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 use alloc::borrow::Cow;
 
@@ -36,10 +37,12 @@ impl StateMetadata for FastaState {
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FastaState {
     type State = ();
+    const TEXT: bool = true;
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
     type State = FastaState;
+    const TEXT: bool = true;
 
     fn parse(
         rb: &[u8],
@@ -54,7 +57,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
             return Ok(false);
         }
         if rb[0] != b'>' {
-            return Err("Valid FASTA records start with '>'".into());
+            return Err(EtError::from("Valid FASTA records start with '>'")
+                .with_kind(ErrorKind::InvalidValue));
         }
         let seq_start = if let Some(p) = memchr(b'\n', rb) {
             if p > 0 && rb[p - 1] == b'\r' {
@@ -71,7 +75,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
 
         if let Some(p) = memchr(b'>', &rb[seq_start..]) {
             if p == 0 || rb.get(seq_start + p - 1) != Some(&b'\n') {
-                return Err("Unexpected '>' found".into());
+                return Err(
+                    EtError::from("Unexpected '>' found").with_kind(ErrorKind::InvalidValue)
+                );
             }
             if rb.get(seq_start + p - 2) == Some(&b'\r') {
                 parser_state.seq = (seq_start, seq_start + p - 2);
@@ -89,6 +95,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
         Ok(true)
     }
 
+    fn resync(buf: &[u8], eof: bool) -> Result<Option<usize>, EtError> {
+        next_line_start(buf, eof, b">").map(Some)
+    }
+
     fn get(&mut self, rb: &'b [u8], state: &Self::State) -> Result<(), EtError> {
         self.id = alloc::str::from_utf8(&rb[1..state.header_end])?;
         let raw_sequence = &rb[state.seq.0..state.seq.1];
@@ -115,6 +125,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastaRecord<'b> {
 
 impl_reader!(FastaReader, FastaRecord, FastaRecord<'r>, FastaState, ());
 
+#[cfg(feature = "parallel")]
+crate::parallel::impl_par_records!(FastaReader, FastaRecord, |buf| buf.first() == Some(&b'>'));
+
 #[cfg(test)]
 mod tests {
     use alloc::borrow::Cow;
@@ -2,10 +2,11 @@ use alloc::vec;
 use alloc::vec::Vec;
 use memchr::memchr;
 
+use crate::parsers::common::next_line_start;
 use crate::parsers::FromSlice;
 use crate::record::StateMetadata;
-use crate::EtError;
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 #[derive(Clone, Debug, Default)]
 /// A single sequence with quality data from a FASTQ file
@@ -37,10 +38,12 @@ impl StateMetadata for FastqState {
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FastqState {
     type State = ();
+    const TEXT: bool = true;
 }
 
-impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
+impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'b> {
     type State = FastqState;
+    const TEXT: bool = true;
 
     fn parse(
         buf: &[u8],
@@ -55,7 +58,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
             return Err(EtError::new("No FASTQ could be parsed").incomplete());
         }
         if buf[0] != b'@' {
-            return Err("Valid FASTQ records start with '@'".into());
+            return Err(EtError::from("Valid FASTQ records start with '@'")
+                .with_kind(ErrorKind::InvalidValue));
         }
         // figure out where the first id/header line ends
         let seq_start = if let Some(p) = memchr(b'\n', buf) {
@@ -72,7 +76,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
         // figure out where the sequence data is
         let id2_start = if let Some(p) = memchr(b'+', &buf[seq_start..]) {
             if p == 0 || buf[seq_start + p - 1] != b'\n' {
-                return Err("Unexpected + found in sequence".into());
+                return Err(EtError::from("Unexpected + found in sequence")
+                    .with_kind(ErrorKind::InvalidValue));
             }
             // the + is technically part of the next header so we're
             // already one short before we even check the \r
@@ -109,6 +114,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
         Ok(true)
     }
 
+    fn resync(buf: &[u8], eof: bool) -> Result<Option<usize>, EtError> {
+        next_line_start(buf, eof, b"@").map(Some)
+    }
+
     fn get(&mut self, buf: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         self.id = alloc::str::from_utf8(&buf[1..state.header_end])?;
         self.sequence = &buf[state.seq.0..state.seq.1];
@@ -119,6 +128,28 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FastqRecord<'s> {
 
 impl_reader!(FastqReader, FastqRecord, FastqRecord<'r>, FastqState, ());
 
+/// Check if `buf` looks like it starts with a FASTQ record (a quality line can also start with
+/// an `@` so this also checks for the `+` line and that the sequence and quality line up).
+#[cfg(feature = "parallel")]
+fn is_record_start(buf: &[u8]) -> bool {
+    if buf.first() != Some(&b'@') {
+        return false;
+    }
+    let mut lines = buf
+        .split(|c| *c == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l));
+    let _ = lines.next();
+    match (lines.next(), lines.next(), lines.next()) {
+        (Some(seq), Some(plus), Some(qual)) => {
+            plus.first() == Some(&b'+') && seq.len() == qual.len()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "parallel")]
+crate::parallel::impl_par_records!(FastqReader, FastqRecord, is_record_start);
+
 #[cfg(test)]
 mod tests {
     use super::*;
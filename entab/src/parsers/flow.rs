@@ -7,11 +7,185 @@ use core::default::Default;
 
 use chrono::{NaiveDate, NaiveTime};
 
+use crate::batch::{BatchRecord, ColumnBatch};
 use crate::impl_reader;
+use crate::params::{take_bool, take_float, take_list, FromParams, ParamInfo};
 use crate::parsers::common::Skip;
 use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
-use crate::EtError;
+use crate::record::{ColumnSchema, RecordSchema, StateMetadata, Value, ValueType};
+use crate::select::{ResolvedSelection, SelectRecord};
+use crate::{ErrorKind, EtError};
+
+/// A transform to apply to channel values after scaling and compensation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FcsTransform {
+    /// `asinh(x / cofactor)`
+    Arcsinh {
+        /// The value the channel is divided by before the transform
+        cofactor: f64,
+    },
+    /// The logicle transform from Parks et al., 2006
+    Logicle {
+        /// The top of the scale
+        t: f64,
+        /// The width of the linear region, in decades
+        w: f64,
+        /// The total width of the display, in decades
+        m: f64,
+        /// Additional decades of negative values to display
+        a: f64,
+    },
+}
+
+impl FcsTransform {
+    /// An arcsinh transform with the default flow cytometry cofactor of 150
+    #[must_use]
+    pub fn arcsinh() -> Self {
+        FcsTransform::Arcsinh { cofactor: 150. }
+    }
+
+    /// A logicle transform with the commonly-used default parameters
+    #[must_use]
+    pub fn logicle() -> Self {
+        FcsTransform::Logicle {
+            t: 262_144.,
+            w: 0.5,
+            m: 4.5,
+            a: 0.,
+        }
+    }
+}
+
+/// Parameters for post-processing the values in an FCS file
+///
+/// These are applied to every record in the order: scaling, compensation and then transformation.
+#[derive(Clone, Debug, Default)]
+pub struct FcsParams {
+    /// Apply the `$PnE` log amplification and `$PnG` gain to each channel.
+    pub scale: bool,
+    /// Compensate the channels with the `$SPILLOVER` (or `SPILL`) matrix in the file.
+    pub compensate: bool,
+    /// Transform the channels with an arcsinh or logicle transform.
+    pub transform: Option<FcsTransform>,
+    /// The channels to transform; if not set, the channels in the spillover matrix (or all of the
+    /// channels if there isn't one) are transformed.
+    pub transform_channels: Option<Vec<String>>,
+}
+
+impl FcsParams {
+    /// Apply the `$PnE` and `$PnG` scaling
+    #[must_use]
+    pub fn scale(mut self, scale: bool) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Apply the spillover matrix
+    #[must_use]
+    pub fn compensate(mut self, compensate: bool) -> Self {
+        self.compensate = compensate;
+        self
+    }
+
+    /// Set the transform to apply
+    #[must_use]
+    pub fn transform(mut self, transform: FcsTransform) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+}
+
+impl FromParams for FcsParams {
+    const PARAMS: &'static [ParamInfo] = &[
+        ParamInfo::new(
+            "scale",
+            ValueType::Boolean,
+            "Apply the $PnE log amplification and $PnG gain to each channel",
+        ),
+        ParamInfo::new(
+            "compensate",
+            ValueType::Boolean,
+            "Compensate the channels with the spillover matrix in the file",
+        ),
+        ParamInfo::new(
+            "transform",
+            ValueType::String,
+            "Transform the channels with \"arcsinh\" or \"logicle\"",
+        ),
+        ParamInfo::new(
+            "cofactor",
+            ValueType::Float,
+            "The cofactor for the arcsinh transform (default 150)",
+        ),
+        ParamInfo::new(
+            "logicle_t",
+            ValueType::Float,
+            "The top of the scale for the logicle transform (default 262144)",
+        ),
+        ParamInfo::new(
+            "logicle_w",
+            ValueType::Float,
+            "The linear width in decades for the logicle transform (default 0.5)",
+        ),
+        ParamInfo::new(
+            "logicle_m",
+            ValueType::Float,
+            "The total width in decades for the logicle transform (default 4.5)",
+        ),
+        ParamInfo::new(
+            "logicle_a",
+            ValueType::Float,
+            "Additional negative decades for the logicle transform (default 0)",
+        ),
+        ParamInfo::new(
+            "transform_channels",
+            ValueType::List,
+            "The channels to transform (defaults to the compensated channels)",
+        ),
+    ];
+
+    fn from_params(params: &mut BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let mut fcs_params = FcsParams::default()
+            .scale(take_bool(params, "scale")?.unwrap_or(false))
+            .compensate(take_bool(params, "compensate")?.unwrap_or(false));
+        let cofactor = take_float(params, "cofactor")?;
+        let t = take_float(params, "logicle_t")?;
+        let w = take_float(params, "logicle_w")?;
+        let m = take_float(params, "logicle_m")?;
+        let a = take_float(params, "logicle_a")?;
+        match params.remove("transform") {
+            None => {}
+            Some(Value::String(s)) if s == "arcsinh" => {
+                fcs_params = fcs_params.transform(FcsTransform::Arcsinh {
+                    cofactor: cofactor.unwrap_or(150.),
+                });
+            }
+            Some(Value::String(s)) if s == "logicle" => {
+                fcs_params = fcs_params.transform(FcsTransform::Logicle {
+                    t: t.unwrap_or(262_144.),
+                    w: w.unwrap_or(0.5),
+                    m: m.unwrap_or(4.5),
+                    a: a.unwrap_or(0.),
+                });
+            }
+            Some(v) => {
+                return Err(EtError::from(format!("Unknown FCS transform {:?}", v))
+                    .with_kind(ErrorKind::InvalidParam))
+            }
+        }
+        if let Some(values) = take_list(params, "transform_channels") {
+            let mut channels = Vec::new();
+            for v in values {
+                channels.push(v.into_string().map_err(|_| {
+                    EtError::from("Param transform_channels must be a list of channels")
+                        .with_kind(ErrorKind::InvalidParam)
+                })?);
+            }
+            fcs_params.transform_channels = Some(channels);
+        }
+        Ok(fcs_params)
+    }
+}
 
 #[derive(Clone, Debug, Default)]
 struct FcsColumn {
@@ -20,6 +194,268 @@ struct FcsColumn {
     range: u64,
     short_name: String,
     long_name: String,
+    log_decades: f64,
+    log_offset: f64,
+    gain: f64,
+}
+
+impl FcsColumn {
+    /// Convert a raw channel value into a scale value using `$PnE` and `$PnG`.
+    fn scale(&self, value: f64) -> f64 {
+        if self.log_decades > 0. {
+            // older files sometimes have a zero offset for log channels; the spec says to use 1
+            let offset = if self.log_offset > 0. {
+                self.log_offset
+            } else {
+                1.
+            };
+            let range = if self.range > 0 {
+                self.range as f64
+            } else {
+                1.
+            };
+            pow10(self.log_decades * value / range) * offset
+        } else if self.gain > 0. {
+            value / self.gain
+        } else {
+            value
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn pow10(x: f64) -> f64 {
+    10_f64.powf(x)
+}
+
+#[cfg(not(feature = "std"))]
+fn pow10(_x: f64) -> f64 {
+    unreachable!("Log scaling is rejected without the std feature")
+}
+
+/// The logicle transform, with its constants precomputed
+///
+/// See Moore and Parks, 2012, "Update for the logicle data scale including operational code
+/// implementations" for the derivation of these.
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Logicle {
+    a: f64,
+    b: f64,
+    c: f64,
+    d: f64,
+    f: f64,
+    x1: f64,
+}
+
+#[cfg(feature = "std")]
+impl Logicle {
+    fn new(t: f64, w: f64, m: f64, a: f64) -> Result<Self, EtError> {
+        #[allow(clippy::suboptimal_flops)]
+        if t <= 0. || m <= 0. || w < 0. || 2. * w > m || -a > w || a + w > m - w {
+            return Err(
+                EtError::from("Invalid logicle parameters").with_kind(ErrorKind::InvalidParam)
+            );
+        }
+        let w = w / (m + a);
+        let x2 = a / (m + a);
+        let x1 = x2 + w;
+        let x0 = x2 + 2. * w;
+        let b = (m + a) * core::f64::consts::LN_10;
+
+        // d is the root of 2 * (ln(d) - ln(b)) + w * (b + d) = 0 on (0, b]
+        let d = if w == 0. {
+            b
+        } else {
+            let (mut lo, mut hi) = (0., b);
+            for _ in 0..200 {
+                let mid = (lo + hi) / 2.;
+                if 2. * (f64::ln(mid) - f64::ln(b)) + w * (b + mid) < 0. {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            (lo + hi) / 2.
+        };
+
+        let c_a = (x0 * (b + d)).exp();
+        let mf_a = (b * x1).exp() - c_a / (d * x1).exp();
+        let a = t / (b.exp() - mf_a - c_a / d.exp());
+        Ok(Logicle {
+            a,
+            b,
+            c: c_a * a,
+            d,
+            f: -mf_a * a,
+            x1,
+        })
+    }
+
+    /// The biexponential function the logicle scale is the inverse of (for `y >= x1`)
+    fn biexponential(&self, y: f64) -> f64 {
+        self.a * (self.b * y).exp() - self.c * (-self.d * y).exp() + self.f
+    }
+
+    fn apply(&self, value: f64) -> f64 {
+        if !value.is_finite() {
+            return value;
+        }
+        let target = value.abs();
+        let (mut lo, mut hi) = (self.x1, 1.);
+        while self.biexponential(hi) < target {
+            hi = self.x1 + 2. * (hi - self.x1);
+        }
+        for _ in 0..64 {
+            let mid = (lo + hi) / 2.;
+            if self.biexponential(mid) < target {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        let y = (lo + hi) / 2.;
+        if value < 0. {
+            2. * self.x1 - y
+        } else {
+            y
+        }
+    }
+}
+
+/// A transform that's been prepared for a specific file
+///
+/// Without the std feature there's no way to compute these so there are no variants at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PreparedTransform {
+    #[cfg(feature = "std")]
+    Arcsinh(f64),
+    #[cfg(feature = "std")]
+    Logicle(Logicle),
+}
+
+impl PreparedTransform {
+    #[cfg(feature = "std")]
+    fn new(transform: FcsTransform) -> Result<Self, EtError> {
+        Ok(match transform {
+            FcsTransform::Arcsinh { cofactor } => {
+                if cofactor <= 0. {
+                    return Err(EtError::from("Arcsinh cofactor must be positive")
+                        .with_kind(ErrorKind::InvalidParam));
+                }
+                PreparedTransform::Arcsinh(cofactor)
+            }
+            FcsTransform::Logicle { t, w, m, a } => {
+                PreparedTransform::Logicle(Logicle::new(t, w, m, a)?)
+            }
+        })
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn new(_transform: FcsTransform) -> Result<Self, EtError> {
+        Err(EtError::from("FCS transforms require the std feature")
+            .with_kind(ErrorKind::UnsupportedFormat))
+    }
+
+    #[cfg(feature = "std")]
+    fn apply(&self, value: f64) -> f64 {
+        match self {
+            PreparedTransform::Arcsinh(cofactor) => (value / cofactor).asinh(),
+            PreparedTransform::Logicle(logicle) => logicle.apply(value),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn apply(&self, _value: f64) -> f64 {
+        match *self {}
+    }
+}
+
+/// Invert a square matrix with Gauss-Jordan elimination.
+fn invert_matrix(matrix: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, EtError> {
+    let n = matrix.len();
+    let mut left = matrix.to_vec();
+    let mut right: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1. } else { 0. }).collect())
+        .collect();
+    for col in 0..n {
+        let mut pivot = col;
+        for row in col + 1..n {
+            if left[row][col].abs() > left[pivot][col].abs() {
+                pivot = row;
+            }
+        }
+        if left[pivot][col].abs() < f64::EPSILON {
+            return Err(EtError::from("Spillover matrix can not be inverted")
+                .with_kind(ErrorKind::InvalidValue));
+        }
+        left.swap(col, pivot);
+        right.swap(col, pivot);
+        let scale = left[col][col];
+        for j in 0..n {
+            left[col][j] /= scale;
+            right[col][j] /= scale;
+        }
+        for row in 0..n {
+            if row == col {
+                continue;
+            }
+            let factor = left[row][col];
+            for j in 0..n {
+                left[row][j] -= factor * left[col][j];
+                right[row][j] -= factor * right[col][j];
+            }
+        }
+    }
+    Ok(right)
+}
+
+/// Parse a `$SPILLOVER` value into the indices of the channels it covers and the inverse of its
+/// matrix.
+fn parse_spillover(
+    value: &str,
+    params: &[FcsColumn],
+) -> Result<(Vec<usize>, Vec<Vec<f64>>), EtError> {
+    let mut items = value.split(',').map(str::trim);
+    let n: usize = items
+        .next()
+        .ok_or_else(|| EtError::from("Empty spillover matrix").with_kind(ErrorKind::InvalidValue))?
+        .parse()?;
+    let mut channels = Vec::with_capacity(n);
+    for _ in 0..n {
+        let name = items.next().ok_or_else(|| {
+            EtError::from("Spillover matrix is missing channel names")
+                .with_kind(ErrorKind::InvalidValue)
+        })?;
+        // channels are usually listed by name, but some files use the parameter numbers instead
+        let channel = params
+            .iter()
+            .position(|p| p.short_name == name)
+            .or_else(|| name.parse::<usize>().ok().and_then(|i| i.checked_sub(1)))
+            .filter(|i| *i < params.len())
+            .ok_or_else(|| {
+                EtError::from(format!("Spillover channel {} not found", name))
+                    .with_kind(ErrorKind::InvalidValue)
+            })?;
+        channels.push(channel);
+    }
+    let mut matrix = Vec::with_capacity(n);
+    for _ in 0..n {
+        let mut row = Vec::with_capacity(n);
+        for _ in 0..n {
+            row.push(
+                items
+                    .next()
+                    .ok_or_else(|| {
+                        EtError::from("Spillover matrix is missing values")
+                            .with_kind(ErrorKind::InvalidValue)
+                    })?
+                    .parse()?,
+            );
+        }
+        matrix.push(row);
+    }
+    Ok((channels, invert_matrix(&matrix)?))
 }
 
 /// State of an `FcsReader`.
@@ -34,6 +470,47 @@ pub struct FcsState {
     n_events_left: usize,
     bytes_data_left: usize,
     metadata: BTreeMap<String, Value<'static>>,
+    options: FcsParams,
+    compensation: Option<(Vec<usize>, Vec<Vec<f64>>)>,
+    transform: Option<(PreparedTransform, Vec<usize>)>,
+}
+
+impl FcsState {
+    /// If any scaling, compensation, or transformation is applied to the records.
+    fn is_processing(&self) -> bool {
+        self.options.scale || self.compensation.is_some() || self.transform.is_some()
+    }
+
+    /// Scale, compensate, and transform a record's values according to the params.
+    fn process(&self, values: &mut [Value<'_>]) {
+        let mut floats: Vec<f64> = values
+            .iter()
+            .map(|v| match v {
+                Value::Float(f) => *f,
+                Value::Integer(i) => *i as f64,
+                _ => f64::NAN,
+            })
+            .collect();
+        if self.options.scale {
+            for (value, param) in floats.iter_mut().zip(&self.params) {
+                *value = param.scale(*value);
+            }
+        }
+        if let Some((channels, inverse)) = &self.compensation {
+            let raw: Vec<f64> = channels.iter().map(|c| floats[*c]).collect();
+            for (j, channel) in channels.iter().enumerate() {
+                floats[*channel] = raw.iter().zip(inverse).map(|(r, row)| r * row[j]).sum();
+            }
+        }
+        if let Some((transform, channels)) = &self.transform {
+            for channel in channels {
+                floats[*channel] = transform.apply(floats[*channel]);
+            }
+        }
+        for (value, float) in values.iter_mut().zip(floats) {
+            *value = Value::Float(float);
+        }
+    }
 }
 
 impl StateMetadata for FcsState {
@@ -49,90 +526,118 @@ impl StateMetadata for FcsState {
         }
         headers
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        let value_type = if self.data_type == 'I' && !self.is_processing() {
+            ValueType::Integer
+        } else {
+            ValueType::Float
+        };
+        self.params
+            .iter()
+            .map(|param| ColumnSchema::new(&param.short_name, value_type))
+            .collect()
+    }
+}
+
+/// Read the key-value pairs out of the TEXT segment at the start of an FCS data set.
+///
+/// Returns them along with the position of the end of the TEXT segment.
+fn read_text(buf: &[u8]) -> Result<(BTreeMap<String, String>, usize), EtError> {
+    let mut map = BTreeMap::new();
+    let con = &mut 0;
+
+    let magic = extract::<&[u8]>(buf, con, &mut 10)?;
+    if &magic[..3] != b"FCS" {
+        return Err(EtError::from("FCS file has invalid header").with_kind(ErrorKind::InvalidMagic));
+    }
+
+    // get the offsets to the different data
+    let text_start: usize = extract::<&str>(buf, con, &mut 8)?.trim().parse()?;
+    let text_end: usize = extract::<&str>(buf, con, &mut 8)?.trim().parse()?;
+    if text_end < text_start {
+        return Err(
+            EtError::from("Invalid end from text segment").with_kind(ErrorKind::InvalidValue)
+        );
+    }
+    if text_start < 58 {
+        return Err(EtError::from("Bad FCS text start offset").with_kind(ErrorKind::InvalidValue));
+    }
+    if buf.len() < text_end {
+        return Err(EtError::from("Text segment shorter than specified").incomplete());
+    }
+    drop(map.insert(
+        "$BEGINDATA".to_string(),
+        extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
+    ));
+    drop(map.insert(
+        "$ENDDATA".to_string(),
+        extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
+    ));
+    drop(map.insert(
+        "$BEGINANALYSIS".to_string(),
+        extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
+    ));
+    drop(map.insert(
+        "$ENDANALYSIS".to_string(),
+        extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
+    ));
+    let _ = extract::<Skip>(buf, con, &mut (text_start - 58))?;
+    let delim: u8 = extract(buf, con, &mut Endian::Little)?;
+    // The spec says repeated delimiters should be parsed as an escaped delimiter, but I've
+    // never seen that so we parse them as empty values (which I have seen in Applied
+    // Biosystems files) which allows us to simplify the parsing logic a lot.
+    let params = extract::<&[u8]>(buf, con, &mut (text_end.saturating_sub(*con)))?;
+    let mut key: Option<String> = None;
+    for item in params.split(|b| b == &delim) {
+        if let Some(k) = key {
+            let value = String::from_utf8_lossy(item);
+            if &k == "$BEGINDATA" || &k == "$ENDDATA" {
+                if map[&k] == "0" {
+                    drop(map.insert(k.to_string(), value.trim().into()));
+                }
+            } else {
+                drop(map.insert(k.to_string(), value.into()));
+            }
+            key = None;
+        } else {
+            key = Some(str::from_utf8(item)?.to_ascii_uppercase());
+        }
+    }
+    Ok((map, *con))
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
-    type State = BTreeMap<String, String>;
+    type State = FcsParams;
 
     fn parse(
         buf: &[u8],
         _eof: bool,
         consumed: &mut usize,
-        map: &mut Self::State,
+        _state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        let con = &mut 0;
-
-        let magic = extract::<&[u8]>(buf, con, &mut 10)?;
-        if &magic[..3] != b"FCS" {
-            return Err("FCS file has invalid header".into());
-        }
-
-        // get the offsets to the different data
-        let text_start: usize = extract::<&str>(buf, con, &mut 8)?.trim().parse()?;
-        let text_end: usize = extract::<&str>(buf, con, &mut 8)?.trim().parse()?;
-        if text_end < text_start {
-            return Err("Invalid end from text segment".into());
-        }
-        if text_start < 58 {
-            return Err("Bad FCS text start offset".into());
-        }
-        if buf.len() < text_end {
-            return Err(EtError::from("Text segment shorter than specified").incomplete());
-        }
-        drop(map.insert(
-            "$BEGINDATA".to_string(),
-            extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
-        ));
-        drop(map.insert(
-            "$ENDDATA".to_string(),
-            extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
-        ));
-        drop(map.insert(
-            "$BEGINANALYSIS".to_string(),
-            extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
-        ));
-        drop(map.insert(
-            "$ENDANALYSIS".to_string(),
-            extract::<&str>(buf, con, &mut 8)?.trim().to_string(),
-        ));
-        let _ = extract::<Skip>(buf, con, &mut (text_start - 58))?;
-        let delim: u8 = extract(buf, con, &mut Endian::Little)?;
-        // The spec says repeated delimiters should be parsed as an escaped delimiter, but I've
-        // never seen that so we parse them as empty values (which I have seen in Applied
-        // Biosystems files) which allows us to simplify the parsing logic a lot.
-        let params = extract::<&[u8]>(buf, con, &mut (text_end.saturating_sub(*con)))?;
-        let mut key: Option<String> = None;
-        for item in params.split(|b| b == &delim) {
-            if let Some(k) = key {
-                let value = String::from_utf8_lossy(item);
-                if &k == "$BEGINDATA" || &k == "$ENDDATA" {
-                    if map[&k] == "0" {
-                        drop(map.insert(k.to_string(), value.trim().into()));
-                    }
-                } else {
-                    drop(map.insert(k.to_string(), value.into()));
-                }
-                key = None;
-            } else {
-                key = Some(str::from_utf8(item)?.to_ascii_uppercase());
-            }
-        }
+        let (map, text_end) = read_text(buf)?;
         let data_start: usize = map["$BEGINDATA"].parse()?;
         let data_end: usize = map["$ENDDATA"].parse()?;
         if data_end < data_start {
-            return Err("Invalid end from data segment".into());
+            return Err(
+                EtError::from("Invalid end from data segment").with_kind(ErrorKind::InvalidValue)
+            );
         }
         // get anything between the end of the text segment and the start of the data segment
         if data_start > text_end {
-            let _ = extract::<Skip>(buf, con, &mut (data_start - *con))?;
+            let _ = extract::<Skip>(buf, &mut text_end.clone(), &mut (data_start - text_end))?;
         }
 
-        *consumed += data_start;
+        // the TEXT segment's read out again in `get` so it has to be part of what's consumed even
+        // if the data supposedly starts inside it
+        *consumed += data_start.max(text_end);
         Ok(true)
     }
 
     #[allow(clippy::too_many_lines)]
-    fn get(&mut self, _buf: &'b [u8], map: &'s Self::State) -> Result<(), EtError> {
+    fn get(&mut self, buf: &'b [u8], options: &'s Self::State) -> Result<(), EtError> {
+        let (map, _) = read_text(buf)?;
         let mut params = Vec::new();
         let mut endian = Endian::Little;
         let mut data_type = 'F';
@@ -155,12 +660,21 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
                 ("$DATATYPE", "D") => data_type = 'D',
                 ("$DATATYPE", "F") => data_type = 'F',
                 ("$DATATYPE", "I") => data_type = 'I',
-                ("$DATATYPE", v) => return Err(format!("Unknown FCS $DATATYPE {}", v).into()),
+                ("$DATATYPE", v) => {
+                    return Err(EtError::from(format!("Unknown FCS $DATATYPE {}", v))
+                        .with_kind(ErrorKind::UnsupportedFormat))
+                }
                 ("$MODE", "L") => {}
                 ("$MODE", "C" | "U") => {
-                    return Err("FCS histograms not yet supported ($MODE=C/U)".into())
+                    return Err(
+                        EtError::from("FCS histograms not yet supported ($MODE=C/U)")
+                            .with_kind(ErrorKind::UnsupportedFormat),
+                    )
+                }
+                ("$MODE", v) => {
+                    return Err(EtError::from(format!("Unknown FCS $MODE {}", v))
+                        .with_kind(ErrorKind::UnsupportedFormat))
                 }
-                ("$MODE", v) => return Err(format!("Unknown FCS $MODE {}", v).into()),
                 ("$TOT", v) => n_events_left = v.trim().parse()?,
                 ("$BTIM", v) => {
                     // TODO: sometimes there's a fractional (/60) part after the last colon
@@ -214,11 +728,13 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
                 ("$PAR", v) => {
                     let n_params = v.trim().parse()?;
                     if n_params < params.len() {
-                        return Err(format!("Declared number of params ({}) is less than the observed number of params ({})", n_params, params.len()).into());
+                        return Err(EtError::from(format!("Declared number of params ({}) is less than the observed number of params ({})", n_params, params.len())).with_kind(ErrorKind::InvalidValue));
                     }
                     params.resize_with(n_params, FcsColumn::default);
                 }
-                (k, v) if k.starts_with("$P") && k.ends_with(&['B', 'N', 'R', 'S'][..]) => {
+                (k, v)
+                    if k.starts_with("$P") && k.ends_with(&['B', 'E', 'G', 'N', 'R', 'S'][..]) =>
+                {
                     let mut i: usize = k[2..k.len() - 1].parse()?;
                     i -= 1; // params are numbered from 1
                     if i >= params.len() {
@@ -246,6 +762,13 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
                         }
                     } else if k.ends_with('S') {
                         params[i].long_name = v.to_string();
+                    } else if k.ends_with('E') {
+                        // "decades,offset"; FCS 2.0 files sometimes only have the decades
+                        let mut parts = v.split(',').map(str::trim);
+                        params[i].log_decades = parts.next().unwrap_or("0").parse()?;
+                        params[i].log_offset = parts.next().unwrap_or("0").parse()?;
+                    } else if k.ends_with('G') {
+                        params[i].gain = v.trim().parse()?;
                     }
                 }
                 _ => {}
@@ -266,18 +789,62 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
             match data_type {
                 'D' => {
                     if p.size != 64 {
-                        return Err("Param size must be 64 for $DATATYPE=D".into());
+                        return Err(EtError::from("Param size must be 64 for $DATATYPE=D")
+                            .with_kind(ErrorKind::InvalidValue));
                     }
                 }
                 'F' => {
                     if p.size != 32 {
-                        return Err("Param size must be 32 for $DATATYPE=F".into());
+                        return Err(EtError::from("Param size must be 32 for $DATATYPE=F")
+                            .with_kind(ErrorKind::InvalidValue));
                     }
                 }
                 _ => {}
             }
         }
 
+        let compensation = if options.compensate {
+            let spillover = map
+                .get("$SPILLOVER")
+                .or_else(|| map.get("SPILL"))
+                .ok_or_else(|| {
+                    EtError::from("No spillover matrix found to compensate with")
+                        .with_kind(ErrorKind::InvalidValue)
+                })?;
+            Some(parse_spillover(spillover, &params)?)
+        } else {
+            None
+        };
+        if !cfg!(feature = "std") && options.scale && params.iter().any(|p| p.log_decades > 0.) {
+            return Err(
+                EtError::from("Log scaling FCS channels requires the std feature")
+                    .with_kind(ErrorKind::UnsupportedFormat),
+            );
+        }
+        let transform = if let Some(t) = options.transform {
+            let channels = if let Some(names) = &options.transform_channels {
+                names
+                    .iter()
+                    .map(|name| {
+                        params
+                            .iter()
+                            .position(|p| &p.short_name == name)
+                            .ok_or_else(|| {
+                                EtError::from(format!("Channel {} not found", name))
+                                    .with_kind(ErrorKind::InvalidParam)
+                            })
+                    })
+                    .collect::<Result<_, EtError>>()?
+            } else if let Some((channels, _)) = &compensation {
+                channels.clone()
+            } else {
+                (0..params.len()).collect()
+            };
+            Some((PreparedTransform::new(t)?, channels))
+        } else {
+            None
+        };
+
         self.params = params;
         self.endian = endian;
         self.data_type = data_type;
@@ -285,6 +852,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsState {
         self.n_events_left = n_events_left;
         self.bytes_data_left = data_end - data_start + 1;
         self.metadata = metadata;
+        self.options = options.clone();
+        self.compensation = compensation;
+        self.transform = transform;
         Ok(())
     }
 }
@@ -315,7 +885,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsRecord<'s> {
         if state.n_events_left == 0 {
             if let Some(next_data) = state.next_data {
                 let _ = extract::<Skip>(buf, con, &mut (next_data + state.bytes_data_left - 1))?;
-                let mut headers = BTreeMap::new();
+                let mut headers = state.options.clone();
                 let start = *con;
                 if !FcsState::parse(&buf[*con..], eof, con, &mut headers)? {
                     return Ok(false);
@@ -330,13 +900,17 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsRecord<'s> {
             *con += match state.data_type {
                 'A' if !param.delimited => param.size as usize,
                 'A' if param.delimited => {
-                    return Err("Delimited-ASCII number datatypes are not yet supported".into());
+                    return Err(EtError::from(
+                        "Delimited-ASCII number datatypes are not yet supported",
+                    )
+                    .with_kind(ErrorKind::UnsupportedFormat));
                 }
                 'D' => 8,
                 'F' => 4,
                 'I' => {
                     if param.size % 8 != 0 {
-                        return Err(format!("Unknown param size {}", param.size).into());
+                        return Err(EtError::from(format!("Unknown param size {}", param.size))
+                            .with_kind(ErrorKind::UnsupportedFormat));
                     }
                     param.size as usize / 8
                 }
@@ -365,7 +939,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsRecord<'s> {
                     str::from_utf8(n)?.trim().parse::<f64>()?.into()
                 }
                 'A' if param.delimited => {
-                    return Err("Delimited-ASCII number datatypes are not yet supported".into());
+                    return Err(EtError::from(
+                        "Delimited-ASCII number datatypes are not yet supported",
+                    )
+                    .with_kind(ErrorKind::UnsupportedFormat));
                 }
                 'D' => extract::<f64>(buf, con, &mut state.endian.clone())?.into(),
                 'F' => extract::<f32>(buf, con, &mut state.endian.clone())?.into(),
@@ -382,11 +959,15 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsRecord<'s> {
                         }
                         32 => extract::<u32>(buf, con, &mut state.endian.clone())?.into(),
                         64 => extract::<u64>(buf, con, &mut state.endian.clone())?,
-                        x => return Err(format!("Unknown param size {}", x).into()),
+                        x => {
+                            return Err(EtError::from(format!("Unknown param size {}", x))
+                                .with_kind(ErrorKind::UnsupportedFormat))
+                        }
                     };
                     if value > param.range && param.range > 0 {
                         if param.range.count_ones() != 1 {
-                            return Err("Only ranges of power 2 can mask values".into());
+                            return Err(EtError::from("Only ranges of power 2 can mask values")
+                                .with_kind(ErrorKind::InvalidValue));
                         }
                         let range_mask = param.range - 1;
                         (value & range_mask).into()
@@ -397,22 +978,40 @@ impl<'b: 's, 's> FromSlice<'b, 's> for FcsRecord<'s> {
                 _ => panic!("Data type is in an unknown state"),
             };
         }
+        if state.is_processing() {
+            state.process(&mut self.values);
+        }
         Ok(())
     }
 }
 
+impl RecordSchema for FcsRecord<'_> {}
+
 impl<'r> From<FcsRecord<'r>> for Vec<Value<'r>> {
     fn from(record: FcsRecord<'r>) -> Self {
         record.values
     }
 }
 
-impl_reader!(FcsReader, FcsRecord, FcsRecord<'r>, FcsState, BTreeMap<String, String>);
+impl<'r> SelectRecord<'r> for FcsRecord<'r> {
+    fn matches(&self, selection: &ResolvedSelection) -> bool {
+        selection.matches(&self.values)
+    }
+}
+
+impl BatchRecord for FcsRecord<'_> {
+    fn push_to(self, batch: &mut ColumnBatch) -> Result<(), EtError> {
+        batch.push_record(self.into())
+    }
+}
+
+impl_reader!(FcsReader, FcsRecord, FcsRecord<'r>, FcsState, FcsParams);
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::readers::RecordReader;
+    use alloc::vec;
 
     #[test]
     fn test_fcs_reader() -> Result<(), EtError> {
@@ -436,6 +1035,10 @@ mod tests {
             ]
         );
 
+        let schema = reader.schema();
+        assert_eq!(schema.len(), 11);
+        assert_eq!(schema[0], ColumnSchema::new("FSC-A", ValueType::Float));
+
         let record = reader.next()?.expect("Reader returns at least one value");
         assert_eq!(record.values.len(), 11);
 
@@ -461,6 +1064,150 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_fcs_scaling() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let mut reader = FcsReader::new(buf, None)?;
+        let raw = reader.next()?.unwrap().values;
+        let mut reader = FcsReader::new(buf, Some(FcsParams::default().scale(true)))?;
+        let scaled = reader.next()?.unwrap().values;
+        // only the Time channel has a gain (of 0.01) in this file
+        assert_eq!(scaled[0], raw[0]);
+        match (&raw[10], &scaled[10]) {
+            (Value::Float(r), Value::Float(s)) => assert!((s - r * 100.).abs() < 1e-6),
+            _ => panic!("Time should be a float"),
+        }
+
+        let column = FcsColumn {
+            range: 1024,
+            gain: 2.,
+            ..FcsColumn::default()
+        };
+        assert!((column.scale(512.) - 256.).abs() < 1e-9);
+        #[cfg(feature = "std")]
+        {
+            let column = FcsColumn {
+                log_decades: 4.,
+                log_offset: 1.,
+                ..column
+            };
+            assert!((column.scale(512.) - 100.).abs() < 1e-9);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_compensation() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let mut reader = FcsReader::new(buf, None)?;
+        let raw = reader.next()?.unwrap().values;
+        let mut reader = FcsReader::new(buf, Some(FcsParams::default().compensate(true)))?;
+        let comp = reader.next()?.unwrap().values;
+        let to_f64 = |v: &Value| match v {
+            Value::Float(f) => *f,
+            _ => panic!("Values should be floats"),
+        };
+        // the scatter channels aren't in the spillover matrix
+        assert_eq!(comp[0], raw[0]);
+        // multiplying the compensated values by the spillover matrix should give the raw ones back
+        let spill = [
+            [1., 0., 0.021_999_996_191_960_042, 0.],
+            [0., 1., 0., 0.],
+            [0.159_999_998_575_999_95, 0., 1., 0.],
+            [0.039_999_996_136_000_02, 0., 0.019_999_999_516_400_014, 1.],
+        ];
+        for j in 0..4 {
+            let uncomp: f64 = (0..4).map(|i| to_f64(&comp[6 + i]) * spill[i][j]).sum();
+            assert!((uncomp - to_f64(&raw[6 + j])).abs() < 1e-3);
+        }
+        assert_ne!(comp[6], raw[6]);
+
+        let mut n_recs = 1;
+        while reader.next()?.is_some() {
+            n_recs += 1;
+        }
+        assert_eq!(n_recs, 14945);
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fcs_transforms() -> Result<(), EtError> {
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let mut reader = FcsReader::new(buf, Some(FcsParams::default().compensate(true)))?;
+        let comp = reader.next()?.unwrap().values;
+        let params = FcsParams::default()
+            .compensate(true)
+            .transform(FcsTransform::arcsinh());
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        let trans = reader.next()?.unwrap().values;
+        assert_eq!(trans[0], comp[0]);
+        match (&comp[6], &trans[6]) {
+            (Value::Float(c), Value::Float(t)) => assert!((t - (c / 150.).asinh()).abs() < 1e-9),
+            _ => panic!("Values should be floats"),
+        }
+
+        let params = FcsParams {
+            transform_channels: Some(vec!["SSC-A".to_string()]),
+            ..FcsParams::default().transform(FcsTransform::logicle())
+        };
+        let mut reader = FcsReader::new(buf, Some(params))?;
+        let trans = reader.next()?.unwrap().values;
+        assert_eq!(trans[0], Value::Float(-28531.25));
+        if let Value::Float(f) = trans[3] {
+            assert!(f > 0.5 / 4.5 && f < 1.);
+        } else {
+            panic!("Values should be floats");
+        }
+
+        let logicle = Logicle::new(262_144., 0.5, 4.5, 0.)?;
+        assert!((logicle.apply(262_144.) - 1.).abs() < 1e-9);
+        assert!((logicle.apply(0.) - 0.5 / 4.5).abs() < 1e-9);
+        assert!((logicle.apply(-100.) + logicle.apply(100.) - 1. / 4.5).abs() < 1e-9);
+        assert!(logicle.apply(1000.) < logicle.apply(10000.));
+        assert!(Logicle::new(262_144., 3., 4.5, 0.).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fcs_params() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+
+        let buf: &[u8] =
+            include_bytes!("../../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs");
+        let mut params = BTreeMap::new();
+        drop(params.insert("compensate".to_string(), true.into()));
+        drop(params.insert("transform".to_string(), "arcsinh".into()));
+        drop(params.insert("cofactor".to_string(), 5.into()));
+        let fcs_params = FcsParams::from_params(&mut params)?;
+        assert!(params.is_empty());
+        assert!(fcs_params.compensate);
+        assert!(!fcs_params.scale);
+        assert_eq!(
+            fcs_params.transform,
+            Some(FcsTransform::Arcsinh { cofactor: 5. })
+        );
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("scale".to_string(), true.into()));
+        let (mut reader, _) = get_reader(buf, None, Some(params))?;
+        assert!(reader.next_record()?.is_some());
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("transform".to_string(), "log".into()));
+        assert!(FcsParams::from_params(&mut params).is_err());
+
+        let params = FcsParams {
+            transform_channels: Some(vec!["not a channel".to_string()]),
+            ..FcsParams::default().transform(FcsTransform::arcsinh())
+        };
+        assert!(FcsReader::new(buf, Some(params)).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_fcs_bad_fuzzes() -> Result<(), EtError> {
         let test_data: &[u8] = b"FCS3.1  \n\n\n0\n\n\n\n\n\n0\n\n\n\n\n\n\n \n\n\n0\n\n\n\n \n\n\n0\n\nCS3.1  \n\n\n0\n\n\n\n\n;";
@@ -5,9 +5,9 @@ use core::marker::Copy;
 
 use crate::parsers::common::SeekPattern;
 use crate::parsers::{extract, extract_opt, Endian, FromSlice};
-use crate::record::StateMetadata;
-use crate::EtError;
+use crate::record::{ColumnSchema, StateMetadata};
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 /// The current state of the Inficon reader
 #[derive(Clone, Debug, Default)]
@@ -25,6 +25,14 @@ impl StateMetadata for InficonState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("mz"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
@@ -39,19 +47,21 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
         let con = &mut 0;
 
         if extract::<&[u8]>(rb, con, &mut 4)? != [4, 3, 2, 1] {
-            return Err("Inficon file has bad magic bytes".into());
+            return Err(EtError::from("Inficon file has bad magic bytes")
+                .with_kind(ErrorKind::InvalidMagic));
         }
 
         // probably not super robust, but it works? this appears at the end of
         // the "instrument collection steps" section and it appears to be
         // a constant distance before the "list of mzs" section
         if extract_opt::<SeekPattern>(rb, eof, con, &mut &b"\xFF\xFF\xFF\xFF\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xF6\xFF\xFF\xFF\x00\x00\x00\x00"[..])?.is_none() {
-            return Err("Could not find m/z header list".into());
+            return Err(EtError::from("Could not find m/z header list").with_kind(ErrorKind::InvalidValue));
         }
         let _ = extract::<&[u8]>(rb, con, &mut 148)?;
         let n_segments = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
         if n_segments > 10000 {
-            return Err("Inficon file has too many segments".into());
+            return Err(EtError::from("Inficon file has too many segments")
+                .with_kind(ErrorKind::InvalidValue));
         }
 
         // now read all of the collection segments
@@ -62,14 +72,15 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
             let _ = extract::<&[u8]>(rb, con, &mut 96)?;
             let n_mzs = extract::<u32>(rb, con, &mut Endian::Little)?;
             if n_mzs > 100_000 {
-                return Err("Too many m/z ranges".into());
+                return Err(EtError::from("Too many m/z ranges").with_kind(ErrorKind::InvalidValue));
             }
             for _ in 0..n_mzs {
                 let start_mz = extract::<u32>(rb, con, &mut Endian::Little)?;
                 let end_mz = extract::<u32>(rb, con, &mut Endian::Little)?;
                 if end_mz > 4_000_000_000u32 {
                     // only malformed data should hit this
-                    return Err("End of m/z range is invalid".into());
+                    return Err(EtError::from("End of m/z range is invalid")
+                        .with_kind(ErrorKind::InvalidValue));
                 }
                 // then dwell time (u32; microseconds) and three more u32s
                 let _ = extract::<&[u8]>(rb, con, &mut 16)?;
@@ -80,7 +91,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
                     segment.push(f64::from(start_mz) / 100.);
                 } else {
                     if start_mz >= end_mz || end_mz - start_mz >= 200_000u32 {
-                        return Err("m/z range is too big or invalid".into());
+                        return Err(EtError::from("m/z range is too big or invalid")
+                            .with_kind(ErrorKind::InvalidValue));
                     }
                     // i_type = 1 appears to be "full scan mode"
                     let mut mz = start_mz;
@@ -94,7 +106,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
         if extract_opt::<SeekPattern>(rb, eof, con, &mut &b"\xFF\xFF\xFF\xFFHapsGPIR"[..])?
             .is_none()
         {
-            return Err("Could not find start of scan data".into());
+            return Err(EtError::from("Could not find start of scan data")
+                .with_kind(ErrorKind::InvalidValue));
         }
         // seek to right before the "HapsScan" section because the section
         // length is encoded in the four bytes before the header for that
@@ -102,7 +115,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonState {
         let data_length = u64::from(extract::<u32>(rb, con, &mut Endian::Little)?);
         let _ = extract::<&[u8]>(rb, con, &mut 8)?;
         if extract::<&[u8]>(rb, con, &mut 8)? != b"HapsScan" {
-            return Err("Data header was malformed".into());
+            return Err(
+                EtError::from("Data header was malformed").with_kind(ErrorKind::InvalidValue)
+            );
         }
         let _ = extract::<&[u8]>(rb, con, &mut 56)?;
         *data_left = usize::try_from(data_length)?;
@@ -154,17 +169,19 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonRecord {
             // F (e.g. values seem to be 0x0F, 0x1F, 0x2F...)
             state.cur_segment = usize::from(extract::<u16>(rb, con, &mut Endian::Little)? >> 4);
             if state.cur_segment >= state.mz_segments.len() {
-                return Err(
-                    format!("Invalid segment number ({}) specified", state.cur_segment).into(),
-                );
+                return Err(EtError::from(format!(
+                    "Invalid segment number ({}) specified",
+                    state.cur_segment
+                ))
+                .with_kind(ErrorKind::InvalidValue));
             }
             if n_mzs != state.mz_segments[state.cur_segment].len() {
-                return Err(format!(
+                return Err(EtError::from(format!(
                     "Number of intensities ({}) doesn't match number of mzs ({})",
                     n_mzs,
                     state.mz_segments[state.cur_segment].len()
-                )
-                .into());
+                ))
+                .with_kind(ErrorKind::InvalidValue));
             }
             mzs_left = n_mzs;
         }
@@ -174,7 +191,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for InficonRecord {
             // i think this is probably more likely an error where mz_segments have 0 length, but I
             // don't know enough about the format above to know if we should error when we parse
             // the initial state instead of here.
-            return Err("Invalid m/z segment".into());
+            return Err(EtError::from("Invalid m/z segment").with_kind(ErrorKind::InvalidValue));
         }
         state.cur_mz = cur_mz_segment[cur_mz_segment.len() - mzs_left];
         state.mzs_left = mzs_left - 1;
@@ -461,4 +478,47 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_inficon_detection() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+        use crate::record::Value;
+
+        // a stripped down Hapsite file with one SIM segment (m/z 100) and one scan
+        let mut data = b"\x04\x03\x02\x01SPAH".to_vec();
+        data.extend(b"\xFF\xFF\xFF\xFF");
+        data.extend([0; 32]);
+        data.extend(b"\xF6\xFF\xFF\xFF\x00\x00\x00\x00");
+        data.extend([0; 104]);
+        data.extend(1u32.to_le_bytes());
+        data.extend([0; 96]);
+        data.extend(1u32.to_le_bytes());
+        data.extend(10_000u32.to_le_bytes());
+        data.extend(10_000u32.to_le_bytes());
+        data.extend([0; 16]);
+        data.extend(0u32.to_le_bytes());
+        data.extend([0; 4]);
+        data.extend(b"\xFF\xFF\xFF\xFFHapsGPIR");
+        data.extend([0; 168]);
+        data.extend(20u32.to_le_bytes());
+        data.extend([0; 8]);
+        data.extend(b"HapsScan");
+        data.extend([0; 56]);
+        data.extend(1u32.to_le_bytes());
+        data.extend(60_000i32.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(1u16.to_le_bytes());
+        data.extend(0xFFFFu16.to_le_bytes());
+        data.extend(0x0Fu16.to_le_bytes());
+        data.extend(5f32.to_le_bytes());
+
+        let (mut reader, parser) = get_reader(&data[..], None, None)?;
+        assert_eq!(parser, "inficon");
+        assert_eq!(
+            reader.next_record()?,
+            Some(vec![Value::Float(1.), Value::Float(100.), Value::Float(5.)])
+        );
+        assert_eq!(reader.next_record()?, None);
+        Ok(())
+    }
 }
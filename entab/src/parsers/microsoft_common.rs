@@ -2,7 +2,7 @@ use core::convert::TryInto;
 
 use chrono::{NaiveDateTime, TimeZone, Utc};
 
-use crate::error::EtError;
+use crate::error::{ErrorKind, EtError};
 use crate::parsers::{extract, Endian, FromSlice};
 
 
@@ -39,7 +39,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MsCfbHeader {
             return Err(EtError::new("MS CFB headers are always 512 bytes long").incomplete());
         }
         if &buffer[..8] != CFB_MAGIC {
-            return Err(EtError::new("CFB header has invalid magic"));
+            return Err(EtError::new("CFB header has invalid magic").with_kind(ErrorKind::InvalidMagic));
         }
 
         // minor_version = buffer[24..26]
@@ -48,7 +48,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MsCfbHeader {
         let sector_size = match buffer[30..32] {
             [0x09, 0] => 512,
             [0x0C, 0] => 4096,
-            _ => return Err("Invalid sector shift specified".into()),
+            _ => return Err(EtError::from("Invalid sector shift specified").with_kind(ErrorKind::InvalidValue)),
         };
         // 32..44 -> ...
     
@@ -64,7 +64,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MsCfbHeader {
         let first_difat_loc = u32::extract(&buffer[68..72], Endian::Little)?;
         let n_difat_sectors = u32::extract(&buffer[72..76], Endian::Little)?;
         if n_difat_sectors > 0 {
-            return Err("DIFAT sectors aren't supported yet".into());
+            return Err(EtError::from("DIFAT sectors aren't supported yet").with_kind(ErrorKind::UnsupportedFormat));
         }
         // 76..512 -> DIFAT array of u32s
 
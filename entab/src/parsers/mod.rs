@@ -1,7 +1,7 @@
 use alloc::format;
 use core::any::type_name;
 
-use crate::EtError;
+use crate::{ErrorKind, EtError};
 
 /// Readers for formats generated by Agilent instruments
 pub mod agilent;
@@ -29,6 +29,9 @@ pub mod tsv_inference;
 // /// Reader for generic XML
 // pub mod xml;
 
+#[cfg(feature = "derive")]
+pub use entab_derive::FromSlice;
+
 /// The default implementation is `impl FromSlice for ()` to simplify implementations for
 /// e.g. state or other objects that don't read from the buffer.
 pub trait FromSlice<'b: 's, 's>: Sized + Default {
@@ -36,6 +39,11 @@ pub trait FromSlice<'b: 's, 's>: Sized + Default {
     /// the value returned.
     type State: core::fmt::Debug + Default + 's;
 
+    /// If this is a text format, in which case lines are counted as the buffer moves through the
+    /// data so errors can say which line they're on. Other formats only count the lines still in
+    /// the buffer when an error is reported.
+    const TEXT: bool = false;
+
     /// Given a slice and state, determine how much of the slice needs to be parsed to return a
     /// value and update `consumed` with that amount. If no value can be parsed, return Ok(false),
     /// otherwise return Ok(true) if a value can be parsed.
@@ -60,6 +68,16 @@ pub trait FromSlice<'b: 's, 's>: Sized + Default {
         Ok(())
     }
 
+    /// Where the record after a malformed one at the start of `buffer` begins, so lenient readers
+    /// can skip bad records. `None` (the default) means the format can't be resynchronized.
+    ///
+    /// # Errors
+    /// If there's not enough data in the buffer to find the next record, an incomplete `EtError`
+    /// is returned.
+    fn resync(_buffer: &[u8], _eof: bool) -> Result<Option<usize>, EtError> {
+        Ok(None)
+    }
+
     /// Essentially the same as `extract` below, but doesn't update the state or consume any space.
     ///
     /// Use only for simple types with defined sizes like u8, i32, &[u8], etc. Using this with more
@@ -94,11 +112,11 @@ where
     T: FromSlice<'b, 's> + Default,
 {
     match extract_opt(buffer, false, consumed, state)? {
-        None => Err(format!(
+        None => Err(EtError::from(format!(
             "Tried to extract {}, but parser indicated no more.",
             type_name::<T>()
-        )
-        .into()),
+        ))
+        .with_kind(ErrorKind::Truncated)),
         Some(value) => Ok(value),
     }
 }
@@ -141,3 +159,99 @@ impl Default for Endian {
         Endian::Little
     }
 }
+
+#[cfg(all(test, feature = "derive"))]
+#[allow(unreachable_pub)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+    use crate::impl_reader;
+    use crate::readers::RecordReader;
+    use crate::record::{StateMetadata, Value};
+
+    #[derive(Debug, Default, FromSlice)]
+    #[entab(endian = "big", magic = b"RC")]
+    pub struct TestRecord<'r> {
+        #[entab(skip = 1)]
+        time: f32,
+        #[entab(endian = "little")]
+        code: u16,
+        n_name: u8,
+        #[entab(count = n_name)]
+        name: &'r str,
+        #[entab(magic = b"|", count = 2)]
+        data: &'r [u8],
+    }
+
+    impl_reader!(TestReader, TestRecord, TestRecord<'r>, TestRecordState, ());
+
+    #[test]
+    fn test_derived_reader() -> Result<(), EtError> {
+        let data: &[u8] = b"RC\x00\x3F\x80\x00\x00\x02\x01\x03abc|\x01\x02RC\x00\x40\x00\x00\x00\x00\x01\x00|\x03\x04";
+        let mut reader = TestReader::new(data, None)?;
+        assert_eq!(
+            reader.headers(),
+            vec!["time", "code", "n_name", "name", "data"]
+        );
+        let record = reader.next()?.expect("first record");
+        assert_eq!(record.time, 1.);
+        assert_eq!(record.code, 258);
+        assert_eq!(record.name, "abc");
+        assert_eq!(record.data, &[1, 2]);
+        let values = reader.next_record()?.expect("second record");
+        assert_eq!(
+            values,
+            vec![
+                Value::Float(2.),
+                Value::Integer(256),
+                Value::Integer(0),
+                "".into(),
+                Value::String("\u{3}\u{4}".into()),
+            ]
+        );
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_derived_errors() -> Result<(), EtError> {
+        assert_eq!(TestRecordState.header().len(), 5);
+
+        let mut reader = TestReader::new(&b"XX\x00\x3F\x80\x00\x00"[..], None)?;
+        let err = reader.next().unwrap_err();
+        assert_eq!(err.msg, "Magic bytes for `TestRecord` did not match");
+
+        let mut reader = TestReader::new(&b"RC\x00\x3F\x80\x00\x00\x02\x01\x03ab"[..], None)?;
+        assert!(reader.next().is_err());
+
+        let mut reader =
+            TestReader::new(&b"RC\x00\x3F\x80\x00\x00\x02\x01\x00-\x01\x02"[..], None)?;
+        let err = reader.next().unwrap_err();
+        assert_eq!(err.msg, "Magic bytes for `data` did not match");
+        Ok(())
+    }
+
+    #[derive(Clone, Copy, Debug, Default, FromSlice)]
+    #[entab(crate = "crate")]
+    pub struct RenamedRecord {
+        value: u16,
+    }
+
+    impl_reader!(
+        RenamedReader,
+        RenamedRecord,
+        RenamedRecord,
+        RenamedRecordState,
+        ()
+    );
+
+    #[test]
+    fn test_derived_crate_path() -> Result<(), EtError> {
+        let mut reader = RenamedReader::new(&b"\x01\x00\x02\x00"[..], None)?;
+        assert_eq!(reader.next()?.expect("first record").value, 1);
+        assert_eq!(reader.next_record()?, Some(vec![Value::Integer(2)]));
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+}
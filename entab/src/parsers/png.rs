@@ -5,11 +5,12 @@ use std::io::Read;
 
 use flate2::read::ZlibDecoder;
 
+use crate::params::{take_bool, FromParams, ParamInfo};
 use crate::parsers::common::Skip;
 use crate::parsers::{extract, Endian, FromSlice};
-use crate::record::{StateMetadata, Value};
-use crate::EtError;
+use crate::record::{StateMetadata, Value, ValueType};
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 /// The way the color is encoded in the PNG
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -40,7 +41,7 @@ impl PngColorType {
             3 => Ok(PngColorType::Indexed),
             4 => Ok(PngColorType::AlphaGrayscale),
             6 => Ok(PngColorType::AlphaColor),
-            _ => Err("Unknown PNG color type".into()),
+            _ => Err(EtError::from("Unknown PNG color type").with_kind(ErrorKind::InvalidValue)),
         }
     }
 
@@ -54,6 +55,36 @@ impl PngColorType {
     }
 }
 
+/// Parameters for reading PNGs
+#[derive(Clone, Copy, Debug)]
+pub struct PngParams {
+    /// Scale colors and alpha up to the full 16-bit range (otherwise they're left at the image's
+    /// bit depth)
+    pub rescale: bool,
+}
+
+impl Default for PngParams {
+    fn default() -> Self {
+        PngParams { rescale: true }
+    }
+}
+
+impl FromParams for PngParams {
+    const PARAMS: &'static [ParamInfo] = &[ParamInfo::new(
+        "rescale",
+        ValueType::Boolean,
+        "Scale colors and alpha to 16 bits; otherwise they're left at the image's bit depth (default true)",
+    )];
+
+    fn from_params(params: &mut BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let mut png_params = PngParams::default();
+        if let Some(rescale) = take_bool(params, "rescale")? {
+            png_params.rescale = rescale;
+        }
+        Ok(png_params)
+    }
+}
+
 /// The state of the PNG parser
 #[derive(Clone, Debug, Default)]
 pub struct PngState {
@@ -65,6 +96,7 @@ pub struct PngState {
     cur_y: usize,
     image_data: Vec<u8>,
     palette: Option<Vec<(u16, u16, u16)>>,
+    rescale: bool,
 }
 
 impl PngState {
@@ -130,7 +162,11 @@ impl PngState {
                     };
                     self.image_data[pos].wrapping_add(paeth)
                 }
-                _ => return Err("Unknown line filter".into()),
+                _ => {
+                    return Err(
+                        EtError::from("Unknown line filter").with_kind(ErrorKind::InvalidValue)
+                    )
+                }
             }
         }
         self.image_data[line_num * line_len] = 0;
@@ -152,7 +188,7 @@ impl StateMetadata for PngState {
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
-    type State = ();
+    type State = PngParams;
 
     fn parse(
         rb: &[u8],
@@ -162,22 +198,25 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
     ) -> Result<bool, EtError> {
         let con = &mut 0;
         if extract::<&[u8]>(rb, con, &mut 8)? != b"\x89PNG\r\n\x1A\n" {
-            return Err("Invalid PNG magic".into());
+            return Err(EtError::from("Invalid PNG magic").with_kind(ErrorKind::InvalidMagic));
         }
         if extract::<&[u8]>(rb, con, &mut 8)? != b"\x00\x00\x00\x0DIHDR" {
-            return Err("Invalid PNG header".into());
+            return Err(EtError::from("Invalid PNG header").with_kind(ErrorKind::InvalidValue));
         }
         // skip width/height/etc for now
         let _ = extract::<Skip>(rb, con, &mut 10)?;
         // skip the compression, filter, and interlace bytes
         if extract::<u8>(rb, con, &mut Endian::Big)? != 0 {
-            return Err("PNG compression must be type 0".into());
+            return Err(EtError::from("PNG compression must be type 0")
+                .with_kind(ErrorKind::UnsupportedFormat));
         }
         if extract::<u8>(rb, con, &mut Endian::Big)? != 0 {
-            return Err("PNG filtering must be type 0".into());
+            return Err(EtError::from("PNG filtering must be type 0")
+                .with_kind(ErrorKind::UnsupportedFormat));
         }
         if extract::<u8>(rb, con, &mut Endian::Big)? != 0 {
-            return Err("PNG interlacing not supported yet".into());
+            return Err(EtError::from("PNG interlacing not supported yet")
+                .with_kind(ErrorKind::UnsupportedFormat));
         }
 
         loop {
@@ -194,7 +233,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
         Ok(true)
     }
 
-    fn get(&mut self, rb: &'b [u8], _state: &'s Self::State) -> Result<(), EtError> {
+    fn get(&mut self, rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        self.rescale = state.rescale;
+        let palette_scale = if state.rescale { 257 } else { 1 };
         let con = &mut 16;
         self.width = extract::<u32>(rb, con, &mut Endian::Big)? as usize;
         self.height = extract::<u32>(rb, con, &mut Endian::Big)? as usize;
@@ -221,9 +262,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngState {
                         let g: u8 = extract(rb, con, &mut Endian::Big)?;
                         let b: u8 = extract(rb, con, &mut Endian::Big)?;
                         raw_palette.push((
-                            257 * u16::from(r),
-                            257 * u16::from(g),
-                            257 * u16::from(b),
+                            palette_scale * u16::from(r),
+                            palette_scale * u16::from(g),
+                            palette_scale * u16::from(b),
                         ));
                     }
                     self.palette = Some(raw_palette);
@@ -316,6 +357,12 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngRecord {
 
     fn get(&mut self, _rb: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
         let bd = usize::from(state.bit_depth);
+        // the alpha of a pixel without transparency (palette colors are always 8-bit)
+        let opaque = match (state.rescale, state.color_type) {
+            (true, _) => u16::MAX,
+            (false, PngColorType::Indexed) => 255,
+            (false, _) => u16::try_from(2u32.pow(u32::try_from(bd)?) - 1)?,
+        };
 
         let line = &state.image_data
             [state.cur_y * state.line_len() + 1..(state.cur_y + 1) * state.line_len()];
@@ -325,34 +372,37 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngRecord {
                 let palette_pos = get_bits(line, pos, bd, false)? as usize;
                 if let Some(palette) = &state.palette {
                     if palette_pos >= palette.len() {
-                        return Err("Color index was outside palette dimensions".into());
+                        return Err(EtError::from("Color index was outside palette dimensions")
+                            .with_kind(ErrorKind::InvalidValue));
                     }
                     let (red, green, blue) = palette[palette_pos];
-                    (red, green, blue, u16::MAX)
+                    (red, green, blue, opaque)
                 } else {
-                    return Err("No palette was provided".into());
+                    return Err(
+                        EtError::from("No palette was provided").with_kind(ErrorKind::InvalidValue)
+                    );
                 }
             }
             PngColorType::Grayscale => {
-                let gray = get_bits(line, pos, bd, true)?;
-                (gray, gray, gray, u16::MAX)
+                let gray = get_bits(line, pos, bd, state.rescale)?;
+                (gray, gray, gray, opaque)
             }
             PngColorType::AlphaGrayscale => {
-                let gray = get_bits(line, pos, bd, true)?;
-                let alpha = get_bits(line, pos + 1, bd, true)?;
+                let gray = get_bits(line, pos, bd, state.rescale)?;
+                let alpha = get_bits(line, pos + 1, bd, state.rescale)?;
                 (gray, gray, gray, alpha)
             }
             PngColorType::Color => {
-                let red = get_bits(line, pos, bd, true)?;
-                let green = get_bits(line, pos + 1, bd, true)?;
-                let blue = get_bits(line, pos + 2, bd, true)?;
-                (red, green, blue, u16::MAX)
+                let red = get_bits(line, pos, bd, state.rescale)?;
+                let green = get_bits(line, pos + 1, bd, state.rescale)?;
+                let blue = get_bits(line, pos + 2, bd, state.rescale)?;
+                (red, green, blue, opaque)
             }
             PngColorType::AlphaColor => {
-                let red = get_bits(line, pos, bd, true)?;
-                let green = get_bits(line, pos + 1, bd, true)?;
-                let blue = get_bits(line, pos + 2, bd, true)?;
-                let alpha = get_bits(line, pos + 3, bd, true)?;
+                let red = get_bits(line, pos, bd, state.rescale)?;
+                let green = get_bits(line, pos + 1, bd, state.rescale)?;
+                let blue = get_bits(line, pos + 2, bd, state.rescale)?;
+                let alpha = get_bits(line, pos + 3, bd, state.rescale)?;
                 (red, green, blue, alpha)
             }
         };
@@ -367,7 +417,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for PngRecord {
     }
 }
 
-impl_reader!(PngReader, PngRecord, PngRecord, PngState, ());
+impl_reader!(PngReader, PngRecord, PngRecord, PngState, PngParams);
 
 #[cfg(test)]
 mod tests {
@@ -427,6 +477,12 @@ mod tests {
         assert_eq!(pixel.alpha, 65535);
         assert!(reader.next()?.is_none());
 
+        let mut reader = PngReader::new(TEST_IMAGE, Some(PngParams { rescale: false }))?;
+        let pixel = reader.next()?.expect("first pixel exists");
+        assert_eq!(pixel.red, 255);
+        assert_eq!(pixel.green, 0);
+        assert_eq!(pixel.alpha, 255);
+
         Ok(())
     }
 }
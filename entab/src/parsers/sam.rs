@@ -1,20 +1,61 @@
 use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::marker::Copy;
 
+use crate::params::{take_bool, FromParams, ParamInfo};
 use crate::parsers::common::{NewLine, Skip};
 use crate::parsers::{extract, extract_opt, Endian, FromSlice};
-use crate::record::StateMetadata;
-use crate::EtError;
+use crate::record::{StateMetadata, Value, ValueType};
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
+
+/// Parameters for reading BAMs
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BamParams {
+    /// Read reference IDs that aren't in the header as unmapped (an empty name) instead of
+    /// failing on them
+    pub allow_unknown_references: bool,
+}
+
+impl FromParams for BamParams {
+    const PARAMS: &'static [ParamInfo] = &[ParamInfo::new(
+        "allow_unknown_references",
+        ValueType::Boolean,
+        "Give reads mapped to references missing from the header an empty ref_name/rnext instead of failing (default false)",
+    )];
+
+    fn from_params(params: &mut BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let mut bam_params = BamParams::default();
+        if let Some(allow) = take_bool(params, "allow_unknown_references")? {
+            bam_params.allow_unknown_references = allow;
+        }
+        Ok(bam_params)
+    }
+}
 
 /// The internal state of the `BamReader`.
 #[derive(Clone, Debug, Default)]
 pub struct BamState {
     references: Vec<(String, usize)>,
+    allow_unknown_references: bool,
+}
+
+impl BamState {
+    /// The name of the reference with the ID `raw_id` (`None` if it's not in the header)
+    fn reference(&self, raw_id: i32) -> Result<Option<&str>, EtError> {
+        if raw_id < 0 {
+            return Ok(Some(""));
+        }
+        match self.references.get(usize::try_from(raw_id)?) {
+            Some((name, _)) => Ok(Some(name)),
+            None if self.allow_unknown_references => Ok(Some("")),
+            None => Ok(None),
+        }
+    }
 }
 
 impl StateMetadata for BamState {
@@ -37,7 +78,7 @@ impl StateMetadata for BamState {
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for BamState {
-    type State = ();
+    type State = BamParams;
 
     fn parse(
         buffer: &[u8],
@@ -48,7 +89,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamState {
         let con = &mut 0;
         // read the magic & header length, and then the header
         if extract::<&[u8]>(buffer, con, &mut 4)? != b"BAM\x01" {
-            return Err("Not a valid BAM file".into());
+            return Err(EtError::from("Not a valid BAM file").with_kind(ErrorKind::InvalidMagic));
         }
         let mut header_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
         let _ = Skip::parse(&buffer[*con..], eof, con, &mut header_len)?;
@@ -64,7 +105,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamState {
         Ok(true)
     }
 
-    fn get(&mut self, buffer: &'b [u8], _state: &Self::State) -> Result<(), EtError> {
+    fn get(&mut self, buffer: &'b [u8], state: &Self::State) -> Result<(), EtError> {
+        self.allow_unknown_references = state.allow_unknown_references;
         let con = &mut 4;
         let mut header_len = extract::<u32>(buffer, con, &mut Endian::Little)? as usize;
         // TODO: we should read the headers and pass them along
@@ -151,7 +193,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         let con = &mut 0;
         let mut record_len = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
         if record_len < 32 {
-            return Err("Record is unexpectedly short".into());
+            return Err(
+                EtError::from("Record is unexpectedly short").with_kind(ErrorKind::Truncated)
+            );
         }
         let _ = Skip::parse(&rb[*con..], eof, con, &mut record_len)?;
         *consumed += *con;
@@ -164,13 +208,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         let record_len = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
 
         let raw_ref_name_id: i32 = extract(rb, con, &mut Endian::Little)?;
-        self.ref_name = if raw_ref_name_id < 0 {
-            ""
-        } else if usize::try_from(raw_ref_name_id)? >= state.references.len() {
-            return Err("Invalid reference sequence ID".into());
-        } else {
-            &state.references[usize::try_from(raw_ref_name_id)?].0
-        };
+        self.ref_name = state.reference(raw_ref_name_id)?.ok_or_else(|| {
+            EtError::from("Invalid reference sequence ID").with_kind(ErrorKind::InvalidValue)
+        })?;
         let raw_pos: i32 = extract(rb, con, &mut Endian::Little)?;
         self.pos = if raw_pos == -1 {
             None
@@ -190,13 +230,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         self.flag = extract::<u16>(rb, con, &mut Endian::Little)?;
         let seq_len = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
         let raw_rnext_id: i32 = extract(rb, con, &mut Endian::Little)?;
-        self.rnext = if raw_rnext_id < 0 {
-            ""
-        } else if usize::try_from(raw_rnext_id)? >= state.references.len() {
-            return Err("Invalid next reference sequence ID".into());
-        } else {
-            &state.references[usize::try_from(raw_rnext_id)?].0
-        };
+        self.rnext = state.reference(raw_rnext_id)?.ok_or_else(|| {
+            EtError::from("Invalid next reference sequence ID").with_kind(ErrorKind::InvalidValue)
+        })?;
         let raw_pnext: i32 = extract(rb, con, &mut Endian::Little)?;
         self.pnext = if raw_pnext == -1 {
             None
@@ -209,7 +245,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
         let data = extract::<&[u8]>(rb, con, &mut (record_len - 32))?;
         if query_name_len + n_cigar_op * 8 + (3 * seq_len + 2) / 2 > data.len() {
             // there's not enough space for the query name, cigar, and sequence/quality?
-            return Err("Record ended abruptly while reading variable-length data".into());
+            return Err(
+                EtError::from("Record ended abruptly while reading variable-length data")
+                    .with_kind(ErrorKind::Truncated),
+            );
         }
 
         let mut start = query_name_len;
@@ -245,7 +284,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for BamRecord<'s> {
     }
 }
 
-impl_reader!(BamReader, BamRecord, BamRecord<'r>, BamState, ());
+impl_reader!(BamReader, BamRecord, BamRecord<'r>, BamState, BamParams);
 
 /// The internal state of the `SamReader`.
 #[derive(Clone, Copy, Debug, Default)]
@@ -272,6 +311,7 @@ impl StateMetadata for SamState {
 
 impl<'b: 's, 's> FromSlice<'b, 's> for SamState {
     type State = ();
+    const TEXT: bool = true;
 
     fn parse(
         rb: &[u8],
@@ -340,8 +380,9 @@ pub struct SamRecord<'r> {
 
 impl_record!(SamRecord<'r>: query_name, flag, ref_name, pos, mapq, cigar, rnext, pnext, tlen, sequence, quality, extra);
 
-impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'s> {
+impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'b> {
     type State = SamState;
+    const TEXT: bool = true;
 
     fn parse(
         rb: &[u8],
@@ -363,7 +404,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'s> {
         // TODO: need to remove terminal newline?
         let chunks: Vec<&[u8]> = buf.split(|c| *c == b'\t').collect();
         if chunks.len() < 11 {
-            return Err("Sam record too short".into());
+            return Err(EtError::from("Sam record too short").with_kind(ErrorKind::InvalidValue));
         }
         self.query_name = alloc::str::from_utf8(chunks[0])?;
         self.flag = alloc::str::from_utf8(chunks[1])?.parse()?;
@@ -418,6 +459,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for SamRecord<'s> {
 
 impl_reader!(SamReader, SamRecord, SamRecord<'r>, SamState, ());
 
+// every line after the header is a record
+#[cfg(feature = "parallel")]
+crate::parallel::impl_par_records!(SamReader, SamRecord, |_| true);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -526,6 +571,31 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_bam_unknown_references() -> Result<(), EtError> {
+        let data: &[u8] = &[
+            b'B', b'A', b'M', 1, 0, 0, 0, 0, 0, 0, 0, 0, // no header text or references
+            35, 0, 0, 0, // record length
+            3, 0, 0, 0, 0, 0, 0, 0, // reference ID 3 at position 0
+            2, 255, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // name length, mapq, bin, cigar, flag, seq
+            255, 255, 255, 255, 255, 255, 255, 255, 0, 0, 0, 0, // no next reference
+            b'r', 0, 255,
+        ];
+        let mut reader = BamReader::new(data, None)?;
+        assert!(reader.next().is_err());
+
+        let params = BamParams {
+            allow_unknown_references: true,
+        };
+        let mut reader = BamReader::new(data, Some(params))?;
+        let record = reader.next()?.expect("a record");
+        assert_eq!(record.query_name, "r");
+        assert_eq!(record.ref_name, "");
+        assert_eq!(record.pos, Some(0));
+        assert!(reader.next()?.is_none());
+        Ok(())
+    }
+
     #[cfg(all(feature = "compression", feature = "std"))]
     #[test]
     fn test_bam_fuzz_errors() -> Result<(), EtError> {
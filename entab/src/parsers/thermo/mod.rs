@@ -1,4 +1,6 @@
 /// Parsers for files from Thermo's isotopic mass specs
 pub mod thermo_iso;
+/// Peak integration and isotope ratios for Thermo's isotopic mass specs
+pub mod thermo_iso_peaks;
 /// Parsers for Thermo "RAW" format
 pub mod thermo_raw;
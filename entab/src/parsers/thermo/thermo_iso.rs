@@ -8,9 +8,9 @@ use core::marker::Copy;
 
 use crate::parsers::common::{SeekPattern, Skip};
 use crate::parsers::{extract, extract_opt, Endian, FromSlice};
-use crate::record::StateMetadata;
-use crate::EtError;
+use crate::record::{ColumnSchema, StateMetadata};
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 /// A string serialized out by the MFC framework.
 #[derive(Debug, Default)]
@@ -38,7 +38,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MfcString<'s> {
         } else if rb[1..3] == [0xFE, 0xFF] {
             4 + 2 * usize::from(rb[3])
         } else {
-            return Err("Unknown string header".into());
+            return Err(EtError::from("Unknown string header").with_kind(ErrorKind::InvalidValue));
         };
         if rb.len() < end {
             return Err(EtError::from("MfcString ended early").incomplete());
@@ -56,7 +56,7 @@ impl<'b: 's, 's> FromSlice<'b, 's> for MfcString<'s> {
         } else if rb[1..3] == [0xFE, 0xFF] {
             (4, true)
         } else {
-            return Err("Unknown string header".into());
+            return Err(EtError::from("Unknown string header").with_kind(ErrorKind::InvalidValue));
         };
 
         let string = if utf16 {
@@ -82,7 +82,10 @@ fn mzs_from_gas(gas: &str) -> Result<Vec<f64>, EtError> {
         "H2" => vec![2., 3.],
         "SO2" => vec![64., 66.],
         "SO2,SO-SO2 Ext,SO" => vec![48., 49., 50., 64., 65., 66.],
-        i => return Err(format!("Gas type {} not supported yet", i).into()),
+        i => {
+            return Err(EtError::from(format!("Gas type {} not supported yet", i))
+                .with_kind(ErrorKind::UnsupportedFormat))
+        }
     })
 }
 
@@ -116,6 +119,14 @@ impl StateMetadata for ThermoDxfState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("mz"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfState {
@@ -150,7 +161,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
             // is the number of sections in the data, but
             if state.first {
                 if extract_opt::<SeekPattern>(rb, eof, con, &mut &b"CRawData"[..])?.is_none() {
-                    return Err("Could not find CRawData section".into());
+                    return Err(EtError::from("Could not find CRawData section")
+                        .with_kind(ErrorKind::InvalidValue));
                 }
                 state.first = false;
                 // str plus a u32 (value 3) and a `2F00`
@@ -196,7 +208,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoDxfRecord {
             state.n_scans_left = bytes_data / (4 + 8 * state.mzs.len());
             if state.n_scans_left == 0 {
                 // this was caught by fuzzing; not sure if real files have this issue
-                return Err("File specified an invalid data length".into());
+                return Err(EtError::from("File specified an invalid data length")
+                    .with_kind(ErrorKind::InvalidValue));
             }
             state.cur_mz_idx = 0;
         }
@@ -243,6 +256,14 @@ impl StateMetadata for ThermoCfState {
     fn header(&self) -> Vec<&str> {
         vec!["time", "mz", "intensity"]
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        vec![
+            ColumnSchema::any("time").unit("min"),
+            ColumnSchema::any("mz"),
+            ColumnSchema::any("intensity"),
+        ]
+    }
 }
 
 impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfState {
@@ -304,7 +325,10 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoCfRecord {
             // sanity check our guess for the masses
             let n_mzs = extract::<u32>(rb, con, &mut Endian::Little)? as usize;
             if n_mzs != state.mzs.len() {
-                return Err(format!("Gas type {} has bad information", gas_type).into());
+                return Err(
+                    EtError::from(format!("Gas type {} has bad information", gas_type))
+                        .with_kind(ErrorKind::InvalidValue),
+                );
             }
 
             // then a CBinary header (or replacement sentinel) followed by a u32
@@ -0,0 +1,589 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::buffer::ReadBuffer;
+use crate::filetype::FileType;
+use crate::params::{take_float, take_list, FromParams, ParamInfo};
+use crate::parsers::thermo::thermo_iso::{ThermoCfReader, ThermoDxfReader};
+use crate::readers::RecordReader;
+use crate::record::{ColumnSchema, Value, ValueType};
+use crate::select::{ResolvedSelection, SelectRecord, Selection};
+use crate::{ErrorKind, EtError};
+
+/// Parameters for detecting and integrating peaks in an isotope trace
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThermoIsoPeakParams {
+    /// Slope (in intensity units per second) on the major mass that starts a peak
+    pub start_slope: f64,
+    /// Slope (in intensity units per second) on the major mass that ends a peak
+    pub end_slope: f64,
+    /// Minimum height above the baseline for a peak to be reported
+    pub min_height: f64,
+    /// The (1-based) numbers of the peaks to use as the reference gas for δ values
+    pub reference_peaks: Vec<usize>,
+}
+
+impl Default for ThermoIsoPeakParams {
+    fn default() -> Self {
+        ThermoIsoPeakParams {
+            start_slope: 1.,
+            end_slope: 0.4,
+            min_height: 5.,
+            reference_peaks: Vec::new(),
+        }
+    }
+}
+
+/// The params for the `thermo_cf` and `thermo_dxf` parsers
+///
+/// The peak params after `integrate_peaks` only apply if it's set.
+pub const THERMO_ISO_PARAMS: &[ParamInfo] = &[
+    ParamInfo::new(
+        "integrate_peaks",
+        ValueType::Boolean,
+        "Integrate the peaks in the trace instead of returning the trace itself",
+    ),
+    ParamInfo::new(
+        "start_slope",
+        ValueType::Float,
+        "Slope (intensity per second) on the major mass that starts a peak (default 1)",
+    ),
+    ParamInfo::new(
+        "end_slope",
+        ValueType::Float,
+        "Slope (intensity per second) on the major mass that ends a peak (default 0.4)",
+    ),
+    ParamInfo::new(
+        "min_height",
+        ValueType::Float,
+        "Minimum height above the baseline for a peak to be reported (default 5)",
+    ),
+    ParamInfo::new(
+        "reference_peaks",
+        ValueType::List,
+        "The (1-based) numbers of the reference gas peaks used for δ values",
+    ),
+];
+
+impl FromParams for ThermoIsoPeakParams {
+    const PARAMS: &'static [ParamInfo] = match THERMO_ISO_PARAMS.split_first() {
+        Some((_, peak_params)) => peak_params,
+        None => &[],
+    };
+
+    fn from_params(params: &mut BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let mut peak_params = ThermoIsoPeakParams::default();
+        if let Some(start_slope) = take_float(params, "start_slope")? {
+            peak_params.start_slope = start_slope;
+        }
+        if let Some(end_slope) = take_float(params, "end_slope")? {
+            peak_params.end_slope = end_slope;
+        }
+        if let Some(min_height) = take_float(params, "min_height")? {
+            peak_params.min_height = min_height;
+        }
+        if let Some(values) = take_list(params, "reference_peaks") {
+            for v in values {
+                match v {
+                    Value::Integer(i) if i > 0 => peak_params.reference_peaks.push(i as usize),
+                    _ => {
+                        return Err(EtError::from(
+                            "Reference peak numbers must be positive integers",
+                        )
+                        .with_kind(ErrorKind::InvalidParam))
+                    }
+                }
+            }
+        }
+        Ok(peak_params)
+    }
+}
+
+/// A single integrated peak from an isotope trace
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ThermoIsoPeak {
+    /// The (1-based) number of this peak in the trace
+    pub peak: u64,
+    /// The time the peak started at
+    pub start_time: f64,
+    /// The time of the peak maximum on the major mass
+    pub apex_time: f64,
+    /// The time the peak ended at
+    pub end_time: f64,
+    /// The baseline-corrected area of each mass channel
+    pub areas: Vec<f64>,
+    /// The ratio of each minor mass channel's area to the major mass channel's area
+    pub ratios: Vec<f64>,
+    /// The δ value (in ‰) of each ratio relative to the reference peaks
+    pub deltas: Vec<f64>,
+}
+
+impl From<ThermoIsoPeak> for Vec<Value<'_>> {
+    fn from(record: ThermoIsoPeak) -> Self {
+        let mut values = vec![
+            record.peak.into(),
+            record.start_time.into(),
+            record.apex_time.into(),
+            record.end_time.into(),
+        ];
+        values.extend(record.areas.into_iter().map(Value::from));
+        values.extend(record.ratios.into_iter().map(Value::from));
+        values.extend(record.deltas.into_iter().map(Value::from));
+        values
+    }
+}
+
+impl SelectRecord<'_> for ThermoIsoPeak {
+    fn matches(&self, selection: &ResolvedSelection) -> bool {
+        selection.matches(&Vec::from(self.clone()))
+    }
+}
+
+/// The peak currently being integrated
+#[derive(Clone, Debug)]
+struct OpenPeak {
+    start_time: f64,
+    apex_time: f64,
+    apex: f64,
+    baseline: Vec<f64>,
+    areas: Vec<f64>,
+    past_apex: bool,
+}
+
+/// Finds and integrates peaks from a stream of (time, mz, intensity) readings
+#[derive(Clone, Debug, Default)]
+struct PeakFinder {
+    params: ThermoIsoPeakParams,
+    mzs: Vec<f64>,
+    mzs_complete: bool,
+    scan_time: f64,
+    scan: Vec<f64>,
+    last_time: f64,
+    last_scan: Vec<f64>,
+    open_peak: Option<OpenPeak>,
+    peaks: Vec<ThermoIsoPeak>,
+}
+
+impl PeakFinder {
+    fn new(params: ThermoIsoPeakParams) -> Self {
+        PeakFinder {
+            params,
+            ..PeakFinder::default()
+        }
+    }
+
+    fn push(&mut self, time: f64, mz: f64, intensity: f64) -> Result<(), EtError> {
+        // a new scan starts every time we come back around to the major mass
+        if self.mzs.first() == Some(&mz) {
+            self.finish_scan()?;
+        }
+        if self.scan.is_empty() {
+            self.scan_time = time;
+        }
+        if !self.mzs_complete {
+            self.mzs.push(mz);
+        } else if self.mzs.get(self.scan.len()) != Some(&mz) {
+            return Err(EtError::from(
+                "Peak integration across multiple gas configurations is unsupported",
+            )
+            .with_kind(ErrorKind::UnsupportedFormat));
+        }
+        self.scan.push(intensity);
+        Ok(())
+    }
+
+    fn finish_scan(&mut self) -> Result<(), EtError> {
+        if self.scan.is_empty() {
+            return Ok(());
+        }
+        if !self.mzs_complete {
+            self.mzs_complete = true;
+        } else if self.scan.len() != self.mzs.len() {
+            return Err(
+                EtError::from("Scan had a different number of masses than expected")
+                    .with_kind(ErrorKind::InvalidValue),
+            );
+        }
+        let scan = core::mem::take(&mut self.scan);
+        if self.last_scan.is_empty() {
+            self.last_time = self.scan_time;
+            self.last_scan = scan;
+            return Ok(());
+        }
+
+        // slopes and areas are calculated per second
+        let dt = (self.scan_time - self.last_time) * 60.;
+        if dt <= 0. {
+            return Err(
+                EtError::from("Scan times must be increasing").with_kind(ErrorKind::InvalidValue)
+            );
+        }
+        let slope = (scan[0] - self.last_scan[0]) / dt;
+
+        if let Some(peak) = &mut self.open_peak {
+            let height = scan[0] - peak.baseline[0];
+            let ends = peak.past_apex
+                && slope >= -self.params.end_slope
+                && 2. * height < peak.apex - peak.baseline[0];
+            if ends {
+                self.close_peak();
+            } else {
+                for (i, intensity) in scan.iter().enumerate() {
+                    peak.areas[i] += (intensity - peak.baseline[i]) * dt;
+                }
+                if scan[0] > peak.apex {
+                    peak.apex = scan[0];
+                    peak.apex_time = self.scan_time;
+                    peak.past_apex = false;
+                } else if slope < -self.params.end_slope {
+                    peak.past_apex = true;
+                }
+            }
+        }
+
+        if self.open_peak.is_none() && slope > self.params.start_slope {
+            self.open_peak = Some(OpenPeak {
+                start_time: self.last_time,
+                apex_time: self.scan_time,
+                apex: scan[0],
+                baseline: self.last_scan.clone(),
+                areas: scan
+                    .iter()
+                    .zip(&self.last_scan)
+                    .map(|(i, b)| (i - b) * dt)
+                    .collect(),
+                past_apex: false,
+            });
+        }
+
+        self.last_time = self.scan_time;
+        self.last_scan = scan;
+        Ok(())
+    }
+
+    fn close_peak(&mut self) {
+        if let Some(peak) = self.open_peak.take() {
+            if peak.apex - peak.baseline[0] < self.params.min_height {
+                return;
+            }
+            // a peak that dips below the baseline on the major mass has no meaningful ratios
+            if peak.areas[0] <= 0. {
+                return;
+            }
+            let ratios = peak.areas[1..].iter().map(|a| a / peak.areas[0]).collect();
+            self.peaks.push(ThermoIsoPeak {
+                peak: self.peaks.len() as u64 + 1,
+                start_time: peak.start_time,
+                apex_time: peak.apex_time,
+                end_time: self.last_time,
+                areas: peak.areas,
+                ratios,
+                deltas: Vec::new(),
+            });
+        }
+    }
+
+    fn finish(&mut self) -> Result<Vec<f64>, EtError> {
+        // files can end partway through a scan so just ignore that scan
+        if self.scan.len() == self.mzs.len() {
+            self.finish_scan()?;
+        }
+        self.close_peak();
+
+        let mut reference_ratios = Vec::new();
+        if !self.params.reference_peaks.is_empty() {
+            reference_ratios = vec![0.; self.mzs.len().saturating_sub(1)];
+            for peak_num in &self.params.reference_peaks {
+                let peak = self.peaks.get(peak_num - 1).ok_or_else(|| {
+                    EtError::from(format!("Reference peak {} was not found", peak_num))
+                        .with_kind(ErrorKind::InvalidParam)
+                })?;
+                for (r, p) in reference_ratios.iter_mut().zip(&peak.ratios) {
+                    *r += p;
+                }
+            }
+            let n_refs = self.params.reference_peaks.len() as f64;
+            for r in &mut reference_ratios {
+                *r /= n_refs;
+            }
+            for peak in &mut self.peaks {
+                peak.deltas = peak
+                    .ratios
+                    .iter()
+                    .zip(&reference_ratios)
+                    .map(|(r, r_ref)| (r / r_ref - 1.) * 1000.)
+                    .collect();
+            }
+        }
+        Ok(reference_ratios)
+    }
+}
+
+/// Reader that integrates the peaks in a Thermo CF or DXF file and reports their isotope ratios
+#[derive(Debug)]
+pub struct ThermoIsoPeakReader {
+    mzs: Vec<f64>,
+    peaks: vec::IntoIter<ThermoIsoPeak>,
+    reference_ratios: Vec<f64>,
+    selection: Option<ResolvedSelection>,
+}
+
+impl ThermoIsoPeakReader {
+    /// Create a new `ThermoIsoPeakReader`
+    ///
+    /// Because peaks are referenced against each other, the entire file is read on creation.
+    ///
+    /// # Errors
+    /// If the file isn't a CF or DXF file or can't be parsed, an error is returned.
+    pub fn new<'r, B>(data: B, params: Option<ThermoIsoPeakParams>) -> Result<Self, EtError>
+    where
+        B: ::core::convert::TryInto<ReadBuffer<'r>>,
+        EtError: From<<B as ::core::convert::TryInto<ReadBuffer<'r>>>::Error>,
+    {
+        Self::from_buffer(data.try_into()?, params.unwrap_or_default())
+    }
+
+    /// Separate from `new` so the inner readers' constructors get a concrete `ReadBuffer`.
+    fn from_buffer(mut rb: ReadBuffer, params: ThermoIsoPeakParams) -> Result<Self, EtError> {
+        let mut finder = PeakFinder::new(params);
+        match rb.sniff_filetype()? {
+            FileType::ThermoCf => {
+                let mut reader = ThermoCfReader::new(rb, None)?;
+                while let Some(r) = reader.next()? {
+                    finder.push(r.time, r.mz, r.intensity)?;
+                }
+            }
+            FileType::ThermoDxf => {
+                let mut reader = ThermoDxfReader::new(rb, None)?;
+                while let Some(r) = reader.next()? {
+                    finder.push(r.time, r.mz, r.intensity)?;
+                }
+            }
+            _ => {
+                return Err(
+                    EtError::from("Peak integration requires a Thermo CF or DXF file")
+                        .with_kind(ErrorKind::UnsupportedFormat),
+                )
+            }
+        }
+        let reference_ratios = finder.finish()?;
+        Ok(ThermoIsoPeakReader {
+            mzs: finder.mzs,
+            peaks: finder.peaks.into_iter(),
+            reference_ratios,
+            selection: None,
+        })
+    }
+
+    /// Return the next integrated peak
+    ///
+    /// # Errors
+    /// Never actually errors; all parsing happens on creation.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<ThermoIsoPeak>, EtError> {
+        Ok(self.peaks.next())
+    }
+
+    /// The headers for every column, ignoring any selection
+    fn all_headers(&self) -> Vec<String> {
+        let mut headers: Vec<String> = ["peak", "start_time", "apex_time", "end_time"]
+            .iter()
+            .map(|s| (*s).to_string())
+            .collect();
+        headers.extend(self.mzs.iter().map(|mz| format!("area_{}", mz)));
+        let ratio_names = self.ratio_names();
+        headers.extend(ratio_names.iter().map(|r| format!("ratio_{}", r)));
+        if !self.reference_ratios.is_empty() {
+            headers.extend(ratio_names.iter().map(|r| format!("delta_{}", r)));
+        }
+        headers
+    }
+
+    fn ratio_names(&self) -> Vec<String> {
+        self.mzs
+            .iter()
+            .skip(1)
+            .map(|mz| format!("{}/{}", mz, self.mzs[0]))
+            .collect()
+    }
+}
+
+impl RecordReader for ThermoIsoPeakReader {
+    /// The next record, expressed as a `Vec` of `Value`s.
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        let selection = match &self.selection {
+            Some(s) => s,
+            None => return Ok(self.next()?.map(Into::into)),
+        };
+        Ok(self.peaks.by_ref().find_map(|peak| peak.select(selection)))
+    }
+
+    /// The headers for this Reader.
+    fn headers(&self) -> Vec<String> {
+        let headers = self.all_headers();
+        match &self.selection {
+            Some(s) => s.project(&headers),
+            None => headers,
+        }
+    }
+
+    /// The schema for this Reader.
+    fn schema(&self) -> Vec<ColumnSchema> {
+        let schema: Vec<ColumnSchema> = self
+            .all_headers()
+            .iter()
+            .map(|h| match h.as_ref() {
+                "peak" => ColumnSchema::new(h, ValueType::Integer),
+                "start_time" | "apex_time" | "end_time" => {
+                    ColumnSchema::new(h, ValueType::Float).unit("min")
+                }
+                h if h.starts_with("delta_") => ColumnSchema::new(h, ValueType::Float).unit("‰"),
+                _ => ColumnSchema::new(h, ValueType::Float),
+            })
+            .collect();
+        match &self.selection {
+            Some(s) => s.project(&schema),
+            None => schema,
+        }
+    }
+
+    /// The metadata for this Reader.
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = BTreeMap::new();
+        for (name, ratio) in self.ratio_names().iter().zip(&self.reference_ratios) {
+            drop(metadata.insert(format!("reference_ratio_{}", name), (*ratio).into()));
+        }
+        metadata
+    }
+
+    /// Only return the selected columns and rows.
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        self.selection = if selection.is_empty() {
+            None
+        } else {
+            Some(selection.resolve(&self.all_headers())?)
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thermo_cf_peaks() -> Result<(), EtError> {
+        let rb: &[u8] = include_bytes!("../../../tests/data/test-0000.cf");
+        let mut reader = ThermoIsoPeakReader::new(rb, None)?;
+        assert_eq!(
+            reader.headers(),
+            [
+                "peak",
+                "start_time",
+                "apex_time",
+                "end_time",
+                "area_44",
+                "area_45",
+                "area_46",
+                "ratio_45/44",
+                "ratio_46/44"
+            ]
+        );
+        let mut n_peaks = 0;
+        while let Some(peak) = reader.next()? {
+            n_peaks += 1;
+            assert!(peak.start_time < peak.apex_time && peak.apex_time <= peak.end_time);
+            assert_eq!(peak.areas.len(), 3);
+            assert!(peak.ratios[0] > 1.1 && peak.ratios[0] < 1.2);
+        }
+        assert_eq!(n_peaks, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_thermo_dxf_deltas() -> Result<(), EtError> {
+        let rb: &[u8] = include_bytes!("../../../tests/data/b3_alkanes.dxf");
+        let params = ThermoIsoPeakParams {
+            reference_peaks: vec![1, 2],
+            ..ThermoIsoPeakParams::default()
+        };
+        let mut reader = ThermoIsoPeakReader::new(rb, Some(params))?;
+        let headers = reader.headers();
+        assert_eq!(headers.len(), 11);
+        assert_eq!(headers[9], "delta_45/44");
+        assert!(reader.metadata().contains_key("reference_ratio_45/44"));
+
+        // the reference peaks should average out to no difference
+        let first = reader.next()?.unwrap();
+        let second = reader.next()?.unwrap();
+        assert!((first.deltas[0] + second.deltas[0]).abs() < 1e-6);
+        let mut n_peaks = 2;
+        while reader.next_record()?.is_some() {
+            n_peaks += 1;
+        }
+        assert!(n_peaks > 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_reader_peaks() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+
+        let rb: &[u8] = include_bytes!("../../../tests/data/b3_alkanes.dxf");
+        let mut params = BTreeMap::new();
+        drop(params.insert("integrate_peaks".to_string(), true.into()));
+        drop(params.insert(
+            "reference_peaks".to_string(),
+            Value::List(vec![1.into(), 3.into()]),
+        ));
+        let (mut reader, parser) = get_reader(rb, None, Some(params))?;
+        assert_eq!(parser, "thermo_dxf");
+        assert_eq!(reader.headers()[0], "peak");
+        assert!(reader.next_record()?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nonpositive_major_area() {
+        let mut finder = PeakFinder::new(ThermoIsoPeakParams::default());
+        finder.open_peak = Some(OpenPeak {
+            start_time: 0.,
+            apex_time: 1.,
+            apex: 1000.,
+            baseline: vec![0., 0.],
+            areas: vec![0., 10.],
+            past_apex: true,
+        });
+        finder.close_peak();
+        assert!(finder.peaks.is_empty());
+
+        finder.open_peak = Some(OpenPeak {
+            start_time: 0.,
+            apex_time: 1.,
+            apex: 1000.,
+            baseline: vec![0., 0.],
+            areas: vec![100., 10.],
+            past_apex: true,
+        });
+        finder.close_peak();
+        assert_eq!(finder.peaks.len(), 1);
+        assert!((finder.peaks[0].ratios[0] - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bad_params() {
+        let mut params = BTreeMap::new();
+        drop(params.insert("min_height".to_string(), "high".into()));
+        assert!(ThermoIsoPeakParams::from_params(&mut params).is_err());
+
+        let rb: &[u8] = include_bytes!("../../../tests/data/b3_alkanes.dxf");
+        let params = ThermoIsoPeakParams {
+            reference_peaks: vec![1000],
+            ..ThermoIsoPeakParams::default()
+        };
+        assert!(ThermoIsoPeakReader::new(rb, Some(params)).is_err());
+    }
+}
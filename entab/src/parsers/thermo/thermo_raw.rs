@@ -5,11 +5,11 @@ use alloc::{format, vec};
 use core::char::{decode_utf16, REPLACEMENT_CHARACTER};
 use core::convert::TryFrom;
 
-use crate::parsers::common::{EndOfFile, Skip};
+use crate::parsers::common::Skip;
 use crate::parsers::{extract, Endian, FromSlice};
 use crate::record::{StateMetadata, Value};
-use crate::EtError;
 use crate::{impl_reader, impl_record};
+use crate::{ErrorKind, EtError};
 
 /// A UTF-16 string with a u32 header describing its length
 #[derive(Debug, Default)]
@@ -73,7 +73,9 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawTrailer {
         } else if *version >= 50 {
             *consumed += 592 + 6816;
         } else {
-            return Err("Version must be >= 50".into());
+            return Err(
+                EtError::from("Version must be >= 50").with_kind(ErrorKind::UnsupportedFormat)
+            );
         }
 
         Ok(true)
@@ -228,27 +230,30 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawScanCoeffs {
             self.b = f64::extract(&buffer[*coeff_pos + 28..], &Endian::Little)?;
             self.c = f64::extract(&buffer[*coeff_pos + 36..], &Endian::Little)?;
         } else if self.n_coeffs != 0 {
-            return Err("Unexpected number of coefficients".into());
+            return Err(EtError::from("Unexpected number of coefficients")
+                .with_kind(ErrorKind::InvalidValue));
         }
         Ok(())
     }
 }
 
 /// The state of a parser that handles Thermo RAW files
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct ThermoRawParams {
     version: u32,
     data_start: usize,
     trailer_start: usize,
     trailer: Option<ThermoRawTrailer>,
+    scan_metadata: Option<Vec<ThermoRawScanMetadata>>,
+    scan_coeffs: Option<Vec<ThermoRawScanCoeffs>>,
 }
 
-/// The state of a parser that handles Thermo RAW files
+/// Where the parser is in the data section
+///
+/// This is kept apart from the rest of the state so a record that runs off the end of the
+/// buffer can be parsed again from the same place.
 #[derive(Clone, Copy, Debug, Default)]
-pub struct ThermoRawState {
-    version: u32,
-    metadata_pos: usize,
-    coeffs_pos: usize,
+struct ThermoRawCursor {
     n_scans_left: usize,
     n_chunks_left: usize,
     n_points_left: usize,
@@ -262,16 +267,31 @@ pub struct ThermoRawState {
     cur_adjustment: f64,
 }
 
+/// The state of a parser that handles Thermo RAW files
+#[derive(Clone, Debug, Default)]
+pub struct ThermoRawState {
+    version: u32,
+    metadata_pos: usize,
+    coeffs_pos: usize,
+    scan_metadata: Vec<ThermoRawScanMetadata>,
+    scan_coeffs: Vec<ThermoRawScanCoeffs>,
+    cursor: ThermoRawCursor,
+}
+
 impl ThermoRawState {
-    /// Update the positions of the pointer in the metadata and coefficients sections
+    /// Update the distances from the current position to the metadata and coefficients sections
     ///
     /// # Errors
     /// If the amount consumed is larger than expected, an error will be returned.
     pub fn data_consumed(&mut self, con: usize) -> Result<(), EtError> {
         if self.metadata_pos < con {
-            return Err("Data section extended into metadata section".into());
+            return Err(EtError::from("Data section extended into metadata section")
+                .with_kind(ErrorKind::InvalidValue));
         } else if self.coeffs_pos < con {
-            return Err("Data section extended into coefficients section".into());
+            return Err(
+                EtError::from("Data section extended into coefficients section")
+                    .with_kind(ErrorKind::InvalidValue),
+            );
         }
         self.metadata_pos -= con;
         self.coeffs_pos -= con;
@@ -297,31 +317,23 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
     fn parse(
         buffer: &[u8],
         eof: bool,
-        consumed: &mut usize,
+        _consumed: &mut usize,
         state: &mut Self::State,
     ) -> Result<bool, EtError> {
-        // make sure the entire file is read in. unfortunately a few of the metadata fields needed
-        // to parse the main data body are located near the end of the file (e.g. times,
-        // transformation coefficients to convert raw signals into m/zs) so this would basically
-        // happen anyhow. by doing it here, we prevent having to reparse because of an incomplete
-        // elsewhere
-        if !EndOfFile::parse(buffer, eof, &mut 0, &mut ())? {
-            return Ok(false);
-        }
-
-        // now return to regular parsing
-        if buffer.len() < 1420 {
-            return Err(EtError::from("Header much too short").incomplete());
-        }
-        if &buffer[..2] != b"\x01\xA1" {
-            return Err("Bad magic".into());
-        }
-        if state.version == 0 {
+        // a few of the metadata fields needed to parse the main data body are located near the
+        // end of the file (e.g. times, transformation coefficients to convert raw signals into
+        // m/zs) so we jump to each of those sections in turn, read them in, and then jump back to
+        // the start of the data
+        if state.data_start == 0 && state.trailer_start == 0 {
+            if buffer.len() < 1420 {
+                return Err(EtError::from("Header much too short").incomplete());
+            }
+            if &buffer[..2] != b"\x01\xA1" {
+                return Err(EtError::from("Bad magic").with_kind(ErrorKind::InvalidMagic));
+            }
             // get the version number
             state.version = u32::extract(&buffer[36..40], &Endian::Little)?;
-        }
 
-        if state.data_start == 0 && state.trailer_start == 0 {
             // the next value (40..48) is a u64 with the run time (?) in "windows time"
             let con = &mut 1420;
             for _ in 1..=13 {
@@ -341,11 +353,11 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
 
             if state.version < 57 {
                 // TODO: find some examples of these?
-                return Err(format!(
+                return Err(EtError::from(format!(
                     "Old RAW files (version {}) are not supported yet",
                     state.version
-                )
-                .into());
+                ))
+                .with_kind(ErrorKind::UnsupportedFormat));
             } else if state.version < 64 {
                 let _: Skip = extract(buffer, con, &mut 52)?;
                 state.data_start =
@@ -361,33 +373,53 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawState {
                 state.trailer_start =
                     usize::try_from(extract::<u64>(buffer, con, &mut Endian::Little)?)?;
             }
+            return Err(EtError::from("Jumping to the trailer").seek(state.trailer_start as u64));
         }
 
-        if state.trailer.is_none() {
-            // load everything until the run_header_start; annoyingly, we need to parse the trailer
-            // *after* all of the data to convert the "signal id" into a m/z for Orbtraps and other
-            // instruments
-            let _: Skip = extract(buffer, &mut 0, &mut state.trailer_start)?;
-            let mut trailer_start = state.trailer_start;
-            state.trailer = Some(extract::<ThermoRawTrailer>(
-                buffer,
-                &mut trailer_start,
-                &mut state.version,
-            )?);
+        let trailer = match state.trailer {
+            Some(trailer) => trailer,
+            None => {
+                // annoyingly, we need to parse the trailer *after* all of the data to convert
+                // the "signal id" into a m/z for Orbtraps and other instruments
+                let trailer: ThermoRawTrailer = extract(buffer, &mut 0, &mut state.version)?;
+                state.trailer = Some(trailer);
+                return Err(EtError::from("Jumping to the scan metadata")
+                    .seek(trailer.metadata_start as u64));
+            }
+        };
+
+        if state.scan_metadata.is_none() {
+            let mut pos = 0;
+            let scan_metadata = (0..trailer.n_scans)
+                .map(|_| extract(buffer, &mut pos, &mut state.version))
+                .collect::<Result<Vec<_>, _>>()?;
+            state.scan_metadata = Some(scan_metadata);
+            return Err(EtError::from("Jumping to the scan coefficients")
+                .seek(trailer.coeffs_start as u64 + 4));
+        }
+
+        if state.scan_coeffs.is_none() {
+            let mut pos = 0;
+            let scan_coeffs = (0..trailer.n_scans)
+                .map(|_| extract(buffer, &mut pos, &mut (state.version, 0)))
+                .collect::<Result<Vec<_>, _>>()?;
+            state.scan_coeffs = Some(scan_coeffs);
+            return Err(EtError::from("Jumping to the data").seek(state.data_start as u64));
         }
 
-        *consumed += state.data_start;
         Ok(true)
     }
 
-    fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
-        self.version = u32::extract(&buffer[36..40], &Endian::Little)?;
+    fn get(&mut self, _buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
+        self.version = state.version;
         let trailer = state
             .trailer
-            .ok_or_else(|| EtError::from("Trailer missing?"))?;
+            .ok_or_else(|| EtError::from("Trailer missing?").with_kind(ErrorKind::InvalidValue))?;
         self.metadata_pos = trailer.metadata_start - state.data_start;
         self.coeffs_pos = trailer.coeffs_start - state.data_start + 4;
-        self.n_scans_left = trailer.n_scans;
+        self.scan_metadata = state.scan_metadata.clone().unwrap_or_default();
+        self.scan_coeffs = state.scan_coeffs.clone().unwrap_or_default();
+        self.cursor.n_scans_left = trailer.n_scans;
         Ok(())
     }
 }
@@ -415,81 +447,84 @@ impl<'b: 's, 's> FromSlice<'b, 's> for ThermoRawRecord {
         state: &mut Self::State,
     ) -> Result<bool, EtError> {
         let mut con = 0;
-        if state.n_scans_left == 0 && state.n_chunks_left == 0 && state.n_points_left == 0 {
+        let mut cur = state.cursor;
+        if cur.n_scans_left == 0 && cur.n_chunks_left == 0 && cur.n_points_left == 0 {
             return Ok(false);
         }
-        let mut extra_bytes = state.extra_bytes;
-        let mut n_scans_left = state.n_scans_left;
-        let mut n_chunks_left = state.n_chunks_left;
-        if state.n_chunks_left == 0 && state.n_points_left == 0 {
+        if cur.n_chunks_left == 0 && cur.n_points_left == 0 {
             let mut size_data = 0;
             while size_data == 0 {
                 // skip the trailer from the last scan
-                let _ = extract::<Skip>(buffer, &mut con, &mut extra_bytes)?;
-
-                // read the extra metadata from the end of the file
-                let scan_metadata: ThermoRawScanMetadata =
-                    extract(buffer, &mut state.metadata_pos, &mut state.version)?;
-                state.cur_time = scan_metadata.time;
-                state.cur_coeffs = extract(buffer, &mut state.coeffs_pos, &mut (state.version, 0))?;
+                let _ = extract::<Skip>(buffer, &mut con, &mut cur.extra_bytes)?;
+
+                // look up the extra metadata from the end of the file
+                let scan = state.scan_metadata.len() - cur.n_scans_left;
+                let (scan_metadata, scan_coeffs) = state
+                    .scan_metadata
+                    .get(scan)
+                    .zip(state.scan_coeffs.get(scan))
+                    .ok_or_else(|| {
+                        EtError::from("Scan metadata missing").with_kind(ErrorKind::InvalidValue)
+                    })?;
+                cur.cur_time = scan_metadata.time;
+                cur.cur_coeffs = *scan_coeffs;
 
                 // now read the record header itself
                 let _ = extract::<Skip>(buffer, &mut con, &mut 4)?;
                 size_data = extract::<u32>(buffer, &mut con, &mut Endian::Little)?;
-                extra_bytes =
+                cur.extra_bytes =
                     4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
                 // only the second bit `01000000` is ever set here?
-                state.chunk_has_adjustment =
+                cur.chunk_has_adjustment =
                     extract::<u32>(buffer, &mut con, &mut Endian::Little)? != 0;
                 // three more sections we need to skip
-                extra_bytes +=
+                cur.extra_bytes +=
                     4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
-                extra_bytes +=
+                cur.extra_bytes +=
                     4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
-                extra_bytes +=
+                cur.extra_bytes +=
                     4 * usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
                 let _ = extract::<Skip>(buffer, &mut con, &mut 12)?;
 
-                n_scans_left -= 1;
-                if n_scans_left == 0 {
-                    state.n_scans_left = n_scans_left;
+                cur.n_scans_left -= 1;
+                if cur.n_scans_left == 0 {
+                    state.cursor.n_scans_left = 0;
                     return Ok(false);
                 }
             }
-            state.base_freq = extract(buffer, &mut con, &mut Endian::Little)?;
-            state.freq_step = extract(buffer, &mut con, &mut Endian::Little)?;
-            n_chunks_left =
+            cur.base_freq = extract(buffer, &mut con, &mut Endian::Little)?;
+            cur.freq_step = extract(buffer, &mut con, &mut Endian::Little)?;
+            cur.n_chunks_left =
                 usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
             let _ = extract::<Skip>(buffer, &mut con, &mut 4)?;
         }
-        if state.n_points_left == 0 {
+        if cur.n_points_left == 0 {
             // read a chunk header
             let freq_offset = f64::from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?);
-            state.cur_freq = state.base_freq + state.freq_step * freq_offset - state.freq_step;
-            state.n_points_left =
+            cur.cur_freq = cur.base_freq + cur.freq_step * freq_offset - cur.freq_step;
+            cur.n_points_left =
                 usize::try_from(extract::<u32>(buffer, &mut con, &mut Endian::Little)?)?;
-            if state.chunk_has_adjustment {
-                state.cur_adjustment =
+            if cur.chunk_has_adjustment {
+                cur.cur_adjustment =
                     f64::from(extract::<f32>(buffer, &mut con, &mut Endian::Little)?);
             }
-            n_chunks_left -= 1;
+            cur.n_chunks_left -= 1;
         }
         // include the point itself
         let _ = extract::<Skip>(buffer, &mut con, &mut 4)?;
-        state.cur_freq += state.freq_step;
+        cur.cur_freq += cur.freq_step;
+        cur.n_points_left -= 1;
 
-        state.n_scans_left = n_scans_left;
-        state.n_chunks_left = n_chunks_left;
-        state.n_points_left -= 1;
-        state.extra_bytes = extra_bytes;
+        state.cursor = cur;
         state.data_consumed(con)?;
         *consumed += con;
         Ok(true)
     }
 
     fn get(&mut self, buffer: &'b [u8], state: &'s Self::State) -> Result<(), EtError> {
-        self.time = state.cur_time;
-        self.mz = state.cur_coeffs.to_mz(state.cur_freq) + state.cur_adjustment;
+        let cur = &state.cursor;
+        self.time = cur.cur_time;
+        self.mz = cur.cur_coeffs.to_mz(cur.cur_freq) + cur.cur_adjustment;
         self.intensity = f32::extract(&buffer[buffer.len() - 4..], &Endian::Little)?;
         Ok(())
     }
@@ -536,4 +571,51 @@ mod tests {
         while reader.next()?.is_some() {}
         Ok(())
     }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_thermo_raw_seekable() -> Result<(), EtError> {
+        use crate::buffer::ReadBuffer;
+        use std::fs::File;
+
+        let data: &[u8] = include_bytes!("../../../tests/data/small.RAW");
+        let mut reader = ThermoRawReader::new(data, None)?;
+        let mut n_records = 0;
+        let mut last = None;
+        while let Some(record) = reader.next()? {
+            n_records += 1;
+            last = Some((record.time, record.mz, record.intensity));
+        }
+
+        // the trailer and scan tables are jumped to so the whole file never has to be in memory
+        let file = File::open("tests/data/small.RAW")?;
+        let mut rb = ReadBuffer::from_seekable_reader(file, None)?;
+        rb.max_buffer_size = Some(data.len() / 8);
+        let mut reader = ThermoRawReader::new(rb, None)?;
+        assert_eq!(reader.metadata()["version"], 57.into());
+        let mut n_seek_records = 0;
+        let mut seek_last = None;
+        while let Some(record) = reader.next()? {
+            n_seek_records += 1;
+            seek_last = Some((record.time, record.mz, record.intensity));
+        }
+        assert_eq!(n_seek_records, n_records);
+        assert_eq!(seek_last, last);
+
+        // but the file's still readable from a stream that can't seek
+        let file = File::open("tests/data/small.RAW")?;
+        let rb = ReadBuffer::from_reader(Box::new(file), None)?;
+        let mut reader = ThermoRawReader::new(rb, None)?;
+        let mut n_stream_records = 0;
+        while reader.next()?.is_some() {
+            n_stream_records += 1;
+        }
+        assert_eq!(n_stream_records, n_records);
+
+        let file = File::open("tests/data/small.RAW")?;
+        let mut rb = ReadBuffer::from_reader(Box::new(file), None)?;
+        rb.max_buffer_size = Some(data.len() / 8);
+        assert!(ThermoRawReader::new(rb, None).is_err());
+        Ok(())
+    }
 }
@@ -1,10 +1,13 @@
 use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
 use alloc::str::from_utf8;
 use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
 
+use crate::batch::{BatchRecord, ColumnBatch};
 use crate::impl_reader;
+use crate::params::{take_bool, take_byte, take_usize, FromParams, ParamInfo};
 use crate::parsers::common::NewLine;
 use crate::parsers::extract_opt;
 use crate::parsers::tsv_inference::{
@@ -12,8 +15,9 @@ use crate::parsers::tsv_inference::{
     DEFAULT_QUOTE,
 };
 use crate::parsers::FromSlice;
-use crate::record::{StateMetadata, Value};
-use crate::EtError;
+use crate::record::{ColumnSchema, RecordSchema, StateMetadata, Value, ValueType};
+use crate::select::{ResolvedSelection, SelectRecord};
+use crate::{ErrorKind, EtError};
 
 /// Parameters for parsing TSVs
 ///
@@ -65,6 +69,52 @@ impl TsvParams {
     }
 }
 
+impl FromParams for TsvParams {
+    const PARAMS: &'static [ParamInfo] = &[
+        ParamInfo::new(
+            "delimiter",
+            ValueType::String,
+            "The character used to separate fields",
+        ),
+        ParamInfo::new(
+            "quote",
+            ValueType::String,
+            "The character used to quote fields",
+        ),
+        ParamInfo::new(
+            "skip_lines",
+            ValueType::Integer,
+            "The number of lines to skip before the column titles",
+        ),
+        ParamInfo::new(
+            "sniff",
+            ValueType::Boolean,
+            "Detect any delimiter, quote, and lines to skip that aren't set (default true)",
+        ),
+        ParamInfo::new(
+            "infer_types",
+            ValueType::Boolean,
+            "Detect the types of each field; otherwise all fields are strings (default true)",
+        ),
+    ];
+
+    fn from_params(params: &mut BTreeMap<String, Value<'_>>) -> Result<Self, EtError> {
+        let mut tsv_params = TsvParams {
+            delim_char: take_byte(params, "delimiter")?,
+            quote_char: take_byte(params, "quote")?,
+            skip_lines: take_usize(params, "skip_lines")?,
+            ..TsvParams::default()
+        };
+        if let Some(sniff) = take_bool(params, "sniff")? {
+            tsv_params.sniff_file = sniff;
+        }
+        if let Some(infer_types) = take_bool(params, "infer_types")? {
+            tsv_params.infer_types = infer_types;
+        }
+        Ok(tsv_params)
+    }
+}
+
 /// Track the current state of the TSV parser
 #[derive(Clone, Debug, Default)]
 pub struct TsvState {
@@ -76,6 +126,7 @@ pub struct TsvState {
 
 impl<'b: 's, 'r, 's> FromSlice<'b, 's> for TsvState {
     type State = TsvParams;
+    const TEXT: bool = true;
 
     fn parse(
         buffer: &[u8],
@@ -92,7 +143,9 @@ impl<'b: 's, 'r, 's> FromSlice<'b, 's> for TsvState {
         let con = &mut 0;
         for _ in 0..state.skip_lines.unwrap_or(0) {
             if extract_opt::<NewLine>(buffer, false, con, &mut 0)?.is_none() {
-                return Err("could not skip header lines".into());
+                return Err(
+                    EtError::from("could not skip header lines").with_kind(ErrorKind::Truncated)
+                );
             }
         }
         if !NewLine::parse(&buffer[*con..], eof, con, &mut 0)? {
@@ -106,13 +159,17 @@ impl<'b: 's, 'r, 's> FromSlice<'b, 's> for TsvState {
         let con = &mut 0;
         for _ in 0..state.skip_lines.unwrap_or(0) {
             if extract_opt::<NewLine>(buffer, false, con, &mut 0)?.is_none() {
-                return Err("could not skip header lines".into());
+                return Err(
+                    EtError::from("could not skip header lines").with_kind(ErrorKind::Truncated)
+                );
             }
         }
         let header = if let Some(NewLine(h)) = extract_opt::<NewLine>(buffer, false, con, &mut 0)? {
             h
         } else {
-            return Err("could not read headers from TSV".into());
+            return Err(
+                EtError::from("could not read headers from TSV").with_kind(ErrorKind::Truncated)
+            );
         };
         if state.infer_types {
             self.types = Some(state.types.clone());
@@ -144,6 +201,21 @@ impl<'r> StateMetadata for TsvState {
         }
         headers
     }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        self.headers
+            .iter()
+            .enumerate()
+            .map(|(ix, header)| {
+                let ty = self.types.as_ref().and_then(|t| t.get(ix));
+                ColumnSchema::new(
+                    header,
+                    ty.map_or(ValueType::String, TsvFieldType::value_type),
+                )
+                .nullable(ty.is_some_and(TsvFieldType::nullable))
+            })
+            .collect()
+    }
 }
 
 /// Values from the current line of the TSV
@@ -152,8 +224,9 @@ pub struct TsvRecord<'r> {
     values: Vec<Value<'r>>,
 }
 
-impl<'b: 's, 's> FromSlice<'b, 's> for TsvRecord<'s> {
+impl<'b: 's, 's> FromSlice<'b, 's> for TsvRecord<'b> {
     type State = TsvState;
+    const TEXT: bool = true;
 
     fn parse(
         buffer: &[u8],
@@ -174,7 +247,8 @@ impl<'b: 's, 's> FromSlice<'b, 's> for TsvRecord<'s> {
         let mut records = vec![Cow::Borrowed(""); state.headers.len()];
         let n_records = split(&mut records, buffer, state.delim_char, state.quote_char)?;
         if n_records != state.headers.len() {
-            return Err("Line had a bad number of records".into());
+            return Err(EtError::from("Line had a bad number of records")
+                .with_kind(ErrorKind::InvalidValue));
         }
         if let Some(types) = &state.types {
             self.values = records
@@ -189,14 +263,32 @@ impl<'b: 's, 's> FromSlice<'b, 's> for TsvRecord<'s> {
     }
 }
 
+impl RecordSchema for TsvRecord<'_> {}
+
 impl<'r> From<TsvRecord<'r>> for Vec<Value<'r>> {
     fn from(record: TsvRecord<'r>) -> Self {
         record.values
     }
 }
 
+impl<'r> SelectRecord<'r> for TsvRecord<'r> {
+    fn matches(&self, selection: &ResolvedSelection) -> bool {
+        selection.matches(&self.values)
+    }
+}
+
+impl BatchRecord for TsvRecord<'_> {
+    fn push_to(self, batch: &mut ColumnBatch) -> Result<(), EtError> {
+        batch.push_record(self.into())
+    }
+}
+
 impl_reader!(TsvReader, TsvRecord, TsvRecord<'r>, TsvState, TsvParams);
 
+// records are always one line (quoted fields can't contain newlines)
+#[cfg(feature = "parallel")]
+crate::parallel::impl_par_records!(TsvReader, TsvRecord, |_| true);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -228,6 +320,79 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_schema() -> Result<(), EtError> {
+        use crate::record::ValueType;
+
+        const TEST_TEXT: &[u8] = b"name\tcount\tratio\na\t2\t0.5\nb\t3\t1.5";
+        let pt = TsvReader::new(TEST_TEXT, Some(TsvParams::default()))?;
+        let types: Vec<ValueType> = pt.schema().iter().map(|c| c.value_type).collect();
+        assert_eq!(
+            types,
+            [ValueType::String, ValueType::Integer, ValueType::Float]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_blank_numeric_cells() -> Result<(), EtError> {
+        use crate::record::ValueType;
+
+        const TEST_TEXT: &[u8] = b"name\tcount\tflag\na\t2\tT\nb\t\tF\nc\tNA\t\n";
+        let mut pt = TsvReader::new(TEST_TEXT, Some(TsvParams::default()))?;
+        let schema = pt.schema();
+        assert_eq!(schema[0].value_type, ValueType::String);
+        assert!(!schema[0].nullable);
+        assert_eq!(schema[1].value_type, ValueType::String);
+        assert_eq!(schema[2].value_type, ValueType::Boolean);
+        assert!(schema[2].nullable);
+
+        const NUMERIC_TEXT: &[u8] = b"name\tcount\na\t2\nb\t\nc\t 4 \n";
+        let mut pt = TsvReader::new(NUMERIC_TEXT, Some(TsvParams::default()))?;
+        let schema = pt.schema();
+        assert_eq!(schema[1].value_type, ValueType::Integer);
+        assert!(schema[1].nullable);
+        assert_eq!(pt.next_record()?.unwrap()[1], 2.into());
+        assert_eq!(pt.next_record()?.unwrap()[1], Value::Null);
+        assert_eq!(pt.next_record()?.unwrap()[1], 4.into());
+
+        // values that can't be parsed as the inferred type are kept as strings
+        let mut ty = TsvFieldType::default();
+        ty.infer("2");
+        assert_eq!(ty.coerce("x".into()), "x".into());
+        assert_eq!(ty.coerce("".into()), Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mismatch_past_sniffed_buffer() -> Result<(), EtError> {
+        use crate::buffer::ReadBuffer;
+        use crate::record::ValueType;
+        use alloc::boxed::Box;
+        use std::io::Cursor;
+
+        let mut text = b"name\tcount\tvalue\tflag\n".to_vec();
+        for _ in 0..20 {
+            text.extend_from_slice(b"a\t2\t1.5\tT\n");
+        }
+        text.extend_from_slice(b"b\t12.5\tn/a\tyes\n");
+        // only the first 64 bytes are used to guess the column types
+        let rb = ReadBuffer::from_reader(Box::new(Cursor::new(text)), Some(64))?;
+        let mut pt = TsvReader::new(rb, Some(TsvParams::default()))?;
+        let schema = pt.schema();
+        assert_eq!(schema[1].value_type, ValueType::Integer);
+        assert_eq!(schema[2].value_type, ValueType::Float);
+        assert_eq!(schema[3].value_type, ValueType::Boolean);
+        for _ in 0..20 {
+            assert_eq!(pt.next_record()?.unwrap()[1], 2.into());
+        }
+        let record = pt.next_record()?.unwrap();
+        assert_eq!(record[1], "12.5".into());
+        assert_eq!(record[2], "n/a".into());
+        assert_eq!(record[3], "yes".into());
+        Ok(())
+    }
+
     #[test]
     fn test_two_size_reader() -> Result<(), EtError> {
         const TEST_TEXT: &[u8] = b"header\tcol1\nrow\t2\nanother row\t3";
@@ -6,11 +6,11 @@ use alloc::vec::Vec;
 use bytecount::count;
 use memchr::memchr;
 
-use crate::error::EtError;
+use crate::error::{ErrorKind, EtError};
 use crate::parsers::common::NewLine;
 use crate::parsers::extract;
 use crate::parsers::tsv::TsvParams;
-use crate::record::Value;
+use crate::record::{Value, ValueType};
 
 /// Used to compute basic statistics on streaming data
 #[derive(Clone, Copy, Debug, Default)]
@@ -94,7 +94,10 @@ pub(crate) fn split<'a>(
                         cur_pos += next + 2;
                         break;
                     } else if line[qpos + next + 1] != quote {
-                        return Err("quotes must start and end next to delimiters".into());
+                        return Err(
+                            EtError::from("quotes must start and end next to delimiters")
+                                .with_kind(ErrorKind::InvalidValue),
+                        );
                     }
                     // or its right before a pair of quotes (how CSVs escape a quote inside quoted
                     // output). note that the error case is above because we need to continue
@@ -107,7 +110,9 @@ pub(crate) fn split<'a>(
                     quoted_quotes = true;
                     cur_pos += next + 2;
                 } else {
-                    return Err("unclosed delimiter".into());
+                    return Err(
+                        EtError::from("unclosed delimiter").with_kind(ErrorKind::InvalidValue)
+                    );
                 }
             }
         } else if let Some(next) = memchr(delim, &line[cur_pos..]) {
@@ -288,6 +293,10 @@ impl TsvFieldType {
     pub fn infer(&mut self, field: &str) {
         let mut possible_type = TSV_STR;
         let field = field.trim();
+        // empty fields are read as nulls so they fit any type
+        if field.is_empty() {
+            return;
+        }
         if field == "F"
             || field == "f"
             || field == "FALSE"
@@ -327,32 +336,56 @@ impl TsvFieldType {
         self.ty &= possible_type;
     }
 
+    /// The type of `Value` fields of this type are coerced into
+    #[must_use]
+    pub fn value_type(&self) -> ValueType {
+        match 128 >> self.ty.leading_zeros() {
+            TSV_BOOL => ValueType::Boolean,
+            TSV_FLOAT => ValueType::Float,
+            TSV_INT => ValueType::Integer,
+            _ => ValueType::String,
+        }
+    }
+
     /// Coerce a string into a Value
+    ///
+    /// Empty fields become `Value::Null`; fields that can't be parsed as the inferred type (e.g.
+    /// because they're past the part of the file that was used to guess it) are kept as strings.
     #[allow(clippy::match_same_arms)] // TODO: remove when dates are supported
     #[must_use]
     pub fn coerce<'a>(&self, field: Cow<'a, str>) -> Value<'a> {
         let f = field.trim();
-        match 128 >> self.ty.leading_zeros() {
+        let ty = 128 >> self.ty.leading_zeros();
+        if f.is_empty() && ty != TSV_STR {
+            return Value::Null;
+        }
+        match ty {
             // TODO: we can't use `trim` because that requires a borrow inside this function :/
             TSV_STR => Value::from(field),
-            TSV_BOOL => {
-                if f == "T" || f == "t" || f == "TRUE" || f == "True" || f == "true" {
-                    Value::Boolean(true)
-                } else {
-                    Value::Boolean(false)
-                }
-            }
-            TSV_FLOAT => f
-                .parse::<f64>()
-                .map_or_else(|_| Value::from(field), Value::from),
-            TSV_INT => f
-                .parse::<i64>()
-                .map_or_else(|_| Value::from(field), Value::from),
+            TSV_BOOL => match f {
+                "T" | "t" | "TRUE" | "True" | "true" => Value::Boolean(true),
+                "F" | "f" | "FALSE" | "False" | "false" => Value::Boolean(false),
+                _ => Value::from(field),
+            },
+            TSV_FLOAT => match f.parse::<f64>() {
+                Ok(v) => Value::from(v),
+                Err(_) => Value::from(field),
+            },
+            TSV_INT => match f.parse::<i64>() {
+                Ok(v) => Value::from(v),
+                Err(_) => Value::from(field),
+            },
             // TODO: handle dates
             TSV_DATE => Value::from(field),
             _ => Value::from(field),
         }
     }
+
+    /// If `coerce` can return `Value::Null` (for empty fields) for fields of this type
+    #[must_use]
+    pub fn nullable(&self) -> bool {
+        !matches!(self.value_type(), ValueType::String)
+    }
 }
 
 #[cfg(test)]
@@ -156,6 +156,7 @@ impl StateMetadata for XmlState {
 
 impl<'b: 's, 's> FromSlice<'b, 's> for XmlState {
     type State = ();
+    const TEXT: bool = true;
 }
 
 /// A single record from an XML stream
@@ -169,6 +170,7 @@ pub struct XmlRecord<'r> {
 
 impl<'b: 's, 's> FromSlice<'b, 's> for XmlRecord<'r> {
     type State = &'r mut XmlState;
+    const TEXT: bool = true;
 
     fn parse(rb: &[u8], eof: bool, consumed: &mut usize, state: &mut Self::State) -> Result<bool, EtError> {
         if rb.is_empty() {
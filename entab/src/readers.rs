@@ -4,17 +4,32 @@ use alloc::format;
 use alloc::string::String;
 use alloc::vec::Vec;
 use core::convert::TryInto;
+use core::task::{Context, Poll};
 
+#[cfg(feature = "async")]
+use futures_io::AsyncRead;
+
+use crate::batch::ColumnBatch;
 use crate::buffer::ReadBuffer;
-use crate::compression::decompress;
-use crate::error::EtError;
-use crate::parsers;
+use crate::compression::decompress_all;
+#[cfg(feature = "async")]
+use crate::compression::decompress_all_buffer_async;
+use crate::error::{ErrorKind, EtError};
+use crate::filetype::FileType;
+use crate::params::{take_bool, take_usize};
 use crate::parsers::FromSlice;
-use crate::record::Value;
+use crate::record::{ColumnSchema, Value};
+use crate::registry::{detect_parser, find_parser};
+use crate::select::Selection;
 
 /// Turn `rb` into a Reader of type `parser`.
 ///
-/// If `parser` is `None`, infer the correct parser from the file type.
+/// If `parser` is `None`, infer the correct parser from the file type (see
+/// `registry::detect_parser`). Parsers added with `registry::register_parser` can be used too.
+///
+/// Every layer of compression (up to `compression::MAX_DECOMPRESSION_DEPTH`) is removed first
+/// and, if there were any, the codecs are listed (outermost first) in the reader's "compression"
+/// metadata.
 ///
 /// # Errors
 /// If an error happens during decompression or parser detection, an `EtError` is returned.
@@ -27,70 +42,173 @@ where
     B: TryInto<ReadBuffer<'r>>,
     EtError: From<<B as TryInto<ReadBuffer<'r>>>::Error>,
 {
-    let (mut rb, _): (ReadBuffer<'r>, _) = decompress(data)?;
-    let parser_name = rb.sniff_filetype()?.to_parser_name(parser)?;
-    _get_reader(rb, parser_name, params.unwrap_or_else(BTreeMap::new))
+    let params = params.unwrap_or_else(BTreeMap::new);
+    let filename = match params.get("filename") {
+        Some(Value::String(f)) => Some(f.as_ref()),
+        _ => None,
+    };
+    let (mut rb, compression) = decompress_all(data, filename)?;
+    let parser_name = match parser {
+        Some(name) => name,
+        None => detect_parser(&mut rb, filename)?,
+    };
+    let (reader, parser_name) = _get_reader(rb, parser_name, params)?;
+    Ok((with_compression(reader, compression), parser_name))
+}
+
+/// Turn an `AsyncRead` into a Reader of type `parser`.
+///
+/// This works the same as `get_reader`, but waits on `reader` for more data rather than
+/// blocking. Records should then be read with `next_record_async`.
+///
+/// # Errors
+/// If an error happens during decompression or parser detection, an `EtError` is returned.
+#[cfg(feature = "async")]
+pub async fn get_reader_async<'n, 'p, 'r, R>(
+    reader: R,
+    parser: Option<&'n str>,
+    params: Option<BTreeMap<String, Value<'p>>>,
+) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError>
+where
+    R: AsyncRead + 'r,
+{
+    let params = params.unwrap_or_default();
+    let filename = match params.get("filename") {
+        Some(Value::String(f)) => Some(f.as_ref()),
+        _ => None,
+    };
+    // apply the buffer limit while decompressing too since some formats have to be read in whole
+    let mut rb = ReadBuffer::from_async_reader(Box::pin(reader), None).await?;
+    rb.max_buffer_size = take_usize(&mut params.clone(), "max_buffer_size")?;
+    let (mut rb, compression) = decompress_all_buffer_async(rb, filename).await?;
+    // the parsers are all synchronous, so every time one runs out of data to set itself up with
+    // we wait for more and try again from the start; the buffer's at least doubled in between so
+    // a long header isn't reparsed once per read
+    loop {
+        let mut trial_rb = rb
+            .try_clone()
+            .ok_or("get_reader_async can only read from async sources")?;
+        let parser_name = match parser {
+            Some(name) => Ok(name),
+            None => detect_parser(&mut trial_rb, filename),
+        };
+        match parser_name.and_then(|name| _get_reader(trial_rb, name, params.clone())) {
+            Err(e) if e.pending => {
+                let wanted = 2 * rb.as_ref().len().max(1);
+                while rb.as_ref().len() < wanted && rb.fill().await? {}
+            }
+            result => {
+                return result.map(|(reader, parser_name)| {
+                    (with_compression(reader, compression), parser_name)
+                })
+            }
+        }
+    }
 }
 
 /// Internal function to handle `get_reader` not inferring that the Reader constructors need to be
 /// created using `ReadBuffer` and not `B`.
 fn _get_reader<'n, 'p, 'r>(
-    rb: ReadBuffer<'r>,
+    mut rb: ReadBuffer<'r>,
     parser_name: &'n str,
     mut params: BTreeMap<String, Value<'p>>,
 ) -> Result<(Box<dyn RecordReader + 'r>, &'n str), EtError> {
-    let reader: Box<dyn RecordReader + 'r> = match parser_name {
-        "bam" => Box::new(parsers::sam::BamReader::new(rb, None)?),
-        "chemstation_fid" => Box::new(parsers::agilent::chemstation::ChemstationFidReader::new(
-            rb, None,
-        )?),
-        "chemstation_ms" => Box::new(parsers::agilent::chemstation::ChemstationMsReader::new(
-            rb, None,
-        )?),
-        "chemstation_mwd" => Box::new(parsers::agilent::chemstation::ChemstationMwdReader::new(
-            rb, None,
-        )?),
-        "chemstation_uv" => Box::new(parsers::agilent::chemstation_new::ChemstationUvReader::new(
-            rb, None,
-        )?),
-        "csv" => Box::new(parsers::tsv::TsvReader::new(
-            rb,
-            Some(parsers::tsv::TsvParams::default().delim(b',')),
-        )?),
-        "fasta" => Box::new(parsers::fasta::FastaReader::new(rb, None)?),
-        "fastq" => Box::new(parsers::fastq::FastqReader::new(rb, None)?),
-        "flow" => Box::new(parsers::flow::FcsReader::new(rb, None)?),
-        "inficon" => Box::new(parsers::inficon::InficonReader::new(rb, None)?),
-        #[cfg(feature = "std")]
-        "masshunter_dad" => Box::new(parsers::agilent::masshunter::MasshunterDadReader::new(
-            rb,
-            Some(
-                params
-                    .remove("filename")
-                    .ok_or_else(|| "No filename found".into())
-                    .and_then(Value::into_string)?,
-            ),
-        )?),
-        #[cfg(feature = "std")]
-        "png" => Box::new(parsers::png::PngReader::new(rb, None)?),
-        "sam" => Box::new(parsers::sam::SamReader::new(rb, None)?),
-        "thermo_cf" => Box::new(parsers::thermo::thermo_iso::ThermoCfReader::new(rb, None)?),
-        "thermo_dxf" => Box::new(parsers::thermo::thermo_iso::ThermoDxfReader::new(rb, None)?),
-        "thermo_raw" => Box::new(parsers::thermo::thermo_raw::ThermoRawReader::new(rb, None)?),
-        "tsv" => Box::new(parsers::tsv::TsvReader::new(
-            rb,
-            Some(parsers::tsv::TsvParams::default().delim(b'\t')),
-        )?),
-        x => return Err(format!("No parser available for the parser {}", x).into()),
-    };
+    let selection = Selection::from_params(&mut params)?;
+    rb.max_buffer_size = take_usize(&mut params, "max_buffer_size")?;
+    rb.max_record_size = take_usize(&mut params, "max_record_size")?;
+    rb.lenient = take_bool(&mut params, "lenient")?.unwrap_or(false);
+    let parser = find_parser(parser_name).ok_or_else(|| {
+        EtError::from(format!(
+            "No parser available for the parser {}",
+            parser_name
+        ))
+        .with_kind(ErrorKind::UnsupportedFormat)
+    })?;
+    let mut reader = (parser.constructor)(rb, &mut params)?;
     drop(params.remove("filename"));
     if !params.is_empty() {
         let keys: Vec<&str> = params.keys().map(AsRef::as_ref).collect();
-        return Err(format!("Unused params remain: {}", keys.join(",")).into());
+        let accepted: Vec<&str> = parser.params.iter().map(|p| p.name).collect();
+        return Err(EtError::from(format!(
+            "Unused params remain: {} (the {} parser accepts: {})",
+            keys.join(","),
+            parser_name,
+            if accepted.is_empty() {
+                "no params of its own".into()
+            } else {
+                accepted.join(",")
+            }
+        ))
+        .with_kind(ErrorKind::UnusedParams));
+    }
+    if !selection.is_empty() {
+        reader.select(&selection)?;
     }
     Ok((reader, parser_name))
 }
 
+/// Add the codecs `reader`'s data was compressed with to its metadata (if there were any).
+fn with_compression<'r>(
+    reader: Box<dyn RecordReader + 'r>,
+    compression: Vec<FileType>,
+) -> Box<dyn RecordReader + 'r> {
+    if compression.is_empty() {
+        return reader;
+    }
+    Box::new(CompressedReader {
+        reader,
+        compression,
+    })
+}
+
+/// Wraps a reader to report the compression its data was stored with as metadata.
+#[derive(Debug)]
+struct CompressedReader<'r> {
+    reader: Box<dyn RecordReader + 'r>,
+    compression: Vec<FileType>,
+}
+
+impl<'r> RecordReader for CompressedReader<'r> {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        self.reader.next_record()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        self.reader.headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        let mut metadata = self.reader.metadata();
+        let codecs = self
+            .compression
+            .iter()
+            .map(|ft| format!("{:?}", ft).to_ascii_lowercase().into())
+            .collect();
+        drop(metadata.insert("compression".into(), Value::List(codecs)));
+        metadata
+    }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        self.reader.schema()
+    }
+
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        self.reader.select(selection)
+    }
+
+    fn next_batch(&mut self, batch: &mut ColumnBatch, n: usize) -> Result<usize, EtError> {
+        self.reader.next_batch(batch, n)
+    }
+
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, EtError>> {
+        self.reader.poll_fill(cx)
+    }
+
+    fn warnings(&self) -> &[EtError] {
+        self.reader.warnings()
+    }
+}
+
 /// The trait that maps over "generic" `RecordReader`s
 ///
 /// Structs that implement this trait should also implement a `new` method that
@@ -112,6 +230,123 @@ pub trait RecordReader: ::core::fmt::Debug {
 
     /// Extra metadata about the file or data in the file
     fn metadata(&self) -> BTreeMap<String, Value>;
+
+    /// The declared name, type, nullability and unit of every item in the record
+    ///
+    /// By default the types are unknown and every column is nullable.
+    fn schema(&self) -> Vec<ColumnSchema> {
+        self.headers()
+            .iter()
+            .map(|h| ColumnSchema::any(h))
+            .collect()
+    }
+
+    /// Only return the columns and rows picked out by `selection`
+    ///
+    /// This replaces any previous selection; the headers and schema change to match.
+    ///
+    /// # Errors
+    /// If a column in `selection` doesn't exist or this reader can't select columns.
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        let _ = selection;
+        Err("This reader doesn't support selecting columns or filtering rows".into())
+    }
+
+    /// Read up to `n` records into the columns of `batch`, returning how many were read.
+    ///
+    /// Any records already in `batch` are cleared first so the same batch can be reused for
+    /// every call; a fresh (default) batch has its columns set up from `schema`. Zero is
+    /// returned once there are no more records.
+    ///
+    /// # Errors
+    /// If a record can't be read or doesn't match the columns in `batch`, an error is returned.
+    fn next_batch(&mut self, batch: &mut ColumnBatch, n: usize) -> Result<usize, EtError> {
+        batch.reset(|| self.schema());
+        while batch.len() < n {
+            match self.next_record()? {
+                Some(record) => batch.push_record(record)?,
+                None => break,
+            }
+        }
+        Ok(batch.len())
+    }
+
+    /// Try to pull more data into this reader's buffer (see `ReadBuffer::poll_fill`).
+    ///
+    /// By default readers can't wait on async sources and this returns an error.
+    ///
+    /// # Errors
+    /// If more data can't be read.
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, EtError>> {
+        let _ = cx;
+        Poll::Ready(Err("This reader can't read from async sources".into()))
+    }
+
+    /// The errors for the malformed records that have been skipped (with the "lenient" param).
+    ///
+    /// By default readers don't skip records and this is empty.
+    fn warnings(&self) -> &[EtError] {
+        &[]
+    }
+}
+
+/// Lets a reader be lent out (e.g. to `parquet::write_parquet`) and still be used afterwards to
+/// check its `warnings`.
+impl<R: RecordReader + ?Sized> RecordReader for &mut R {
+    fn next_record(&mut self) -> Result<Option<Vec<Value<'_>>>, EtError> {
+        (**self).next_record()
+    }
+
+    fn headers(&self) -> Vec<String> {
+        (**self).headers()
+    }
+
+    fn metadata(&self) -> BTreeMap<String, Value<'_>> {
+        (**self).metadata()
+    }
+
+    fn schema(&self) -> Vec<ColumnSchema> {
+        (**self).schema()
+    }
+
+    fn select(&mut self, selection: &Selection) -> Result<(), EtError> {
+        (**self).select(selection)
+    }
+
+    fn next_batch(&mut self, batch: &mut ColumnBatch, n: usize) -> Result<usize, EtError> {
+        (**self).next_batch(batch, n)
+    }
+
+    fn poll_fill(&mut self, cx: &mut Context<'_>) -> Poll<Result<bool, EtError>> {
+        (**self).poll_fill(cx)
+    }
+
+    fn warnings(&self) -> &[EtError] {
+        (**self).warnings()
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'r> dyn RecordReader + 'r {
+    /// Returns the next record from the file, waiting on an async source for more data as needed.
+    ///
+    /// The values are copied out of the buffer (see `Value::into_owned`) so that reading can be
+    /// retried after waiting without the borrow checker tying every attempt to the returned record.
+    ///
+    /// # Errors
+    /// If the record can't be read, an error is returned.
+    pub async fn next_record_async(&mut self) -> Result<Option<Vec<Value<'static>>>, EtError> {
+        loop {
+            match self.next_record() {
+                Ok(record) => {
+                    return Ok(record.map(|r| r.into_iter().map(Value::into_owned).collect()))
+                }
+                Err(e) if e.pending => {}
+                Err(e) => return Err(e),
+            }
+            let _ = core::future::poll_fn(|cx| self.poll_fill(cx)).await?;
+        }
+    }
 }
 
 /// Generates a `...Reader` struct for the associated state-based file parsers
@@ -125,6 +360,7 @@ macro_rules! impl_reader {
         pub struct $reader<'r> {
             rb: $crate::buffer::ReadBuffer<'r>,
             state: $state,
+            selection: Option<$crate::select::ResolvedSelection>,
         }
 
         impl<'r> $reader<'r> {
@@ -133,12 +369,16 @@ macro_rules! impl_reader {
             /// # Errors
             /// If data could not be turned into a `ReadBuffer` successfully or if the initial state
             /// could not be extracted, returns an `EtError`.
-            pub fn new<B>(data: B, params: Option<$new_params>) -> Result<Self, EtError> where
+            pub fn new<B>(data: B, params: Option<$new_params>) -> Result<Self, $crate::EtError> where
                 B: ::core::convert::TryInto<$crate::buffer::ReadBuffer<'r>>,
-                EtError: From<<B as ::core::convert::TryInto<$crate::buffer::ReadBuffer<'r>>>::Error>,
+                $crate::EtError: From<<B as ::core::convert::TryInto<$crate::buffer::ReadBuffer<'r>>>::Error>,
             {
                 let (rb, state) = $crate::readers::init_state(data, params)?;
-                Ok($reader { rb, state })
+                Ok($reader {
+                    rb,
+                    state,
+                    selection: None,
+                })
             }
 
             /// Return the specialized version of this record.
@@ -149,8 +389,8 @@ macro_rules! impl_reader {
             /// # Errors
             /// If a value could not be extracted, return an `EtError`.
             #[allow(clippy::should_implement_trait)]
-            pub fn next(&mut self) -> Result<Option<$record>, EtError> {
-                self.rb.next::<$record>(&mut self.state)
+            pub fn next(&mut self) -> Result<Option<$record>, $crate::EtError> {
+                self.rb.next_kept::<$record, _>(&mut self.state, |_| true)
             }
         }
 
@@ -158,22 +398,97 @@ macro_rules! impl_reader {
             /// The next record, expressed as a `Vec` of `Value`s.
             fn next_record(
                 &mut self,
-            ) -> Result<Option<::alloc::vec::Vec<$crate::record::Value>>, EtError> {
-                Ok(self.next()?.map(|r| r.into()))
+            ) -> Result<Option<$crate::__alloc::vec::Vec<$crate::record::Value>>, $crate::EtError> {
+                use $crate::select::SelectRecord;
+                let selection = self.selection.as_ref();
+                let record = self.rb.next_kept::<$record, _>(&mut self.state, |record| {
+                    selection.map_or(true, |s| record.matches(s))
+                })?;
+                Ok(record.map(|record| match selection {
+                    Some(s) => record.project(s),
+                    None => record.into(),
+                }))
             }
 
             /// The headers for this Reader.
-            fn headers(&self) -> ::alloc::vec::Vec<::alloc::string::String> {
+            fn headers(&self) -> $crate::__alloc::vec::Vec<$crate::__alloc::string::String> {
                 use $crate::record::StateMetadata;
-                use ::alloc::string::ToString;
-                self.state.header().iter().map(|s| s.to_string()).collect()
+                use $crate::__alloc::string::ToString;
+                let headers = self.state.header();
+                match &self.selection {
+                    Some(s) => s.project(&headers),
+                    None => headers,
+                }
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
             }
 
             /// The metadata for this Reader.
-            fn metadata(&self) -> ::alloc::collections::BTreeMap<::alloc::string::String, $crate::record::Value> {
+            fn metadata(&self) -> $crate::__alloc::collections::BTreeMap<$crate::__alloc::string::String, $crate::record::Value> {
                 use $crate::record::StateMetadata;
                 self.state.metadata()
             }
+
+            /// The schema for this Reader.
+            fn schema(&self) -> $crate::__alloc::vec::Vec<$crate::record::ColumnSchema> {
+                use $crate::record::{RecordSchema, StateMetadata};
+                let schema =
+                    $crate::record::merge_schemas(self.state.schema(), <$record_lt>::schema());
+                match &self.selection {
+                    Some(s) => s.project(&schema),
+                    None => schema,
+                }
+            }
+
+            /// Only return the selected columns and rows.
+            fn select(&mut self, selection: &$crate::select::Selection) -> Result<(), $crate::EtError> {
+                use $crate::record::StateMetadata;
+                self.selection = if selection.is_empty() {
+                    None
+                } else {
+                    Some(selection.resolve(&self.state.header())?)
+                };
+                Ok(())
+            }
+
+            /// Read up to `n` records directly into the columns of `batch`.
+            fn next_batch(
+                &mut self,
+                batch: &mut $crate::batch::ColumnBatch,
+                n: usize,
+            ) -> Result<usize, $crate::EtError> {
+                use $crate::batch::BatchRecord;
+                use $crate::select::SelectRecord;
+                batch.reset(|| self.schema());
+                let selection = self.selection.as_ref();
+                while batch.len() < n {
+                    let record = match self.rb.next_kept::<$record, _>(&mut self.state, |record| {
+                        selection.map_or(true, |s| record.matches(s))
+                    })? {
+                        Some(record) => record,
+                        None => break,
+                    };
+                    match selection {
+                        Some(s) => batch.push_record(record.project(s))?,
+                        None => record.push_to(batch)?,
+                    }
+                }
+                Ok(batch.len())
+            }
+
+            /// Try to pull more data into the buffer.
+            fn poll_fill(
+                &mut self,
+                cx: &mut ::core::task::Context<'_>,
+            ) -> ::core::task::Poll<Result<bool, $crate::EtError>> {
+                self.rb.poll_fill(cx)
+            }
+
+            /// The errors for the records that have been skipped.
+            fn warnings(&self) -> &[$crate::EtError] {
+                &self.rb.warnings
+            }
         }
     };
 }
@@ -203,6 +518,9 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+    use core::num::NonZeroU64;
 
     #[test]
     #[cfg(all(feature = "compression", feature = "std"))]
@@ -221,4 +539,396 @@ mod test {
         assert!(reader.next_record().is_err());
         Ok(())
     }
+
+    #[test]
+    fn test_declared_schema() -> Result<(), EtError> {
+        use crate::record::ValueType;
+
+        let data: &[u8] = b"@SRR1\nACGT\n+\nAAAA\n";
+        let (reader, _) = get_reader(data, None, None)?;
+        assert_eq!(
+            reader.schema(),
+            [
+                ColumnSchema::new("id", ValueType::String),
+                ColumnSchema::new("sequence", ValueType::String),
+                ColumnSchema::new("quality", ValueType::String),
+            ]
+        );
+
+        let data: &[u8] = include_bytes!("../tests/data/test.sam");
+        let (reader, _) = get_reader(data, None, None)?;
+        let schema = reader.schema();
+        assert_eq!(schema.len(), reader.headers().len());
+        assert_eq!(
+            schema[3],
+            ColumnSchema::new("pos", ValueType::Integer).nullable(true)
+        );
+
+        let data: &[u8] = include_bytes!("../tests/data/carotenoid_extract.d/MSD1.MS");
+        let (reader, _) = get_reader(data, None, None)?;
+        assert_eq!(
+            reader.schema()[0],
+            ColumnSchema::new("time", ValueType::Float).unit("min")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_next_batch() -> Result<(), EtError> {
+        use crate::batch::ColumnData;
+
+        // batches should match the records read one at a time
+        for (data, parser) in [
+            (
+                &include_bytes!("../tests/data/carotenoid_extract.d/MSD1.MS")[..],
+                None,
+            ),
+            (&include_bytes!("../tests/data/test.sam")[..], None),
+            (
+                &include_bytes!("../tests/data/HTS_BD_LSR_II_Mixed_Specimen_001_D6_D06.fcs")[..],
+                None,
+            ),
+            (&include_bytes!("../tests/data/test.fastq")[..], None),
+            (&b"a,b\n1,x\n,2.5\n"[..], Some("csv")),
+        ] {
+            let (mut reader, _) = get_reader(data, parser, None)?;
+            let (mut batch_reader, _) = get_reader(data, parser, None)?;
+            let mut batch = ColumnBatch::default();
+            let mut n_records = 0;
+            while batch_reader.next_batch(&mut batch, 100)? > 0 {
+                assert!(batch.len() <= 100);
+                for i in 0..batch.len() {
+                    let record = reader.next_record()?.unwrap();
+                    assert_eq!(batch.record(i), record);
+                    n_records += 1;
+                }
+            }
+            assert!(n_records > 0);
+            assert!(reader.next_record()?.is_none());
+        }
+
+        let data: &[u8] = include_bytes!("../tests/data/carotenoid_extract.d/MSD1.MS");
+        let (mut reader, _) = get_reader(data, None, None)?;
+        let mut batch = ColumnBatch::default();
+        assert_eq!(reader.next_batch(&mut batch, 10)?, 10);
+        let time = batch.column("time").unwrap();
+        assert!(matches!(&time.data, ColumnData::Float(v) if v.len() == 10));
+        assert_eq!(time.validity, None);
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "compression")]
+    fn test_nested_compression_metadata() -> Result<(), EtError> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(include_bytes!("../tests/data/test.csv.zst"))?;
+        let data = encoder.finish()?;
+        let mut params = BTreeMap::new();
+        let _ = params.insert("filename".into(), "test.csv.zst.gz".into());
+        let (mut reader, parser) = get_reader(&data[..], Some("csv"), Some(params))?;
+        assert_eq!(parser, "csv");
+        assert_eq!(
+            reader.metadata()["compression"],
+            Value::List(vec!["gzip".into(), "zstd".into()])
+        );
+        assert!(reader.next_record()?.is_some());
+
+        // uncompressed data has no compression metadata
+        let (reader, _) = get_reader(&b"a,b\n1,2\n"[..], Some("csv"), None)?;
+        assert!(!reader.metadata().contains_key("compression"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_size_limit_params() -> Result<(), EtError> {
+        let data = b">short\nACGT\n>long\nACGTACGTACGTACGTACGTACGTACGTACGT\n";
+        let mut params = BTreeMap::new();
+        let _ = params.insert("max_record_size".into(), Value::Integer(20));
+        let (mut reader, _) = get_reader(&data[..], None, Some(params))?;
+        assert!(reader.next_record()?.is_some());
+        let e = reader
+            .next_record()
+            .expect_err("the second record is too long");
+        assert!(e.to_string().contains("max_record_size"));
+
+        let mut params = BTreeMap::new();
+        let _ = params.insert("max_buffer_size".into(), Value::Integer(-1));
+        assert!(get_reader(&data[..], None, Some(params)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_kinds() -> Result<(), EtError> {
+        let kind = |data: &'static [u8], parser, params: Option<BTreeMap<String, Value>>| {
+            match get_reader(data, parser, params) {
+                Ok((mut reader, _)) => loop {
+                    match reader.next_record() {
+                        Ok(Some(_)) => {}
+                        Ok(None) => panic!("the data should be bad"),
+                        Err(e) => break e.kind,
+                    }
+                },
+                Err(e) => e.kind,
+            }
+        };
+        assert_eq!(
+            kind(b"XAM\x01\0\0\0\0\0\0\0\0", Some("bam"), None),
+            ErrorKind::InvalidMagic
+        );
+        assert_eq!(kind(b"@a\nACGT\n+\nII", None, None), ErrorKind::Truncated);
+        assert_eq!(kind(b">a\nAC>GT\n", None, None), ErrorKind::InvalidValue);
+        assert_eq!(
+            kind(b"a,b\n", Some("nope"), None),
+            ErrorKind::UnsupportedFormat
+        );
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimeter".into(), ";".into());
+        assert_eq!(
+            kind(b"a,b\n", Some("csv"), Some(params)),
+            ErrorKind::UnusedParams
+        );
+        let mut params = BTreeMap::new();
+        let _ = params.insert("delimiter".into(), Value::Integer(2));
+        assert_eq!(
+            kind(b"a,b\n", Some("csv"), Some(params)),
+            ErrorKind::InvalidParam
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_error_lines() -> Result<(), EtError> {
+        // enough lines that the buffer has to be refilled before the bad one
+        let mut data = b"a\tb\n".to_vec();
+        for _ in 0..5000 {
+            data.extend_from_slice(b"1\t2\n");
+        }
+        data.extend_from_slice(b"3\t4\t5\n6\t7\n");
+        let buffer: Box<dyn std::io::Read> = Box::new(std::io::Cursor::new(data));
+        let (mut reader, _) = get_reader(buffer, Some("tsv"), None)?;
+        let err = loop {
+            if let Err(e) = reader.next_record() {
+                break e;
+            }
+        };
+        let context = err.context.as_ref().expect("the error has a position");
+        assert_eq!(
+            (context.line, context.column),
+            (NonZeroU64::new(5002), NonZeroU64::new(1))
+        );
+        assert_eq!(context.byte, 20004);
+        assert_eq!(
+            err.to_string(),
+            "Line had a bad number of records\nline 5002, column 1 (byte 20004):\n3\t4\t5\n^\n"
+        );
+
+        // binary data is still shown as bytes
+        let data: &[u8] = b"XAM\x01\0\0\0\0\0\0\0\0";
+        let err = get_reader(data, Some("bam"), None).expect_err("the magic is wrong");
+        let context = err.context.as_ref().expect("the error has a position");
+        assert_eq!(context.line, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        let _ = params.insert("lenient".to_string(), Value::Boolean(true));
+
+        let data: &[u8] = b"a\tb\n1\t2\n3\n4\t5\n";
+        let (mut reader, _) = get_reader(data, Some("tsv"), None)?;
+        assert!(reader.next_record()?.is_some());
+        assert!(reader.next_record().is_err());
+
+        let (mut reader, _) = get_reader(data, Some("tsv"), Some(params.clone()))?;
+        let mut ids = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            ids.push(record[0].clone().into_owned());
+        }
+        assert_eq!(ids, [Value::Integer(1), Value::Integer(4)]);
+        assert_eq!(reader.warnings().len(), 1);
+        let warning = &reader.warnings()[0];
+        assert_eq!(warning.kind, ErrorKind::InvalidValue);
+        let context = warning.context.as_ref().expect("warnings have a position");
+        assert_eq!((context.byte, context.record), (8, 3));
+
+        // the typed readers skip them too
+        let mut rb = ReadBuffer::from(data);
+        rb.lenient = true;
+        let tsv_params = crate::parsers::tsv::TsvParams {
+            sniff_file: false,
+            ..Default::default()
+        };
+        let mut reader = crate::parsers::tsv::TsvReader::new(rb, Some(tsv_params.delim(b'\t')))?;
+        let mut n_records = 0;
+        while reader.next()?.is_some() {
+            n_records += 1;
+        }
+        assert_eq!(n_records, 2);
+        assert_eq!(reader.warnings().len(), 1);
+
+        // malformed FASTA records are skipped up to the next header
+        let data: &[u8] = b">a\nAC>GT\nAA\n>b\nTT\n";
+        let (mut reader, _) = get_reader(data, None, Some(params))?;
+        let record = reader.next_record()?.expect("the second record is fine");
+        assert_eq!(record[0], "b".into());
+        assert!(reader.next_record()?.is_none());
+        assert_eq!(reader.warnings().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_params() -> Result<(), EtError> {
+        let data: &[u8] = include_bytes!("../tests/data/carotenoid_extract.d/MSD1.MS");
+        let (mut reader, _) = get_reader(data, None, None)?;
+        assert_eq!(reader.headers(), ["time", "mz", "intensity"]);
+        let mut expected = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            if let [time, Value::Float(mz), Value::Float(intensity)] = &record[..] {
+                if *mz >= 100. && *intensity > 1000. {
+                    expected.push(vec![Value::Float(*intensity), time.clone().into_owned()]);
+                }
+            }
+        }
+        assert!(!expected.is_empty());
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("columns".to_string(), "intensity,time".into()));
+        drop(params.insert(
+            "filter".to_string(),
+            Value::List(vec!["mz >= 100".into(), "intensity > 1000".into()]),
+        ));
+        let (mut reader, _) = get_reader(data, None, Some(params.clone()))?;
+        assert_eq!(reader.headers(), ["intensity", "time"]);
+        assert_eq!(reader.schema()[1].name, "time");
+        let mut selected = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            selected.push(
+                record
+                    .into_iter()
+                    .map(Value::into_owned)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(selected, expected);
+
+        // batches obey the selection too
+        let (mut reader, _) = get_reader(data, None, Some(params))?;
+        let mut batch = ColumnBatch::default();
+        assert_eq!(
+            reader.next_batch(&mut batch, 1000)?,
+            1000.min(expected.len())
+        );
+        assert_eq!(batch.columns().len(), 2);
+        assert_eq!(batch.record(0), expected[0]);
+
+        // readers with borrowed records can skip over filtered ones too
+        let data: &[u8] = include_bytes!("../tests/data/test.fastq");
+        let mut params = BTreeMap::new();
+        drop(params.insert("columns".to_string(), "id".into()));
+        drop(params.insert(
+            "filter".to_string(),
+            "id != 'SRR062634.1 HWI-EAS110_103327062:6:1:1092:8469/1'".into(),
+        ));
+        let (mut reader, _) = get_reader(data, None, Some(params))?;
+        let mut ids = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            assert_eq!(record.len(), 1);
+            ids.push(record[0].clone().into_string()?);
+        }
+        assert_eq!(ids.len(), 2499);
+        assert!(ids[0].starts_with("SRR062634.2 "));
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("columns".to_string(), "not_a_column".into()));
+        assert!(get_reader(data, None, Some(params)).is_err());
+        Ok(())
+    }
+
+    /// An in-memory stream that's only ready every other poll and returns a few bytes at a time
+    #[cfg(feature = "async")]
+    struct SlowReader {
+        data: &'static [u8],
+        ready: bool,
+    }
+
+    #[cfg(feature = "async")]
+    impl AsyncRead for SlowReader {
+        fn poll_read(
+            mut self: core::pin::Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<std::io::Result<usize>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let amt = buf.len().min(self.data.len()).min(100);
+            buf[..amt].copy_from_slice(&self.data[..amt]);
+            self.data = &self.data[amt..];
+            Poll::Ready(Ok(amt))
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn test_get_reader_async() -> Result<(), EtError> {
+        use futures_executor::block_on;
+
+        let data = include_bytes!("../tests/data/test.fastq");
+        let slow_reader = SlowReader { data, ready: false };
+        let (mut reader, parser) = block_on(get_reader_async(slow_reader, None, None))?;
+        assert_eq!(parser, "fastq");
+        let mut n_records = 0;
+        let mut last_id = String::new();
+        while let Some(record) = block_on(reader.next_record_async())? {
+            last_id = record[0].clone().into_string()?;
+            n_records += 1;
+        }
+        assert_eq!(n_records, 2500);
+        assert!(last_id.starts_with("SRR062634.2508 "));
+
+        // compressed streams are decompressed as the data comes in
+        let data = include_bytes!("../tests/data/test.bam");
+        let (mut reader, parser) = get_reader(&data[..], None, None)?;
+        let mut expected = Vec::new();
+        while let Some(record) = reader.next_record()? {
+            expected.push(format!("{:?}", record));
+        }
+        let slow_reader = SlowReader { data, ready: false };
+        let (mut reader, async_parser) = block_on(get_reader_async(slow_reader, None, None))?;
+        assert_eq!(async_parser, parser);
+        let mut records = Vec::new();
+        while let Some(record) = block_on(reader.next_record_async())? {
+            records.push(format!("{:?}", record));
+        }
+        assert!(!records.is_empty());
+        assert_eq!(records, expected);
+
+        // a stream that's fully in memory works too
+        let cursor = futures_util::io::Cursor::new(b"a,b\n1,2\n".to_vec());
+        let (mut reader, _) = block_on(get_reader_async(cursor, Some("csv"), None))?;
+        assert_eq!(reader.headers(), ["a", "b"]);
+        assert!(block_on(reader.next_record_async())?.is_some());
+        assert!(block_on(reader.next_record_async())?.is_none());
+
+        // and the sync API reports that it's waiting on more data
+        let slow_reader = SlowReader { data, ready: false };
+        let (mut reader, _) = block_on(get_reader_async(slow_reader, None, None))?;
+        let mut err = None;
+        for _ in 0..2500 {
+            if let Err(e) = reader.next_record() {
+                err = Some(e);
+                break;
+            }
+        }
+        assert!(err.is_some_and(|e| e.pending));
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use alloc::borrow::Cow;
+use alloc::borrow::{Cow, ToOwned};
 use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::vec::Vec;
@@ -7,7 +7,213 @@ use core::convert::TryFrom;
 use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Serialize, Serializer};
 
-use crate::error::EtError;
+use crate::error::{ErrorKind, EtError};
+
+/// The type of the `Value`s expected in a column
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    /// The type isn't known ahead of time
+    Any,
+    /// `Value::Boolean`
+    Boolean,
+    /// `Value::Datetime`
+    Datetime,
+    /// `Value::Float`
+    Float,
+    /// `Value::Integer`
+    Integer,
+    /// `Value::String`
+    String,
+    /// `Value::List`
+    List,
+    /// `Value::Record`
+    Record,
+}
+
+impl ValueType {
+    /// The name of this type
+    #[must_use]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValueType::Any => "any",
+            ValueType::Boolean => "boolean",
+            ValueType::Datetime => "datetime",
+            ValueType::Float => "float",
+            ValueType::Integer => "integer",
+            ValueType::String => "string",
+            ValueType::List => "list",
+            ValueType::Record => "record",
+        }
+    }
+}
+
+/// The declared name, type, nullability and unit of a column
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ColumnSchema {
+    /// The name of the column (matching the corresponding header)
+    pub name: String,
+    /// The type of the values in the column
+    #[serde(rename = "type")]
+    pub value_type: ValueType,
+    /// If the column can contain `Value::Null`s
+    pub nullable: bool,
+    /// The unit of the values in the column, if known
+    pub unit: Option<String>,
+}
+
+impl ColumnSchema {
+    /// Create a new non-nullable column without units
+    #[must_use]
+    pub fn new(name: &str, value_type: ValueType) -> Self {
+        ColumnSchema {
+            name: name.to_string(),
+            value_type,
+            nullable: false,
+            unit: None,
+        }
+    }
+
+    /// Create a new column that could contain anything
+    #[must_use]
+    pub fn any(name: &str) -> Self {
+        ColumnSchema::new(name, ValueType::Any).nullable(true)
+    }
+
+    /// Set if the column can contain nulls
+    #[must_use]
+    pub fn nullable(mut self, nullable: bool) -> Self {
+        self.nullable = nullable;
+        self
+    }
+
+    /// Set the unit of the column
+    #[must_use]
+    pub fn unit(mut self, unit: &str) -> Self {
+        self.unit = Some(unit.to_string());
+        self
+    }
+
+    /// Create a column from the type of a field on a record struct
+    ///
+    /// Only the type of `field` is used; this is how `impl_record!` derives its schema.
+    #[must_use]
+    pub fn from_field<R, T: SchemaType + ?Sized>(
+        name: &str,
+        _field: for<'a> fn(&'a R) -> &'a T,
+    ) -> Self {
+        ColumnSchema::new(name, T::value_type()).nullable(T::nullable())
+    }
+}
+
+/// Combine the schema a state declares with the one derived from its record type.
+///
+/// The state's columns are used as the base and any of them with a type of `ValueType::Any`
+/// take their type and nullability from the matching column in the record schema.
+#[must_use]
+pub fn merge_schemas(
+    state_schema: Vec<ColumnSchema>,
+    record_schema: Vec<ColumnSchema>,
+) -> Vec<ColumnSchema> {
+    if state_schema.len() != record_schema.len() {
+        return state_schema;
+    }
+    state_schema
+        .into_iter()
+        .zip(record_schema)
+        .map(|(mut column, record_column)| {
+            if column.value_type == ValueType::Any {
+                column.value_type = record_column.value_type;
+                column.nullable = record_column.nullable;
+            }
+            if column.unit.is_none() {
+                column.unit = record_column.unit;
+            }
+            column
+        })
+        .collect()
+}
+
+/// A Rust type that can be turned into a `Value` of a known `ValueType`
+pub trait SchemaType {
+    /// The `ValueType` this type is turned into
+    fn value_type() -> ValueType;
+
+    /// If this type can be turned into a `Value::Null`
+    fn nullable() -> bool {
+        false
+    }
+}
+
+macro_rules! impl_schema_type {
+    ($value_type:ident: $($type:ty),*) => {
+        $(
+            impl SchemaType for $type {
+                fn value_type() -> ValueType {
+                    ValueType::$value_type
+                }
+            }
+        )*
+    };
+}
+
+impl_schema_type!(Boolean: bool);
+impl_schema_type!(Datetime: NaiveDate, NaiveDateTime);
+impl_schema_type!(Float: f32, f64);
+impl_schema_type!(Integer: u8, u16, i32, u32, i64, u64);
+impl_schema_type!(String: str, [u8], String, Vec<u8>);
+impl_schema_type!(List: [String], Vec<String>, Vec<Value<'_>>);
+
+impl<T: SchemaType + ?Sized> SchemaType for &T {
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+
+    fn nullable() -> bool {
+        T::nullable()
+    }
+}
+
+impl<T: SchemaType + ToOwned + ?Sized> SchemaType for Cow<'_, T> {
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+
+    fn nullable() -> bool {
+        T::nullable()
+    }
+}
+
+impl<T: SchemaType> SchemaType for Option<T> {
+    fn value_type() -> ValueType {
+        T::value_type()
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+}
+
+impl SchemaType for Value<'_> {
+    fn value_type() -> ValueType {
+        ValueType::Any
+    }
+
+    fn nullable() -> bool {
+        true
+    }
+}
+
+/// The declared schema of the columns a record struct is turned into
+///
+/// This is derived by `impl_record!`; records with a variable set of columns use the default
+/// (empty) schema and rely on their state to declare one instead.
+pub trait RecordSchema {
+    /// The columns produced from this record
+    fn schema() -> Vec<ColumnSchema> {
+        Vec::new()
+    }
+}
 
 /// For a given state struct, the metadata associated with that struct.
 ///
@@ -21,6 +227,14 @@ pub trait StateMetadata {
 
     /// The fields in the associated struct
     fn header(&self) -> Vec<&str>;
+
+    /// The declared schema of the fields in the associated struct
+    ///
+    /// By default every column can be anything; columns left as `ValueType::Any` are filled in
+    /// from the record's `RecordSchema` by `impl_reader!`.
+    fn schema(&self) -> Vec<ColumnSchema> {
+        self.header().into_iter().map(ColumnSchema::any).collect()
+    }
 }
 
 impl StateMetadata for () {
@@ -36,9 +250,69 @@ impl StateMetadata for () {
 #[macro_export]
 macro_rules! impl_record {
     ($type:ty : $($key:ident),* ) => {
-        impl<'r> From<$type> for ::alloc::vec::Vec<$crate::record::Value<'r>> {
+        impl<'r> From<$type> for $crate::__alloc::vec::Vec<$crate::record::Value<'r>> {
             fn from(record: $type) -> Self {
-                ::alloc::vec![$(record.$key.into(),)*]
+                $crate::__alloc::vec![$(record.$key.into(),)*]
+            }
+        }
+
+        impl<'r> $crate::record::RecordSchema for $type {
+            fn schema() -> $crate::__alloc::vec::Vec<$crate::record::ColumnSchema> {
+                $crate::__alloc::vec![$(
+                    $crate::record::ColumnSchema::from_field::<$type, _>(
+                        stringify!($key),
+                        |record| &record.$key,
+                    ),
+                )*]
+            }
+        }
+
+        impl<'r> $crate::batch::BatchRecord for $type {
+            fn push_to(
+                self,
+                batch: &mut $crate::batch::ColumnBatch,
+            ) -> Result<(), $crate::EtError> {
+                let mut columns = batch.next_row([$(stringify!($key),)*].len())?.iter_mut();
+                $(
+                    if let Some(column) = columns.next() {
+                        $crate::batch::ColumnValue::push_to(self.$key, column);
+                    }
+                )*
+                Ok(())
+            }
+        }
+
+        impl<'r> $crate::select::SelectRecord<'r> for $type {
+            #[allow(unused_assignments)]
+            fn matches(&self, selection: &$crate::select::ResolvedSelection) -> bool {
+                // only the fields with filters on them are converted
+                let mut field = 0;
+                $(
+                    if !selection.matches_field(field, || {
+                        ::core::clone::Clone::clone(&self.$key).into()
+                    }) {
+                        return false;
+                    }
+                    field += 1;
+                )*
+                true
+            }
+
+            #[allow(unused_assignments)]
+            fn project(
+                self,
+                selection: &$crate::select::ResolvedSelection,
+            ) -> $crate::__alloc::vec::Vec<$crate::record::Value<'r>> {
+                let mut values =
+                    $crate::__alloc::vec![$crate::record::Value::Null; selection.n_columns()];
+                let mut field = 0;
+                $(
+                    if let Some(position) = selection.position(field) {
+                        values[position] = self.$key.into();
+                    }
+                    field += 1;
+                )*
+                values
             }
         }
     };
@@ -77,7 +351,7 @@ impl<'a> Value<'a> {
     /// If the string can't be interpreted as a date, an error is returned.
     pub fn from_iso_date(string: &str) -> Result<Self, EtError> {
         let datetime = NaiveDateTime::parse_from_str(string, "%+")
-            .map_err(|e| EtError::from(e.to_string()))?;
+            .map_err(|e| EtError::from(e.to_string()).with_kind(ErrorKind::InvalidValue))?;
         Ok(Self::Datetime(datetime))
     }
 
@@ -89,7 +363,24 @@ impl<'a> Value<'a> {
         if let Value::String(s) = self {
             return Ok(s.into_owned());
         }
-        Err(EtError::from("Value was not a string"))
+        Err(EtError::from("Value was not a string").with_kind(ErrorKind::InvalidValue))
+    }
+
+    /// Copy any borrowed data so the Value no longer depends on the buffer it came from.
+    #[must_use]
+    pub fn into_owned(self) -> Value<'static> {
+        match self {
+            Value::Null => Value::Null,
+            Value::Boolean(b) => Value::Boolean(b),
+            Value::Datetime(d) => Value::Datetime(d),
+            Value::Float(f) => Value::Float(f),
+            Value::Integer(i) => Value::Integer(i),
+            Value::String(s) => Value::String(Cow::Owned(s.into_owned())),
+            Value::List(l) => Value::List(l.into_iter().map(Value::into_owned).collect()),
+            Value::Record(r) => {
+                Value::Record(r.into_iter().map(|(k, v)| (k, v.into_owned())).collect())
+            }
+        }
     }
 }
 
@@ -0,0 +1,491 @@
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+#[cfg(feature = "std")]
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::sync::RwLock;
+
+use crate::buffer::ReadBuffer;
+#[cfg(feature = "std")]
+use crate::error::ErrorKind;
+use crate::error::EtError;
+use crate::filetype::FileType;
+#[cfg(feature = "std")]
+use crate::params::take_string;
+use crate::params::{take_bool, FromParams, ParamInfo};
+use crate::parsers;
+use crate::parsers::flow::FcsParams;
+#[cfg(feature = "std")]
+use crate::parsers::png::PngParams;
+use crate::parsers::sam::BamParams;
+use crate::parsers::thermo::thermo_iso_peaks::{ThermoIsoPeakParams, THERMO_ISO_PARAMS};
+use crate::parsers::tsv::TsvParams;
+use crate::readers::RecordReader;
+use crate::record::{Value, ValueType};
+
+/// Creates a reader for a parser from a buffer and the params passed to `get_reader`.
+///
+/// Constructors should remove any params they use from `params`; any that are left over are
+/// reported as unused.
+pub type ReaderConstructor = for<'r, 'p> fn(
+    ReadBuffer<'r>,
+    &mut BTreeMap<String, Value<'p>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError>;
+
+/// A parser that `get_reader` can create by name.
+#[derive(Clone, Copy, Debug)]
+pub struct ParserInfo {
+    /// The name used to select this parser, e.g. "fasta"
+    pub name: &'static str,
+    /// A short description of the format
+    pub description: &'static str,
+    /// File extensions (without the leading ".") this format usually has
+    pub extensions: &'static [&'static str],
+    /// Checks the start of a file and returns true if this parser can read it
+    pub sniff: Option<fn(&[u8]) -> bool>,
+    /// The params this parser accepts (in addition to `params::COMMON_PARAMS`)
+    pub params: &'static [ParamInfo],
+    /// Creates the reader
+    pub constructor: ReaderConstructor,
+}
+
+impl ParserInfo {
+    /// Create a new parser with the given name and constructor.
+    #[must_use]
+    pub const fn new(name: &'static str, constructor: ReaderConstructor) -> Self {
+        ParserInfo {
+            name,
+            description: "",
+            extensions: &[],
+            sniff: None,
+            params: &[],
+            constructor,
+        }
+    }
+
+    /// Set the description of the parser.
+    #[must_use]
+    pub const fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    /// Set the file extensions the parser reads.
+    ///
+    /// For registered parsers, these are used to pick a parser from the "filename" param when the
+    /// file type can't be detected from its contents.
+    #[must_use]
+    pub const fn extensions(mut self, extensions: &'static [&'static str]) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Set a function that detects this format from the start of a file.
+    ///
+    /// Sniffers for registered parsers are checked before the built-in file type detection.
+    #[must_use]
+    pub const fn sniff(mut self, sniff: fn(&[u8]) -> bool) -> Self {
+        self.sniff = Some(sniff);
+        self
+    }
+
+    /// Set the params the parser accepts.
+    #[must_use]
+    pub const fn params(mut self, params: &'static [ParamInfo]) -> Self {
+        self.params = params;
+        self
+    }
+}
+
+macro_rules! constructor {
+    ($name:ident, $reader:ty) => {
+        fn $name<'r>(
+            rb: ReadBuffer<'r>,
+            _params: &mut BTreeMap<String, Value<'_>>,
+        ) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+            Ok(Box::new(<$reader>::new(rb, None)?))
+        }
+    };
+}
+
+constructor!(
+    chemstation_fid,
+    parsers::agilent::chemstation::ChemstationFidReader
+);
+constructor!(
+    chemstation_mwd,
+    parsers::agilent::chemstation::ChemstationMwdReader
+);
+constructor!(
+    chemstation_uv,
+    parsers::agilent::chemstation_new::ChemstationUvReader
+);
+constructor!(fasta, parsers::fasta::FastaReader);
+constructor!(fastq, parsers::fastq::FastqReader);
+constructor!(inficon, parsers::inficon::InficonReader);
+constructor!(sam, parsers::sam::SamReader);
+constructor!(thermo_raw, parsers::thermo::thermo_raw::ThermoRawReader);
+
+fn bam<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    let bam_params = BamParams::from_params(params)?;
+    Ok(Box::new(parsers::sam::BamReader::new(
+        rb,
+        Some(bam_params),
+    )?))
+}
+
+fn chemstation_ms<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    if take_bool(params, "per_scan")? == Some(true) {
+        return Ok(Box::new(
+            parsers::agilent::chemstation::ChemstationMsScanReader::new(rb, None)?,
+        ));
+    }
+    Ok(Box::new(
+        parsers::agilent::chemstation::ChemstationMsReader::new(rb, None)?,
+    ))
+}
+
+fn delimited<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+    delim: u8,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    let mut tsv_params = TsvParams::from_params(params)?;
+    if tsv_params.delim_char.is_none() {
+        tsv_params = tsv_params.delim(delim);
+    }
+    Ok(Box::new(parsers::tsv::TsvReader::new(
+        rb,
+        Some(tsv_params),
+    )?))
+}
+
+fn csv<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    delimited(rb, params, b',')
+}
+
+fn flow<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    let fcs_params = FcsParams::from_params(params)?;
+    Ok(Box::new(parsers::flow::FcsReader::new(
+        rb,
+        Some(fcs_params),
+    )?))
+}
+
+#[cfg(feature = "std")]
+fn masshunter_dad<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    let filename = take_string(params, "filename")?
+        .ok_or_else(|| EtError::from("No filename found").with_kind(ErrorKind::InvalidParam))?;
+    Ok(Box::new(
+        parsers::agilent::masshunter::MasshunterDadReader::new(rb, Some(filename))?,
+    ))
+}
+
+#[cfg(feature = "std")]
+fn png<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    let png_params = PngParams::from_params(params)?;
+    Ok(Box::new(parsers::png::PngReader::new(
+        rb,
+        Some(png_params),
+    )?))
+}
+
+fn thermo_iso<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+    dxf: bool,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    if take_bool(params, "integrate_peaks")? == Some(true) {
+        let peak_params = ThermoIsoPeakParams::from_params(params)?;
+        return Ok(Box::new(
+            parsers::thermo::thermo_iso_peaks::ThermoIsoPeakReader::new(rb, Some(peak_params))?,
+        ));
+    }
+    Ok(if dxf {
+        Box::new(parsers::thermo::thermo_iso::ThermoDxfReader::new(rb, None)?)
+    } else {
+        Box::new(parsers::thermo::thermo_iso::ThermoCfReader::new(rb, None)?)
+    })
+}
+
+fn thermo_cf<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    thermo_iso(rb, params, false)
+}
+
+fn thermo_dxf<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    thermo_iso(rb, params, true)
+}
+
+fn tsv<'r>(
+    rb: ReadBuffer<'r>,
+    params: &mut BTreeMap<String, Value<'_>>,
+) -> Result<Box<dyn RecordReader + 'r>, EtError> {
+    delimited(rb, params, b'\t')
+}
+
+/// The parsers built into entab
+///
+/// These are detected from file contents through `FileType` rather than with sniffers.
+static BUILTIN_PARSERS: &[ParserInfo] = &[
+    ParserInfo::new("bam", bam)
+        .description("Binary sequence alignment map")
+        .extensions(&["bam"])
+        .params(BamParams::PARAMS),
+    ParserInfo::new("chemstation_fid", chemstation_fid)
+        .description("Agilent Chemstation flame ionization detector trace")
+        .extensions(&["ch"]),
+    ParserInfo::new("chemstation_ms", chemstation_ms)
+        .description("Agilent Chemstation mass spectrometry data")
+        .extensions(&["ms"])
+        .params(&[ParamInfo::new(
+            "per_scan",
+            ValueType::Boolean,
+            "Return one row per scan (with its TIC, base peak and number of peaks)",
+        )]),
+    ParserInfo::new("chemstation_mwd", chemstation_mwd)
+        .description("Agilent Chemstation moving wavelength detector trace")
+        .extensions(&["ch"]),
+    ParserInfo::new("chemstation_uv", chemstation_uv)
+        .description("Agilent Chemstation UV-visible detector data")
+        .extensions(&["uv"]),
+    ParserInfo::new("csv", csv)
+        .description("Comma-separated values")
+        .extensions(&["csv"])
+        .params(TsvParams::PARAMS),
+    ParserInfo::new("fasta", fasta)
+        .description("FASTA sequences")
+        .extensions(&["fa", "faa", "fasta", "fna"]),
+    ParserInfo::new("fastq", fastq)
+        .description("FASTQ sequences with qualities")
+        .extensions(&["faq", "fastq", "fq"]),
+    ParserInfo::new("flow", flow)
+        .description("FCS flow cytometry data")
+        .extensions(&["fcs", "lmd"])
+        .params(FcsParams::PARAMS),
+    ParserInfo::new("inficon", inficon)
+        .description("Inficon Hapsite mass spectrometry data")
+        .extensions(&["hps"]),
+    #[cfg(feature = "std")]
+    ParserInfo::new("masshunter_dad", masshunter_dad)
+        .description("Agilent Masshunter diode array detector data")
+        .extensions(&["sp"])
+        .params(&[ParamInfo::new(
+            "filename",
+            ValueType::String,
+            "The path of the file (required to find the .sd file next to it)",
+        )]),
+    #[cfg(feature = "std")]
+    ParserInfo::new("png", png)
+        .description("PNG image pixels")
+        .extensions(&["png"])
+        .params(PngParams::PARAMS),
+    ParserInfo::new("sam", sam)
+        .description("Sequence alignment map")
+        .extensions(&["sam"]),
+    ParserInfo::new("thermo_cf", thermo_cf)
+        .description("Thermo continuous flow isotope data")
+        .extensions(&["cf"])
+        .params(THERMO_ISO_PARAMS),
+    ParserInfo::new("thermo_dxf", thermo_dxf)
+        .description("Thermo dual inlet isotope data")
+        .extensions(&["dxf"])
+        .params(THERMO_ISO_PARAMS),
+    ParserInfo::new("thermo_raw", thermo_raw)
+        .description("Thermo RAW mass spectrometry data")
+        .extensions(&["raw"]),
+    ParserInfo::new("tsv", tsv)
+        .description("Tab-separated values")
+        .extensions(&["tsv"])
+        .params(TsvParams::PARAMS),
+];
+
+#[cfg(feature = "std")]
+static REGISTERED_PARSERS: RwLock<Vec<ParserInfo>> = RwLock::new(Vec::new());
+
+/// The parsers registered through `register_parser`
+#[cfg(feature = "std")]
+fn registered_parsers() -> Vec<ParserInfo> {
+    REGISTERED_PARSERS
+        .read()
+        .map_or_else(|e| e.into_inner().clone(), |p| p.clone())
+}
+
+#[cfg(not(feature = "std"))]
+fn registered_parsers() -> Vec<ParserInfo> {
+    Vec::new()
+}
+
+/// Add a parser so that `get_reader` can create it by name or detect it with its sniffer.
+///
+/// # Errors
+/// If a parser with the same name is already available, an error is returned.
+#[cfg(feature = "std")]
+pub fn register_parser(parser: ParserInfo) -> Result<(), EtError> {
+    let mut registered = REGISTERED_PARSERS
+        .write()
+        .map_err(|_| EtError::from("Parser registry is poisoned"))?;
+    if BUILTIN_PARSERS
+        .iter()
+        .chain(registered.iter())
+        .any(|p| p.name == parser.name)
+    {
+        return Err(format!("A parser named {} is already registered", parser.name).into());
+    }
+    registered.push(parser);
+    Ok(())
+}
+
+/// Every parser available to `get_reader`, built-in ones first.
+#[must_use]
+pub fn parsers() -> Vec<ParserInfo> {
+    let mut parsers = BUILTIN_PARSERS.to_vec();
+    parsers.extend(registered_parsers());
+    parsers
+}
+
+/// Look up a parser by name.
+#[must_use]
+pub fn find_parser(name: &str) -> Option<ParserInfo> {
+    BUILTIN_PARSERS
+        .iter()
+        .copied()
+        .find(|p| p.name == name)
+        .or_else(|| registered_parsers().into_iter().find(|p| p.name == name))
+}
+
+/// Work out which parser should read `rb`.
+///
+/// Registered sniffers are tried first, then the built-in file type detection and finally the
+/// extensions of registered parsers (if a `filename` is available).
+///
+/// # Errors
+/// If no parser can read the file, an error is returned.
+pub fn detect_parser(
+    rb: &mut ReadBuffer<'_>,
+    filename: Option<&str>,
+) -> Result<&'static str, EtError> {
+    let file_type = rb.sniff_filetype()?;
+    let registered = registered_parsers();
+    if let Some(parser) = registered
+        .iter()
+        .find(|p| p.sniff.is_some_and(|sniff| sniff(rb.as_ref())))
+    {
+        return Ok(parser.name);
+    }
+    if file_type == FileType::Unknown {
+        let extension = filename
+            .and_then(|f| f.rsplit_once('.'))
+            .map(|(_, ext)| ext.to_ascii_lowercase());
+        if let Some(ext) = extension {
+            if let Some(parser) = registered
+                .iter()
+                .find(|p| p.extensions.iter().any(|e| e.eq_ignore_ascii_case(&ext)))
+            {
+                return Ok(parser.name);
+            }
+        }
+    }
+    file_type.to_parser_name(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn test_builtin_parsers() {
+        let names: Vec<&str> = parsers().iter().map(|p| p.name).collect();
+        assert!(names.contains(&"fasta"));
+        assert!(names.contains(&"tsv"));
+        for parser in BUILTIN_PARSERS {
+            assert!(!parser.description.is_empty());
+            assert!(!parser.extensions.is_empty());
+        }
+        assert!(find_parser("fastq").is_some());
+        assert!(find_parser("not_a_parser").is_none());
+    }
+
+    #[test]
+    fn test_parser_params() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+
+        let tsv_params = find_parser("tsv").map(|p| p.params).unwrap_or_default();
+        assert!(tsv_params.iter().any(|p| p.name == "delimiter"));
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("delimiter".into(), "|".into()));
+        let (reader, _) = get_reader(&b"a|b\n1|2\n"[..], Some("tsv"), Some(params))?;
+        assert_eq!(reader.headers(), ["a", "b"]);
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("delimiter".into(), Value::Integer(2)));
+        assert!(get_reader(&b"a\tb\n"[..], Some("tsv"), Some(params)).is_err());
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("delimeter".into(), "|".into()));
+        let err = get_reader(&b"a\tb\n"[..], Some("tsv"), Some(params))
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default();
+        assert!(err.contains("delimeter"));
+        assert!(err.contains("delimiter"));
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_register_parser() -> Result<(), EtError> {
+        use crate::readers::get_reader;
+
+        // reads lines that start with "!XYZ" as CSVs
+        let parser = ParserInfo::new("test_xyz", csv)
+            .description("Test format")
+            .extensions(&["xyz"])
+            .sniff(|magic| magic.starts_with(b"!XYZ"));
+        register_parser(parser)?;
+        assert!(register_parser(parser).is_err());
+        assert!(register_parser(ParserInfo::new("fasta", csv)).is_err());
+        assert!(parsers().iter().any(|p| p.name == "test_xyz"));
+
+        let (mut reader, parser_name) = get_reader(&b"!XYZ,b\n1,2\n"[..], None, None)?;
+        assert_eq!(parser_name, "test_xyz");
+        assert_eq!(reader.headers(), ["!XYZ", "b"]);
+        assert!(reader.next_record()?.is_some());
+
+        let (_, parser_name) = get_reader(&b"a,b\n1,2\n"[..], Some("test_xyz"), None)?;
+        assert_eq!(parser_name, "test_xyz");
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("filename".into(), "data.XYZ".into()));
+        let (_, parser_name) = get_reader(&b"a,b\n1,2\n"[..], None, Some(params))?;
+        assert_eq!(parser_name, "test_xyz");
+        Ok(())
+    }
+}
@@ -0,0 +1,478 @@
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::str::FromStr;
+
+use crate::params::parse_constant;
+use crate::record::Value;
+use crate::{ErrorKind, EtError};
+
+/// How a `Predicate` compares the value in a column to its constant
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    /// `==` (or `=`)
+    Equal,
+    /// `!=`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    fn holds(self, ordering: Option<Ordering>) -> bool {
+        match (self, ordering) {
+            (Comparison::NotEqual, None) => true,
+            (_, None) => false,
+            (Comparison::Equal, Some(o)) => o == Ordering::Equal,
+            (Comparison::NotEqual, Some(o)) => o != Ordering::Equal,
+            (Comparison::Less, Some(o)) => o == Ordering::Less,
+            (Comparison::LessOrEqual, Some(o)) => o != Ordering::Greater,
+            (Comparison::Greater, Some(o)) => o == Ordering::Greater,
+            (Comparison::GreaterOrEqual, Some(o)) => o != Ordering::Less,
+        }
+    }
+}
+
+/// Compare two values, if they're comparable
+///
+/// Integers and floats can be compared with each other; everything else has to be the same type.
+#[allow(clippy::cast_precision_loss)]
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(b)),
+        (Value::Datetime(a), Value::Datetime(b)) => Some(a.cmp(b)),
+        (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+        (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+        (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// Split a comma-separated list, ignoring commas inside quotes (e.g. in `id == "a,b"`)
+fn split_unquoted(s: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (pos, c) in s.char_indices() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (',', None) => {
+                items.push(s[start..pos].trim().to_string());
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(s[start..].trim().to_string());
+    items
+}
+
+/// A filter on the value in a column, e.g. `mapq > 30`
+///
+/// Values that can't be compared to the constant (e.g. nulls) only match `!=`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Predicate {
+    /// The name of the column to check
+    pub column: String,
+    /// How to compare the column's value to `value`
+    pub comparison: Comparison,
+    /// The constant to compare against
+    pub value: Value<'static>,
+}
+
+impl Predicate {
+    /// Create a new `Predicate`
+    #[must_use]
+    pub fn new(column: &str, comparison: Comparison, value: Value<'static>) -> Self {
+        Predicate {
+            column: column.to_string(),
+            comparison,
+            value,
+        }
+    }
+
+    /// If `value` passes this filter
+    #[must_use]
+    pub fn matches(&self, value: &Value) -> bool {
+        self.comparison.holds(compare(value, &self.value))
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = EtError;
+
+    /// Parse a filter like `mapq > 30` or `ref_name == "chr1"`
+    ///
+    /// Unquoted constants are read as integers, floats or booleans if possible and as strings
+    /// otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let start = s.find(['=', '!', '<', '>']).ok_or_else(|| {
+            EtError::from(format!("Filter {} has no comparison", s))
+                .with_kind(ErrorKind::InvalidParam)
+        })?;
+        let (comparison, len) = match &s[start..] {
+            x if x.starts_with("==") => (Comparison::Equal, 2),
+            x if x.starts_with("!=") => (Comparison::NotEqual, 2),
+            x if x.starts_with("<=") => (Comparison::LessOrEqual, 2),
+            x if x.starts_with(">=") => (Comparison::GreaterOrEqual, 2),
+            x if x.starts_with('=') => (Comparison::Equal, 1),
+            x if x.starts_with('<') => (Comparison::Less, 1),
+            x if x.starts_with('>') => (Comparison::Greater, 1),
+            _ => {
+                return Err(
+                    EtError::from(format!("Filter {} has an unknown comparison", s))
+                        .with_kind(ErrorKind::InvalidParam),
+                )
+            }
+        };
+        let column = s[..start].trim();
+        if column.is_empty() {
+            return Err(EtError::from(format!("Filter {} has no column", s))
+                .with_kind(ErrorKind::InvalidParam));
+        }
+
+        let value = parse_constant(&s[start + len..]);
+        Ok(Predicate::new(column, comparison, value))
+    }
+}
+
+/// Which columns a reader should return and which rows it should skip
+///
+/// Passed to `get_reader` through the `columns` and `filter` params or set directly on a reader
+/// with `RecordReader::select`. Rows have to pass every predicate to be returned.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Selection {
+    /// The columns to return (in this order) or `None` for all of them
+    pub columns: Option<Vec<String>>,
+    /// The filters every returned row has to pass
+    pub predicates: Vec<Predicate>,
+}
+
+impl Selection {
+    /// Only return the columns in `columns`
+    #[must_use]
+    pub fn columns<S: AsRef<str>>(mut self, columns: &[S]) -> Self {
+        self.columns = Some(columns.iter().map(|c| c.as_ref().to_string()).collect());
+        self
+    }
+
+    /// Only return rows that pass `predicate` (in addition to any other predicates)
+    #[must_use]
+    pub fn filter(mut self, predicate: Predicate) -> Self {
+        self.predicates.push(predicate);
+        self
+    }
+
+    /// If this selects every column and row
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.columns.is_none() && self.predicates.is_empty()
+    }
+
+    /// Build a `Selection` from the `columns` and `filter` keys in `params`, removing them.
+    ///
+    /// Both can be either a list of strings or a single comma-separated string (commas inside
+    /// quotes, e.g. in `id == "a,b"`, don't split it).
+    ///
+    /// # Errors
+    /// If either key has the wrong type or a filter can't be parsed.
+    pub fn from_params(params: &mut BTreeMap<String, Value>) -> Result<Self, EtError> {
+        fn strings(key: &str, value: Value) -> Result<Vec<String>, EtError> {
+            match value {
+                Value::String(s) => Ok(split_unquoted(&s)),
+                Value::List(l) => l.into_iter().map(Value::into_string).collect(),
+                _ => Err(
+                    EtError::from(format!("{} must be a string or a list of strings", key))
+                        .with_kind(ErrorKind::InvalidParam),
+                ),
+            }
+        }
+
+        let mut selection = Selection::default();
+        if let Some(columns) = params.remove("columns") {
+            selection.columns = Some(strings("columns", columns)?);
+        }
+        if let Some(filters) = params.remove("filter") {
+            for filter in strings("filter", filters)? {
+                selection.predicates.push(filter.parse()?);
+            }
+        }
+        Ok(selection)
+    }
+
+    /// Match the columns in this selection up against the `headers` of a reader.
+    ///
+    /// # Errors
+    /// If a selected or filtered column isn't in `headers` or a column is selected twice.
+    pub fn resolve<S: AsRef<str>>(&self, headers: &[S]) -> Result<ResolvedSelection, EtError> {
+        let index = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h.as_ref() == name)
+                .ok_or_else(|| {
+                    EtError::from(format!("Column {} not found", name))
+                        .with_kind(ErrorKind::InvalidParam)
+                })
+        };
+
+        let mut positions = vec![None; headers.len()];
+        let n_columns = if let Some(columns) = &self.columns {
+            for (position, name) in columns.iter().enumerate() {
+                let field = index(name)?;
+                if positions[field].is_some() {
+                    return Err(EtError::from(format!(
+                        "Column {} was selected more than once",
+                        name
+                    ))
+                    .with_kind(ErrorKind::InvalidParam));
+                }
+                positions[field] = Some(position);
+            }
+            columns.len()
+        } else {
+            for (field, position) in positions.iter_mut().enumerate() {
+                *position = Some(field);
+            }
+            headers.len()
+        };
+        let predicates = self
+            .predicates
+            .iter()
+            .map(|p| Ok((index(&p.column)?, p.clone())))
+            .collect::<Result<_, EtError>>()?;
+        Ok(ResolvedSelection {
+            positions,
+            n_columns,
+            predicates,
+        })
+    }
+}
+
+/// A `Selection` that's been matched up against the columns of a specific reader
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedSelection {
+    /// For every column in the record, where it goes in the output (if it's kept)
+    positions: Vec<Option<usize>>,
+    n_columns: usize,
+    predicates: Vec<(usize, Predicate)>,
+}
+
+impl ResolvedSelection {
+    /// The number of columns that are returned
+    #[must_use]
+    pub fn n_columns(&self) -> usize {
+        self.n_columns
+    }
+
+    /// If any rows are filtered out (rather than just columns being picked)
+    #[must_use]
+    pub fn has_filters(&self) -> bool {
+        !self.predicates.is_empty()
+    }
+
+    /// Where the `field`th column of a record ends up in the output, if it's kept
+    #[must_use]
+    pub fn position(&self, field: usize) -> Option<usize> {
+        self.positions.get(field).copied().flatten()
+    }
+
+    /// If the `field`th column of a record passes all the filters on it
+    ///
+    /// `value` is only called if there's a filter on that column.
+    pub fn matches_field<'v, F: FnOnce() -> Value<'v>>(&self, field: usize, value: F) -> bool {
+        let mut predicates = self
+            .predicates
+            .iter()
+            .filter(|(f, _)| *f == field)
+            .peekable();
+        if predicates.peek().is_none() {
+            return true;
+        }
+        let value = value();
+        predicates.all(|(_, p)| p.matches(&value))
+    }
+
+    /// Pick out the selected columns from e.g. a reader's headers
+    #[must_use]
+    pub fn project<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        let mut projected: Vec<(usize, &T)> = items
+            .iter()
+            .enumerate()
+            .filter_map(|(field, item)| Some((self.position(field)?, item)))
+            .collect();
+        projected.sort_by_key(|(position, _)| *position);
+        projected
+            .into_iter()
+            .map(|(_, item)| item.clone())
+            .collect()
+    }
+
+    /// If a record's values pass all of the filters
+    #[must_use]
+    pub fn matches(&self, values: &[Value]) -> bool {
+        self.predicates
+            .iter()
+            .all(|(field, p)| values.get(*field).is_some_and(|v| p.matches(v)))
+    }
+
+    /// Pick out the selected columns of a record (without checking the filters)
+    #[must_use]
+    pub fn project_values<'r>(&self, values: Vec<Value<'r>>) -> Vec<Value<'r>> {
+        let mut projected = vec![Value::Null; self.n_columns];
+        for (field, value) in values.into_iter().enumerate() {
+            if let Some(position) = self.position(field) {
+                projected[position] = value;
+            }
+        }
+        projected
+    }
+
+    /// Filter and then project a record, returning `None` if it's filtered out
+    #[must_use]
+    pub fn apply<'r>(&self, values: Vec<Value<'r>>) -> Option<Vec<Value<'r>>> {
+        if !self.matches(&values) {
+            return None;
+        }
+        Some(self.project_values(values))
+    }
+}
+
+/// A record that can be filtered and projected into `Value`s
+///
+/// `impl_record!` derives a version of this that only converts the fields it needs to; other
+/// records only have to say how to check the filters and are converted completely first.
+pub trait SelectRecord<'r>: Sized + Into<Vec<Value<'r>>> {
+    /// If this record passes the selection's filters
+    fn matches(&self, selection: &ResolvedSelection) -> bool;
+
+    /// Convert the selected columns of this record into `Value`s (without checking the filters)
+    fn project(self, selection: &ResolvedSelection) -> Vec<Value<'r>> {
+        selection.project_values(self.into())
+    }
+
+    /// Convert the selected columns of this record into `Value`s, or return `None` if the record
+    /// is filtered out.
+    fn select(self, selection: &ResolvedSelection) -> Option<Vec<Value<'r>>> {
+        if !self.matches(selection) {
+            return None;
+        }
+        Some(self.project(selection))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_predicates() -> Result<(), EtError> {
+        assert_eq!(
+            "mapq > 30".parse::<Predicate>()?,
+            Predicate::new("mapq", Comparison::Greater, Value::Integer(30))
+        );
+        assert_eq!(
+            "time<=1.5".parse::<Predicate>()?,
+            Predicate::new("time", Comparison::LessOrEqual, Value::Float(1.5))
+        );
+        assert_eq!(
+            "ref_name = '12'".parse::<Predicate>()?,
+            Predicate::new("ref_name", Comparison::Equal, "12".into())
+        );
+        assert_eq!(
+            "id != test".parse::<Predicate>()?,
+            Predicate::new("id", Comparison::NotEqual, "test".into())
+        );
+        assert!("mapq".parse::<Predicate>().is_err());
+        assert!("> 30".parse::<Predicate>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_predicate_matches() {
+        let p = Predicate::new("x", Comparison::Greater, Value::Integer(30));
+        assert!(p.matches(&Value::Integer(31)));
+        assert!(p.matches(&Value::Float(30.5)));
+        assert!(!p.matches(&Value::Integer(30)));
+        assert!(!p.matches(&Value::Null));
+        assert!(!p.matches(&"40".into()));
+        let p = Predicate::new("x", Comparison::NotEqual, Value::Integer(30));
+        assert!(p.matches(&Value::Null));
+    }
+
+    #[test]
+    fn test_resolve_selection() -> Result<(), EtError> {
+        let headers = ["a", "b", "c"];
+        let selection = Selection::default()
+            .columns(&["c", "a"])
+            .filter("b >= 2".parse()?);
+        let resolved = selection.resolve(&headers)?;
+        assert_eq!(resolved.n_columns(), 2);
+        assert_eq!(resolved.project(&headers), ["c", "a"]);
+        assert_eq!(
+            resolved.apply(vec![1.into(), 2.into(), "x".into()]),
+            Some(vec!["x".into(), 1.into()])
+        );
+        assert_eq!(resolved.apply(vec![1.into(), 1.into(), "x".into()]), None);
+        assert!(resolved.matches(&[1.into(), 2.into()]));
+        assert!(!resolved.matches(&[1.into()]));
+        assert_eq!(
+            resolved.project_values(vec![1.into(), 1.into(), "x".into()]),
+            ["x".into(), 1.into()]
+        );
+
+        assert!(Selection::default()
+            .columns(&["d"])
+            .resolve(&headers)
+            .is_err());
+        assert!(Selection::default()
+            .columns(&["a", "a"])
+            .resolve(&headers)
+            .is_err());
+
+        let mut params = BTreeMap::new();
+        drop(params.insert("columns".to_string(), "a, c".into()));
+        drop(params.insert(
+            "filter".to_string(),
+            Value::List(vec!["a > 1".into(), "c == x".into()]),
+        ));
+        let selection = Selection::from_params(&mut params)?;
+        assert!(params.is_empty());
+        assert_eq!(
+            selection.columns,
+            Some(vec!["a".to_string(), "c".to_string()])
+        );
+        assert_eq!(selection.predicates.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_filter_params() -> Result<(), EtError> {
+        let mut params = BTreeMap::new();
+        drop(params.insert(
+            "filter".to_string(),
+            "id == \"a,b\", name != 'c, d',x>1".into(),
+        ));
+        let selection = Selection::from_params(&mut params)?;
+        assert_eq!(
+            selection.predicates,
+            [
+                Predicate::new("id", Comparison::Equal, "a,b".into()),
+                Predicate::new("name", Comparison::NotEqual, "c, d".into()),
+                Predicate::new("x", Comparison::Greater, Value::Integer(1)),
+            ]
+        );
+        Ok(())
+    }
+}